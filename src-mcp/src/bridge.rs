@@ -18,6 +18,9 @@ use tokio::time::{timeout, Duration};
 #[cfg(unix)]
 use tokio::net::UnixStream;
 
+/// How long to wait for the app to answer a command.
+pub const DEFAULT_RESPONSE_TIMEOUT: Duration = Duration::from_secs(30);
+
 #[allow(dead_code)]
 #[derive(Serialize)]
 struct IpcRequest {
@@ -83,6 +86,18 @@ impl AppBridge {
 
     /// Send a command to the Tauri app and wait for the response (30 s timeout).
     pub async fn send_command(&self, command: &str, params: Value) -> Result<Value> {
+        self.send_command_with_timeout(command, params, DEFAULT_RESPONSE_TIMEOUT)
+            .await
+    }
+
+    /// Like [`send_command`](Self::send_command), for commands that are
+    /// expected to run longer than the default timeout (e.g. recordings).
+    pub async fn send_command_with_timeout(
+        &self,
+        command: &str,
+        params: Value,
+        response_timeout: Duration,
+    ) -> Result<Value> {
         #[cfg(unix)]
         {
             // Generate a simple request ID.
@@ -111,14 +126,14 @@ impl AppBridge {
 
             // Read response length
             let mut len_buf = [0u8; 4];
-            timeout(Duration::from_secs(30), stream.read_exact(&mut len_buf))
+            timeout(response_timeout, stream.read_exact(&mut len_buf))
                 .await
                 .map_err(|_| anyhow!("timeout waiting for IPC response to '{command}'"))??;
 
             // Read response body
             let resp_len = u32::from_be_bytes(len_buf) as usize;
             let mut resp_buf = vec![0u8; resp_len];
-            timeout(Duration::from_secs(30), stream.read_exact(&mut resp_buf))
+            timeout(response_timeout, stream.read_exact(&mut resp_buf))
                 .await
                 .map_err(|_| anyhow!("timeout reading IPC response body"))??;
 
@@ -1,3 +1,7 @@
+// The annotate_screenshot input schema nests deeper than `json!`'s default
+// recursion limit allows.
+#![recursion_limit = "256"]
+
 mod bridge;
 mod prompts;
 mod resources;
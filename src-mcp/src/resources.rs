@@ -127,13 +127,12 @@ async fn read_screenshot(
             blob: b64.to_owned(),
         });
     }
-    let meta = json!({
-        "id":        value.get("id"),
-        "timestamp": value.get("timestamp"),
-        "width":     value.get("width"),
-        "height":    value.get("height"),
-        "mode":      value.get("mode"),
-    });
+    // Everything but the image itself: id, timestamp, mode, monitor, window
+    // title, app name, dimensions, scale factor, parent id.
+    let mut meta = value.clone();
+    if let Some(obj) = meta.as_object_mut() {
+        obj.remove("image_b64");
+    }
     contents.push(ResourceContents::TextResourceContents {
         uri: format!("{uri}#metadata"),
         mime_type: Some("application/json".to_owned()),
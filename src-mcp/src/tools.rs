@@ -4,27 +4,28 @@
 /// is unavailable (app not running), calls return an error content block
 /// rather than an MCP protocol error, per the spec.
 use std::sync::Arc;
+use std::time::Duration;
 
 use rmcp::model::{CallToolResult, Content, ListToolsResult, Tool};
 use rmcp::Error as McpError;
 use serde_json::{Map, Value};
 
-use crate::bridge::AppBridge;
+use crate::bridge::{AppBridge, DEFAULT_RESPONSE_TIMEOUT};
 
-/// Returns all 6 tool definitions for `tools/list`.
+/// Returns all 15 tool definitions for `tools/list`.
 pub fn list() -> ListToolsResult {
     ListToolsResult {
         next_cursor: None,
         tools: vec![
             Tool::new(
                 "take_screenshot",
-                "Capture a screenshot of the desktop, a specific monitor, or a specific window.",
+                "Capture a screenshot of the desktop, a specific monitor, a specific window, or a rectangular region.",
                 schema(serde_json::json!({
                     "type": "object",
                     "properties": {
                         "mode": {
                             "type": "string",
-                            "enum": ["fullscreen", "monitor", "window"],
+                            "enum": ["fullscreen", "monitor", "window", "region"],
                             "default": "fullscreen",
                             "description": "The capture mode"
                         },
@@ -36,10 +37,33 @@ pub fn list() -> ListToolsResult {
                             "type": "string",
                             "description": "Substring to match against window titles (used when mode is 'window')"
                         },
+                        "x": {
+                            "type": "integer",
+                            "description": "Left edge in desktop coordinates (used when mode is 'region'); may be negative"
+                        },
+                        "y": {
+                            "type": "integer",
+                            "description": "Top edge in desktop coordinates (used when mode is 'region'); may be negative"
+                        },
+                        "width": {
+                            "type": "integer",
+                            "minimum": 1,
+                            "description": "Region width in desktop units (used when mode is 'region')"
+                        },
+                        "height": {
+                            "type": "integer",
+                            "minimum": 1,
+                            "description": "Region height in desktop units (used when mode is 'region')"
+                        },
                         "delay_ms": {
                             "type": "integer",
                             "default": 0,
                             "description": "Delay in milliseconds before capture"
+                        },
+                        "dedupe": {
+                            "type": "boolean",
+                            "default": false,
+                            "description": "If the new frame looks the same as the most recent capture, return that capture's id (with duplicate: true) instead of storing another copy"
                         }
                     }
                 })),
@@ -64,7 +88,7 @@ pub fn list() -> ListToolsResult {
             ),
             Tool::new(
                 "annotate_screenshot",
-                "Add annotations (rectangles, arrows, text, blur regions) to a screenshot and return the composited image.",
+                "Add annotations (rectangles, arrows, text, blur regions) to a screenshot. The result is stored as a new screenshot derived from the original; returns its screenshot_id and the composited image.",
                 schema(serde_json::json!({
                     "type": "object",
                     "required": ["screenshot_id", "annotations"],
@@ -79,14 +103,18 @@ pub fn list() -> ListToolsResult {
                             "items": {
                                 "type": "object",
                                 "required": ["type"],
+                                "description": "Required fields by type: rect, ellipse, highlight, blur: x, y, width, height; arrow, freehand: points (at least 2); text: x, y, text; step: x, y, stepNumber; callout: x, y, width, height, tail (bubble box plus the point its tail reaches); magnifier, spotlight: x, y, width, height; image: x, y, width, height and exactly one of imageId, stamp. Colors are #RRGGBB, #RRGGBBAA or \"transparent\". Invalid annotations fail the call with one error per problem.",
                                 "properties": {
                                     "type": {
                                         "type": "string",
-                                        "description": "Annotation type (rect, arrow, text, step, blur, freehand)"
+                                        "enum": ["rect", "ellipse", "arrow", "freehand", "highlight", "blur", "step", "text", "callout", "magnifier", "spotlight", "image"]
                                     },
                                     "x": { "type": "number" },
                                     "y": { "type": "number" },
-                                    "width": { "type": "number" },
+                                    "width": {
+                                        "type": "number",
+                                        "description": "Shape width; for text, the width to wrap at (0 or absent for no wrapping)"
+                                    },
                                     "height": { "type": "number" },
                                     "points": {
                                         "type": "array",
@@ -99,6 +127,27 @@ pub fn list() -> ListToolsResult {
                                         }
                                     },
                                     "text": { "type": "string" },
+                                    "tail": {
+                                        "type": "object",
+                                        "description": "Callout: where the bubble's tail points",
+                                        "properties": {
+                                            "x": { "type": "number" },
+                                            "y": { "type": "number" }
+                                        }
+                                    },
+                                    "textColor": { "type": "string", "description": "Callout text; default black" },
+                                    "source": {
+                                        "type": "object",
+                                        "description": "Magnifier: centre of the area to enlarge (default: the loupe's own centre)",
+                                        "properties": {
+                                            "x": { "type": "number" },
+                                            "y": { "type": "number" }
+                                        }
+                                    },
+                                    "zoom": { "type": "number", "minimum": 1, "default": 2, "description": "Magnifier enlargement" },
+                                    "dimColor": { "type": "string", "default": "#00000099", "description": "Spotlight: colour laid over everything outside the box" },
+                                    "imageId": { "type": "string", "description": "Image: stored screenshot to embed, stretched over the box" },
+                                    "stamp": { "type": "string", "enum": ["checkmark", "cross", "warning"], "description": "Image: built-in stamp to draw over the box" },
                                     "strokeColor": {
                                         "type": "string",
                                         "default": "#FF0000"
@@ -106,6 +155,50 @@ pub fn list() -> ListToolsResult {
                                     "strokeWidth": {
                                         "type": "number",
                                         "default": 2
+                                    },
+                                    "fillColor": { "type": "string" },
+                                    "lineDash": {
+                                        "type": "array",
+                                        "items": { "type": "number", "minimum": 0 },
+                                        "description": "Dash pattern for rect, ellipse, arrow and freehand strokes, e.g. [6, 4]"
+                                    },
+                                    "highlightColor": { "type": "string" },
+                                    "fontSize": { "type": "number" },
+                                    "fontFamily": {
+                                        "type": "string",
+                                        "default": "sans-serif",
+                                        "description": "CSS family list for text, e.g. \"Inter, monospace\"; resolved against the system's fonts"
+                                    },
+                                    "bold": { "type": "boolean" },
+                                    "italic": { "type": "boolean" },
+                                    "align": {
+                                        "type": "string",
+                                        "enum": ["start", "end", "left", "center", "right"],
+                                        "default": "start",
+                                        "description": "Text alignment; start and end follow each paragraph's direction"
+                                    },
+                                    "backgroundColor": { "type": "string", "description": "Fill behind text, e.g. \"#FFFFFFCC\"" },
+                                    "borderColor": { "type": "string", "description": "Outline around the text box" },
+                                    "borderWidth": { "type": "number", "minimum": 0, "default": 1 },
+                                    "cornerRadius": { "type": "number", "minimum": 0, "default": 4 },
+                                    "padding": { "type": "number", "minimum": 0, "default": 4 },
+                                    "stepNumber": { "type": "integer", "minimum": 0 },
+                                    "blurRadius": { "type": "number", "minimum": 1 },
+                                    "redactStyle": {
+                                        "type": "string",
+                                        "enum": ["pixelate", "solid", "noise", "gaussian"],
+                                        "default": "pixelate",
+                                        "description": "How a blur hides its area; use solid for secrets"
+                                    },
+                                    "opacity": {
+                                        "type": "number",
+                                        "minimum": 0,
+                                        "maximum": 1,
+                                        "description": "Applies to every type except blur, which always redacts fully"
+                                    },
+                                    "rotation": {
+                                        "type": "number",
+                                        "description": "Clockwise degrees about the centre of a rect, ellipse, text box or image"
                                     }
                                 }
                             }
@@ -138,7 +231,7 @@ pub fn list() -> ListToolsResult {
             ),
             Tool::new(
                 "auto_redact_pii",
-                "Detect and blur personally identifiable information (email, phone, SSN, credit card, etc.) in a screenshot.",
+                "Detect and redact personally identifiable information (email, phone, SSN, credit card, etc.) in a screenshot. The redacted copy is stored as a new screenshot derived from the original; returns its screenshot_id, the image and the detections.",
                 schema(serde_json::json!({
                     "type": "object",
                     "required": ["screenshot_id"],
@@ -146,13 +239,19 @@ pub fn list() -> ListToolsResult {
                         "screenshot_id": {
                             "type": "string",
                             "description": "ID of the screenshot to redact"
+                        },
+                        "style": {
+                            "type": "string",
+                            "enum": ["solid", "noise", "gaussian", "pixelate"],
+                            "default": "solid",
+                            "description": "How to hide each detection: an opaque black box, random noise, a strong blur or a coarse mosaic. All are irreversible"
                         }
                     }
                 })),
             ),
             Tool::new(
                 "list_screenshots",
-                "Return metadata for recent screenshots in the current session.",
+                "Return metadata for recent screenshots in the library (including earlier sessions), newest first: id, timestamp, capture mode, monitor, window title and app name, dimensions, scale factor and the id of the screenshot it was derived from.",
                 schema(serde_json::json!({
                     "type": "object",
                     "properties": {
@@ -164,10 +263,245 @@ pub fn list() -> ListToolsResult {
                     }
                 })),
             ),
+            Tool::new(
+                "delete_screenshot",
+                "Permanently delete a screenshot from the Fotos library.",
+                schema(serde_json::json!({
+                    "type": "object",
+                    "required": ["screenshot_id"],
+                    "properties": {
+                        "screenshot_id": {
+                            "type": "string",
+                            "description": "ID of the screenshot to delete"
+                        }
+                    }
+                })),
+            ),
+            Tool::new(
+                "record_screen",
+                "Record the desktop, a monitor, a window, or a region to an animated GIF, APNG, or WebP file. Identical consecutive frames are merged. On Wayland each frame goes through the screenshot portal, which limits recordings to a frame or two per second.",
+                schema(serde_json::json!({
+                    "type": "object",
+                    "required": ["duration_ms"],
+                    "properties": {
+                        "mode": {
+                            "type": "string",
+                            "enum": ["fullscreen", "monitor", "window", "region"],
+                            "default": "fullscreen",
+                            "description": "What to record; takes the same mode parameters as take_screenshot"
+                        },
+                        "monitor_index": {
+                            "type": "integer",
+                            "description": "Monitor index (used when mode is 'monitor')"
+                        },
+                        "window_title": {
+                            "type": "string",
+                            "description": "Substring to match against window titles (used when mode is 'window')"
+                        },
+                        "x": { "type": "integer", "description": "Region left edge (mode 'region')" },
+                        "y": { "type": "integer", "description": "Region top edge (mode 'region')" },
+                        "width": { "type": "integer", "minimum": 1, "description": "Region width (mode 'region')" },
+                        "height": { "type": "integer", "minimum": 1, "description": "Region height (mode 'region')" },
+                        "duration_ms": {
+                            "type": "integer",
+                            "minimum": 1,
+                            "maximum": MAX_RECORDING_MS,
+                            "description": "Recording length in milliseconds"
+                        },
+                        "fps": {
+                            "type": "integer",
+                            "minimum": 1,
+                            "maximum": 30,
+                            "default": 10,
+                            "description": "Frames sampled per second"
+                        },
+                        "format": {
+                            "type": "string",
+                            "enum": ["gif", "apng", "webp"],
+                            "default": "gif",
+                            "description": "Output container"
+                        },
+                        "path": {
+                            "type": "string",
+                            "description": "Output file path; defaults to a timestamped file in ~/Pictures/Fotos"
+                        }
+                    }
+                })),
+            ),
+            Tool::new(
+                "start_capture_session",
+                "Start a background timelapse: capture a screenshot every N seconds or on a cron schedule for a bounded duration. Returns the session id and status immediately; each frame is stored like a normal screenshot.",
+                schema(serde_json::json!({
+                    "type": "object",
+                    "required": ["duration_secs"],
+                    "properties": {
+                        "mode": {
+                            "type": "string",
+                            "enum": ["fullscreen", "monitor", "window", "region"],
+                            "default": "fullscreen",
+                            "description": "What to capture; takes the same mode parameters as take_screenshot"
+                        },
+                        "monitor_index": {
+                            "type": "integer",
+                            "description": "Monitor index (used when mode is 'monitor')"
+                        },
+                        "window_title": {
+                            "type": "string",
+                            "description": "Substring to match against window titles (used when mode is 'window')"
+                        },
+                        "x": { "type": "integer", "description": "Region left edge (mode 'region')" },
+                        "y": { "type": "integer", "description": "Region top edge (mode 'region')" },
+                        "width": { "type": "integer", "minimum": 1, "description": "Region width (mode 'region')" },
+                        "height": { "type": "integer", "minimum": 1, "description": "Region height (mode 'region')" },
+                        "interval_secs": {
+                            "type": "number",
+                            "minimum": 1,
+                            "description": "Seconds between captures; give this or 'cron'"
+                        },
+                        "cron": {
+                            "type": "string",
+                            "description": "Five-field cron expression in local time, e.g. '*/15 9-17 * * 1-5'; give this or 'interval_secs'"
+                        },
+                        "duration_secs": {
+                            "type": "integer",
+                            "minimum": 1,
+                            "maximum": 86400,
+                            "description": "How long the session runs"
+                        },
+                        "max_frames": {
+                            "type": "integer",
+                            "minimum": 1,
+                            "description": "Stop after this many stored frames"
+                        },
+                        "skip_unchanged": {
+                            "type": "boolean",
+                            "default": false,
+                            "description": "Drop frames that look the same as the previous stored frame"
+                        }
+                    }
+                })),
+            ),
+            Tool::new(
+                "get_capture_session",
+                "Return the status of a capture session: state, stored frames with their metadata, and skipped count.",
+                schema(serde_json::json!({
+                    "type": "object",
+                    "required": ["session_id"],
+                    "properties": {
+                        "session_id": {
+                            "type": "string",
+                            "description": "ID returned by start_capture_session"
+                        }
+                    }
+                })),
+            ),
+            Tool::new(
+                "list_capture_sessions",
+                "Return the status of every capture session, newest first: running sessions and the most recent ended ones.",
+                schema(serde_json::json!({
+                    "type": "object",
+                    "properties": {}
+                })),
+            ),
+            Tool::new(
+                "stop_capture_session",
+                "Stop a running capture session. Frames captured so far are kept.",
+                schema(serde_json::json!({
+                    "type": "object",
+                    "required": ["session_id"],
+                    "properties": {
+                        "session_id": {
+                            "type": "string",
+                            "description": "ID returned by start_capture_session"
+                        }
+                    }
+                })),
+            ),
+            Tool::new(
+                "search_screenshots",
+                "Full-text search over the OCR text of stored screenshots (indexed in the background after each capture). Every word of the query must appear, prefixes match. Returns matching screenshots, best first, with the bounding boxes of the matching words.",
+                schema(serde_json::json!({
+                    "type": "object",
+                    "required": ["query"],
+                    "properties": {
+                        "query": {
+                            "type": "string",
+                            "description": "Words to look for, e.g. \"connection refused\""
+                        },
+                        "limit": {
+                            "type": "integer",
+                            "minimum": 1,
+                            "default": 20,
+                            "description": "Maximum number of screenshots to return"
+                        }
+                    }
+                })),
+            ),
+            Tool::new(
+                "find_similar_screenshots",
+                "Find stored screenshots that look like the given one (near-duplicates first), using a perceptual hash.",
+                schema(serde_json::json!({
+                    "type": "object",
+                    "required": ["screenshot_id"],
+                    "properties": {
+                        "screenshot_id": {
+                            "type": "string",
+                            "description": "ID of the screenshot to compare against"
+                        },
+                        "max_distance": {
+                            "type": "integer",
+                            "minimum": 0,
+                            "maximum": 64,
+                            "default": 10,
+                            "description": "Largest hash distance in bits to report (0 = identical)"
+                        },
+                        "limit": {
+                            "type": "integer",
+                            "minimum": 1,
+                            "description": "Maximum number of results"
+                        }
+                    }
+                })),
+            ),
+            Tool::new(
+                "get_screenshot_lineage",
+                "Show where a screenshot came from and what was made from it: its ancestors back to the original capture, every derived copy (crops, redactions, annotations), and the operations that produced it.",
+                schema(serde_json::json!({
+                    "type": "object",
+                    "required": ["screenshot_id"],
+                    "properties": {
+                        "screenshot_id": {
+                            "type": "string",
+                            "description": "ID of the screenshot"
+                        }
+                    }
+                })),
+            ),
+            Tool::new(
+                "reapply_operations",
+                "Replay the operations that produced a derived screenshot (e.g. crop then redact) on another screenshot, or on a fresh fullscreen capture. Returns the final derived screenshot.",
+                schema(serde_json::json!({
+                    "type": "object",
+                    "required": ["source_id"],
+                    "properties": {
+                        "source_id": {
+                            "type": "string",
+                            "description": "ID of the derived screenshot whose operation chain to replay"
+                        },
+                        "screenshot_id": {
+                            "type": "string",
+                            "description": "Screenshot to apply the chain to. Omit to take a new fullscreen capture."
+                        }
+                    }
+                })),
+            ),
         ],
     }
 }
 
+/// Longest recording the `record_screen` tool accepts (matches the app's cap).
+const MAX_RECORDING_MS: u64 = 60_000;
+
 /// Dispatch a `tools/call` request.
 pub async fn call(
     bridge: Option<&AppBridge>,
@@ -183,7 +517,36 @@ pub async fn call(
         | "annotate_screenshot"
         | "analyze_screenshot"
         | "auto_redact_pii"
-        | "list_screenshots" => call_via_bridge(bridge, name, args).await,
+        | "list_screenshots"
+        | "delete_screenshot"
+        | "start_capture_session"
+        | "get_capture_session"
+        | "list_capture_sessions"
+        | "stop_capture_session"
+        | "search_screenshots"
+        | "find_similar_screenshots"
+        | "get_screenshot_lineage" => {
+            call_via_bridge(bridge, name, args, DEFAULT_RESPONSE_TIMEOUT).await
+        }
+        "reapply_operations" => {
+            // Replaying a redaction runs OCR once per step.
+            call_via_bridge(bridge, name, args, DEFAULT_RESPONSE_TIMEOUT * 4).await
+        }
+        "record_screen" => {
+            let duration_ms = args
+                .get("duration_ms")
+                .and_then(Value::as_u64)
+                .ok_or_else(|| McpError::invalid_params("duration_ms is required", None))?;
+            if duration_ms == 0 || duration_ms > MAX_RECORDING_MS {
+                return Err(McpError::invalid_params(
+                    format!("duration_ms must be between 1 and {MAX_RECORDING_MS}"),
+                    None,
+                ));
+            }
+            // Allow for window hiding and encoding on top of the recording.
+            let wait = Duration::from_millis(duration_ms) + DEFAULT_RESPONSE_TIMEOUT;
+            call_via_bridge(bridge, name, args, wait).await
+        }
         _ => Err(McpError::invalid_params(
             format!("unknown tool: {name}"),
             None,
@@ -197,6 +560,7 @@ async fn call_via_bridge(
     bridge: Option<&AppBridge>,
     command: &str,
     params: &Map<String, Value>,
+    response_timeout: Duration,
 ) -> Result<CallToolResult, McpError> {
     let Some(bridge) = bridge else {
         return Ok(CallToolResult::error(vec![Content::text(
@@ -205,7 +569,7 @@ async fn call_via_bridge(
     };
 
     match bridge
-        .send_command(command, Value::Object(params.clone()))
+        .send_command_with_timeout(command, Value::Object(params.clone()), response_timeout)
         .await
     {
         Ok(value) => Ok(CallToolResult::success(format_result(command, value))),
@@ -219,13 +583,14 @@ async fn call_via_bridge(
 fn format_result(command: &str, value: Value) -> Vec<Content> {
     match command {
         "take_screenshot" => {
-            // { id, image_b64, width, height, timestamp, mode }
+            // { id, image_b64, width, height, timestamp, mode, duplicate }
             let meta = serde_json::json!({
                 "id":        value.get("id"),
                 "width":     value.get("width"),
                 "height":    value.get("height"),
                 "timestamp": value.get("timestamp"),
                 "mode":      value.get("mode"),
+                "duplicate": value.get("duplicate"),
             });
             let mut out = vec![Content::text(meta.to_string())];
             if let Some(b64) = value.get("image_b64").and_then(|v| v.as_str()) {
@@ -234,24 +599,29 @@ fn format_result(command: &str, value: Value) -> Vec<Content> {
             out
         }
         "annotate_screenshot" => {
-            // { image_b64 }
+            // { screenshot_id, parent_id, image_b64 }
+            let ids = serde_json::json!({
+                "screenshot_id": value.get("screenshot_id"),
+                "parent_id":     value.get("parent_id"),
+            });
+            let mut out = vec![Content::text(ids.to_string())];
             if let Some(b64) = value.get("image_b64").and_then(|v| v.as_str()) {
-                vec![Content::image(b64.to_owned(), "image/png")]
-            } else {
-                vec![Content::text(value.to_string())]
+                out.push(Content::image(b64.to_owned(), "image/png"));
             }
+            out
         }
         "auto_redact_pii" => {
-            // { image_b64, detections: [{type, x, y, w, h}] }
+            // { screenshot_id, parent_id, image_b64, detections: [{type, x, y, w, h}] }
             let mut out = vec![];
             if let Some(b64) = value.get("image_b64").and_then(|v| v.as_str()) {
                 out.push(Content::image(b64.to_owned(), "image/png"));
             }
-            let detections = value
-                .get("detections")
-                .cloned()
-                .unwrap_or(Value::Array(vec![]));
-            out.push(Content::text(detections.to_string()));
+            let summary = serde_json::json!({
+                "screenshot_id": value.get("screenshot_id"),
+                "parent_id":     value.get("parent_id"),
+                "detections":    value.get("detections").cloned().unwrap_or(Value::Array(vec![])),
+            });
+            out.push(Content::text(summary.to_string()));
             out
         }
         _ => vec![Content::text(value.to_string())],
@@ -3,3 +3,4 @@ pub mod llm;
 pub mod ocr;
 pub mod openai_compat;
 pub mod pii;
+pub mod search;
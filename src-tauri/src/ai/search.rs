@@ -0,0 +1,374 @@
+/// Full-text search over OCR output.
+///
+/// Each indexed screenshot keeps its OCR words with their bounding boxes,
+/// and an inverted index maps every lowercased token to the screenshots that
+/// contain it. A query matches a screenshot when each query token is a
+/// prefix of some token in it; the boxes of the matching words come back so
+/// the UI can highlight them.
+///
+/// The index can be persisted as an append-only JSON-lines log that is
+/// compacted on open, the same way the screenshot library keeps its index.
+use super::ocr::OcrRegion;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use uuid::Uuid;
+
+/// An OCR word and where it sits in the image.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexedWord {
+    pub text: String,
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+impl From<&OcrRegion> for IndexedWord {
+    fn from(r: &OcrRegion) -> Self {
+        Self {
+            text: r.text.clone(),
+            x: r.x,
+            y: r.y,
+            w: r.w,
+            h: r.h,
+        }
+    }
+}
+
+/// A screenshot matching a query.
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub id: Uuid,
+    /// The words that matched a query token, in reading order.
+    pub words: Vec<IndexedWord>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Record {
+    Add { id: Uuid, words: Vec<IndexedWord> },
+    Remove { id: Uuid },
+}
+
+#[derive(Default)]
+struct State {
+    docs: HashMap<Uuid, Vec<IndexedWord>>,
+    postings: BTreeMap<String, HashSet<Uuid>>,
+}
+
+impl State {
+    fn add(&mut self, id: Uuid, words: Vec<IndexedWord>) {
+        self.remove(&id);
+        for word in &words {
+            for token in tokenize(&word.text) {
+                self.postings.entry(token).or_default().insert(id);
+            }
+        }
+        self.docs.insert(id, words);
+    }
+
+    fn remove(&mut self, id: &Uuid) -> bool {
+        let Some(words) = self.docs.remove(id) else {
+            return false;
+        };
+        for word in &words {
+            for token in tokenize(&word.text) {
+                if let Some(ids) = self.postings.get_mut(&token) {
+                    ids.remove(id);
+                    if ids.is_empty() {
+                        self.postings.remove(&token);
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// Screenshots with a token starting with `prefix`.
+    fn with_prefix(&self, prefix: &str) -> HashSet<Uuid> {
+        self.postings
+            .range(prefix.to_owned()..)
+            .take_while(|(token, _)| token.starts_with(prefix))
+            .flat_map(|(_, ids)| ids.iter().copied())
+            .collect()
+    }
+}
+
+pub struct SearchIndex {
+    /// Log file, `None` for an in-memory index.
+    path: Option<PathBuf>,
+    state: RwLock<State>,
+    writer: Mutex<()>,
+}
+
+impl SearchIndex {
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            state: RwLock::new(State::default()),
+            writer: Mutex::new(()),
+        }
+    }
+
+    /// Open (or create) the index log at `path`, compacting it.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let mut state = State::default();
+        for (id, words) in read_log(&path)? {
+            state.add(id, words);
+        }
+        write_log(&path, &state.docs)?;
+        tracing::info!(
+            "search index: {} screenshots in {}",
+            state.docs.len(),
+            path.display()
+        );
+        Ok(Self {
+            path: Some(path),
+            state: RwLock::new(state),
+            writer: Mutex::new(()),
+        })
+    }
+
+    /// Index (or re-index) `id` with its OCR words. An empty list still
+    /// marks the screenshot as indexed.
+    pub fn add(&self, id: Uuid, words: Vec<IndexedWord>) {
+        self.append(&Record::Add {
+            id,
+            words: words.clone(),
+        });
+        self.state.write().unwrap().add(id, words);
+    }
+
+    pub fn add_ocr(&self, id: Uuid, regions: &[OcrRegion]) {
+        self.add(id, regions.iter().map(IndexedWord::from).collect());
+    }
+
+    pub fn remove(&self, id: &Uuid) -> bool {
+        let removed = self.state.write().unwrap().remove(id);
+        if removed {
+            self.append(&Record::Remove { id: *id });
+        }
+        removed
+    }
+
+    /// The OCR words indexed for `id`.
+    pub fn words(&self, id: &Uuid) -> Option<Vec<IndexedWord>> {
+        self.state.read().unwrap().docs.get(id).cloned()
+    }
+
+    pub fn contains(&self, id: &Uuid) -> bool {
+        self.state.read().unwrap().docs.contains_key(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.read().unwrap().docs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Screenshots containing every token of `query` (as a word prefix),
+    /// most matching words first. An empty query matches nothing.
+    pub fn search(&self, query: &str) -> Vec<SearchHit> {
+        let terms = tokenize(query);
+        if terms.is_empty() {
+            return Vec::new();
+        }
+        let state = self.state.read().unwrap();
+        let mut candidates = state.with_prefix(&terms[0]);
+        for term in &terms[1..] {
+            let ids = state.with_prefix(term);
+            candidates.retain(|id| ids.contains(id));
+        }
+
+        let mut hits: Vec<SearchHit> = candidates
+            .into_iter()
+            .map(|id| {
+                let words = state.docs[&id]
+                    .iter()
+                    .filter(|word| {
+                        tokenize(&word.text)
+                            .iter()
+                            .any(|token| terms.iter().any(|t| token.starts_with(t.as_str())))
+                    })
+                    .cloned()
+                    .collect();
+                SearchHit { id, words }
+            })
+            .collect();
+        hits.sort_by_key(|hit| std::cmp::Reverse(hit.words.len()));
+        hits
+    }
+
+    fn append(&self, record: &Record) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let _guard = self.writer.lock().unwrap();
+        let result = (|| -> Result<()> {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            let mut line = serde_json::to_vec(record)?;
+            line.push(b'\n');
+            file.write_all(&line)?;
+            Ok(())
+        })();
+        if let Err(e) = result {
+            tracing::error!("search index: failed to write {}: {e:#}", path.display());
+        }
+    }
+}
+
+/// Lowercased alphanumeric runs of `text`.
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Replay the log. Unreadable lines (e.g. a write cut short by a crash) are
+/// skipped.
+fn read_log(path: &Path) -> Result<HashMap<Uuid, Vec<IndexedWord>>> {
+    let mut docs = HashMap::new();
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(docs),
+        Err(e) => return Err(e).with_context(|| format!("Failed to open {}", path.display())),
+    };
+    for (n, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(Record::Add { id, words }) => {
+                docs.insert(id, words);
+            }
+            Ok(Record::Remove { id }) => {
+                docs.remove(&id);
+            }
+            Err(e) => tracing::warn!("search index: skipping line {}: {e}", n + 1),
+        }
+    }
+    Ok(docs)
+}
+
+/// Rewrite the log with one add record per screenshot.
+fn write_log(path: &Path, docs: &HashMap<Uuid, Vec<IndexedWord>>) -> Result<()> {
+    let tmp = path.with_extension("jsonl.tmp");
+    {
+        let mut out = BufWriter::new(File::create(&tmp)?);
+        for (id, words) in docs {
+            let record = Record::Add {
+                id: *id,
+                words: words.clone(),
+            };
+            serde_json::to_writer(&mut out, &record)?;
+            out.write_all(b"\n")?;
+        }
+        out.flush()?;
+    }
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(text: &str, x: u32) -> IndexedWord {
+        IndexedWord {
+            text: text.to_owned(),
+            x,
+            y: 10,
+            w: 40,
+            h: 12,
+        }
+    }
+
+    fn sample() -> (SearchIndex, Uuid, Uuid) {
+        let index = SearchIndex::in_memory();
+        let error = Uuid::new_v4();
+        index.add(
+            error,
+            vec![
+                word("Error:", 0),
+                word("connection", 50),
+                word("refused", 100),
+                word("(os", 150),
+                word("error", 200),
+                word("111)", 250),
+            ],
+        );
+        let editor = Uuid::new_v4();
+        index.add(editor, vec![word("fn", 0), word("connect()", 50)]);
+        (index, error, editor)
+    }
+
+    #[test]
+    fn every_term_must_match_and_hits_carry_boxes() {
+        let (index, error, _) = sample();
+        let hits = index.search("connection ERROR");
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].id, error);
+        let xs: Vec<u32> = hits[0].words.iter().map(|w| w.x).collect();
+        assert_eq!(xs, vec![0, 50, 200]);
+        assert!(index.search("error editor").is_empty());
+        assert!(index.search("  ").is_empty());
+    }
+
+    #[test]
+    fn terms_match_word_prefixes_and_rank_by_hit_count() {
+        let (index, error, editor) = sample();
+        let hits = index.search("conn");
+        let ids: Vec<Uuid> = hits.iter().map(|h| h.id).collect();
+        assert_eq!(ids.len(), 2);
+        assert!(ids.contains(&error) && ids.contains(&editor));
+        // Punctuation splits tokens: "111)" is found by "111".
+        assert_eq!(index.search("111")[0].words[0].text, "111)");
+        let hits = index.search("e");
+        assert_eq!(hits[0].id, error);
+    }
+
+    #[test]
+    fn remove_and_reindex_update_postings() {
+        let (index, error, editor) = sample();
+        assert!(index.remove(&error));
+        assert!(!index.remove(&error));
+        assert!(index.search("refused").is_empty());
+        index.add(editor, vec![word("refused", 0)]);
+        assert_eq!(index.search("refused")[0].id, editor);
+        assert!(index.search("connect").is_empty());
+        assert_eq!(index.len(), 1);
+    }
+
+    #[test]
+    fn index_survives_reopen() {
+        let dir = std::env::temp_dir().join(format!("fotos-search-{}", Uuid::new_v4()));
+        let path = dir.join("search.jsonl");
+        let (kept, dropped) = (Uuid::new_v4(), Uuid::new_v4());
+        {
+            let index = SearchIndex::open(&path).unwrap();
+            index.add(kept, vec![word("kept", 0)]);
+            index.add(dropped, vec![word("dropped", 0)]);
+            index.add(Uuid::new_v4(), Vec::new());
+            index.remove(&dropped);
+        }
+        let index = SearchIndex::open(&path).unwrap();
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.search("kept")[0].id, kept);
+        assert!(index.search("dropped").is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,123 @@
+/// Pictures for image annotations: stored screenshots, image files and
+/// the built-in stamps.
+use super::raster::{self, Cap, Join, Pt, Rotation, Stroke};
+use super::render::faded;
+use crate::capture::ImageStore;
+use image::{DynamicImage, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Built-in stamps, drawn as vectors so they stay sharp at any size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stamp {
+    /// White tick on a green disc.
+    Checkmark,
+    /// White cross on a red disc.
+    Cross,
+    /// Exclamation mark on an amber triangle.
+    Warning,
+}
+
+/// The picture an image annotation names by `imageId` or `path`.
+pub fn load_image(
+    image_id: Option<&str>,
+    path: Option<&str>,
+    store: &ImageStore,
+) -> Result<Arc<DynamicImage>, String> {
+    if let Some(id) = image_id {
+        let uuid = Uuid::parse_str(id).map_err(|e| format!("imageId: {e}"))?;
+        return store
+            .get(&uuid)
+            .ok_or_else(|| format!("imageId: screenshot not found: {id}"));
+    }
+    if let Some(path) = path {
+        let path = crate::commands::files::expand_tilde(path)?;
+        return image::open(&path)
+            .map(Arc::new)
+            .map_err(|e| format!("path: cannot read {}: {e}", path.display()));
+    }
+    Err("image: needs an imageId or a path".to_owned())
+}
+
+/// A piece of a stamp, laid out in a unit square stretched over the box.
+pub enum StampPart {
+    /// Ellipse centred on the point, its radius a fraction of the box's
+    /// width and height.
+    Oval(Pt, f32, Rgba<u8>),
+    Polygon(&'static [Pt], Rgba<u8>),
+    /// Round-capped polyline, its width a fraction of the box's shorter
+    /// side.
+    Line(&'static [Pt], f32, Rgba<u8>),
+}
+
+const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+impl Stamp {
+    /// What the stamp draws, back to front.
+    pub fn parts(self) -> &'static [StampPart] {
+        use StampPart::*;
+        match self {
+            Stamp::Checkmark => &[
+                Oval((0.5, 0.5), 0.5, Rgba([46, 160, 67, 255])),
+                Line(&[(0.28, 0.52), (0.43, 0.67), (0.72, 0.36)], 0.1, WHITE),
+            ],
+            Stamp::Cross => &[
+                Oval((0.5, 0.5), 0.5, Rgba([215, 58, 73, 255])),
+                Line(&[(0.33, 0.33), (0.67, 0.67)], 0.1, WHITE),
+                Line(&[(0.67, 0.33), (0.33, 0.67)], 0.1, WHITE),
+            ],
+            Stamp::Warning => &[
+                Polygon(
+                    &[(0.5, 0.04), (0.97, 0.92), (0.03, 0.92)],
+                    Rgba([240, 180, 0, 255]),
+                ),
+                Line(&[(0.5, 0.36), (0.5, 0.6)], 0.09, BLACK),
+                Oval((0.5, 0.74), 0.05, BLACK),
+            ],
+        }
+    }
+}
+
+/// Draw `stamp` stretched over the box, turned by `rotation`.
+pub fn draw_stamp(
+    composite: &mut RgbaImage,
+    stamp: Stamp,
+    (x, y, w, h): (f32, f32, f32, f32),
+    rotation: Rotation,
+    opacity: f64,
+) {
+    if w <= 0.0 || h <= 0.0 {
+        return;
+    }
+    let at = |(u, v): Pt| rotation.apply((x + u * w, y + v * h));
+    for part in stamp.parts() {
+        match *part {
+            StampPart::Oval((u, v), r, color) => {
+                // Flattened at its drawn size, then turned.
+                let oval = raster::ellipse(x + u * w, y + v * h, r * w, r * h);
+                raster::fill(
+                    composite,
+                    &[rotation.apply_all(oval)],
+                    faded(color, opacity),
+                );
+            }
+            StampPart::Polygon(pts, color) => {
+                let polygon = pts.iter().map(|&p| at(p)).collect();
+                raster::fill(composite, &[polygon], faded(color, opacity));
+            }
+            StampPart::Line(pts, width, color) => {
+                let line = Stroke {
+                    width: width * w.min(h),
+                    join: Join::Round,
+                    cap: Cap::Round,
+                    dash: Vec::new(),
+                };
+                let path: Vec<Pt> = pts.iter().map(|&p| at(p)).collect();
+                raster::stroke(composite, &path, false, &line, faded(color, opacity));
+            }
+        }
+    }
+}
@@ -0,0 +1,204 @@
+/// Exported annotation files.
+///
+/// Files are written as an envelope recording the schema version, the
+/// Fotos version that wrote them and the size of the image the annotations
+/// were drawn on:
+///
+/// ```json
+/// { "schemaVersion": 1, "appVersion": "0.3.0",
+///   "imageSize": { "width": 1920, "height": 1080 },
+///   "annotations": [ … ] }
+/// ```
+///
+/// Reading runs the file through every migration between its version and
+/// [`SCHEMA_VERSION`], then rescales the annotations if the image they are
+/// applied to has a different size. Files from before the envelope, a bare
+/// array of annotations, are version 0.
+use super::{parse_annotations, Annotation};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Schema version written by this build. Bump it with every change to the
+/// file or annotation JSON and add the step to [`migrate_if_needed`].
+pub const SCHEMA_VERSION: u64 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl From<(u32, u32)> for ImageSize {
+    fn from((width, height): (u32, u32)) -> Self {
+        Self { width, height }
+    }
+}
+
+/// The envelope of an exported annotation file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnnotationFile {
+    pub schema_version: u64,
+    /// Version of Fotos that wrote the file, if known.
+    pub app_version: Option<String>,
+    /// Size of the image the annotations were drawn on, if known.
+    pub image_size: Option<ImageSize>,
+    pub annotations: Vec<Annotation>,
+}
+
+impl AnnotationFile {
+    /// A file of the current version, written by this build.
+    pub fn new(annotations: Vec<Annotation>, image_size: Option<ImageSize>) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            app_version: Some(env!("CARGO_PKG_VERSION").to_owned()),
+            image_size,
+            annotations,
+        }
+    }
+}
+
+/// Read an exported annotation file of any version up to this build's,
+/// rescaled onto an image of `target` size when both it and the size the
+/// file was drawn on are known and differ.
+pub fn read_annotation_file(
+    value: Value,
+    target: Option<ImageSize>,
+) -> Result<Vec<Annotation>, String> {
+    let mut value = migrate_if_needed(value)?;
+    let source: Option<ImageSize> = value
+        .get("imageSize")
+        .filter(|size| !size.is_null())
+        .map(|size| serde_json::from_value(size.clone()))
+        .transpose()
+        .map_err(|e| format!("imageSize: {e}"))?;
+    let annotations = value
+        .get_mut("annotations")
+        .map(Value::take)
+        .ok_or("missing annotations")?;
+    let mut annotations = parse_annotations(annotations).map_err(|e| e.to_string())?;
+
+    if let (Some(from), Some(to)) = (source, target) {
+        if from != to && from.width > 0 && from.height > 0 {
+            let sx = to.width as f64 / from.width as f64;
+            let sy = to.height as f64 / from.height as f64;
+            for annotation in &mut annotations {
+                annotation.rescale(sx, sy);
+            }
+        }
+    }
+    Ok(annotations)
+}
+
+/// The file's schema version: 0 for a bare array.
+fn schema_version(value: &Value) -> Result<u64, String> {
+    match value {
+        Value::Array(_) => Ok(0),
+        Value::Object(fields) => fields
+            .get("schemaVersion")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| "not an annotation file: no schemaVersion".to_owned()),
+        _ => Err("not an annotation file: expected an object or an array".to_owned()),
+    }
+}
+
+/// Run any pending migrations, oldest first.
+fn migrate_if_needed(mut value: Value) -> Result<Value, String> {
+    let version = schema_version(&value)?;
+    if version > SCHEMA_VERSION {
+        return Err(format!(
+            "annotation file is schema version {version}, newer than this version of Fotos reads ({SCHEMA_VERSION})"
+        ));
+    }
+    if version < 1 {
+        value = migrate_v0_to_v1(value);
+    }
+    Ok(value)
+}
+
+/// v0 → v1: wrap the bare array in an envelope. Neither the image size nor
+/// the writer's version was recorded, so the annotations are not rescaled.
+fn migrate_v0_to_v1(annotations: Value) -> Value {
+    json!({
+        "schemaVersion": 1,
+        "appVersion": null,
+        "imageSize": null,
+        "annotations": annotations,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotate::{Point, Shape};
+
+    fn annotations() -> Value {
+        json!([
+            { "id": "r", "type": "rect", "x": 10, "y": 20, "width": 30, "height": 40,
+              "strokeWidth": 4 },
+            { "id": "a", "type": "arrow", "points": [{ "x": 0, "y": 0 }, { "x": 100, "y": 50 }] },
+        ])
+    }
+
+    fn size(width: u32, height: u32) -> Option<ImageSize> {
+        Some(ImageSize { width, height })
+    }
+
+    #[test]
+    fn written_files_read_back() {
+        let file = AnnotationFile::new(parse_annotations(annotations()).unwrap(), size(200, 100));
+        let value = serde_json::to_value(&file).unwrap();
+        assert_eq!(value["schemaVersion"], json!(SCHEMA_VERSION));
+        assert_eq!(value["imageSize"], json!({ "width": 200, "height": 100 }));
+        let read = read_annotation_file(value, size(200, 100)).unwrap();
+        assert_eq!(read, file.annotations);
+    }
+
+    #[test]
+    fn bare_arrays_migrate_without_rescaling() {
+        let read = read_annotation_file(annotations(), size(50, 50)).unwrap();
+        assert_eq!(read, parse_annotations(annotations()).unwrap());
+    }
+
+    #[test]
+    fn annotations_follow_the_image_size() {
+        let file = json!({
+            "schemaVersion": 1,
+            "imageSize": { "width": 200, "height": 100 },
+            "annotations": annotations(),
+        });
+        let read = read_annotation_file(file, size(100, 200)).unwrap();
+        let Shape::Rect {
+            x,
+            y,
+            width,
+            height,
+            stroke_width,
+            ..
+        } = read[0].shape
+        else {
+            panic!("not a rect");
+        };
+        assert_eq!((x, y, width, height), (5.0, 40.0, 15.0, 80.0));
+        // √(½ · 2) = 1: lengths keep their size.
+        assert_eq!(stroke_width, Some(4.0));
+        let Shape::Arrow { points, .. } = &read[1].shape else {
+            panic!("not an arrow");
+        };
+        assert_eq!(points[1], Point { x: 50.0, y: 100.0 });
+    }
+
+    #[test]
+    fn newer_and_malformed_files_are_refused() {
+        let newer = json!({ "schemaVersion": SCHEMA_VERSION + 1, "annotations": [] });
+        assert!(read_annotation_file(newer, None)
+            .unwrap_err()
+            .contains("newer than this version"));
+        assert!(read_annotation_file(json!({ "annotations": [] }), None).is_err());
+        assert!(read_annotation_file(json!("rect"), None).is_err());
+        let invalid = json!({ "schemaVersion": 1, "annotations": [{ "type": "rect" }] });
+        assert!(read_annotation_file(invalid, None)
+            .unwrap_err()
+            .contains("invalid annotations"));
+    }
+}
@@ -0,0 +1,648 @@
+/// Font discovery, caching and per-glyph fallback for rendered text.
+///
+/// The installed faces are listed once per process — from fontconfig's
+/// `fc-list` where it exists, otherwise by scanning the platform's font
+/// directories — and each face is loaded at most once, on first use. A
+/// [`FontChain`] resolves a CSS-style family list (`"Inter, monospace"`)
+/// to the best matching faces, followed by CJK and emoji fallbacks and
+/// finally the embedded Liberation Sans, so every character has some
+/// face to draw with.
+use ab_glyph::{Font as _, FontArc, FontRef};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Mutex, OnceLock};
+
+/// Bundled with the app so text renders even with no system fonts. PDF
+/// export also embeds it for its text layer.
+pub(crate) static EMBEDDED: &[u8] = include_bytes!("../../fonts/LiberationSans-Regular.ttf");
+
+/// Families tried for any character the requested family lacks, before
+/// the embedded font. Outline fonts only: colour bitmap emoji fonts have
+/// no outlines to rasterize.
+const FALLBACK_FAMILIES: &[&str] = &[
+    "Noto Sans CJK SC",
+    "Noto Sans CJK JP",
+    "Noto Sans CJK KR",
+    "Source Han Sans SC",
+    "WenQuanYi Micro Hei",
+    "Droid Sans Fallback",
+    "PingFang SC",
+    "Hiragino Sans",
+    "Microsoft YaHei",
+    "Yu Gothic",
+    "Malgun Gothic",
+    "Noto Emoji",
+    "Twemoji Mozilla",
+    "Symbola",
+    "Segoe UI Emoji",
+    "Segoe UI Symbol",
+    "Apple Symbols",
+    "DejaVu Sans",
+    "Noto Sans",
+    "Arial Unicode MS",
+];
+
+/// A face as wanted by an annotation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FontStyle {
+    pub bold: bool,
+    pub italic: bool,
+}
+
+/// A loaded face, and whether it actually has the weight and slant that
+/// were asked for. When it doesn't, the renderer synthesizes them.
+#[derive(Clone)]
+pub struct Face {
+    pub font: FontArc,
+    /// The font file, for the OpenType tables shaping reads.
+    pub data: &'static [u8],
+    pub index: u32,
+    pub bold: bool,
+    pub italic: bool,
+}
+
+/// Faces to draw a run of text with, most preferred first. Each is read
+/// from disk only once a character needs it, so a CJK fallback costs
+/// nothing for Latin text. The last face is always the embedded font.
+pub struct FontChain {
+    slots: Vec<Slot>,
+}
+
+struct Slot {
+    /// `None` for the embedded font.
+    info: Option<FaceInfo>,
+    style: FontStyle,
+    face: OnceLock<Option<Face>>,
+}
+
+impl Slot {
+    fn face(&self) -> Option<&Face> {
+        self.face
+            .get_or_init(|| {
+                let ((data, font), index, bold, italic) = match &self.info {
+                    Some(info) => (load(info)?, info.index, info.weight >= 600, info.italic),
+                    None => ((EMBEDDED, embedded()), 0, false, false),
+                };
+                Some(Face {
+                    font,
+                    data,
+                    index,
+                    bold: !self.style.bold || bold,
+                    italic: !self.style.italic || italic,
+                })
+            })
+            .as_ref()
+    }
+}
+
+impl FontChain {
+    /// Resolve `family`, a comma-separated CSS font-family list, against
+    /// the system's fonts.
+    pub fn resolve(family: &str, style: FontStyle) -> Self {
+        library().chain(family, style)
+    }
+
+    /// The face whose metrics set the line: the first one that loads.
+    pub fn primary(&self) -> &Face {
+        self.slots
+            .iter()
+            .find_map(Slot::face)
+            .expect("embedded font is valid")
+    }
+
+    /// The first face that can draw `c`. Whitespace and characters no
+    /// face covers use the primary face (the latter drawn as its notdef).
+    pub fn face_for(&self, c: char) -> &Face {
+        if c.is_whitespace() || c.is_control() {
+            return self.primary();
+        }
+        self.slots
+            .iter()
+            .filter_map(Slot::face)
+            .find(|face| {
+                let id = face.font.glyph_id(c);
+                id.0 != 0 && face.font.outline(id).is_some()
+            })
+            .unwrap_or_else(|| self.primary())
+    }
+}
+
+/// One face of an installed font file.
+#[derive(Debug, Clone, PartialEq)]
+struct FaceInfo {
+    path: PathBuf,
+    index: u32,
+    /// Lowercased family names, including localized and width variants.
+    families: Vec<String>,
+    /// CSS weight, 100–900.
+    weight: u16,
+    italic: bool,
+    monospace: bool,
+    /// Neither condensed nor expanded.
+    normal_width: bool,
+}
+
+struct FontLibrary {
+    faces: Vec<FaceInfo>,
+    /// Family names fontconfig substitutes for each CSS generic family.
+    generics: HashMap<&'static str, Vec<String>>,
+}
+
+fn library() -> &'static FontLibrary {
+    static LIBRARY: OnceLock<FontLibrary> = OnceLock::new();
+    LIBRARY.get_or_init(|| {
+        let faces = fontconfig_faces().unwrap_or_else(|| scan_font_dirs(&font_dirs()));
+        tracing::debug!("font library: {} faces", faces.len());
+        FontLibrary::new(faces, fontconfig_generics())
+    })
+}
+
+fn embedded() -> FontArc {
+    static FONT: OnceLock<FontArc> = OnceLock::new();
+    FONT.get_or_init(|| FontArc::try_from_slice(EMBEDDED).expect("embedded font is valid"))
+        .clone()
+}
+
+impl FontLibrary {
+    fn new(faces: Vec<FaceInfo>, generics: HashMap<&'static str, Vec<String>>) -> Self {
+        Self { faces, generics }
+    }
+
+    fn chain<'a>(&'a self, family: &str, style: FontStyle) -> FontChain {
+        let mut picked: Vec<&'a FaceInfo> = Vec::new();
+        let mut pick = |info: Option<&'a FaceInfo>| {
+            if let Some(info) = info {
+                if !picked.iter().any(|p| std::ptr::eq(*p, info)) {
+                    picked.push(info);
+                }
+            }
+        };
+
+        let mut requested = parse_family_list(family);
+        if requested.is_empty() {
+            requested.push("sans-serif".to_owned());
+        }
+        for name in &requested {
+            match generic_family(name) {
+                Some(generic) => pick(
+                    self.generic_candidates(generic)
+                        .iter()
+                        .find_map(|name| self.best_face(name, style))
+                        .or_else(|| {
+                            (generic == "monospace")
+                                .then(|| self.best_monospace(style))
+                                .flatten()
+                        }),
+                ),
+                None => pick(self.best_face(name, style)),
+            }
+        }
+        for name in FALLBACK_FAMILIES {
+            pick(self.best_face(name, style));
+        }
+
+        let slot = |info: Option<FaceInfo>| Slot {
+            info,
+            style,
+            face: OnceLock::new(),
+        };
+        let mut slots: Vec<Slot> = picked.into_iter().cloned().map(Some).map(slot).collect();
+        slots.push(slot(None));
+        FontChain { slots }
+    }
+
+    /// Concrete families for a generic one: fontconfig's choice first,
+    /// then common defaults on each platform.
+    fn generic_candidates(&self, generic: &str) -> Vec<String> {
+        let defaults: &[&str] = match generic {
+            "serif" => &[
+                "DejaVu Serif",
+                "Liberation Serif",
+                "Noto Serif",
+                "Times New Roman",
+                "Times",
+            ],
+            "monospace" => &[
+                "DejaVu Sans Mono",
+                "Liberation Mono",
+                "Noto Sans Mono",
+                "Menlo",
+                "Consolas",
+                "Courier New",
+            ],
+            _ => &[
+                "DejaVu Sans",
+                "Liberation Sans",
+                "Noto Sans",
+                "Cantarell",
+                "Helvetica Neue",
+                "Helvetica",
+                "Segoe UI",
+                "Arial",
+            ],
+        };
+        let mut names = self.generics.get(generic).cloned().unwrap_or_default();
+        names.extend(defaults.iter().map(|s| s.to_string()));
+        names
+    }
+
+    /// The face of `family` closest to `style`, as CSS font matching
+    /// would pick it: slant first, then width, then nearest weight.
+    fn best_face(&self, family: &str, style: FontStyle) -> Option<&FaceInfo> {
+        let family = family.to_lowercase();
+        self.faces
+            .iter()
+            .filter(|info| info.families.contains(&family))
+            .min_by_key(|info| style_distance(info, style))
+    }
+
+    fn best_monospace(&self, style: FontStyle) -> Option<&FaceInfo> {
+        self.faces
+            .iter()
+            .filter(|info| info.monospace)
+            .min_by_key(|info| style_distance(info, style))
+    }
+}
+
+/// Read a face from disk, once per process. Loaded files are kept for
+/// the life of the process, so they are leaked rather than reference
+/// counted; faces of one collection share the file.
+fn load(info: &FaceInfo) -> Option<(&'static [u8], FontArc)> {
+    type Files = HashMap<PathBuf, Option<&'static [u8]>>;
+    type Faces = HashMap<(PathBuf, u32), Option<FontArc>>;
+    static LOADED: OnceLock<Mutex<(Files, Faces)>> = OnceLock::new();
+    let mut loaded = LOADED
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    let (files, faces) = &mut *loaded;
+
+    let data = (*files.entry(info.path.clone()).or_insert_with(|| {
+        let data = std::fs::read(&info.path).ok()?;
+        Some(&*Box::leak(data.into_boxed_slice()))
+    }))?;
+    let font = faces
+        .entry((info.path.clone(), info.index))
+        .or_insert_with(
+            || match FontRef::try_from_slice_and_index(data, info.index) {
+                Ok(font) => Some(FontArc::new(font)),
+                Err(e) => {
+                    tracing::warn!("unusable font {}: {e}", info.path.display());
+                    None
+                }
+            },
+        )
+        .clone()?;
+    Some((data, font))
+}
+
+fn style_distance(info: &FaceInfo, style: FontStyle) -> u32 {
+    let target = if style.bold { 700 } else { 400 };
+    let slant = if info.italic == style.italic {
+        0
+    } else {
+        10_000
+    };
+    let width = if info.normal_width { 0 } else { 1_000 };
+    slant + width + info.weight.abs_diff(target) as u32
+}
+
+/// Split a CSS font-family list into unquoted names.
+fn parse_family_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|name| name.trim().trim_matches(|c| c == '"' || c == '\'').trim())
+        .filter(|name| !name.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// The CSS generic family `name` stands for, if any.
+fn generic_family(name: &str) -> Option<&'static str> {
+    match name.to_ascii_lowercase().as_str() {
+        "sans-serif" | "system-ui" | "ui-sans-serif" | "cursive" | "fantasy" => Some("sans-serif"),
+        "serif" | "ui-serif" => Some("serif"),
+        "monospace" | "ui-monospace" => Some("monospace"),
+        _ => None,
+    }
+}
+
+// ── Discovery ────────────────────────────────────────────────────────────────
+
+const FC_FORMAT: &str = "%{file}\t%{index}\t%{family}\t%{weight}\t%{slant}\t%{spacing}\t%{width}\n";
+
+fn fontconfig_faces() -> Option<Vec<FaceInfo>> {
+    let output = Command::new("fc-list")
+        .args(["--format", FC_FORMAT])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let faces: Vec<FaceInfo> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(parse_fc_line)
+        .collect();
+    (!faces.is_empty()).then_some(faces)
+}
+
+/// The family fontconfig's configuration maps each generic family to.
+fn fontconfig_generics() -> HashMap<&'static str, Vec<String>> {
+    let mut generics = HashMap::new();
+    for generic in ["sans-serif", "serif", "monospace"] {
+        let Ok(output) = Command::new("fc-match")
+            .args(["--format", "%{family}", generic])
+            .output()
+        else {
+            break;
+        };
+        let families: Vec<String> = String::from_utf8_lossy(&output.stdout)
+            .split(',')
+            .map(str::to_owned)
+            .filter(|s| !s.is_empty())
+            .collect();
+        if output.status.success() && !families.is_empty() {
+            generics.insert(generic, families);
+        }
+    }
+    generics
+}
+
+/// Parse one `fc-list` line in [`FC_FORMAT`].
+fn parse_fc_line(line: &str) -> Option<FaceInfo> {
+    let mut fields = line.split('\t');
+    let path = PathBuf::from(fields.next().filter(|f| !f.is_empty())?);
+    let index = fields.next()?.parse().unwrap_or(0);
+    let families: Vec<String> = fields
+        .next()?
+        .split(',')
+        .filter(|f| !f.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let number = |field: Option<&str>| field.and_then(|f| f.parse::<f64>().ok());
+    let weight = number(fields.next()).map_or(400, fc_weight_to_css);
+    let italic = number(fields.next()).is_some_and(|slant| slant > 0.0);
+    let monospace = number(fields.next()) == Some(100.0);
+    let normal_width = number(fields.next()).is_none_or(|width| width == 100.0);
+    (!families.is_empty()).then_some(FaceInfo {
+        path,
+        index,
+        families,
+        weight,
+        italic,
+        monospace,
+        normal_width,
+    })
+}
+
+/// Map a fontconfig weight (regular 80, bold 200) onto the CSS scale.
+fn fc_weight_to_css(weight: f64) -> u16 {
+    const SCALE: [(f64, u16); 9] = [
+        (0.0, 100),
+        (40.0, 200),
+        (50.0, 300),
+        (80.0, 400),
+        (100.0, 500),
+        (180.0, 600),
+        (200.0, 700),
+        (205.0, 800),
+        (210.0, 900),
+    ];
+    SCALE
+        .iter()
+        .min_by(|a, b| (a.0 - weight).abs().total_cmp(&(b.0 - weight).abs()))
+        .map_or(400, |(_, css)| *css)
+}
+
+/// Where fonts live when fontconfig isn't around to say.
+fn font_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    if cfg!(target_os = "windows") {
+        if let Some(windir) = std::env::var_os("WINDIR") {
+            dirs.push(Path::new(&windir).join("Fonts"));
+        }
+        if let Some(local) = std::env::var_os("LOCALAPPDATA") {
+            dirs.push(Path::new(&local).join("Microsoft/Windows/Fonts"));
+        }
+    } else if cfg!(target_os = "macos") {
+        dirs.push("/System/Library/Fonts".into());
+        dirs.push("/Library/Fonts".into());
+        dirs.extend(home.map(|h| h.join("Library/Fonts")));
+    } else {
+        let data_home = std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home.as_ref().map(|h| h.join(".local/share")));
+        dirs.extend(data_home.map(|d| d.join("fonts")));
+        dirs.extend(home.map(|h| h.join(".fonts")));
+        let data_dirs = std::env::var("XDG_DATA_DIRS")
+            .unwrap_or_else(|_| "/usr/local/share:/usr/share".to_owned());
+        dirs.extend(
+            data_dirs
+                .split(':')
+                .filter(|d| !d.is_empty())
+                .map(|d| Path::new(d).join("fonts")),
+        );
+        // Host fonts exposed inside the Flatpak sandbox.
+        dirs.push("/run/host/fonts".into());
+        dirs.push("/run/host/user-fonts".into());
+    }
+    dirs
+}
+
+fn scan_font_dirs(dirs: &[PathBuf]) -> Vec<FaceInfo> {
+    let mut files = Vec::new();
+    for dir in dirs {
+        collect_font_files(dir, 0, &mut files);
+    }
+    files.sort();
+    files.dedup();
+    files
+        .iter()
+        .flat_map(|path| match std::fs::read(path) {
+            Ok(data) => read_faces(path, &data),
+            Err(_) => Vec::new(),
+        })
+        .collect()
+}
+
+fn collect_font_files(dir: &Path, depth: usize, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            if depth < 8 {
+                collect_font_files(&path, depth + 1, files);
+            }
+        } else if path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|ext| {
+                ["ttf", "otf", "ttc", "otc"]
+                    .iter()
+                    .any(|known| ext.eq_ignore_ascii_case(known))
+            })
+        {
+            files.push(path);
+        }
+    }
+}
+
+/// Every face in one font file or collection.
+fn read_faces(path: &Path, data: &[u8]) -> Vec<FaceInfo> {
+    let count = ttf_parser::fonts_in_collection(data).unwrap_or(1);
+    (0..count)
+        .filter_map(|index| {
+            let face = ttf_parser::Face::parse(data, index).ok()?;
+            let mut families: Vec<String> = face
+                .names()
+                .into_iter()
+                .filter(|name| {
+                    name.name_id == ttf_parser::name_id::TYPOGRAPHIC_FAMILY
+                        || name.name_id == ttf_parser::name_id::FAMILY
+                })
+                .filter_map(|name| name.to_string())
+                .map(|name| name.to_lowercase())
+                .collect();
+            families.dedup();
+            (!families.is_empty()).then(|| FaceInfo {
+                path: path.to_owned(),
+                index,
+                families,
+                weight: face.weight().to_number(),
+                italic: face.is_italic() || face.is_oblique(),
+                monospace: face.is_monospaced(),
+                normal_width: face.width() == ttf_parser::Width::Normal,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUNDLED: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/fonts/LiberationSans-Regular.ttf"
+    );
+
+    fn info(family: &str, weight: u16, italic: bool) -> FaceInfo {
+        FaceInfo {
+            path: PathBuf::from(BUNDLED),
+            index: 0,
+            families: vec![family.to_lowercase()],
+            weight,
+            italic,
+            monospace: false,
+            normal_width: true,
+        }
+    }
+
+    #[test]
+    fn parses_fc_list_lines() {
+        let face = parse_fc_line(
+            "/usr/share/fonts/DejaVuSans-BoldOblique.ttf\t0\tDejaVu Sans,DejaVu Sans Book\t200\t110\t\t100",
+        )
+        .unwrap();
+        assert_eq!(face.families, ["dejavu sans", "dejavu sans book"]);
+        assert_eq!(face.weight, 700);
+        assert!(face.italic);
+        assert!(!face.monospace);
+        assert!(face.normal_width);
+
+        let mono = parse_fc_line("/f/Mono.ttc\t2\tMono\t80\t0\t100\t75").unwrap();
+        assert_eq!((mono.index, mono.weight), (2, 400));
+        assert!(mono.monospace && !mono.normal_width);
+
+        assert!(parse_fc_line("").is_none());
+        assert!(parse_fc_line("/f/NoFamily.ttf\t0\t\t80").is_none());
+    }
+
+    #[test]
+    fn family_lists_and_generics() {
+        assert_eq!(
+            parse_family_list(" \"Fira Code\", 'Inter',monospace ,"),
+            ["Fira Code", "Inter", "monospace"]
+        );
+        assert_eq!(generic_family("Monospace"), Some("monospace"));
+        assert_eq!(generic_family("system-ui"), Some("sans-serif"));
+        assert_eq!(generic_family("Inter"), None);
+    }
+
+    #[test]
+    fn picks_the_face_closest_to_the_style() {
+        let mut light = info("Test Sans", 300, false);
+        light.index = 1;
+        let mut bold = info("Test Sans", 700, false);
+        bold.index = 2;
+        let mut italic = info("Test Sans", 400, true);
+        italic.index = 3;
+        let lib = FontLibrary::new(vec![light, bold, italic], HashMap::new());
+
+        let regular = FontStyle::default();
+        let best = |style| lib.best_face("test sans", style).unwrap().index;
+        assert_eq!(best(regular), 1);
+        assert_eq!(
+            best(FontStyle {
+                bold: true,
+                italic: false
+            }),
+            2
+        );
+        assert_eq!(
+            best(FontStyle {
+                bold: true,
+                italic: true
+            }),
+            3
+        );
+        assert!(lib.best_face("Other", regular).is_none());
+    }
+
+    #[test]
+    fn chain_prefers_the_requested_family_and_ends_with_the_embedded_font() {
+        let lib = FontLibrary::new(vec![info("Test Sans", 400, false)], HashMap::new());
+        let chain = lib.chain(
+            "Missing, Test Sans",
+            FontStyle {
+                bold: true,
+                italic: false,
+            },
+        );
+        assert_eq!(chain.slots.len(), 2);
+        // Found, but regular: the renderer has to embolden it.
+        assert!(!chain.primary().bold);
+        assert!(chain.primary().italic);
+
+        // Liberation Sans has no CJK; every face misses, so the primary
+        // face draws its notdef.
+        let a = chain.face_for('A');
+        assert!(std::ptr::eq(a, chain.primary()));
+        let han = chain.face_for('中');
+        assert!(std::ptr::eq(han, chain.primary()));
+    }
+
+    #[test]
+    fn unreadable_files_fall_back_to_the_embedded_font() {
+        let mut broken = info("Broken", 400, false);
+        broken.path = PathBuf::from("/nonexistent/font.ttf");
+        let lib = FontLibrary::new(vec![broken], HashMap::new());
+        let chain = lib.chain("Broken", FontStyle::default());
+        assert_eq!(chain.slots.len(), 2);
+        assert!(chain.slots[0].face().is_none());
+        assert_ne!(chain.primary().font.glyph_id('A').0, 0);
+    }
+
+    #[test]
+    fn reads_faces_from_font_files() {
+        let data = std::fs::read(BUNDLED).unwrap();
+        let faces = read_faces(Path::new(BUNDLED), &data);
+        assert_eq!(faces.len(), 1);
+        assert!(faces[0].families.contains(&"liberation sans".to_owned()));
+        assert_eq!(faces[0].weight, 400);
+        assert!(!faces[0].italic && !faces[0].monospace);
+    }
+}
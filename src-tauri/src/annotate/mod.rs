@@ -0,0 +1,1011 @@
+/// Annotation model shared by the editor, the file commands and IPC.
+///
+/// Annotations travel as the editor's camelCase JSON — `{"id", "type",
+/// "x", "y", "strokeColor", …}` — and deserialize into one [`Shape`]
+/// variant per `type`, each with the fields that kind needs. Input from
+/// outside the editor (IPC, imported files) goes through
+/// [`parse_annotations`], which reports every malformed entry by index
+/// instead of silently drawing nothing.
+mod embed;
+mod envelope;
+mod fonts;
+mod raster;
+pub mod render;
+mod svg;
+mod text;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use uuid::Uuid;
+
+pub use embed::{load_image, Stamp};
+pub use envelope::{read_annotation_file, AnnotationFile, ImageSize};
+pub(crate) use fonts::EMBEDDED as EMBEDDED_FONT;
+pub use render::{parse_color, render_annotations};
+pub use svg::render_svg;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Annotation {
+    pub id: String,
+    #[serde(flatten)]
+    pub shape: Shape,
+    /// 0–1, applied to everything the annotation draws except blurs,
+    /// which always redact fully.
+    #[serde(default)]
+    pub opacity: Option<f64>,
+    /// Clockwise turn in degrees about the centre of a rect, ellipse, text
+    /// box or image; other types ignore it.
+    #[serde(default)]
+    pub rotation: Option<f64>,
+    #[serde(default)]
+    pub created_at: Option<String>,
+    #[serde(default)]
+    pub locked: Option<bool>,
+}
+
+/// What an annotation draws. The variant is chosen by the JSON `type`.
+/// Optional fields fall back to the editor's defaults when rendered.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "lowercase",
+    rename_all_fields = "camelCase"
+)]
+pub enum Shape {
+    /// Strokes have mitred corners.
+    Rect {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+        stroke_color: Option<String>,
+        fill_color: Option<String>,
+        stroke_width: Option<f64>,
+        /// Dash pattern, as for canvas `setLineDash`.
+        line_dash: Option<Vec<f64>>,
+    },
+    Ellipse {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+        stroke_color: Option<String>,
+        fill_color: Option<String>,
+        stroke_width: Option<f64>,
+        /// Dash pattern, as for canvas `setLineDash`.
+        line_dash: Option<Vec<f64>>,
+    },
+    /// A line from `points[0]` to a filled head at `points[1]`, with a
+    /// round tail.
+    Arrow {
+        points: Vec<Point>,
+        stroke_color: Option<String>,
+        stroke_width: Option<f64>,
+        /// Dash pattern, as for canvas `setLineDash`.
+        line_dash: Option<Vec<f64>>,
+    },
+    /// A polyline with round joins and caps.
+    Freehand {
+        points: Vec<Point>,
+        stroke_color: Option<String>,
+        stroke_width: Option<f64>,
+        /// Dash pattern, as for canvas `setLineDash`.
+        line_dash: Option<Vec<f64>>,
+    },
+    Highlight {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+        highlight_color: Option<String>,
+    },
+    /// Hides what is under the rectangle. `blurRadius` is the block size
+    /// for `pixelate` and the strength for `gaussian`.
+    Blur {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+        blur_radius: Option<f64>,
+        #[serde(default)]
+        redact_style: RedactStyle,
+    },
+    /// A numbered circle centred on `(x, y)`.
+    Step {
+        x: f64,
+        y: f64,
+        step_number: u32,
+        font_size: Option<f64>,
+        stroke_color: Option<String>,
+    },
+    Text {
+        x: f64,
+        y: f64,
+        text: String,
+        font_size: Option<f64>,
+        font_family: Option<String>,
+        bold: Option<bool>,
+        italic: Option<bool>,
+        stroke_color: Option<String>,
+        /// Width to wrap lines at; unset or 0 never wraps.
+        width: Option<f64>,
+        #[serde(default)]
+        align: TextAlign,
+        /// Box drawn behind the text, `padding` px larger on each side.
+        background_color: Option<String>,
+        border_color: Option<String>,
+        border_width: Option<f64>,
+        corner_radius: Option<f64>,
+        padding: Option<f64>,
+    },
+    /// A speech bubble over the box with a tail pointing at `tail`, holding
+    /// `text` wrapped to fit inside it.
+    Callout {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+        tail: Point,
+        #[serde(default)]
+        text: String,
+        font_size: Option<f64>,
+        font_family: Option<String>,
+        /// Border.
+        stroke_color: Option<String>,
+        /// Bubble.
+        fill_color: Option<String>,
+        text_color: Option<String>,
+        stroke_width: Option<f64>,
+        corner_radius: Option<f64>,
+    },
+    /// A loupe: the ellipse inscribed in the box shows the original
+    /// screenshot around `source` (default: the box's centre), enlarged
+    /// `zoom` times.
+    Magnifier {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+        source: Option<Point>,
+        zoom: Option<f64>,
+        stroke_color: Option<String>,
+        stroke_width: Option<f64>,
+    },
+    /// A picture stretched over the box: a stored screenshot (`imageId`),
+    /// an image file (`path`) or a built-in `stamp`, exactly one of them.
+    Image {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+        image_id: Option<String>,
+        path: Option<String>,
+        stamp: Option<Stamp>,
+    },
+    /// Dims everything outside the box with `dimColor`.
+    Spotlight {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+        dim_color: Option<String>,
+        corner_radius: Option<f64>,
+    },
+}
+
+/// How a blur annotation hides the pixels under it.
+///
+/// Every style is irreversible: `solid` and `noise` ignore the original
+/// entirely, and `pixelate` and `gaussian` keep only the mean colour of
+/// blocks of at least [`render::MIN_REDACT_BLOCK`] pixels, too coarse to
+/// recover text from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RedactStyle {
+    /// Block averages (the editor's mosaic look).
+    #[default]
+    Pixelate,
+    /// An opaque black box.
+    Solid,
+    /// Random grey noise.
+    Noise,
+    /// Block averages smoothed into a soft blur.
+    Gaussian,
+}
+
+/// Horizontal alignment of a text annotation's lines, as CSS
+/// `text-align`: `start` and `end` follow each paragraph's direction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextAlign {
+    #[default]
+    Start,
+    End,
+    Left,
+    Center,
+    Right,
+}
+
+impl RedactStyle {
+    /// Style used for automatic PII redaction when none is asked for.
+    pub const SECURE: Self = Self::Solid;
+}
+
+impl Shape {
+    /// The JSON `type` tag.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Rect { .. } => "rect",
+            Self::Ellipse { .. } => "ellipse",
+            Self::Arrow { .. } => "arrow",
+            Self::Freehand { .. } => "freehand",
+            Self::Highlight { .. } => "highlight",
+            Self::Blur { .. } => "blur",
+            Self::Step { .. } => "step",
+            Self::Text { .. } => "text",
+            Self::Callout { .. } => "callout",
+            Self::Magnifier { .. } => "magnifier",
+            Self::Image { .. } => "image",
+            Self::Spotlight { .. } => "spotlight",
+        }
+    }
+}
+
+impl Annotation {
+    /// A new annotation with a random id and default styling.
+    pub fn new(shape: Shape) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            shape,
+            opacity: None,
+            rotation: None,
+            created_at: None,
+            locked: None,
+        }
+    }
+
+    /// A blur over the given rectangle in `style`.
+    pub fn blur(x: u32, y: u32, w: u32, h: u32, style: RedactStyle) -> Self {
+        Self::new(Shape::Blur {
+            x: x as f64,
+            y: y as f64,
+            width: w as f64,
+            height: h as f64,
+            blur_radius: None,
+            redact_style: style,
+        })
+    }
+
+    /// Check values that deserialize fine but can't be drawn: non-finite
+    /// or negative sizes, too few points, unparseable colors, empty text.
+    /// Returns one message per problem, prefixed with the field name.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut finite = |field: &str, v: f64| {
+            if !v.is_finite() {
+                problems.push(format!("{field}: must be a finite number"));
+                false
+            } else {
+                true
+            }
+        };
+        let mut checks: Vec<(&str, f64, f64)> = Vec::new(); // (field, value, minimum)
+        let mut colors: Vec<(&str, &Option<String>)> = Vec::new();
+        let mut points: Option<&Vec<Point>> = None;
+        let mut dash: Option<&Vec<f64>> = None;
+        match &self.shape {
+            Shape::Rect {
+                x,
+                y,
+                width,
+                height,
+                stroke_color,
+                fill_color,
+                stroke_width,
+                line_dash,
+            }
+            | Shape::Ellipse {
+                x,
+                y,
+                width,
+                height,
+                stroke_color,
+                fill_color,
+                stroke_width,
+                line_dash,
+            } => {
+                finite("x", *x);
+                finite("y", *y);
+                checks.extend([("width", *width, 0.0), ("height", *height, 0.0)]);
+                checks.extend(stroke_width.map(|w| ("strokeWidth", w, 0.0)));
+                checks.extend(line_dash.iter().flatten().map(|d| ("lineDash", *d, 0.0)));
+                dash = line_dash.as_ref();
+                colors.extend([("strokeColor", stroke_color), ("fillColor", fill_color)]);
+            }
+            Shape::Arrow {
+                points: pts,
+                stroke_color,
+                stroke_width,
+                line_dash,
+            }
+            | Shape::Freehand {
+                points: pts,
+                stroke_color,
+                stroke_width,
+                line_dash,
+            } => {
+                points = Some(pts);
+                checks.extend(stroke_width.map(|w| ("strokeWidth", w, 0.0)));
+                checks.extend(line_dash.iter().flatten().map(|d| ("lineDash", *d, 0.0)));
+                dash = line_dash.as_ref();
+                colors.push(("strokeColor", stroke_color));
+            }
+            Shape::Highlight {
+                x,
+                y,
+                width,
+                height,
+                highlight_color,
+            } => {
+                finite("x", *x);
+                finite("y", *y);
+                checks.extend([("width", *width, 0.0), ("height", *height, 0.0)]);
+                colors.push(("highlightColor", highlight_color));
+            }
+            Shape::Blur {
+                x,
+                y,
+                width,
+                height,
+                blur_radius,
+                ..
+            } => {
+                finite("x", *x);
+                finite("y", *y);
+                checks.extend([("width", *width, 0.0), ("height", *height, 0.0)]);
+                checks.extend(blur_radius.map(|r| ("blurRadius", r, 1.0)));
+            }
+            Shape::Step {
+                x,
+                y,
+                font_size,
+                stroke_color,
+                ..
+            } => {
+                finite("x", *x);
+                finite("y", *y);
+                checks.extend(font_size.map(|s| ("fontSize", s, 1.0)));
+                colors.push(("strokeColor", stroke_color));
+            }
+            Shape::Text {
+                x,
+                y,
+                text,
+                font_size,
+                stroke_color,
+                width,
+                background_color,
+                border_color,
+                border_width,
+                corner_radius,
+                padding,
+                ..
+            } => {
+                finite("x", *x);
+                finite("y", *y);
+                if text.trim().is_empty() {
+                    problems.push("text: must not be empty".to_owned());
+                }
+                checks.extend(font_size.map(|s| ("fontSize", s, 1.0)));
+                checks.extend(width.map(|w| ("width", w, 0.0)));
+                checks.extend(border_width.map(|w| ("borderWidth", w, 0.0)));
+                checks.extend(corner_radius.map(|r| ("cornerRadius", r, 0.0)));
+                checks.extend(padding.map(|p| ("padding", p, 0.0)));
+                colors.extend([
+                    ("strokeColor", stroke_color),
+                    ("backgroundColor", background_color),
+                    ("borderColor", border_color),
+                ]);
+            }
+            Shape::Callout {
+                x,
+                y,
+                width,
+                height,
+                tail,
+                font_size,
+                stroke_color,
+                fill_color,
+                text_color,
+                stroke_width,
+                corner_radius,
+                ..
+            } => {
+                finite("x", *x);
+                finite("y", *y);
+                if finite("tail", tail.x) {
+                    finite("tail", tail.y);
+                }
+                checks.extend([("width", *width, 0.0), ("height", *height, 0.0)]);
+                checks.extend(font_size.map(|s| ("fontSize", s, 1.0)));
+                checks.extend(stroke_width.map(|w| ("strokeWidth", w, 0.0)));
+                checks.extend(corner_radius.map(|r| ("cornerRadius", r, 0.0)));
+                colors.extend([
+                    ("strokeColor", stroke_color),
+                    ("fillColor", fill_color),
+                    ("textColor", text_color),
+                ]);
+            }
+            Shape::Magnifier {
+                x,
+                y,
+                width,
+                height,
+                source,
+                zoom,
+                stroke_color,
+                stroke_width,
+            } => {
+                finite("x", *x);
+                finite("y", *y);
+                if let Some(source) = source {
+                    if finite("source", source.x) {
+                        finite("source", source.y);
+                    }
+                }
+                checks.extend([("width", *width, 0.0), ("height", *height, 0.0)]);
+                checks.extend(zoom.map(|z| ("zoom", z, 1.0)));
+                checks.extend(stroke_width.map(|w| ("strokeWidth", w, 0.0)));
+                colors.push(("strokeColor", stroke_color));
+            }
+            Shape::Image {
+                x,
+                y,
+                width,
+                height,
+                image_id,
+                path,
+                stamp,
+            } => {
+                finite("x", *x);
+                finite("y", *y);
+                checks.extend([("width", *width, 0.0), ("height", *height, 0.0)]);
+                let sources = [image_id.is_some(), path.is_some(), stamp.is_some()];
+                if sources.iter().filter(|s| **s).count() != 1 {
+                    problems.push("image: needs exactly one of imageId, path or stamp".to_owned());
+                }
+                if let Some(Err(e)) = image_id.as_deref().map(Uuid::parse_str) {
+                    problems.push(format!("imageId: {e}"));
+                }
+            }
+            Shape::Spotlight {
+                x,
+                y,
+                width,
+                height,
+                dim_color,
+                corner_radius,
+            } => {
+                finite("x", *x);
+                finite("y", *y);
+                checks.extend([("width", *width, 0.0), ("height", *height, 0.0)]);
+                checks.extend(corner_radius.map(|r| ("cornerRadius", r, 0.0)));
+                colors.push(("dimColor", dim_color));
+            }
+        }
+        checks.extend(self.rotation.map(|r| ("rotation", r, f64::NEG_INFINITY)));
+        if let Some(opacity) = self.opacity {
+            if !(0.0..=1.0).contains(&opacity) {
+                problems.push("opacity: must be between 0 and 1".to_owned());
+            }
+        }
+        for (field, value, min) in checks {
+            if !value.is_finite() {
+                problems.push(format!("{field}: must be a finite number"));
+            } else if value < min {
+                problems.push(format!("{field}: must be at least {min}"));
+            }
+        }
+        if let Some(dash) = dash.filter(|d| !d.is_empty()) {
+            // Shorter periods only blur into a solid line, and take a dash
+            // per fraction of a pixel to draw.
+            let period: f64 = dash.iter().sum();
+            if period.is_finite() && period < 1.0 {
+                problems.push("lineDash: must add up to at least 1".to_owned());
+            }
+        }
+        if let Some(points) = points {
+            if points.len() < 2 {
+                problems.push(format!("points: needs at least 2, got {}", points.len()));
+            }
+            if points.iter().any(|p| !p.x.is_finite() || !p.y.is_finite()) {
+                problems.push("points: coordinates must be finite numbers".to_owned());
+            }
+        }
+        for (field, color) in colors {
+            if let Some(color) = color {
+                if let Err(e) = parse_color(color) {
+                    problems.push(format!("{field}: {e}"));
+                }
+            }
+        }
+        problems
+    }
+
+    /// Map the annotation onto the same picture scaled `sx` times across
+    /// and `sy` times down. Positions and boxes scale per axis; stroke
+    /// widths, font sizes and other lengths that are set scale by the
+    /// geometric mean of the two, and unset ones keep their defaults.
+    pub fn rescale(&mut self, sx: f64, sy: f64) {
+        let s = (sx * sy).sqrt();
+        let length = |v: &mut Option<f64>| {
+            if let Some(v) = v {
+                *v *= s;
+            }
+        };
+        let dash = |d: &mut Option<Vec<f64>>| d.iter_mut().flatten().for_each(|v| *v *= s);
+        let point = |p: &mut Point| {
+            p.x *= sx;
+            p.y *= sy;
+        };
+        let rect = |x: &mut f64, y: &mut f64, w: &mut f64, h: &mut f64| {
+            *x *= sx;
+            *y *= sy;
+            *w *= sx;
+            *h *= sy;
+        };
+        match &mut self.shape {
+            Shape::Rect {
+                x,
+                y,
+                width,
+                height,
+                stroke_width,
+                line_dash,
+                ..
+            }
+            | Shape::Ellipse {
+                x,
+                y,
+                width,
+                height,
+                stroke_width,
+                line_dash,
+                ..
+            } => {
+                rect(x, y, width, height);
+                length(stroke_width);
+                dash(line_dash);
+            }
+            Shape::Arrow {
+                points,
+                stroke_width,
+                line_dash,
+                ..
+            }
+            | Shape::Freehand {
+                points,
+                stroke_width,
+                line_dash,
+                ..
+            } => {
+                points.iter_mut().for_each(point);
+                length(stroke_width);
+                dash(line_dash);
+            }
+            Shape::Highlight {
+                x,
+                y,
+                width,
+                height,
+                ..
+            }
+            | Shape::Image {
+                x,
+                y,
+                width,
+                height,
+                ..
+            } => rect(x, y, width, height),
+            Shape::Blur {
+                x,
+                y,
+                width,
+                height,
+                blur_radius,
+                ..
+            } => {
+                rect(x, y, width, height);
+                length(blur_radius);
+            }
+            Shape::Step {
+                x, y, font_size, ..
+            } => {
+                *x *= sx;
+                *y *= sy;
+                length(font_size);
+            }
+            Shape::Text {
+                x,
+                y,
+                font_size,
+                width,
+                border_width,
+                corner_radius,
+                padding,
+                ..
+            } => {
+                *x *= sx;
+                *y *= sy;
+                if let Some(width) = width {
+                    *width *= sx;
+                }
+                for v in [font_size, border_width, corner_radius, padding] {
+                    length(v);
+                }
+            }
+            Shape::Callout {
+                x,
+                y,
+                width,
+                height,
+                tail,
+                font_size,
+                stroke_width,
+                corner_radius,
+                ..
+            } => {
+                rect(x, y, width, height);
+                point(tail);
+                for v in [font_size, stroke_width, corner_radius] {
+                    length(v);
+                }
+            }
+            Shape::Magnifier {
+                x,
+                y,
+                width,
+                height,
+                source,
+                stroke_width,
+                ..
+            } => {
+                rect(x, y, width, height);
+                if let Some(source) = source {
+                    point(source);
+                }
+                length(stroke_width);
+            }
+            Shape::Spotlight {
+                x,
+                y,
+                width,
+                height,
+                corner_radius,
+                ..
+            } => {
+                rect(x, y, width, height);
+                length(corner_radius);
+            }
+        }
+    }
+}
+
+/// One invalid entry in an annotation list.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AnnotationError {
+    /// Position in the submitted list.
+    pub index: usize,
+    pub id: Option<String>,
+    /// The submitted `type`, if there was one.
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for AnnotationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "annotation {}", self.index)?;
+        if let Some(kind) = &self.kind {
+            write!(f, " ({kind})")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Every problem found in an annotation list.
+#[derive(Debug, Clone, PartialEq, Serialize, thiserror::Error)]
+#[serde(transparent)]
+pub struct AnnotationErrors(pub Vec<AnnotationError>);
+
+impl fmt::Display for AnnotationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid annotations: ")?;
+        for (i, error) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{error}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Deserialize and validate an annotation list (a JSON array).
+pub fn parse_annotations(value: Value) -> Result<Vec<Annotation>, AnnotationErrors> {
+    let items = match value {
+        Value::Array(items) => items,
+        Value::Null => Vec::new(),
+        other => {
+            return Err(AnnotationErrors(vec![AnnotationError {
+                index: 0,
+                id: None,
+                kind: None,
+                message: format!("expected an array of annotations, got {other}"),
+            }]))
+        }
+    };
+    let mut annotations = Vec::with_capacity(items.len());
+    let mut errors = Vec::new();
+    for (index, item) in items.into_iter().enumerate() {
+        let field = |name: &str| item.get(name).and_then(Value::as_str).map(str::to_owned);
+        let (id, kind) = (field("id"), field("type"));
+        match serde_json::from_value::<Annotation>(item) {
+            Ok(annotation) => {
+                for message in annotation.problems() {
+                    errors.push(AnnotationError {
+                        index,
+                        id: id.clone(),
+                        kind: kind.clone(),
+                        message,
+                    });
+                }
+                annotations.push(annotation);
+            }
+            Err(e) => errors.push(AnnotationError {
+                index,
+                id,
+                kind,
+                message: e.to_string(),
+            }),
+        }
+    }
+    if errors.is_empty() {
+        Ok(annotations)
+    } else {
+        Err(AnnotationErrors(errors))
+    }
+}
+
+/// Validate already-deserialized annotations.
+pub fn validate_annotations(annotations: &[Annotation]) -> Result<(), AnnotationErrors> {
+    let errors: Vec<AnnotationError> = annotations
+        .iter()
+        .enumerate()
+        .flat_map(|(index, a)| {
+            a.problems()
+                .into_iter()
+                .map(move |message| AnnotationError {
+                    index,
+                    id: Some(a.id.clone()),
+                    kind: Some(a.shape.kind().to_owned()),
+                    message,
+                })
+        })
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(AnnotationErrors(errors))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn annotation_serde_camel_case_round_trip() {
+        let json = r##"{
+            "id": "abc",
+            "type": "rect",
+            "x": 5.0,
+            "y": 10.0,
+            "width": 100.0,
+            "height": 50.0,
+            "strokeColor": "#ff0000",
+            "fillColor": "transparent",
+            "strokeWidth": 2.0,
+            "opacity": 0.8
+        }"##;
+
+        let anno: Annotation = serde_json::from_str(json).expect("deserialization failed");
+        assert_eq!(anno.opacity, Some(0.8));
+        let Shape::Rect {
+            stroke_color,
+            fill_color,
+            stroke_width,
+            width,
+            ..
+        } = &anno.shape
+        else {
+            panic!("expected a rect, got {:?}", anno.shape);
+        };
+        assert_eq!(stroke_color.as_deref(), Some("#ff0000"));
+        assert_eq!(fill_color.as_deref(), Some("transparent"));
+        assert_eq!(*stroke_width, Some(2.0));
+        assert_eq!(*width, 100.0);
+
+        let back = serde_json::to_value(&anno).unwrap();
+        assert_eq!(back["type"], "rect");
+        assert_eq!(back["strokeColor"], "#ff0000");
+        assert_eq!(serde_json::from_value::<Annotation>(back).unwrap(), anno);
+    }
+
+    #[test]
+    fn annotation_serde_new_fields() {
+        let json = r##"{
+            "id": "def",
+            "type": "step",
+            "x": 50.0,
+            "y": 50.0,
+            "strokeColor": "#ff0000",
+            "stepNumber": 3,
+            "fontSize": 24.0,
+            "highlightColor": "#FFFF00",
+            "blurRadius": 10.0
+        }"##;
+
+        let anno: Annotation = serde_json::from_str(json).expect("deserialization failed");
+        assert_eq!(
+            anno.shape,
+            Shape::Step {
+                x: 50.0,
+                y: 50.0,
+                step_number: 3,
+                font_size: Some(24.0),
+                stroke_color: Some("#ff0000".into()),
+            }
+        );
+    }
+
+    #[test]
+    fn editor_payloads_with_extra_fields_parse() {
+        // The editor sends every field on every kind; extras are ignored.
+        let annotations = parse_annotations(json!([{
+            "id": "a1", "type": "arrow", "x": 0, "y": 0, "width": 10, "height": 0,
+            "points": [{"x": 0, "y": 0}, {"x": 10, "y": 0}],
+            "strokeColor": "#FF0000", "fillColor": "transparent", "strokeWidth": 3,
+            "opacity": 1, "createdAt": "2026-01-01T00:00:00Z", "locked": false
+        }, {
+            "id": "b1", "type": "blur", "x": 1, "y": 2, "width": 30, "height": 20,
+            "points": [], "strokeColor": "#FF0000", "blurRadius": 10
+        }, {
+            "id": "b2", "type": "blur", "x": 1, "y": 2, "width": 30, "height": 20,
+            "redactStyle": "gaussian"
+        }]))
+        .unwrap();
+        assert_eq!(annotations.len(), 3);
+        assert_eq!(annotations[0].locked, Some(false));
+        assert!(matches!(
+            annotations[1].shape,
+            Shape::Blur {
+                redact_style: RedactStyle::Pixelate,
+                ..
+            }
+        ));
+        assert!(matches!(
+            annotations[2].shape,
+            Shape::Blur {
+                redact_style: RedactStyle::Gaussian,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn unknown_types_and_missing_fields_are_reported_by_index() {
+        let err = parse_annotations(json!([
+            { "id": "ok", "type": "rect", "x": 0, "y": 0, "width": 5, "height": 5 },
+            { "id": "typo", "type": "rectangle", "x": 0, "y": 0, "width": 5, "height": 5 },
+            { "id": "nopts", "type": "arrow" },
+            { "type": "text", "x": 1, "y": 1, "text": "hi" },
+        ]))
+        .unwrap_err();
+        let indexes: Vec<usize> = err.0.iter().map(|e| e.index).collect();
+        assert_eq!(indexes, vec![1, 2, 3]);
+        assert!(err.0[0].message.contains("unknown variant `rectangle`"));
+        assert_eq!(err.0[0].kind.as_deref(), Some("rectangle"));
+        assert!(err.0[1].message.contains("missing field `points`"));
+        assert!(err.0[2].message.contains("missing field `id`"));
+        let text = err.to_string();
+        assert!(text.starts_with("invalid annotations: annotation 1 (rectangle)"));
+    }
+
+    #[test]
+    fn values_that_cannot_be_drawn_are_rejected() {
+        let err = parse_annotations(json!([
+            { "id": "a", "type": "rect", "x": 0, "y": 0, "width": -5, "height": 5,
+              "strokeColor": "red", "lineDash": [4, 2] },
+            { "id": "b", "type": "arrow", "points": [{"x": 1, "y": 1}], "lineDash": [4, -2] },
+            { "id": "c", "type": "text", "x": 0, "y": 0, "text": "  ", "opacity": 2 },
+        ]))
+        .unwrap_err();
+        let messages: Vec<(usize, &str)> = err
+            .0
+            .iter()
+            .map(|e| (e.index, e.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (0, "width: must be at least 0"),
+                (0, "strokeColor: Unsupported color format: red"),
+                (1, "lineDash: must be at least 0"),
+                (1, "points: needs at least 2, got 1"),
+                (2, "text: must not be empty"),
+                (2, "opacity: must be between 0 and 1"),
+            ]
+        );
+        assert!(parse_annotations(json!({ "type": "rect" })).is_err());
+        let err = parse_annotations(json!([{ "id": "d", "type": "blur", "x": 0, "y": 0,
+            "width": 5, "height": 5, "redactStyle": "smudge" }]))
+        .unwrap_err();
+        assert!(err.0[0].message.contains("unknown variant `smudge`"));
+        let err = parse_annotations(json!([{ "id": "e", "type": "freehand",
+            "points": [{"x": 0, "y": 0}, {"x": 9, "y": 9}], "lineDash": [0.001, 0.001] }]))
+        .unwrap_err();
+        assert_eq!(err.0[0].message, "lineDash: must add up to at least 1");
+        assert_eq!(parse_annotations(Value::Null).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn image_annotations_name_exactly_one_picture() {
+        let image = |extra: Value| {
+            let mut item = json!({ "id": "i", "type": "image", "x": 0, "y": 0,
+                "width": 10, "height": 10 });
+            item.as_object_mut()
+                .unwrap()
+                .extend(extra.as_object().unwrap().clone());
+            parse_annotations(json!([item]))
+        };
+        let ok = image(json!({ "stamp": "warning" })).unwrap();
+        assert!(matches!(
+            ok[0].shape,
+            Shape::Image {
+                stamp: Some(Stamp::Warning),
+                ..
+            }
+        ));
+        let err = image(json!({})).unwrap_err();
+        assert_eq!(
+            err.0[0].message,
+            "image: needs exactly one of imageId, path or stamp"
+        );
+        let err = image(json!({ "stamp": "checkmark", "imageId": "nope" })).unwrap_err();
+        assert_eq!(err.0.len(), 2);
+        assert!(err.0[1].message.starts_with("imageId: "));
+        assert!(image(json!({ "stamp": "thumbsup" })).is_err());
+    }
+
+    #[test]
+    fn validate_reports_kind_and_id() {
+        let mut blur = Annotation::blur(0, 0, 10, 10, RedactStyle::Pixelate);
+        assert!(validate_annotations(std::slice::from_ref(&blur)).is_ok());
+        blur.opacity = Some(-1.0);
+        let err = validate_annotations(&[blur.clone()]).unwrap_err();
+        assert_eq!(err.0[0].kind.as_deref(), Some("blur"));
+        assert_eq!(err.0[0].id.as_deref(), Some(blur.id.as_str()));
+        let json = serde_json::to_value(&err).unwrap();
+        assert_eq!(json[0]["type"], "blur");
+    }
+}
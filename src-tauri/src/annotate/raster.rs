@@ -0,0 +1,592 @@
+/// Anti-aliased path filling and stroking.
+///
+/// Paths are polygons in image coordinates. Fills go through
+/// `ab_glyph_rasterizer`'s exact-area coverage accumulation; strokes are
+/// decomposed into simple pieces (one quad per segment plus join and cap
+/// polygons) whose coverages are unioned by taking the maximum, which keeps
+/// overlapping pieces from double-blending at joins.
+use ab_glyph_rasterizer::{point, Rasterizer};
+use image::{Rgba, RgbaImage};
+use std::f32::consts::{PI, TAU};
+
+pub type Pt = (f32, f32);
+
+/// How segments of a stroke meet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Join {
+    /// Sharp corners, bevelled beyond the canvas default miter limit of 10.
+    Miter,
+    Round,
+}
+
+/// How open stroke ends (including each dash) are finished.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cap {
+    Butt,
+    Round,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stroke {
+    pub width: f32,
+    pub join: Join,
+    pub cap: Cap,
+    /// Alternating on/off lengths, as for canvas `setLineDash`. Empty (or
+    /// all zeros) draws a solid line.
+    pub dash: Vec<f32>,
+}
+
+const MITER_LIMIT: f32 = 10.0;
+
+/// Coverage of a region of the image, each value in `0.0..=1.0`.
+struct Mask {
+    x0: u32,
+    y0: u32,
+    width: u32,
+    height: u32,
+    data: Vec<f32>,
+}
+
+impl Mask {
+    /// An empty mask over the part of `image` inside the bounds of `points`.
+    fn covering(image: &RgbaImage, points: impl IntoIterator<Item = Pt>) -> Option<Self> {
+        let (x0, y0, x1, y1) = bounds(points, image)?;
+        Some(Self {
+            x0,
+            y0,
+            width: x1 - x0,
+            height: y1 - y0,
+            data: vec![0.0; ((x1 - x0) * (y1 - y0)) as usize],
+        })
+    }
+
+    /// Union in the coverage of the polygon `contours` (non-zero for
+    /// consistently wound rings, as the accumulation is signed).
+    fn add(&mut self, contours: &[Vec<Pt>]) {
+        let window = (
+            self.x0 as f32,
+            self.y0 as f32,
+            (self.x0 + self.width) as f32,
+            (self.y0 + self.height) as f32,
+        );
+        let clipped: Vec<Vec<Pt>> = contours
+            .iter()
+            .map(|c| clip(c, window))
+            .filter(|c| c.len() >= 3)
+            .collect();
+        let Some((x0, y0, x1, y1)) = bounds_in(clipped.iter().flatten().copied(), window) else {
+            return;
+        };
+        let (w, h) = (x1 - x0, y1 - y0);
+        let mut raster = Rasterizer::new(w as usize, h as usize);
+        for contour in &clipped {
+            for (i, &(ax, ay)) in contour.iter().enumerate() {
+                let (bx, by) = contour[(i + 1) % contour.len()];
+                raster.draw_line(
+                    point(ax - x0 as f32, ay - y0 as f32),
+                    point(bx - x0 as f32, by - y0 as f32),
+                );
+            }
+        }
+        raster.for_each_pixel_2d(|x, y, alpha| {
+            if alpha > 0.0 {
+                let i = ((y + y0 - self.y0) * self.width + (x + x0 - self.x0)) as usize;
+                self.data[i] = self.data[i].max(alpha.min(1.0));
+            }
+        });
+    }
+
+    /// Blend the colour `paint` gives each pixel into `image`, weighted by
+    /// coverage.
+    fn paint(&self, image: &mut RgbaImage, paint: impl Fn(u32, u32) -> Rgba<u8>) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let coverage = self.data[(y * self.width + x) as usize];
+                if coverage <= 0.0 {
+                    continue;
+                }
+                let (px, py) = (self.x0 + x, self.y0 + y);
+                let mut src = paint(px, py);
+                src[3] = (src[3] as f32 * coverage).round() as u8;
+                blend_pixel(image.get_pixel_mut(px, py), src);
+            }
+        }
+    }
+}
+
+/// Fill the polygon `contours` with `color`.
+pub fn fill(image: &mut RgbaImage, contours: &[Vec<Pt>], color: Rgba<u8>) {
+    if color[3] == 0 {
+        return;
+    }
+    fill_with(image, contours, |_, _| color);
+}
+
+/// Fill the polygon `contours` with the colour `paint` gives each pixel.
+pub fn fill_with(
+    image: &mut RgbaImage,
+    contours: &[Vec<Pt>],
+    paint: impl Fn(u32, u32) -> Rgba<u8>,
+) {
+    if let Some(mut mask) = Mask::covering(image, contours.iter().flatten().copied()) {
+        mask.add(contours);
+        mask.paint(image, paint);
+    }
+}
+
+/// Stroke the polyline `path` (closed back to its start if `closed`).
+pub fn stroke(image: &mut RgbaImage, path: &[Pt], closed: bool, style: &Stroke, color: Rgba<u8>) {
+    if color[3] == 0 || style.width <= 0.0 || path.is_empty() {
+        return;
+    }
+    let pieces = stroke_pieces(path, closed, style);
+    if let Some(mut mask) = Mask::covering(image, pieces.iter().flatten().copied()) {
+        for piece in pieces {
+            mask.add(&[piece]);
+        }
+        mask.paint(image, |_, _| color);
+    }
+}
+
+/// Polygon approximating an axis-aligned ellipse to within a tenth of a
+/// pixel.
+pub fn ellipse(cx: f32, cy: f32, rx: f32, ry: f32) -> Vec<Pt> {
+    let r = rx.max(ry);
+    let n = if r <= 0.1 {
+        8
+    } else {
+        (PI / (1.0 - 0.1 / r).acos()).ceil().clamp(8.0, 1024.0) as usize
+    };
+    (0..n)
+        .map(|i| {
+            let t = i as f32 / n as f32 * TAU;
+            (cx + rx * t.cos(), cy + ry * t.sin())
+        })
+        .collect()
+}
+
+/// Polygon of a rectangle with corners rounded to radius `r`, which is
+/// clamped to half the shorter side.
+pub fn rounded_rect(x: f32, y: f32, w: f32, h: f32, r: f32) -> Vec<Pt> {
+    let r = r.clamp(0.0, w.min(h) / 2.0);
+    if r <= 0.0 {
+        return vec![(x, y), (x + w, y), (x + w, y + h), (x, y + h)];
+    }
+    // Each corner is a quarter of the circle `ellipse` would draw.
+    let quarter = ellipse(0.0, 0.0, r, r).len().div_ceil(4);
+    let corners = [
+        (x + w - r, y + h - r),
+        (x + r, y + h - r),
+        (x + r, y + r),
+        (x + w - r, y + r),
+    ];
+    let mut pts = Vec::with_capacity(4 * (quarter + 1));
+    for (k, (cx, cy)) in corners.into_iter().enumerate() {
+        for i in 0..=quarter {
+            let t = (k as f32 + i as f32 / quarter as f32) * TAU / 4.0;
+            pts.push((cx + r * t.cos(), cy + r * t.sin()));
+        }
+    }
+    pts
+}
+
+/// A turn about a fixed centre, clockwise on screen (y points down), as
+/// canvas `rotate` with positive angles.
+#[derive(Debug, Clone, Copy)]
+pub struct Rotation {
+    center: Pt,
+    cos: f32,
+    sin: f32,
+}
+
+impl Rotation {
+    pub const NONE: Self = Self {
+        center: (0.0, 0.0),
+        cos: 1.0,
+        sin: 0.0,
+    };
+
+    pub fn new(degrees: f64, center: Pt) -> Self {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        Self {
+            center,
+            cos: cos as f32,
+            sin: sin as f32,
+        }
+    }
+
+    pub fn apply(&self, (x, y): Pt) -> Pt {
+        let (dx, dy) = (x - self.center.0, y - self.center.1);
+        (
+            self.center.0 + dx * self.cos - dy * self.sin,
+            self.center.1 + dx * self.sin + dy * self.cos,
+        )
+    }
+
+    pub fn apply_all(&self, path: Vec<Pt>) -> Vec<Pt> {
+        if self.sin == 0.0 && self.cos == 1.0 {
+            return path;
+        }
+        path.into_iter().map(|p| self.apply(p)).collect()
+    }
+}
+
+/// The polygons whose union is the stroke of `path`.
+fn stroke_pieces(path: &[Pt], closed: bool, style: &Stroke) -> Vec<Vec<Pt>> {
+    let h = style.width / 2.0;
+    let mut pieces = Vec::new();
+    for (run, run_closed) in dash_runs(path, closed, &style.dash) {
+        let mut pts: Vec<Pt> = Vec::with_capacity(run.len());
+        for p in run {
+            if pts.last().is_none_or(|&q| dist(p, q) > 1e-4) {
+                pts.push(p);
+            }
+        }
+        let run_closed = run_closed && pts.len() > 2;
+        if run_closed && dist(pts[0], pts[pts.len() - 1]) <= 1e-4 {
+            pts.pop();
+        }
+        if pts.len() == 1 {
+            if style.cap == Cap::Round {
+                pieces.push(ellipse(pts[0].0, pts[0].1, h, h));
+            }
+            continue;
+        }
+
+        let n = pts.len();
+        let segments = if run_closed { n } else { n - 1 };
+        for i in 0..segments {
+            let (a, b) = (pts[i], pts[(i + 1) % n]);
+            let (nx, ny) = normal(a, b);
+            pieces.push(vec![
+                (a.0 + nx * h, a.1 + ny * h),
+                (b.0 + nx * h, b.1 + ny * h),
+                (b.0 - nx * h, b.1 - ny * h),
+                (a.0 - nx * h, a.1 - ny * h),
+            ]);
+        }
+
+        let joins = if run_closed { 0..n } else { 1..n - 1 };
+        for i in joins {
+            let prev = pts[(i + n - 1) % n];
+            let (v, next) = (pts[i], pts[(i + 1) % n]);
+            match style.join {
+                Join::Round => pieces.push(ellipse(v.0, v.1, h, h)),
+                Join::Miter => pieces.extend(miter(prev, v, next, h)),
+            }
+        }
+
+        if !run_closed && style.cap == Cap::Round {
+            for p in [pts[0], pts[n - 1]] {
+                pieces.push(ellipse(p.0, p.1, h, h));
+            }
+        }
+    }
+    pieces
+}
+
+/// The outer corner where segments `prev→v` and `v→next` meet.
+fn miter(prev: Pt, v: Pt, next: Pt, h: f32) -> Option<Vec<Pt>> {
+    let (n1, n2) = (normal(prev, v), normal(v, next));
+    let d1 = (v.0 - prev.0, v.1 - prev.1);
+    let d2 = (next.0 - v.0, next.1 - v.1);
+    let cross = d1.0 * d2.1 - d1.1 * d2.0;
+    if cross.abs() < 1e-6 * dist(prev, v) * dist(v, next) {
+        return None; // Straight on (or doubling back, where a miter is unbounded).
+    }
+    // The outer side is the one the path turns away from.
+    let s = if cross > 0.0 { -1.0 } else { 1.0 };
+    let a = (v.0 + n1.0 * h * s, v.1 + n1.1 * h * s);
+    let b = (v.0 + n2.0 * h * s, v.1 + n2.1 * h * s);
+    let (mx, my) = (n1.0 + n2.0, n1.1 + n2.1);
+    let len = (mx * mx + my * my).sqrt();
+    let cos_half = len / 2.0;
+    if len < 1e-6 || 1.0 / cos_half > MITER_LIMIT {
+        return Some(vec![v, a, b]);
+    }
+    let reach = h / cos_half;
+    let m = (v.0 + mx / len * reach * s, v.1 + my / len * reach * s);
+    Some(vec![v, a, m, b])
+}
+
+/// Most dashes [`dash_runs`] will cut a path into; longer paths are
+/// stroked solid rather than stall the render.
+const MAX_DASHES: usize = 100_000;
+
+/// Split `path` into the "on" runs of `dash`. Each run is open unless no
+/// dashing applies, in which case the whole path keeps `closed`.
+fn dash_runs(path: &[Pt], closed: bool, dash: &[f32]) -> Vec<(Vec<Pt>, bool)> {
+    let mut pattern: Vec<f32> = dash.iter().map(|d| d.max(0.0)).collect();
+    if pattern.len() % 2 == 1 {
+        pattern.extend_from_within(..);
+    }
+    let solid = || vec![(path.to_vec(), closed)];
+    let period: f32 = pattern.iter().sum();
+    if !period.is_finite() || period < 1.0 || path.len() < 2 {
+        return solid();
+    }
+
+    let mut vertices = path.to_vec();
+    if closed {
+        vertices.push(path[0]);
+    }
+    let mut runs = Vec::new();
+    let mut current: Vec<Pt> = vec![vertices[0]];
+    let (mut index, mut left) = (0, pattern[0]);
+    let mut on = true;
+    let mut cuts = 0;
+    for pair in vertices.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        let length = dist(a, b);
+        let mut travelled = 0.0;
+        while length - travelled > left {
+            cuts += 1;
+            if cuts > 2 * MAX_DASHES {
+                return solid();
+            }
+            travelled += left;
+            let t = travelled / length;
+            let p = (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t);
+            if on {
+                current.push(p);
+                runs.push((std::mem::take(&mut current), false));
+            } else {
+                current = vec![p];
+            }
+            on = !on;
+            index = (index + 1) % pattern.len();
+            left = pattern[index];
+        }
+        left -= length - travelled;
+        if on {
+            current.push(b);
+        }
+    }
+    if on && current.len() > 1 {
+        runs.push((current, false));
+    }
+    runs
+}
+
+fn dist(a: Pt, b: Pt) -> f32 {
+    ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt()
+}
+
+/// Unit normal of `a→b` (pointing left of travel in y-down coordinates).
+fn normal(a: Pt, b: Pt) -> Pt {
+    let len = dist(a, b).max(f32::EPSILON);
+    (-(b.1 - a.1) / len, (b.0 - a.0) / len)
+}
+
+/// Integer pixel bounds of `points` clipped to `image`, if non-empty.
+fn bounds(points: impl IntoIterator<Item = Pt>, image: &RgbaImage) -> Option<(u32, u32, u32, u32)> {
+    let window = (0.0, 0.0, image.width() as f32, image.height() as f32);
+    bounds_in(points, window)
+}
+
+fn bounds_in(
+    points: impl IntoIterator<Item = Pt>,
+    (wx0, wy0, wx1, wy1): (f32, f32, f32, f32),
+) -> Option<(u32, u32, u32, u32)> {
+    let (mut x0, mut y0, mut x1, mut y1) = (f32::MAX, f32::MAX, f32::MIN, f32::MIN);
+    for (x, y) in points {
+        if !x.is_finite() || !y.is_finite() {
+            return None;
+        }
+        x0 = x0.min(x);
+        y0 = y0.min(y);
+        x1 = x1.max(x);
+        y1 = y1.max(y);
+    }
+    let x0 = x0.floor().max(wx0);
+    let y0 = y0.floor().max(wy0);
+    let x1 = x1.ceil().min(wx1);
+    let y1 = y1.ceil().min(wy1);
+    (x0 < x1 && y0 < y1).then_some((x0 as u32, y0 as u32, x1 as u32, y1 as u32))
+}
+
+/// Sutherland–Hodgman clip of `polygon` to a rectangle. Preserves the
+/// covered area inside the window, which is all the rasterizer needs.
+fn clip(polygon: &[Pt], (x0, y0, x1, y1): (f32, f32, f32, f32)) -> Vec<Pt> {
+    type Inside = fn(Pt, f32) -> bool;
+    type Cross = fn(Pt, Pt, f32) -> Pt;
+    let at_x: Cross = |a, b, x| (x, a.1 + (b.1 - a.1) * (x - a.0) / (b.0 - a.0));
+    let at_y: Cross = |a, b, y| (a.0 + (b.0 - a.0) * (y - a.1) / (b.1 - a.1), y);
+    let edges: [(Inside, Cross, f32); 4] = [
+        (|p, v| p.0 >= v, at_x, x0),
+        (|p, v| p.0 <= v, at_x, x1),
+        (|p, v| p.1 >= v, at_y, y0),
+        (|p, v| p.1 <= v, at_y, y1),
+    ];
+    let mut out = polygon.to_vec();
+    for (inside, cross, v) in edges {
+        let input = std::mem::take(&mut out);
+        for (i, &p) in input.iter().enumerate() {
+            let prev = input[(i + input.len() - 1) % input.len()];
+            match (inside(prev, v), inside(p, v)) {
+                (true, true) => out.push(p),
+                (true, false) => out.push(cross(prev, p, v)),
+                (false, true) => {
+                    out.push(cross(prev, p, v));
+                    out.push(p);
+                }
+                (false, false) => {}
+            }
+        }
+    }
+    out
+}
+
+/// Alpha-composite `src` over `dst` in place (src-over).
+#[inline]
+pub fn blend_pixel(dst: &mut Rgba<u8>, src: Rgba<u8>) {
+    let sa = src[3] as f64 / 255.0;
+    let da = dst[3] as f64 / 255.0;
+    let out_a = sa + da * (1.0 - sa);
+    if out_a < f64::EPSILON {
+        *dst = Rgba([0, 0, 0, 0]);
+        return;
+    }
+    dst[0] = ((src[0] as f64 * sa + dst[0] as f64 * da * (1.0 - sa)) / out_a) as u8;
+    dst[1] = ((src[1] as f64 * sa + dst[1] as f64 * da * (1.0 - sa)) / out_a) as u8;
+    dst[2] = ((src[2] as f64 * sa + dst[2] as f64 * da * (1.0 - sa)) / out_a) as u8;
+    dst[3] = (out_a * 255.0) as u8;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+    fn canvas() -> RgbaImage {
+        RgbaImage::from_pixel(40, 40, WHITE)
+    }
+
+    fn solid(width: f32, join: Join, cap: Cap) -> Stroke {
+        Stroke {
+            width,
+            join,
+            cap,
+            dash: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn diagonal_edges_are_anti_aliased() {
+        let mut img = canvas();
+        stroke(
+            &mut img,
+            &[(5.0, 5.0), (35.0, 20.0)],
+            false,
+            &solid(3.0, Join::Round, Cap::Butt),
+            RED,
+        );
+        let partial = img.pixels().filter(|p| p[1] > 0 && p[1] < 255).count();
+        assert!(partial > 20, "only {partial} partially covered pixels");
+        assert_eq!(*img.get_pixel(20, 12), RED);
+        assert_eq!(*img.get_pixel(20, 30), WHITE);
+    }
+
+    #[test]
+    fn pixel_aligned_fill_is_crisp_and_exact() {
+        let mut img = canvas();
+        let square = vec![(10.0, 10.0), (20.0, 10.0), (20.0, 20.0), (10.0, 20.0)];
+        fill(&mut img, &[square], RED);
+        assert_eq!(img.pixels().filter(|p| **p == RED).count(), 100);
+        assert_eq!(
+            img.pixels().filter(|p| **p != RED && **p != WHITE).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn joins_and_caps_shape_the_corners() {
+        let path = [(10.0, 30.0), (10.0, 10.0), (30.0, 10.0)];
+        let mut miter = canvas();
+        stroke(
+            &mut miter,
+            &path,
+            false,
+            &solid(8.0, Join::Miter, Cap::Butt),
+            RED,
+        );
+        let mut round = canvas();
+        stroke(
+            &mut round,
+            &path,
+            false,
+            &solid(8.0, Join::Round, Cap::Round),
+            RED,
+        );
+        // The outer corner is filled by a miter but cut off by a round join.
+        assert_eq!(*miter.get_pixel(6, 6), RED);
+        assert_eq!(*round.get_pixel(6, 6), WHITE);
+        // Round caps extend past the ends; butt caps stop at them.
+        assert_eq!(*round.get_pixel(32, 10), RED);
+        assert_eq!(*miter.get_pixel(32, 10), WHITE);
+        // Overlapping pieces at the join do not double-blend.
+        let mut faint = canvas();
+        let half_red = Rgba([255, 0, 0, 128]);
+        stroke(
+            &mut faint,
+            &path,
+            false,
+            &solid(8.0, Join::Round, Cap::Butt),
+            half_red,
+        );
+        assert_eq!(faint.get_pixel(10, 10), faint.get_pixel(10, 20));
+    }
+
+    #[test]
+    fn dashes_leave_gaps() {
+        let mut img = canvas();
+        let style = Stroke {
+            dash: vec![6.0, 4.0],
+            ..solid(2.0, Join::Miter, Cap::Butt)
+        };
+        stroke(&mut img, &[(0.0, 20.0), (40.0, 20.0)], false, &style, RED);
+        let row: Vec<bool> = (0..40).map(|x| *img.get_pixel(x, 20) == RED).collect();
+        assert_eq!(
+            &row[..12],
+            &[true, true, true, true, true, true, false, false, false, false, true, true]
+        );
+        // Odd patterns repeat, as with canvas `setLineDash`.
+        let runs = dash_runs(&[(0.0, 0.0), (30.0, 0.0)], false, &[5.0]);
+        assert_eq!(runs.len(), 3);
+        // Dashes carry on around corners and along the closing edge.
+        let square = [(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)];
+        let runs = dash_runs(&square, true, &[15.0, 5.0]);
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0].0, vec![(0.0, 0.0), (10.0, 0.0), (10.0, 5.0)]);
+        assert_eq!(runs[1].0, vec![(10.0, 10.0), (0.0, 10.0), (0.0, 5.0)]);
+    }
+
+    #[test]
+    fn degenerate_dashes_stroke_solid() {
+        let line = [(0.0, 0.0), (5000.0, 0.0)];
+        for dash in [&[1e-7, 1e-7][..], &[0.001, 0.001], &[f32::INFINITY, 1.0]] {
+            assert_eq!(dash_runs(&line, false, dash).len(), 1, "{dash:?}");
+        }
+        // Too many dashes to be worth cutting.
+        let long = [(0.0, 0.0), (1e7, 0.0)];
+        assert_eq!(dash_runs(&long, false, &[1.0, 1.0]).len(), 1);
+    }
+
+    #[test]
+    fn shapes_past_the_edge_are_clipped() {
+        let mut img = canvas();
+        fill(&mut img, &[ellipse(0.0, 0.0, 1000.0, 1000.0)], RED);
+        assert!(img.pixels().all(|p| *p == RED));
+        let mut img = canvas();
+        stroke(
+            &mut img,
+            &[(-100.0, -100.0), (-50.0, -50.0)],
+            false,
+            &solid(4.0, Join::Round, Cap::Round),
+            RED,
+        );
+        assert!(img.pixels().all(|p| *p == WHITE));
+    }
+}
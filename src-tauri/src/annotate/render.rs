@@ -0,0 +1,1606 @@
+/// Raster compositing of annotations onto a screenshot.
+use super::embed::{self, Stamp};
+use super::fonts::{FontChain, FontStyle};
+use super::raster::{self, blend_pixel, Cap, Join, Pt, Rotation, Stroke};
+use super::text::{self, PlacedGlyph};
+use super::{Annotation, AnnotationError, AnnotationErrors, Point, RedactStyle, Shape};
+use crate::capture::ImageStore;
+use ab_glyph::{Font as _, OutlineCurve, ScaleFont as _};
+use ab_glyph_rasterizer::{point, Rasterizer};
+use image::{DynamicImage, Rgba, RgbaImage};
+use std::sync::Arc;
+
+/// Smallest block `pixelate` and `gaussian` redaction will average over,
+/// whatever `blurRadius` asks for. Smaller mosaics over text can be undone
+/// by matching candidate strings against the block averages.
+pub const MIN_REDACT_BLOCK: u32 = 12;
+
+/// Composite `annotations`, in order, onto a copy of `image`. Image
+/// annotations naming a screenshot take it from `store`; one whose picture
+/// can't be loaded fails the whole render before anything is drawn.
+pub fn render_annotations(
+    image: &DynamicImage,
+    annotations: &[Annotation],
+    store: &ImageStore,
+) -> Result<RgbaImage, AnnotationErrors> {
+    let pictures = load_pictures(annotations, store)?;
+    let mut composite = image.to_rgba8();
+    let mut base = composite.clone();
+    redact(&mut base, annotations);
+    for (anno, picture) in annotations.iter().zip(&pictures) {
+        composite_annotation(&mut composite, &base, anno, picture.as_deref());
+    }
+    Ok(composite)
+}
+
+/// Apply every blur in `annotations` to `image`, returning the region each
+/// one covered, in step with `annotations`.
+pub(super) fn redact(
+    image: &mut RgbaImage,
+    annotations: &[Annotation],
+) -> Vec<Option<(u32, u32, u32, u32)>> {
+    annotations
+        .iter()
+        .map(|anno| match &anno.shape {
+            Shape::Blur {
+                x,
+                y,
+                width,
+                height,
+                blur_radius,
+                redact_style,
+            } => composite_blur(
+                image,
+                (*x, *y, *width, *height),
+                *blur_radius,
+                *redact_style,
+                uuid::Uuid::new_v4().as_u64_pair().0,
+            ),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The picture of each image annotation that names a screenshot or a
+/// file, in step with `annotations`; one error per picture that can't be
+/// loaded.
+pub(super) fn load_pictures(
+    annotations: &[Annotation],
+    store: &ImageStore,
+) -> Result<Vec<Option<Arc<DynamicImage>>>, AnnotationErrors> {
+    let mut pictures = Vec::with_capacity(annotations.len());
+    let mut errors = Vec::new();
+    for (index, anno) in annotations.iter().enumerate() {
+        let picture = match &anno.shape {
+            Shape::Image {
+                image_id,
+                path,
+                stamp: None,
+                ..
+            } => embed::load_image(image_id.as_deref(), path.as_deref(), store)
+                .map_err(|message| {
+                    errors.push(AnnotationError {
+                        index,
+                        id: Some(anno.id.clone()),
+                        kind: Some(anno.shape.kind().to_owned()),
+                        message,
+                    })
+                })
+                .ok(),
+            _ => None,
+        };
+        pictures.push(picture);
+    }
+    if !errors.is_empty() {
+        return Err(AnnotationErrors(errors));
+    }
+    Ok(pictures)
+}
+
+/// Draw `anno` onto `composite`; magnifiers sample `base`, the screenshot
+/// with its redactions but no other annotations, and image annotations draw
+/// their loaded `picture`.
+fn composite_annotation(
+    composite: &mut RgbaImage,
+    base: &RgbaImage,
+    anno: &Annotation,
+    picture: Option<&DynamicImage>,
+) {
+    let opacity = anno.opacity.unwrap_or(1.0).clamp(0.0, 1.0);
+    let rotation = anno.rotation.unwrap_or(0.0);
+    match &anno.shape {
+        Shape::Rect {
+            x,
+            y,
+            width,
+            height,
+            stroke_color,
+            fill_color,
+            stroke_width,
+            line_dash,
+        } => composite_rectangle(
+            composite,
+            (*x, *y, *width, *height),
+            stroke_color.as_deref(),
+            fill_color.as_deref(),
+            outline(*stroke_width, line_dash, Join::Miter, Cap::Butt),
+            opacity,
+            rotation,
+        ),
+        Shape::Ellipse {
+            x,
+            y,
+            width,
+            height,
+            stroke_color,
+            fill_color,
+            stroke_width,
+            line_dash,
+        } => composite_ellipse(
+            composite,
+            (*x, *y, *width, *height),
+            stroke_color.as_deref(),
+            fill_color.as_deref(),
+            outline(*stroke_width, line_dash, Join::Round, Cap::Butt),
+            opacity,
+            rotation,
+        ),
+        Shape::Arrow {
+            points,
+            stroke_color,
+            stroke_width,
+            line_dash,
+        } => composite_arrow(
+            composite,
+            points,
+            stroke_color.as_deref(),
+            outline(*stroke_width, line_dash, Join::Round, Cap::Round),
+            opacity,
+        ),
+        Shape::Freehand {
+            points,
+            stroke_color,
+            stroke_width,
+            line_dash,
+        } => composite_freehand(
+            composite,
+            points,
+            stroke_color.as_deref(),
+            outline(*stroke_width, line_dash, Join::Round, Cap::Round),
+            opacity,
+        ),
+        Shape::Highlight {
+            x,
+            y,
+            width,
+            height,
+            highlight_color,
+        } => composite_highlight(
+            composite,
+            (*x, *y, *width, *height),
+            highlight_color.as_deref(),
+            opacity,
+        ),
+        Shape::Blur {
+            x,
+            y,
+            width,
+            height,
+            blur_radius,
+            redact_style,
+        } => {
+            composite_blur(
+                composite,
+                (*x, *y, *width, *height),
+                *blur_radius,
+                *redact_style,
+                uuid::Uuid::new_v4().as_u64_pair().0,
+            );
+        }
+        Shape::Step {
+            x,
+            y,
+            step_number,
+            font_size,
+            stroke_color,
+        } => composite_step(
+            composite,
+            (*x, *y),
+            *step_number,
+            *font_size,
+            stroke_color.as_deref(),
+            opacity,
+        ),
+        Shape::Text { .. } => composite_text(composite, &anno.shape, opacity, rotation),
+        Shape::Callout { .. } => composite_callout(composite, &anno.shape, opacity),
+        Shape::Magnifier {
+            x,
+            y,
+            width,
+            height,
+            source,
+            zoom,
+            stroke_color,
+            stroke_width,
+        } => composite_magnifier(
+            composite,
+            base,
+            (*x, *y, *width, *height),
+            source.as_ref(),
+            zoom.unwrap_or(2.0),
+            stroke_color.as_deref(),
+            outline(*stroke_width, &None, Join::Round, Cap::Butt),
+            opacity,
+        ),
+        Shape::Image {
+            x,
+            y,
+            width,
+            height,
+            stamp,
+            ..
+        } => composite_image(
+            composite,
+            (*x, *y, *width, *height),
+            *stamp,
+            picture,
+            opacity,
+            rotation,
+        ),
+        Shape::Spotlight {
+            x,
+            y,
+            width,
+            height,
+            dim_color,
+            corner_radius,
+        } => composite_spotlight(
+            composite,
+            (*x, *y, *width, *height),
+            dim_color.as_deref(),
+            corner_radius.unwrap_or(0.0),
+            opacity,
+        ),
+    }
+}
+
+/// Stroke style with the editor's default width of 2.
+pub(super) fn outline(width: Option<f64>, dash: &Option<Vec<f64>>, join: Join, cap: Cap) -> Stroke {
+    Stroke {
+        width: width.unwrap_or(2.0).max(0.0) as f32,
+        join,
+        cap,
+        dash: dash.iter().flatten().map(|d| *d as f32).collect(),
+    }
+}
+
+/// `color` with its alpha scaled by `opacity`.
+pub(super) fn faded(mut color: Rgba<u8>, opacity: f64) -> Rgba<u8> {
+    color[3] = (color[3] as f64 * opacity).round() as u8;
+    color
+}
+
+fn pt(p: &Point) -> Pt {
+    (p.x as f32, p.y as f32)
+}
+
+// ── Per-type compositing ──────────────────────────────────────────────────────
+
+fn composite_rectangle(
+    composite: &mut RgbaImage,
+    (x, y, width, height): (f64, f64, f64, f64),
+    stroke_color: Option<&str>,
+    fill_color: Option<&str>,
+    stroke: Stroke,
+    opacity: f64,
+    rotation: f64,
+) {
+    if width <= 0.0 || height <= 0.0 {
+        return;
+    }
+    let (x0, y0) = (x as f32, y as f32);
+    let (x1, y1) = ((x + width) as f32, (y + height) as f32);
+    let turn = Rotation::new(rotation, ((x0 + x1) / 2.0, (y0 + y1) / 2.0));
+    let corners = turn.apply_all(vec![(x0, y0), (x1, y0), (x1, y1), (x0, y1)]);
+
+    // Fill (drawn first so stroke overlays it).
+    if let Ok(fill) = parse_color(fill_color.unwrap_or("transparent")) {
+        raster::fill(
+            composite,
+            std::slice::from_ref(&corners),
+            faded(fill, opacity),
+        );
+    }
+
+    // Stroke — centered on the rect edge (half inside, half outside).
+    if let Ok(color) = parse_color(stroke_color.unwrap_or("#FF0000")) {
+        raster::stroke(composite, &corners, true, &stroke, faded(color, opacity));
+    }
+}
+
+fn composite_arrow(
+    composite: &mut RgbaImage,
+    points: &[Point],
+    stroke_color: Option<&str>,
+    mut stroke: Stroke,
+    opacity: f64,
+) {
+    let [p1, p2, ..] = points else {
+        return;
+    };
+    let Ok(color) = parse_color(stroke_color.unwrap_or("#FF0000")) else {
+        return;
+    };
+    let color = faded(color, opacity);
+    stroke.width = stroke.width.max(1.0);
+
+    let dx = p2.x - p1.x;
+    let dy = p2.y - p1.y;
+    let len = (dx * dx + dy * dy).sqrt();
+    if len < 0.001 {
+        return;
+    }
+    let head_len = (stroke.width as f64 * 5.0).max(12.0);
+    let angle = dy.atan2(dx);
+    let wing = std::f64::consts::PI / 6.0;
+
+    // Shaft, stopping at the base of the head so it doesn't poke out.
+    let back = head_len * wing.cos();
+    if back < len {
+        let base = (
+            (p2.x - back * angle.cos()) as f32,
+            (p2.y - back * angle.sin()) as f32,
+        );
+        raster::stroke(composite, &[pt(p1), base], false, &stroke, color);
+    }
+
+    // Arrowhead — a filled triangle at the tip.
+    let head = vec![
+        pt(p2),
+        (
+            (p2.x - head_len * (angle - wing).cos()) as f32,
+            (p2.y - head_len * (angle - wing).sin()) as f32,
+        ),
+        (
+            (p2.x - head_len * (angle + wing).cos()) as f32,
+            (p2.y - head_len * (angle + wing).sin()) as f32,
+        ),
+    ];
+    raster::fill(composite, &[head], color);
+}
+
+fn composite_ellipse(
+    composite: &mut RgbaImage,
+    (x, y, width, height): (f64, f64, f64, f64),
+    stroke_color: Option<&str>,
+    fill_color: Option<&str>,
+    stroke: Stroke,
+    opacity: f64,
+    rotation: f64,
+) {
+    if width <= 0.0 || height <= 0.0 {
+        return;
+    }
+    let center = ((x + width / 2.0) as f32, (y + height / 2.0) as f32);
+    let outline = raster::ellipse(
+        center.0,
+        center.1,
+        (width / 2.0) as f32,
+        (height / 2.0) as f32,
+    );
+    let outline = Rotation::new(rotation, center).apply_all(outline);
+
+    if let Ok(fill) = parse_color(fill_color.unwrap_or("transparent")) {
+        raster::fill(
+            composite,
+            std::slice::from_ref(&outline),
+            faded(fill, opacity),
+        );
+    }
+    if let Ok(color) = parse_color(stroke_color.unwrap_or("#FF0000")) {
+        raster::stroke(composite, &outline, true, &stroke, faded(color, opacity));
+    }
+}
+
+fn composite_freehand(
+    composite: &mut RgbaImage,
+    points: &[Point],
+    stroke_color: Option<&str>,
+    mut stroke: Stroke,
+    opacity: f64,
+) {
+    if points.len() < 2 {
+        return;
+    }
+    let Ok(color) = parse_color(stroke_color.unwrap_or("#FF0000")) else {
+        return;
+    };
+    stroke.width = stroke.width.max(1.0);
+    let path: Vec<Pt> = points.iter().map(pt).collect();
+    raster::stroke(composite, &path, false, &stroke, faded(color, opacity));
+}
+
+fn composite_highlight(
+    composite: &mut RgbaImage,
+    (x, y, width, height): (f64, f64, f64, f64),
+    highlight_color: Option<&str>,
+    opacity: f64,
+) {
+    let x = x as i32;
+    let y = y as i32;
+    let width = width as i32;
+    let height = height as i32;
+
+    if width <= 0 || height <= 0 {
+        return;
+    }
+
+    let color_str = highlight_color.unwrap_or("#FFFF00");
+    let Ok(mut color) = parse_color(color_str) else {
+        return;
+    };
+    // Always 0.4 opacity per spec, on top of the annotation's own.
+    color[3] = (0.4 * 255.0 * opacity) as u8;
+
+    let img_w = composite.width() as i32;
+    let img_h = composite.height() as i32;
+
+    for py in y..(y + height) {
+        for px in x..(x + width) {
+            if px >= 0 && py >= 0 && px < img_w && py < img_h {
+                let base = composite.get_pixel_mut(px as u32, py as u32);
+                blend_pixel(base, color);
+            }
+        }
+    }
+}
+
+/// Redact the rectangle in place, returning the part of the image it
+/// covered as `(x1, y1, x2, y2)`. `seed` drives the `noise` style.
+pub(super) fn composite_blur(
+    composite: &mut RgbaImage,
+    (x, y, width, height): (f64, f64, f64, f64),
+    blur_radius: Option<f64>,
+    style: RedactStyle,
+    seed: u64,
+) -> Option<(u32, u32, u32, u32)> {
+    let x = x.max(0.0) as u32;
+    let y = y.max(0.0) as u32;
+    let w = width as u32;
+    let h = height as u32;
+    let block_size = (blur_radius.unwrap_or(10.0).max(1.0) as u32).max(MIN_REDACT_BLOCK);
+
+    if w == 0 || h == 0 {
+        return None;
+    }
+
+    let img_w = composite.width();
+    let img_h = composite.height();
+
+    let x2 = x.saturating_add(w).min(img_w);
+    let y2 = y.saturating_add(h).min(img_h);
+    let x1 = x.min(x2);
+    let y1 = y.min(y2);
+    if x1 == x2 || y1 == y2 {
+        return None;
+    }
+
+    match style {
+        RedactStyle::Solid => {
+            for py in y1..y2 {
+                for px in x1..x2 {
+                    composite.put_pixel(px, py, Rgba([0, 0, 0, 255]));
+                }
+            }
+        }
+        RedactStyle::Noise => {
+            // xorshift64; any non-zero state works.
+            let mut state = (seed ^ 0x9E37_79B9_7F4A_7C15).max(1);
+            for py in y1..y2 {
+                for px in x1..x2 {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    let v = (state >> 56) as u8;
+                    composite.put_pixel(px, py, Rgba([v, v, v, 255]));
+                }
+            }
+        }
+        RedactStyle::Pixelate => pixelate(composite, (x1, y1, x2, y2), block_size),
+        RedactStyle::Gaussian => {
+            // Smooth the block averages rather than the original pixels, so
+            // nothing finer than a block survives for deconvolution to find.
+            pixelate(composite, (x1, y1, x2, y2), block_size);
+            let region = image::imageops::crop_imm(composite, x1, y1, x2 - x1, y2 - y1).to_image();
+            let blurred = imageproc::filter::gaussian_blur_f32(&region, block_size as f32 / 2.0);
+            image::imageops::replace(composite, &blurred, x1 as i64, y1 as i64);
+        }
+    }
+    Some((x1, y1, x2, y2))
+}
+
+/// Replace each `block_size` square of the region with its mean colour.
+fn pixelate(composite: &mut RgbaImage, (x1, y1, x2, y2): (u32, u32, u32, u32), block_size: u32) {
+    let mut bx = x1;
+    while bx < x2 {
+        let bx2 = (bx + block_size).min(x2);
+        let mut by = y1;
+        while by < y2 {
+            let by2 = (by + block_size).min(y2);
+            let count = (bx2 - bx) * (by2 - by);
+
+            let mut r_sum = 0u32;
+            let mut g_sum = 0u32;
+            let mut b_sum = 0u32;
+            let mut a_sum = 0u32;
+
+            for py in by..by2 {
+                for px in bx..bx2 {
+                    let p = composite.get_pixel(px, py);
+                    r_sum += p[0] as u32;
+                    g_sum += p[1] as u32;
+                    b_sum += p[2] as u32;
+                    a_sum += p[3] as u32;
+                }
+            }
+
+            let avg = Rgba([
+                (r_sum / count) as u8,
+                (g_sum / count) as u8,
+                (b_sum / count) as u8,
+                (a_sum / count) as u8,
+            ]);
+
+            for py in by..by2 {
+                for px in bx..bx2 {
+                    composite.put_pixel(px, py, avg);
+                }
+            }
+
+            by += block_size;
+        }
+        bx += block_size;
+    }
+}
+
+fn composite_step(
+    composite: &mut RgbaImage,
+    (x, y): (f64, f64),
+    step_number: u32,
+    font_size: Option<f64>,
+    stroke_color: Option<&str>,
+    opacity: f64,
+) {
+    let size = font_size.unwrap_or(24.0) as f32;
+    let radius = size / 2.0;
+
+    let stroke_str = stroke_color.unwrap_or("#FF0000");
+    let Ok(color) = parse_color(stroke_str) else {
+        return;
+    };
+
+    raster::fill(
+        composite,
+        &[raster::ellipse(x as f32, y as f32, radius, radius)],
+        faded(color, opacity),
+    );
+
+    // The number, centred in the circle as the editor's bold sans-serif.
+    let chain = FontChain::resolve(
+        "sans-serif",
+        FontStyle {
+            bold: true,
+            italic: false,
+        },
+    );
+    let text_size = (size * 0.6).floor().max(8.0);
+    let lines = text::layout(&chain, &step_number.to_string(), text_size, None);
+    let primary = chain.primary();
+    let metrics = primary.font.as_scaled(text::em_scale(primary, text_size));
+    let baseline = y as f32 + (metrics.ascent() + metrics.descent()) / 2.0;
+    for line in &lines {
+        for glyph in &line.glyphs {
+            draw_glyph(
+                composite,
+                glyph,
+                (x as f32 - line.width / 2.0, baseline),
+                faded(Rgba([255, 255, 255, 255]), opacity),
+                Rotation::NONE,
+            );
+        }
+    }
+}
+
+fn composite_text(composite: &mut RgbaImage, shape: &Shape, opacity: f64, rotation: f64) {
+    let Shape::Text {
+        x,
+        y,
+        text,
+        font_size,
+        font_family,
+        bold,
+        italic,
+        stroke_color,
+        width,
+        align,
+        background_color,
+        border_color,
+        border_width,
+        corner_radius,
+        padding,
+    } = shape
+    else {
+        return;
+    };
+    if text.is_empty() {
+        return;
+    }
+
+    let font_size = font_size.unwrap_or(20.0) as f32;
+    let stroke_str = stroke_color.as_deref().unwrap_or("#FF0000");
+    let Ok(color) = parse_color(stroke_str) else {
+        return;
+    };
+    let color = faded(color, opacity);
+    let style = FontStyle {
+        bold: bold.unwrap_or(false),
+        italic: italic.unwrap_or(false),
+    };
+    let chain = FontChain::resolve(font_family.as_deref().unwrap_or("sans-serif"), style);
+    let wrap = width.filter(|w| *w > 0.0).map(|w| w as f32);
+    let lines = text::layout(&chain, text, font_size, wrap);
+    let line_height = font_size * 1.4;
+    let box_width = wrap.unwrap_or_else(|| lines.iter().map(|l| l.width).fold(0.0, f32::max));
+    let box_height = lines.len() as f32 * line_height;
+    let (x, y) = (*x as f32, *y as f32);
+    let turn = Rotation::new(rotation, (x + box_width / 2.0, y + box_height / 2.0));
+
+    // Background and border, `padding` outside the text on every side.
+    let color_of = |c: &Option<String>| c.as_deref().and_then(|c| parse_color(c).ok());
+    let background = color_of(background_color).map(|c| faded(c, opacity));
+    let border = color_of(border_color).map(|c| faded(c, opacity));
+    if background.is_some() || border.is_some() {
+        let pad = padding.unwrap_or(4.0) as f32;
+        let outline = turn.apply_all(raster::rounded_rect(
+            x - pad,
+            y - pad,
+            box_width + 2.0 * pad,
+            box_height + 2.0 * pad,
+            corner_radius.unwrap_or(4.0) as f32,
+        ));
+        if let Some(background) = background {
+            raster::fill(composite, std::slice::from_ref(&outline), background);
+        }
+        if let Some(border) = border {
+            let stroke = outline_style(border_width.unwrap_or(1.0) as f32);
+            raster::stroke(composite, &outline, true, &stroke, border);
+        }
+    }
+
+    // `textBaseline = "top"`: lines hang from the primary face's ascent.
+    let primary = chain.primary();
+    let ascent = primary
+        .font
+        .as_scaled(text::em_scale(primary, font_size))
+        .ascent();
+    for (i, line) in lines.iter().enumerate() {
+        let left = x + line.indent(*align, box_width);
+        let baseline = y + i as f32 * line_height + ascent;
+        for glyph in &line.glyphs {
+            draw_glyph(composite, glyph, (left, baseline), color, turn);
+        }
+    }
+}
+
+fn composite_callout(composite: &mut RgbaImage, shape: &Shape, opacity: f64) {
+    let Shape::Callout {
+        x,
+        y,
+        width,
+        height,
+        tail,
+        text,
+        font_size,
+        font_family,
+        stroke_color,
+        fill_color,
+        text_color,
+        stroke_width,
+        corner_radius,
+    } = shape
+    else {
+        return;
+    };
+    if *width <= 0.0 || *height <= 0.0 {
+        return;
+    }
+    let (x, y, w, h) = (*x as f32, *y as f32, *width as f32, *height as f32);
+    let bubble = callout_outline((x, y, w, h), corner_radius.unwrap_or(8.0) as f32, pt(tail));
+    if let Ok(fill) = parse_color(fill_color.as_deref().unwrap_or("#FFFFFF")) {
+        raster::fill(
+            composite,
+            std::slice::from_ref(&bubble),
+            faded(fill, opacity),
+        );
+    }
+    if let Ok(color) = parse_color(stroke_color.as_deref().unwrap_or("#FF0000")) {
+        let stroke = outline_style(stroke_width.unwrap_or(2.0) as f32);
+        raster::stroke(composite, &bubble, true, &stroke, faded(color, opacity));
+    }
+
+    // Text wraps inside the bubble, `CALLOUT_PADDING` in from its edges.
+    let Ok(color) = parse_color(text_color.as_deref().unwrap_or("#000000")) else {
+        return;
+    };
+    let font_size = font_size.unwrap_or(16.0) as f32;
+    let chain = FontChain::resolve(
+        font_family.as_deref().unwrap_or("sans-serif"),
+        FontStyle::default(),
+    );
+    let inner = (w - 2.0 * CALLOUT_PADDING).max(1.0);
+    let lines = text::layout(&chain, text, font_size, Some(inner));
+    let primary = chain.primary();
+    let ascent = primary
+        .font
+        .as_scaled(text::em_scale(primary, font_size))
+        .ascent();
+    for (i, line) in lines.iter().enumerate() {
+        let left = x + CALLOUT_PADDING + line.indent(Default::default(), inner);
+        let baseline = y + CALLOUT_PADDING + i as f32 * font_size * 1.4 + ascent;
+        for glyph in &line.glyphs {
+            draw_glyph(
+                composite,
+                glyph,
+                (left, baseline),
+                faded(color, opacity),
+                Rotation::NONE,
+            );
+        }
+    }
+}
+
+/// Gap between a callout's bubble and its text.
+pub(super) const CALLOUT_PADDING: f32 = 8.0;
+
+/// Outline of a rounded bubble with a triangular tail reaching out to
+/// `tip` from the side facing it. Without room for a tail (or with the
+/// tip inside the box), just the rounded box.
+pub(super) fn callout_outline((x, y, w, h): (f32, f32, f32, f32), radius: f32, tip: Pt) -> Vec<Pt> {
+    let r = radius.clamp(0.0, w.min(h) / 2.0);
+    let (cx, cy) = (x + w / 2.0, y + h / 2.0);
+    let (dx, dy) = ((tip.0 - cx) / (w / 2.0), (tip.1 - cy) / (h / 2.0));
+    let outside = dx.abs() > 1.0 || dy.abs() > 1.0;
+    // The side the tail leaves from, numbered clockwise from the top.
+    let side = if dx.abs() >= dy.abs() {
+        if dx > 0.0 {
+            1
+        } else {
+            3
+        }
+    } else if dy > 0.0 {
+        2
+    } else {
+        0
+    };
+
+    // Corner centres clockwise from top-left, each followed by the side
+    // that starts at it.
+    let centres = [
+        (x + r, y + r),
+        (x + w - r, y + r),
+        (x + w - r, y + h - r),
+        (x + r, y + h - r),
+    ];
+    let quarter = raster::ellipse(0.0, 0.0, r, r).len().div_ceil(4);
+    let mut pts = Vec::new();
+    for (k, (ox, oy)) in centres.into_iter().enumerate() {
+        // Arc from the end of side k-1 to the start of side k.
+        for i in 0..=quarter {
+            let t = (k as f32 + 2.0 + i as f32 / quarter as f32) * std::f32::consts::FRAC_PI_2;
+            pts.push((ox + r * t.cos(), oy + r * t.sin()));
+        }
+        if !outside || k != side {
+            continue;
+        }
+        // Side k runs from this corner to the next; centre the tail's base
+        // on the tip, keeping it clear of the corners.
+        let horizontal = k % 2 == 0;
+        let (start, end) = if horizontal { (x, x + w) } else { (y, y + h) };
+        let half = ((end - start) / 2.0 - r).min(w.min(h) / 6.0).min(16.0);
+        if half < 1.0 {
+            continue;
+        }
+        let along = if horizontal { tip.0 } else { tip.1 };
+        let mid = along.clamp(start + r + half, end - r - half);
+        let edge = match k {
+            0 => y,
+            1 => x + w,
+            2 => y + h,
+            _ => x,
+        };
+        // Sides 0 and 1 run forwards, 2 and 3 backwards.
+        let dir = if k < 2 { 1.0 } else { -1.0 };
+        let at = |along: f32| {
+            if horizontal {
+                (along, edge)
+            } else {
+                (edge, along)
+            }
+        };
+        pts.extend([at(mid - dir * half), tip, at(mid + dir * half)]);
+    }
+    pts
+}
+
+/// Show `base` around `source`, enlarged `zoom` times, in the ellipse
+/// inscribed in the box.
+#[allow(clippy::too_many_arguments)]
+fn composite_magnifier(
+    composite: &mut RgbaImage,
+    base: &RgbaImage,
+    (x, y, width, height): (f64, f64, f64, f64),
+    source: Option<&Point>,
+    zoom: f64,
+    stroke_color: Option<&str>,
+    stroke: Stroke,
+    opacity: f64,
+) {
+    if width <= 0.0 || height <= 0.0 || base.width() == 0 || base.height() == 0 {
+        return;
+    }
+    let (cx, cy) = (x + width / 2.0, y + height / 2.0);
+    let (sx, sy) = source.map_or((cx, cy), |p| (p.x, p.y));
+    let zoom = zoom.max(1.0);
+    let lens = raster::ellipse(
+        cx as f32,
+        cy as f32,
+        (width / 2.0) as f32,
+        (height / 2.0) as f32,
+    );
+    let (max_x, max_y) = ((base.width() - 1) as f32, (base.height() - 1) as f32);
+    raster::fill_with(composite, std::slice::from_ref(&lens), |px, py| {
+        // Pixel centres map back to `source`, sampled between pixels.
+        let u = sx + (px as f64 + 0.5 - cx) / zoom - 0.5;
+        let v = sy + (py as f64 + 0.5 - cy) / zoom - 0.5;
+        let (u, v) = ((u as f32).clamp(0.0, max_x), (v as f32).clamp(0.0, max_y));
+        let sample = image::imageops::interpolate_bilinear(base, u, v).unwrap_or(Rgba([0; 4]));
+        faded(sample, opacity)
+    });
+    if let Ok(color) = parse_color(stroke_color.unwrap_or("#FF0000")) {
+        raster::stroke(composite, &lens, true, &stroke, faded(color, opacity));
+    }
+}
+
+/// Stretch a stamp or `picture` over the box.
+fn composite_image(
+    composite: &mut RgbaImage,
+    (x, y, width, height): (f64, f64, f64, f64),
+    stamp: Option<Stamp>,
+    picture: Option<&DynamicImage>,
+    opacity: f64,
+    rotation: f64,
+) {
+    if width <= 0.0 || height <= 0.0 {
+        return;
+    }
+    let (x, y, w, h) = (x as f32, y as f32, width as f32, height as f32);
+    let turn = Rotation::new(rotation, (x + w / 2.0, y + h / 2.0));
+    if let Some(stamp) = stamp {
+        embed::draw_stamp(composite, stamp, (x, y, w, h), turn, opacity);
+        return;
+    }
+    let Some(picture) = picture else {
+        return;
+    };
+
+    // Resample to the box's size first so shrinking a large screenshot
+    // averages its pixels instead of skipping most of them.
+    let (pw, ph) = (
+        w.round().clamp(1.0, MAX_PICTURE_SIDE),
+        h.round().clamp(1.0, MAX_PICTURE_SIDE),
+    );
+    let scaled = image::imageops::resize(
+        picture,
+        pw as u32,
+        ph as u32,
+        image::imageops::FilterType::Triangle,
+    );
+    let undo = Rotation::new(-rotation, (x + w / 2.0, y + h / 2.0));
+    let frame = turn.apply_all(vec![(x, y), (x + w, y), (x + w, y + h), (x, y + h)]);
+    raster::fill_with(composite, &[frame], |px, py| {
+        let (u, v) = undo.apply((px as f32 + 0.5, py as f32 + 0.5));
+        let u = ((u - x) / w * pw - 0.5).clamp(0.0, pw - 1.0);
+        let v = ((v - y) / h * ph - 0.5).clamp(0.0, ph - 1.0);
+        let sample = image::imageops::interpolate_bilinear(&scaled, u, v).unwrap_or(Rgba([0; 4]));
+        faded(sample, opacity)
+    });
+}
+
+/// Largest side an embedded picture is resampled to.
+const MAX_PICTURE_SIDE: f32 = 8192.0;
+
+/// Darken everything outside the box.
+fn composite_spotlight(
+    composite: &mut RgbaImage,
+    (x, y, width, height): (f64, f64, f64, f64),
+    dim_color: Option<&str>,
+    corner_radius: f64,
+    opacity: f64,
+) {
+    let Ok(dim) = parse_color(dim_color.unwrap_or(SPOTLIGHT_DIM)) else {
+        return;
+    };
+    let (w, h) = (composite.width() as f32, composite.height() as f32);
+    let frame = vec![(0.0, 0.0), (w, 0.0), (w, h), (0.0, h)];
+    // Wound against the frame, the box cuts a hole in it.
+    let mut hole = raster::rounded_rect(
+        x as f32,
+        y as f32,
+        width.max(0.0) as f32,
+        height.max(0.0) as f32,
+        corner_radius as f32,
+    );
+    hole.reverse();
+    raster::fill(composite, &[frame, hole], faded(dim, opacity));
+}
+
+/// Default spotlight dimming: black at 60%.
+pub(super) const SPOTLIGHT_DIM: &str = "#00000099";
+
+fn outline_style(width: f32) -> Stroke {
+    Stroke {
+        width,
+        join: Join::Round,
+        cap: Cap::Butt,
+        dash: Vec::new(),
+    }
+}
+
+/// Rasterize `glyph` on the line whose baseline starts at `origin`,
+/// slanting it when the face has no italic and smearing it when it has
+/// no bold, then turning it with the rest of its annotation.
+fn draw_glyph(
+    composite: &mut RgbaImage,
+    glyph: &PlacedGlyph,
+    origin: (f32, f32),
+    color: Rgba<u8>,
+    rotation: Rotation,
+) {
+    let Some(outline) = glyph.face.font.outline(glyph.id) else {
+        return;
+    };
+    let scaled = glyph.face.font.as_scaled(glyph.scale);
+    let (sx, sy) = (scaled.h_scale_factor(), scaled.v_scale_factor());
+    let skew = if glyph.face.italic { 0.0 } else { 0.2 };
+    let embolden = text::synthetic_bold(glyph.face, glyph.scale);
+    let (ox, oy) = (origin.0 + glyph.x, origin.1 + glyph.y);
+    let offsets: &[f32] = if embolden > 0.0 {
+        &[0.0, embolden]
+    } else {
+        &[0.0]
+    };
+    let to_px = |p: ab_glyph::Point, dx: f32| {
+        rotation.apply((ox + dx + (p.x + skew * p.y) * sx, oy - p.y * sy))
+    };
+    let b = outline.bounds;
+    let corners: Vec<Pt> = offsets
+        .iter()
+        .flat_map(|&dx| {
+            [
+                b.min,
+                b.max,
+                point(b.min.x, b.max.y),
+                point(b.max.x, b.min.y),
+            ]
+            .map(|c| to_px(c, dx))
+        })
+        .collect();
+    let x0 = corners.iter().map(|c| c.0).fold(f32::MAX, f32::min).floor();
+    let y0 = corners.iter().map(|c| c.1).fold(f32::MAX, f32::min).floor();
+    let x1 = corners.iter().map(|c| c.0).fold(f32::MIN, f32::max);
+    let y1 = corners.iter().map(|c| c.1).fold(f32::MIN, f32::max);
+    let (w, h) = ((x1 - x0).ceil() as usize + 1, (y1 - y0).ceil() as usize + 1);
+    if w > 4096 || h > 4096 {
+        return;
+    }
+
+    let mut rasterizer = Rasterizer::new(w, h);
+    for &dx in offsets {
+        let p = |q: &ab_glyph::Point| {
+            let (x, y) = to_px(*q, dx);
+            point(x - x0, y - y0)
+        };
+        for curve in &outline.curves {
+            match curve {
+                OutlineCurve::Line(a, b) => rasterizer.draw_line(p(a), p(b)),
+                OutlineCurve::Quad(a, b, c) => rasterizer.draw_quad(p(a), p(b), p(c)),
+                OutlineCurve::Cubic(a, b, c, d) => rasterizer.draw_cubic(p(a), p(b), p(c), p(d)),
+            }
+        }
+    }
+
+    let (img_w, img_h) = (composite.width() as i64, composite.height() as i64);
+    rasterizer.for_each_pixel_2d(|px, py, coverage| {
+        let (ix, iy) = (x0 as i64 + px as i64, y0 as i64 + py as i64);
+        if coverage > 0.0 && (0..img_w).contains(&ix) && (0..img_h).contains(&iy) {
+            let base = composite.get_pixel_mut(ix as u32, iy as u32);
+            blend_pixel(base, faded(color, coverage.min(1.0) as f64));
+        }
+    });
+}
+
+/// Parse a CSS color string into `Rgba<u8>`.
+///
+/// Supports:
+/// - `"transparent"` → `[0, 0, 0, 0]`
+/// - `#RRGGBB` → alpha 255
+/// - `#RRGGBBAA` → explicit alpha
+///
+/// Returns `Err` for unrecognized formats.
+pub fn parse_color(color: &str) -> Result<Rgba<u8>, String> {
+    if color.eq_ignore_ascii_case("transparent") {
+        return Ok(Rgba([0, 0, 0, 0]));
+    }
+
+    let hex = color
+        .strip_prefix('#')
+        .filter(|hex| hex.is_ascii())
+        .ok_or_else(|| format!("Unsupported color format: {color}"))?;
+
+    let parse_byte = |s: &str| {
+        u8::from_str_radix(s, 16).map_err(|_| format!("Invalid hex byte '{s}' in color {color}"))
+    };
+
+    match hex.len() {
+        6 => Ok(Rgba([
+            parse_byte(&hex[0..2])?,
+            parse_byte(&hex[2..4])?,
+            parse_byte(&hex[4..6])?,
+            255,
+        ])),
+        8 => Ok(Rgba([
+            parse_byte(&hex[0..2])?,
+            parse_byte(&hex[2..4])?,
+            parse_byte(&hex[4..6])?,
+            parse_byte(&hex[6..8])?,
+        ])),
+        _ => Err(format!("Unsupported color format: {color}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(fill: &str, stroke: &str, stroke_width: f64) -> Annotation {
+        Annotation::new(Shape::Rect {
+            x: 10.0,
+            y: 10.0,
+            width: 30.0,
+            height: 20.0,
+            stroke_color: Some(stroke.into()),
+            fill_color: Some(fill.into()),
+            stroke_width: Some(stroke_width),
+            line_dash: None,
+        })
+    }
+
+    fn render(img: RgbaImage, anno: Annotation) -> RgbaImage {
+        render_annotations(&DynamicImage::ImageRgba8(img), &[anno], &ImageStore::new()).unwrap()
+    }
+
+    // ── parse_color ──────────────────────────────────────────────────────────
+
+    #[test]
+    fn parse_color_transparent() {
+        assert_eq!(parse_color("transparent").unwrap(), Rgba([0, 0, 0, 0]));
+        assert_eq!(parse_color("TRANSPARENT").unwrap(), Rgba([0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn parse_color_rrggbb() {
+        assert_eq!(parse_color("#ff0000").unwrap(), Rgba([255, 0, 0, 255]));
+        assert_eq!(parse_color("#00FF00").unwrap(), Rgba([0, 255, 0, 255]));
+    }
+
+    #[test]
+    fn parse_color_rrggbbaa() {
+        assert_eq!(parse_color("#ff000080").unwrap(), Rgba([255, 0, 0, 128]));
+    }
+
+    #[test]
+    fn parse_color_invalid_returns_err() {
+        assert!(parse_color("red").is_err());
+        assert!(parse_color("#zzz").is_err());
+        assert!(parse_color("").is_err());
+        assert!(parse_color("#ééé").is_err());
+    }
+
+    // ── compositing ──────────────────────────────────────────────────────────
+
+    #[test]
+    fn compositing_stroke_pixel_is_stroke_color() {
+        let img = RgbaImage::from_pixel(100, 100, Rgba([255, 255, 255, 255]));
+        // A 2 px stroke centred on the edge covers the pixels either side of
+        // it exactly; a 1 px one would half-cover both, as on the canvas.
+        let img = render(img, rect("transparent", "#ff0000", 2.0));
+        assert_eq!(*img.get_pixel(10, 10), Rgba([255, 0, 0, 255]));
+        assert_eq!(*img.get_pixel(9, 9), Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn compositing_outside_pixel_unchanged() {
+        let img = RgbaImage::from_pixel(100, 100, Rgba([255, 255, 255, 255]));
+        let img = render(img, rect("transparent", "#ff0000", 1.0));
+        assert_eq!(*img.get_pixel(0, 0), Rgba([255, 255, 255, 255]));
+        assert_eq!(*img.get_pixel(99, 99), Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn compositing_fill_pixel_is_fill_color() {
+        let img = RgbaImage::from_pixel(100, 100, Rgba([255, 255, 255, 255]));
+        let img = render(img, rect("#0000ff", "transparent", 0.0));
+        let px = img.get_pixel(20, 15);
+        assert_eq!(px[2], 255);
+        assert_eq!(px[0], 0);
+    }
+
+    #[test]
+    fn compositing_highlight_uses_fixed_opacity() {
+        let img = RgbaImage::from_pixel(100, 100, Rgba([0, 0, 0, 255]));
+        let anno = Annotation::new(Shape::Highlight {
+            x: 10.0,
+            y: 10.0,
+            width: 20.0,
+            height: 20.0,
+            highlight_color: Some("#FFFF00".into()),
+        });
+        let img = render(img, anno);
+        // The yellow should be blended at 0.4 opacity over black.
+        let px = img.get_pixel(20, 20);
+        assert!(
+            px[0] > 0,
+            "yellow channel should be non-zero after highlight"
+        );
+        assert!(px[0] < 255, "should be blended, not fully opaque");
+    }
+
+    #[test]
+    fn compositing_blur_pixelates_region() {
+        // Fill top-left 20×20 with red, rest with blue.
+        let mut img = RgbaImage::from_pixel(100, 100, Rgba([0, 0, 255, 255]));
+        for y in 0..20u32 {
+            for x in 0..20u32 {
+                img.put_pixel(x, y, Rgba([255, 0, 0, 255]));
+            }
+        }
+        let img = render(img, Annotation::blur(0, 0, 20, 20, RedactStyle::Pixelate));
+        // After blur, each block should have a single average color.
+        let block1 = *img.get_pixel(0, 0);
+        let block2 = *img.get_pixel(5, 5);
+        assert_eq!(
+            block1, block2,
+            "pixels within a blur block should be identical"
+        );
+    }
+
+    #[test]
+    fn compositing_arrow_draws_something() {
+        let img = RgbaImage::from_pixel(100, 100, Rgba([255, 255, 255, 255]));
+        let anno = Annotation::new(Shape::Arrow {
+            points: vec![Point { x: 10.0, y: 50.0 }, Point { x: 80.0, y: 50.0 }],
+            stroke_color: Some("#ff0000".into()),
+            stroke_width: Some(2.0),
+            line_dash: None,
+        });
+        let img = render(img, anno);
+        // At least the shaft midpoint should be red.
+        let mid = img.get_pixel(45, 50);
+        assert_eq!(mid[0], 255, "arrow shaft should be red");
+        assert_eq!(mid[1], 0);
+    }
+
+    fn text(family: &str, bold: bool) -> Annotation {
+        Annotation::new(Shape::Text {
+            x: 5.0,
+            y: 5.0,
+            text: "Hello".into(),
+            font_size: Some(20.0),
+            font_family: Some(family.into()),
+            bold: Some(bold),
+            italic: None,
+            stroke_color: Some("#ff0000".into()),
+            width: None,
+            align: Default::default(),
+            background_color: None,
+            border_color: None,
+            border_width: None,
+            corner_radius: None,
+            padding: None,
+        })
+    }
+
+    fn inked(img: &RgbaImage) -> Vec<(u32, u32)> {
+        img.enumerate_pixels()
+            .filter(|(_, _, p)| p[1] < 255)
+            .map(|(x, y, _)| (x, y))
+            .collect()
+    }
+
+    #[test]
+    fn compositing_text_hangs_from_its_position() {
+        let img = RgbaImage::from_pixel(160, 60, Rgba([255, 255, 255, 255]));
+        // Whatever the system has, an unknown family still renders.
+        let img = render(img, text("No Such Family", false));
+        let ink = inked(&img);
+        assert!(!ink.is_empty());
+        assert!(ink.iter().all(|&(x, y)| x >= 5 && (5..5 + 28).contains(&y)));
+        assert!(
+            ink.iter().any(|&(_, y)| y < 12),
+            "cap height starts near the top"
+        );
+    }
+
+    #[test]
+    fn compositing_bold_text_is_heavier() {
+        let blank = RgbaImage::from_pixel(160, 60, Rgba([255, 255, 255, 255]));
+        let regular = inked(&render(blank.clone(), text("sans-serif", false))).len();
+        let bold = inked(&render(blank, text("sans-serif", true))).len();
+        assert!(bold > regular, "{bold} vs {regular}");
+    }
+
+    #[test]
+    fn opacity_fades_every_annotation_but_blurs() {
+        let white = RgbaImage::from_pixel(160, 60, Rgba([255, 255, 255, 255]));
+        let mut faint = text("sans-serif", false);
+        faint.opacity = Some(0.5);
+        let darkest = |img: &RgbaImage| img.pixels().map(|p| p[1]).min().unwrap();
+        assert_eq!(
+            darkest(&render(white.clone(), text("sans-serif", false))),
+            0
+        );
+        assert!((120..=135).contains(&darkest(&render(white.clone(), faint))));
+
+        let mut box_ = rect("#0000ff", "#0000ff", 0.0);
+        box_.opacity = Some(0.5);
+        assert_eq!(render(white.clone(), box_).get_pixel(20, 20)[0], 127);
+
+        let mut blur = Annotation::blur(0, 0, 40, 40, RedactStyle::Solid);
+        blur.opacity = Some(0.1);
+        assert_eq!(*render(white, blur).get_pixel(20, 20), Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn rotation_turns_shapes_about_their_centre() {
+        let white = RgbaImage::from_pixel(60, 60, Rgba([255, 255, 255, 255]));
+        let mut bar = Annotation::new(Shape::Rect {
+            x: 10.0,
+            y: 25.0,
+            width: 40.0,
+            height: 10.0,
+            stroke_color: Some("transparent".into()),
+            fill_color: Some("#000000".into()),
+            stroke_width: None,
+            line_dash: None,
+        });
+        let flat = render(white.clone(), bar.clone());
+        bar.rotation = Some(90.0);
+        let upright = render(white.clone(), bar.clone());
+        assert_eq!(flat.get_pixel(12, 30)[0], 0);
+        assert_eq!(upright.get_pixel(12, 30)[0], 255);
+        assert_eq!(upright.get_pixel(30, 12)[0], 0);
+        assert_eq!(upright.get_pixel(30, 30)[0], 0);
+
+        // Text turns with its box: a quarter turn stands a line on end.
+        let mut label = text("sans-serif", false);
+        label.rotation = Some(90.0);
+        let ink = inked(&render(
+            RgbaImage::from_pixel(160, 160, Rgba([255; 4])),
+            label,
+        ));
+        let span = |f: fn(&(u32, u32)) -> u32| {
+            ink.iter().map(f).max().unwrap() - ink.iter().map(f).min().unwrap()
+        };
+        assert!(span(|p| p.1) > span(|p| p.0), "text should run vertically");
+    }
+
+    #[test]
+    fn spotlight_dims_only_outside_its_box() {
+        let white = RgbaImage::from_pixel(60, 60, Rgba([255, 255, 255, 255]));
+        let spot = Annotation::new(Shape::Spotlight {
+            x: 20.0,
+            y: 20.0,
+            width: 20.0,
+            height: 20.0,
+            dim_color: None,
+            corner_radius: None,
+        });
+        let img = render(white, spot);
+        assert_eq!(*img.get_pixel(30, 30), Rgba([255, 255, 255, 255]));
+        assert_eq!(img.get_pixel(5, 5)[0], 102);
+        assert_eq!(img.get_pixel(50, 30)[0], 102);
+    }
+
+    #[test]
+    fn magnifier_enlarges_the_original_screenshot() {
+        // A 4×4 red dot at (10, 10), then covered by a blue box.
+        let mut img = RgbaImage::from_pixel(80, 80, Rgba([255, 255, 255, 255]));
+        for (x, y) in (10..14).flat_map(|x| (10..14).map(move |y| (x, y))) {
+            img.put_pixel(x, y, Rgba([255, 0, 0, 255]));
+        }
+        let cover = Annotation::new(Shape::Rect {
+            x: 0.0,
+            y: 0.0,
+            width: 30.0,
+            height: 30.0,
+            stroke_color: None,
+            fill_color: Some("#0000ff".into()),
+            stroke_width: Some(0.0),
+            line_dash: None,
+        });
+        let loupe = Annotation::new(Shape::Magnifier {
+            x: 40.0,
+            y: 40.0,
+            width: 40.0,
+            height: 40.0,
+            source: Some(Point { x: 12.0, y: 12.0 }),
+            zoom: Some(4.0),
+            stroke_color: None,
+            stroke_width: Some(0.0),
+        });
+        let out = render_annotations(
+            &DynamicImage::ImageRgba8(img),
+            &[cover, loupe],
+            &ImageStore::new(),
+        )
+        .unwrap();
+        // The dot fills the middle 16×16 of the lens, from under the cover.
+        assert_eq!(*out.get_pixel(60, 60), Rgba([255, 0, 0, 255]));
+        assert_eq!(*out.get_pixel(54, 54), Rgba([255, 0, 0, 255]));
+        assert_eq!(*out.get_pixel(46, 60), Rgba([255, 255, 255, 255]));
+        // Outside the ellipse the box's corner is untouched.
+        assert_eq!(*out.get_pixel(41, 41), Rgba([255, 255, 255, 255]));
+        assert_eq!(*out.get_pixel(5, 5), Rgba([0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn magnifier_never_shows_what_a_redaction_hides() {
+        let img = RgbaImage::from_pixel(80, 80, Rgba([255, 0, 0, 255]));
+        let redaction = Annotation::new(Shape::Blur {
+            x: 0.0,
+            y: 0.0,
+            width: 30.0,
+            height: 30.0,
+            blur_radius: None,
+            redact_style: RedactStyle::Solid,
+        });
+        // Drawn before the redaction, the lens still looks at it.
+        let loupe = Annotation::new(Shape::Magnifier {
+            x: 40.0,
+            y: 40.0,
+            width: 40.0,
+            height: 40.0,
+            source: Some(Point { x: 15.0, y: 15.0 }),
+            zoom: Some(4.0),
+            stroke_color: None,
+            stroke_width: Some(0.0),
+        });
+        let out = render_annotations(
+            &DynamicImage::ImageRgba8(img),
+            &[loupe, redaction],
+            &ImageStore::new(),
+        )
+        .unwrap();
+        // Everything the lens shows lies under the redaction.
+        for (x, y) in (45..75).flat_map(|x| (45..75).map(move |y| (x, y))) {
+            if (x as f64 - 59.5).hypot(y as f64 - 59.5) < 19.0 {
+                assert_eq!(*out.get_pixel(x, y), Rgba([0, 0, 0, 255]), "({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn callout_tail_reaches_its_tip() {
+        let grey = RgbaImage::from_pixel(120, 100, Rgba([128, 128, 128, 255]));
+        let callout = Annotation::new(Shape::Callout {
+            x: 10.0,
+            y: 10.0,
+            width: 100.0,
+            height: 40.0,
+            tail: Point { x: 30.0, y: 90.0 },
+            text: "Hi".into(),
+            font_size: None,
+            font_family: None,
+            stroke_color: Some("#ff0000".into()),
+            fill_color: None,
+            text_color: None,
+            stroke_width: None,
+            corner_radius: None,
+        });
+        let img = render(grey, callout);
+        // White bubble, red border, and a tail down to the tip.
+        assert_eq!(*img.get_pixel(90, 40), Rgba([255, 255, 255, 255]));
+        assert_eq!(img.get_pixel(60, 10)[1], 0);
+        assert_eq!(*img.get_pixel(30, 70), Rgba([255, 255, 255, 255]));
+        assert_eq!(img.get_pixel(30, 88)[1], 0);
+        assert_eq!(*img.get_pixel(90, 70), Rgba([128, 128, 128, 255]));
+        // Black text inside, in from the corner.
+        let text_ink = (18..40)
+            .flat_map(|x| (18..40).map(move |y| (x, y)))
+            .filter(|&(x, y)| img.get_pixel(x, y)[0] < 100)
+            .count();
+        assert!(text_ink > 10);
+    }
+
+    fn picture(x: f64, y: f64, w: f64, h: f64) -> Shape {
+        Shape::Image {
+            x,
+            y,
+            width: w,
+            height: h,
+            image_id: None,
+            path: None,
+            stamp: None,
+        }
+    }
+
+    #[test]
+    fn image_annotations_embed_stored_screenshots_and_stamps() {
+        let store = ImageStore::new();
+        let id = uuid::Uuid::new_v4();
+        // Left half red, right half blue.
+        let inset = RgbaImage::from_fn(20, 10, |x, _| {
+            if x < 10 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 255, 255])
+            }
+        });
+        store.insert(
+            id,
+            std::sync::Arc::new(DynamicImage::ImageRgba8(inset)),
+            crate::capture::CaptureMetadata::new(crate::capture::CaptureMode::Fullscreen, (20, 10)),
+        );
+        let mut pip = picture(10.0, 10.0, 40.0, 20.0);
+        if let Shape::Image { image_id, .. } = &mut pip {
+            *image_id = Some(id.to_string());
+        }
+        let mut stamp = picture(60.0, 10.0, 30.0, 30.0);
+        if let Shape::Image { stamp: s, .. } = &mut stamp {
+            *s = Some(Stamp::Checkmark);
+        }
+        let white = RgbaImage::from_pixel(100, 50, Rgba([255, 255, 255, 255]));
+        let out = render_annotations(
+            &DynamicImage::ImageRgba8(white),
+            &[Annotation::new(pip), Annotation::new(stamp)],
+            &store,
+        )
+        .unwrap();
+        // Scaled up twice, halves intact.
+        assert_eq!(*out.get_pixel(15, 20), Rgba([255, 0, 0, 255]));
+        assert_eq!(*out.get_pixel(45, 20), Rgba([0, 0, 255, 255]));
+        assert_eq!(*out.get_pixel(55, 20), Rgba([255, 255, 255, 255]));
+        // The checkmark's green disc, with its white tick through the middle.
+        assert_eq!(*out.get_pixel(63, 25), Rgba([46, 160, 67, 255]));
+        assert_eq!(*out.get_pixel(73, 30), Rgba([255, 255, 255, 255]));
+        assert_eq!(*out.get_pixel(61, 11), Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn missing_pictures_fail_the_render() {
+        let mut gone = picture(0.0, 0.0, 10.0, 10.0);
+        if let Shape::Image { image_id, .. } = &mut gone {
+            *image_id = Some(uuid::Uuid::new_v4().to_string());
+        }
+        let mut unreadable = picture(0.0, 0.0, 10.0, 10.0);
+        if let Shape::Image { path, .. } = &mut unreadable {
+            *path = Some("/nonexistent/picture.png".into());
+        }
+        let err = render_annotations(
+            &DynamicImage::ImageRgba8(RgbaImage::new(20, 20)),
+            &[
+                rect("#ff0000", "#ff0000", 1.0),
+                Annotation::new(gone),
+                Annotation::new(unreadable),
+            ],
+            &ImageStore::new(),
+        )
+        .unwrap_err();
+        let indexes: Vec<usize> = err.0.iter().map(|e| e.index).collect();
+        assert_eq!(indexes, [1, 2]);
+        assert!(err.0[0].message.contains("screenshot not found"));
+        assert!(err.0[1].message.starts_with("path: cannot read"));
+    }
+
+    // ── redaction styles ─────────────────────────────────────────────────────
+
+    /// 48×24 of pseudo-random black-on-white "text".
+    fn text_like(seed: u32) -> RgbaImage {
+        RgbaImage::from_fn(48, 24, |x, y| {
+            let h = (x * 7919 + y * 104_729 + seed * 15_485_863) % 97;
+            if h < 40 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        })
+    }
+
+    /// `img` with every `MIN_REDACT_BLOCK` square mirrored: different pixels,
+    /// same block averages.
+    fn mirror_blocks(img: &RgbaImage) -> RgbaImage {
+        let b = MIN_REDACT_BLOCK;
+        RgbaImage::from_fn(img.width(), img.height(), |x, y| {
+            let bx = x / b * b;
+            *img.get_pixel(bx + (b - 1 - (x - bx)), y)
+        })
+    }
+
+    fn redact(img: &RgbaImage, radius: f64, style: RedactStyle, seed: u64) -> RgbaImage {
+        let mut out = img.clone();
+        let (w, h) = (img.width() as f64, img.height() as f64);
+        composite_blur(&mut out, (0.0, 0.0, w, h), Some(radius), style, seed);
+        out
+    }
+
+    #[test]
+    fn redaction_output_does_not_depend_on_fine_detail() {
+        let original = text_like(1);
+        let twin = mirror_blocks(&original);
+        assert_ne!(original, twin);
+        for style in [RedactStyle::Pixelate, RedactStyle::Gaussian] {
+            // Even a tiny requested block is raised to the minimum.
+            let a = redact(&original, 2.0, style, 0);
+            let b = redact(&twin, 2.0, style, 0);
+            assert_eq!(a, b, "{style:?} output reveals detail below a block");
+            assert_ne!(a, original);
+        }
+        let a = redact(&original, 2.0, RedactStyle::Pixelate, 0);
+        assert_eq!(
+            a.get_pixel(0, 0),
+            a.get_pixel(MIN_REDACT_BLOCK - 1, MIN_REDACT_BLOCK - 1)
+        );
+    }
+
+    #[test]
+    fn solid_and_noise_ignore_the_original() {
+        let (a, b) = (text_like(1), text_like(2));
+        let solid = redact(&a, 10.0, RedactStyle::Solid, 0);
+        assert!(solid.pixels().all(|p| *p == Rgba([0, 0, 0, 255])));
+        assert_eq!(solid, redact(&b, 10.0, RedactStyle::Solid, 0));
+
+        let noise = redact(&a, 10.0, RedactStyle::Noise, 42);
+        assert_eq!(noise, redact(&b, 10.0, RedactStyle::Noise, 42));
+        assert_ne!(noise, redact(&a, 10.0, RedactStyle::Noise, 43));
+        // Not a flat fill, and not correlated with the text underneath.
+        let dark_in = a.pixels().filter(|p| p[0] == 0).count();
+        let dark_out = noise.pixels().filter(|p| p[0] < 128).count();
+        let both = a
+            .pixels()
+            .zip(noise.pixels())
+            .filter(|(i, o)| i[0] == 0 && o[0] < 128)
+            .count();
+        let expected = dark_in * dark_out / (48 * 24);
+        assert!(dark_out > 0 && dark_out < 48 * 24);
+        assert!(both.abs_diff(expected) < 48, "{both} vs {expected}");
+    }
+
+    #[test]
+    fn redaction_stays_inside_its_rectangle() {
+        let img = RgbaImage::from_pixel(40, 40, Rgba([255, 255, 255, 255]));
+        for style in [
+            RedactStyle::Pixelate,
+            RedactStyle::Solid,
+            RedactStyle::Noise,
+            RedactStyle::Gaussian,
+        ] {
+            let mut out = img.clone();
+            composite_blur(&mut out, (10.0, 10.0, 100.0, 5.0), None, style, 7);
+            for (x, y, p) in out.enumerate_pixels() {
+                if !(10..40).contains(&x) || !(10..15).contains(&y) {
+                    assert_eq!(*p, Rgba([255, 255, 255, 255]), "{style:?} at {x},{y}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,789 @@
+/// Vector export: the screenshot as an embedded image with each annotation
+/// on its own layer of native SVG elements, for editing in Inkscape,
+/// Figma and the like.
+///
+/// Geometry and defaults follow the raster renderer. Blurs can't be left
+/// to SVG filters, which would ship the pixels they hide: redactions are
+/// baked into the embedded screenshot, and each blur's layer holds the
+/// redacted patch as an image so it still covers what was drawn before it.
+use super::embed::StampPart;
+use super::fonts::{FontChain, FontStyle};
+use super::raster::{self, Join, Pt, Stroke};
+use super::render::{self, parse_color, CALLOUT_PADDING, SPOTLIGHT_DIM};
+use super::text::{self, Line};
+use super::{Annotation, AnnotationErrors, Shape, TextAlign};
+use crate::capture::ImageStore;
+use ab_glyph::{Font as _, ScaleFont as _};
+use base64::Engine;
+use image::{DynamicImage, RgbaImage};
+use std::fmt::Write as _;
+use std::io::Cursor;
+
+/// Write `annotations` over `image` as an SVG document. Image annotations
+/// naming a screenshot take it from `store`; pictures that can't be loaded
+/// fail the export as they do [`render::render_annotations`].
+pub fn render_svg(
+    image: &DynamicImage,
+    annotations: &[Annotation],
+    store: &ImageStore,
+) -> Result<String, AnnotationErrors> {
+    let pictures = render::load_pictures(annotations, store)?;
+
+    let mut screenshot = image.to_rgba8();
+    let regions = render::redact(&mut screenshot, annotations);
+
+    let (w, h) = screenshot.dimensions();
+    let mut svg = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <svg xmlns=\"http://www.w3.org/2000/svg\" \
+         xmlns:xlink=\"http://www.w3.org/1999/xlink\" \
+         xmlns:inkscape=\"http://www.inkscape.org/namespaces/inkscape\" \
+         width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n\
+         <g id=\"screenshot-layer\" inkscape:groupmode=\"layer\" inkscape:label=\"Screenshot\">\n\
+         <image id=\"screenshot\" x=\"0\" y=\"0\" width=\"{w}\" height=\"{h}\" xlink:href=\"{}\"/>\n\
+         </g>\n",
+        png_data_uri(&screenshot)
+    );
+
+    for (i, anno) in annotations.iter().enumerate() {
+        let layer = format!("annotation-{}", i + 1);
+        let body = match (&anno.shape, regions[i]) {
+            (Shape::Blur { .. }, Some((x1, y1, x2, y2))) => {
+                let patch =
+                    image::imageops::crop_imm(&screenshot, x1, y1, x2 - x1, y2 - y1).to_image();
+                format!(
+                    "<image x=\"{x1}\" y=\"{y1}\" width=\"{}\" height=\"{}\" \
+                     style=\"image-rendering:pixelated\" xlink:href=\"{}\"/>\n",
+                    x2 - x1,
+                    y2 - y1,
+                    png_data_uri(&patch)
+                )
+            }
+            _ => shape_svg(anno, &layer, pictures[i].as_deref(), (w, h)),
+        };
+        if body.is_empty() {
+            continue;
+        }
+        let opacity = anno.opacity.unwrap_or(1.0).clamp(0.0, 1.0);
+        let faded = match anno.shape {
+            Shape::Blur { .. } => String::new(),
+            _ if opacity < 1.0 => format!(" opacity=\"{}\"", num(opacity)),
+            _ => String::new(),
+        };
+        let _ = write!(
+            svg,
+            "<g id=\"{layer}\" inkscape:groupmode=\"layer\" inkscape:label=\"{} {}\" \
+             data-annotation-id=\"{}\"{faded}>\n{body}</g>\n",
+            anno.shape.kind(),
+            i + 1,
+            escape(&anno.id),
+        );
+    }
+    svg.push_str("</svg>\n");
+    Ok(svg)
+}
+
+/// The elements drawing `anno` on an image `size` pixels large; `layer`
+/// prefixes any ids they need.
+fn shape_svg(
+    anno: &Annotation,
+    layer: &str,
+    picture: Option<&DynamicImage>,
+    size: (u32, u32),
+) -> String {
+    let rotation = anno.rotation.unwrap_or(0.0);
+    match &anno.shape {
+        Shape::Rect {
+            x,
+            y,
+            width,
+            height,
+            stroke_color,
+            fill_color,
+            stroke_width,
+            line_dash,
+        } => {
+            if *width <= 0.0 || *height <= 0.0 {
+                return String::new();
+            }
+            let stroke = render::outline(*stroke_width, line_dash, Join::Miter, raster::Cap::Butt);
+            rotated(
+                rotation,
+                (x + width / 2.0, y + height / 2.0),
+                format!(
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"{}{}/>\n",
+                    num(*x),
+                    num(*y),
+                    num(*width),
+                    num(*height),
+                    paint("fill", fill_color.as_deref().unwrap_or("transparent")),
+                    stroke_attrs(stroke_color.as_deref().unwrap_or("#FF0000"), &stroke),
+                ),
+            )
+        }
+        Shape::Ellipse {
+            x,
+            y,
+            width,
+            height,
+            stroke_color,
+            fill_color,
+            stroke_width,
+            line_dash,
+        } => {
+            if *width <= 0.0 || *height <= 0.0 {
+                return String::new();
+            }
+            let stroke = render::outline(*stroke_width, line_dash, Join::Round, raster::Cap::Butt);
+            let (cx, cy) = (x + width / 2.0, y + height / 2.0);
+            rotated(
+                rotation,
+                (cx, cy),
+                format!(
+                    "<ellipse cx=\"{}\" cy=\"{}\" rx=\"{}\" ry=\"{}\"{}{}/>\n",
+                    num(cx),
+                    num(cy),
+                    num(width / 2.0),
+                    num(height / 2.0),
+                    paint("fill", fill_color.as_deref().unwrap_or("transparent")),
+                    stroke_attrs(stroke_color.as_deref().unwrap_or("#FF0000"), &stroke),
+                ),
+            )
+        }
+        Shape::Arrow {
+            points,
+            stroke_color,
+            stroke_width,
+            line_dash,
+        } => {
+            let [p1, p2, ..] = points.as_slice() else {
+                return String::new();
+            };
+            let color = stroke_color.as_deref().unwrap_or("#FF0000");
+            let mut stroke =
+                render::outline(*stroke_width, line_dash, Join::Round, raster::Cap::Round);
+            stroke.width = stroke.width.max(1.0);
+            let (dx, dy) = (p2.x - p1.x, p2.y - p1.y);
+            let len = (dx * dx + dy * dy).sqrt();
+            if len < 0.001 {
+                return String::new();
+            }
+            // The same head as the raster arrow, with the shaft stopping at
+            // its base.
+            let head_len = (stroke.width as f64 * 5.0).max(12.0);
+            let angle = dy.atan2(dx);
+            let wing = std::f64::consts::PI / 6.0;
+            let back = head_len * wing.cos();
+            let mut out = String::new();
+            if back < len {
+                let _ = writeln!(
+                    out,
+                    "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" fill=\"none\"{}/>",
+                    num(p1.x),
+                    num(p1.y),
+                    num(p2.x - back * angle.cos()),
+                    num(p2.y - back * angle.sin()),
+                    stroke_attrs(color, &stroke),
+                );
+            }
+            let head = [
+                (p2.x, p2.y),
+                (
+                    p2.x - head_len * (angle - wing).cos(),
+                    p2.y - head_len * (angle - wing).sin(),
+                ),
+                (
+                    p2.x - head_len * (angle + wing).cos(),
+                    p2.y - head_len * (angle + wing).sin(),
+                ),
+            ]
+            .map(|(x, y)| (x as f32, y as f32));
+            let _ = writeln!(
+                out,
+                "<polygon points=\"{}\"{}/>",
+                point_list(&head),
+                paint("fill", color),
+            );
+            out
+        }
+        Shape::Freehand {
+            points,
+            stroke_color,
+            stroke_width,
+            line_dash,
+        } => {
+            if points.len() < 2 {
+                return String::new();
+            }
+            let mut stroke =
+                render::outline(*stroke_width, line_dash, Join::Round, raster::Cap::Round);
+            stroke.width = stroke.width.max(1.0);
+            let path: Vec<Pt> = points.iter().map(|p| (p.x as f32, p.y as f32)).collect();
+            format!(
+                "<polyline points=\"{}\" fill=\"none\"{}/>\n",
+                point_list(&path),
+                stroke_attrs(stroke_color.as_deref().unwrap_or("#FF0000"), &stroke),
+            )
+        }
+        Shape::Highlight {
+            x,
+            y,
+            width,
+            height,
+            highlight_color,
+        } => {
+            // Whole pixels at a fixed 40%, as the raster highlight.
+            let (x, y, w, h) = (*x as i32, *y as i32, *width as i32, *height as i32);
+            let Ok(color) = parse_color(highlight_color.as_deref().unwrap_or("#FFFF00")) else {
+                return String::new();
+            };
+            if w <= 0 || h <= 0 {
+                return String::new();
+            }
+            format!(
+                "<rect x=\"{x}\" y=\"{y}\" width=\"{w}\" height=\"{h}\" fill=\"{}\" fill-opacity=\"0.4\"/>\n",
+                hex(color)
+            )
+        }
+        // Redactions are baked in by `render_svg`.
+        Shape::Blur { .. } => String::new(),
+        Shape::Step {
+            x,
+            y,
+            step_number,
+            font_size,
+            stroke_color,
+        } => {
+            let size = font_size.unwrap_or(24.0) as f32;
+            let chain = FontChain::resolve(
+                "sans-serif",
+                FontStyle {
+                    bold: true,
+                    italic: false,
+                },
+            );
+            let text_size = (size * 0.6).floor().max(8.0);
+            let primary = chain.primary();
+            let metrics = primary.font.as_scaled(text::em_scale(primary, text_size));
+            let baseline = *y as f32 + (metrics.ascent() + metrics.descent()) / 2.0;
+            format!(
+                "<circle cx=\"{}\" cy=\"{}\" r=\"{}\"{}/>\n\
+                 <text x=\"{}\" y=\"{}\" text-anchor=\"middle\" font-family=\"sans-serif\" \
+                 font-weight=\"bold\" font-size=\"{}\" fill=\"#ffffff\">{step_number}</text>\n",
+                num(*x),
+                num(*y),
+                num(size as f64 / 2.0),
+                paint("fill", stroke_color.as_deref().unwrap_or("#FF0000")),
+                num(*x),
+                num(baseline as f64),
+                num(text_size as f64),
+            )
+        }
+        Shape::Text {
+            x,
+            y,
+            text,
+            font_size,
+            font_family,
+            bold,
+            italic,
+            stroke_color,
+            width,
+            align,
+            background_color,
+            border_color,
+            border_width,
+            corner_radius,
+            padding,
+        } => {
+            if text.is_empty() {
+                return String::new();
+            }
+            let size = font_size.unwrap_or(20.0) as f32;
+            let family = font_family.as_deref().unwrap_or("sans-serif");
+            let style = FontStyle {
+                bold: bold.unwrap_or(false),
+                italic: italic.unwrap_or(false),
+            };
+            let chain = FontChain::resolve(family, style);
+            let wrap = width.filter(|w| *w > 0.0).map(|w| w as f32);
+            let lines = text::layout(&chain, text, size, wrap);
+            let box_width =
+                wrap.unwrap_or_else(|| lines.iter().map(|l| l.width).fold(0.0, f32::max));
+            let box_height = lines.len() as f32 * size * 1.4;
+            let (x, y) = (*x as f32, *y as f32);
+
+            let mut out = String::new();
+            if background_color.is_some() || border_color.is_some() {
+                let pad = padding.unwrap_or(4.0) as f32;
+                let (w, h) = (box_width + 2.0 * pad, box_height + 2.0 * pad);
+                let r = (corner_radius.unwrap_or(4.0) as f32).clamp(0.0, w.min(h) / 2.0);
+                let border = match border_color {
+                    Some(color) => format!(
+                        "{} stroke-width=\"{}\" stroke-linejoin=\"round\"",
+                        paint("stroke", color),
+                        num(border_width.unwrap_or(1.0)),
+                    ),
+                    None => String::new(),
+                };
+                let _ = writeln!(
+                    out,
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"{}\"{}{border}/>",
+                    num((x - pad) as f64),
+                    num((y - pad) as f64),
+                    num(w as f64),
+                    num(h as f64),
+                    num(r as f64),
+                    paint("fill", background_color.as_deref().unwrap_or("transparent")),
+                );
+            }
+            let mut attrs = format!(
+                " font-family=\"{}\" font-size=\"{}\"{}",
+                escape(family),
+                num(size as f64),
+                paint("fill", stroke_color.as_deref().unwrap_or("#FF0000")),
+            );
+            if style.bold {
+                attrs.push_str(" font-weight=\"bold\"");
+            }
+            if style.italic {
+                attrs.push_str(" font-style=\"italic\"");
+            }
+            out.push_str(&text_lines(
+                &chain,
+                &lines,
+                size,
+                (x, y),
+                *align,
+                box_width,
+                &attrs,
+            ));
+            rotated(
+                rotation,
+                ((x + box_width / 2.0) as f64, (y + box_height / 2.0) as f64),
+                out,
+            )
+        }
+        Shape::Callout {
+            x,
+            y,
+            width,
+            height,
+            tail,
+            text,
+            font_size,
+            font_family,
+            stroke_color,
+            fill_color,
+            text_color,
+            stroke_width,
+            corner_radius,
+        } => {
+            if *width <= 0.0 || *height <= 0.0 {
+                return String::new();
+            }
+            let (x, y, w, h) = (*x as f32, *y as f32, *width as f32, *height as f32);
+            let bubble = render::callout_outline(
+                (x, y, w, h),
+                corner_radius.unwrap_or(8.0) as f32,
+                (tail.x as f32, tail.y as f32),
+            );
+            let stroke = Stroke {
+                width: stroke_width.unwrap_or(2.0) as f32,
+                join: Join::Round,
+                cap: raster::Cap::Butt,
+                dash: Vec::new(),
+            };
+            let mut out = format!(
+                "<polygon points=\"{}\"{}{}/>\n",
+                point_list(&bubble),
+                paint("fill", fill_color.as_deref().unwrap_or("#FFFFFF")),
+                stroke_attrs(stroke_color.as_deref().unwrap_or("#FF0000"), &stroke),
+            );
+            let size = font_size.unwrap_or(16.0) as f32;
+            let family = font_family.as_deref().unwrap_or("sans-serif");
+            let chain = FontChain::resolve(family, FontStyle::default());
+            let inner = (w - 2.0 * CALLOUT_PADDING).max(1.0);
+            let lines = text::layout(&chain, text, size, Some(inner));
+            let attrs = format!(
+                " font-family=\"{}\" font-size=\"{}\"{}",
+                escape(family),
+                num(size as f64),
+                paint("fill", text_color.as_deref().unwrap_or("#000000")),
+            );
+            out.push_str(&text_lines(
+                &chain,
+                &lines,
+                size,
+                (x + CALLOUT_PADDING, y + CALLOUT_PADDING),
+                TextAlign::default(),
+                inner,
+                &attrs,
+            ));
+            out
+        }
+        Shape::Magnifier {
+            x,
+            y,
+            width,
+            height,
+            source,
+            zoom,
+            stroke_color,
+            stroke_width,
+        } => {
+            if *width <= 0.0 || *height <= 0.0 {
+                return String::new();
+            }
+            let (cx, cy) = (x + width / 2.0, y + height / 2.0);
+            let (sx, sy) = source.map_or((cx, cy), |p| (p.x, p.y));
+            let zoom = zoom.unwrap_or(2.0).max(1.0);
+            let lens = format!(
+                "cx=\"{}\" cy=\"{}\" rx=\"{}\" ry=\"{}\"",
+                num(cx),
+                num(cy),
+                num(width / 2.0),
+                num(height / 2.0)
+            );
+            let stroke = render::outline(*stroke_width, &None, Join::Round, raster::Cap::Butt);
+            // The screenshot, scaled about `source` and moved so that it
+            // lands on the lens centre, seen through the lens.
+            format!(
+                "<clipPath id=\"{layer}-lens\"><ellipse {lens}/></clipPath>\n\
+                 <g clip-path=\"url(#{layer}-lens)\">\
+                 <use xlink:href=\"#screenshot\" transform=\"translate({} {}) scale({})\"/></g>\n\
+                 <ellipse {lens} fill=\"none\"{}/>\n",
+                num(cx - sx * zoom),
+                num(cy - sy * zoom),
+                num(zoom),
+                stroke_attrs(stroke_color.as_deref().unwrap_or("#FF0000"), &stroke),
+            )
+        }
+        Shape::Image {
+            x,
+            y,
+            width,
+            height,
+            stamp,
+            ..
+        } => {
+            if *width <= 0.0 || *height <= 0.0 {
+                return String::new();
+            }
+            let centre = (x + width / 2.0, y + height / 2.0);
+            if let Some(stamp) = stamp {
+                let (x, y, w, h) = (*x as f32, *y as f32, *width as f32, *height as f32);
+                let at = |pts: &[Pt]| -> Vec<Pt> {
+                    pts.iter().map(|(u, v)| (x + u * w, y + v * h)).collect()
+                };
+                let mut out = String::new();
+                for part in stamp.parts() {
+                    let _ = match *part {
+                        StampPart::Oval((u, v), r, color) => writeln!(
+                            out,
+                            "<ellipse cx=\"{}\" cy=\"{}\" rx=\"{}\" ry=\"{}\" fill=\"{}\"/>",
+                            num((x + u * w) as f64),
+                            num((y + v * h) as f64),
+                            num((r * w) as f64),
+                            num((r * h) as f64),
+                            hex(color),
+                        ),
+                        StampPart::Polygon(pts, color) => writeln!(
+                            out,
+                            "<polygon points=\"{}\" fill=\"{}\"/>",
+                            point_list(&at(pts)),
+                            hex(color),
+                        ),
+                        StampPart::Line(pts, width, color) => writeln!(
+                            out,
+                            "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" \
+                             stroke-width=\"{}\" stroke-linecap=\"round\" stroke-linejoin=\"round\"/>",
+                            point_list(&at(pts)),
+                            hex(color),
+                            num((width * w.min(h)) as f64),
+                        ),
+                    };
+                }
+                return rotated(rotation, centre, out);
+            }
+            let Some(picture) = picture else {
+                return String::new();
+            };
+            rotated(
+                rotation,
+                centre,
+                format!(
+                    "<image x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" \
+                     preserveAspectRatio=\"none\" xlink:href=\"{}\"/>\n",
+                    num(*x),
+                    num(*y),
+                    num(*width),
+                    num(*height),
+                    png_data_uri(&picture.to_rgba8()),
+                ),
+            )
+        }
+        Shape::Spotlight {
+            x,
+            y,
+            width,
+            height,
+            dim_color,
+            corner_radius,
+        } => {
+            // The image's frame with the box cut out of it.
+            let frame = [
+                (0.0, 0.0),
+                (size.0 as f32, 0.0),
+                (size.0 as f32, size.1 as f32),
+                (0.0, size.1 as f32),
+            ];
+            let hole = raster::rounded_rect(
+                *x as f32,
+                *y as f32,
+                width.max(0.0) as f32,
+                height.max(0.0) as f32,
+                corner_radius.unwrap_or(0.0) as f32,
+            );
+            format!(
+                "<path fill-rule=\"evenodd\" d=\"{} {}\"{}/>\n",
+                path_data(&frame),
+                path_data(&hole),
+                paint("fill", dim_color.as_deref().unwrap_or(SPOTLIGHT_DIM)),
+            )
+        }
+    }
+}
+
+/// One `<text>` element with a `<tspan>` per laid-out line, placed as the
+/// raster renderer places them so wrapping and alignment survive, but
+/// holding the characters themselves so the text stays editable.
+fn text_lines(
+    chain: &FontChain,
+    lines: &[Line],
+    size: f32,
+    (x, y): (f32, f32),
+    align: TextAlign,
+    box_width: f32,
+    attrs: &str,
+) -> String {
+    let primary = chain.primary();
+    let ascent = primary
+        .font
+        .as_scaled(text::em_scale(primary, size))
+        .ascent();
+    let mut out = format!("<text xml:space=\"preserve\"{attrs}>");
+    for (i, line) in lines.iter().enumerate() {
+        if line.text.is_empty() {
+            continue;
+        }
+        let left = x + line.indent(align, box_width);
+        let baseline = y + i as f32 * size * 1.4 + ascent;
+        // Right-to-left lines start from their right edge.
+        let (start, direction) = if line.rtl {
+            (left + line.width, " direction=\"rtl\"")
+        } else {
+            (left, "")
+        };
+        let _ = write!(
+            out,
+            "<tspan x=\"{}\" y=\"{}\"{direction}>{}</tspan>",
+            num(start as f64),
+            num(baseline as f64),
+            escape(&line.text),
+        );
+    }
+    out.push_str("</text>\n");
+    out
+}
+
+/// Wrap `body` in a clockwise turn of `degrees` about `centre`.
+fn rotated(degrees: f64, (cx, cy): (f64, f64), body: String) -> String {
+    if degrees == 0.0 || !degrees.is_finite() || body.is_empty() {
+        return body;
+    }
+    format!(
+        "<g transform=\"rotate({} {} {})\">\n{body}</g>\n",
+        num(degrees),
+        num(cx),
+        num(cy)
+    )
+}
+
+/// ` {attr}="#rrggbb"`, with `{attr}-opacity` for translucent colours;
+/// `none` for transparent or unparseable ones, which the raster renderer
+/// doesn't draw either.
+fn paint(attr: &str, color: &str) -> String {
+    match parse_color(color) {
+        Ok(c) if c[3] == 255 => format!(" {attr}=\"{}\"", hex(c)),
+        Ok(c) if c[3] > 0 => format!(
+            " {attr}=\"{}\" {attr}-opacity=\"{}\"",
+            hex(c),
+            num(c[3] as f64 / 255.0)
+        ),
+        _ => format!(" {attr}=\"none\""),
+    }
+}
+
+fn stroke_attrs(color: &str, stroke: &Stroke) -> String {
+    let mut attrs = format!(
+        "{} stroke-width=\"{}\" stroke-linejoin=\"{}\" stroke-linecap=\"{}\"",
+        paint("stroke", color),
+        num(stroke.width as f64),
+        match stroke.join {
+            Join::Miter => "miter",
+            Join::Round => "round",
+        },
+        match stroke.cap {
+            raster::Cap::Butt => "butt",
+            raster::Cap::Round => "round",
+        },
+    );
+    if !stroke.dash.is_empty() {
+        let dash: Vec<String> = stroke.dash.iter().map(|d| num(*d as f64)).collect();
+        let _ = write!(attrs, " stroke-dasharray=\"{}\"", dash.join(" "));
+    }
+    attrs
+}
+
+fn hex(c: image::Rgba<u8>) -> String {
+    format!("#{:02x}{:02x}{:02x}", c[0], c[1], c[2])
+}
+
+fn point_list(pts: &[Pt]) -> String {
+    pts.iter()
+        .map(|(x, y)| format!("{},{}", num(*x as f64), num(*y as f64)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A closed path through `pts`.
+fn path_data(pts: &[Pt]) -> String {
+    format!("M{}Z", point_list(pts).replace(' ', " L"))
+}
+
+/// `v` to two decimals, without trailing zeros.
+fn num(v: f64) -> String {
+    let s = format!("{v:.2}");
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    match s {
+        "-0" | "" => "0".to_owned(),
+        s => s.to_owned(),
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn png_data_uri(img: &RgbaImage) -> String {
+    let mut png = Vec::new();
+    // Encoding an in-memory RGBA buffer to PNG can't fail.
+    let _ = img.write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png);
+    format!(
+        "data:image/png;base64,{}",
+        base64::engine::general_purpose::STANDARD.encode(png)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotate::parse_annotations;
+    use image::Rgba;
+    use serde_json::json;
+
+    fn export(img: RgbaImage, annotations: serde_json::Value) -> String {
+        let annotations = parse_annotations(annotations).unwrap();
+        render_svg(
+            &DynamicImage::ImageRgba8(img),
+            &annotations,
+            &ImageStore::new(),
+        )
+        .unwrap()
+    }
+
+    /// The PNG of the `n`th embedded image.
+    fn embedded(svg: &str, n: usize) -> RgbaImage {
+        let prefix = "xlink:href=\"data:image/png;base64,";
+        let start = svg.match_indices(prefix).nth(n).unwrap().0 + prefix.len();
+        let end = start + svg[start..].find('"').unwrap();
+        let png = base64::engine::general_purpose::STANDARD
+            .decode(&svg[start..end])
+            .unwrap();
+        image::load_from_memory(&png).unwrap().to_rgba8()
+    }
+
+    #[test]
+    fn annotations_become_layers_of_native_elements() {
+        let svg = export(
+            RgbaImage::new(100, 80),
+            json!([
+                { "id": "a", "type": "rect", "x": 10, "y": 10, "width": 30, "height": 20,
+                  "strokeColor": "#00FF0080", "rotation": 90, "opacity": 0.5 },
+                { "id": "b", "type": "text", "x": 5, "y": 50, "text": "a < b & c",
+                  "strokeColor": "#0000FF" },
+                { "id": "c", "type": "spotlight", "x": 0, "y": 0, "width": 10, "height": 10 },
+            ]),
+        );
+        assert!(svg.starts_with("<?xml"));
+        assert!(svg.contains("viewBox=\"0 0 100 80\""));
+        assert_eq!(svg.matches("inkscape:groupmode=\"layer\"").count(), 4);
+        assert!(svg.contains("inkscape:label=\"rect 1\" data-annotation-id=\"a\" opacity=\"0.5\""));
+        assert!(svg.contains("<g transform=\"rotate(90 25 20)\">"));
+        assert!(svg.contains(
+            "<rect x=\"10\" y=\"10\" width=\"30\" height=\"20\" fill=\"none\" \
+             stroke=\"#00ff00\" stroke-opacity=\"0.5\" stroke-width=\"2\""
+        ));
+        // Text stays text, escaped.
+        assert!(svg.contains(">a &lt; b &amp; c</tspan>"));
+        assert!(svg.contains("fill=\"#0000ff\""));
+        assert!(svg.contains("fill-rule=\"evenodd\" d=\"M0,0 L100,0 L100,80 L0,80Z"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+
+    #[test]
+    fn blurred_pixels_never_reach_the_file() {
+        let secret =
+            RgbaImage::from_fn(60, 40, |x, y| Rgba([(x * 4) as u8, (y * 6) as u8, 99, 255]));
+        let svg = export(
+            secret.clone(),
+            json!([{ "id": "b", "type": "blur", "x": 10, "y": 5, "width": 20, "height": 10,
+                     "redactStyle": "solid", "opacity": 0.2 }]),
+        );
+        let screenshot = embedded(&svg, 0);
+        let patch = embedded(&svg, 1);
+        assert_eq!(patch.dimensions(), (20, 10));
+        assert!(patch.pixels().all(|p| *p == Rgba([0, 0, 0, 255])));
+        for (x, y, p) in screenshot.enumerate_pixels() {
+            let inside = (10..30).contains(&x) && (5..15).contains(&y);
+            let expected = if inside {
+                Rgba([0, 0, 0, 255])
+            } else {
+                *secret.get_pixel(x, y)
+            };
+            assert_eq!(*p, expected, "at ({x}, {y})");
+        }
+        // Redactions are never faded.
+        assert!(!svg.contains("opacity="));
+    }
+
+    #[test]
+    fn numbers_are_written_compactly() {
+        assert_eq!(num(2.0), "2");
+        assert_eq!(num(0.5), "0.5");
+        assert_eq!(num(1.005_1), "1.01");
+        assert_eq!(num(-0.001), "0");
+    }
+}
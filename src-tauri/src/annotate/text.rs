@@ -0,0 +1,446 @@
+/// Line layout for text annotations.
+///
+/// Each paragraph (`\n`-separated) is split into runs of one face,
+/// script and bidi level, shaped run by run with rustybuzz, broken into
+/// lines no wider than the annotation's width, and each line reordered
+/// for display by the Unicode Bidirectional Algorithm.
+use super::fonts::{Face, FontChain};
+use super::TextAlign;
+use ab_glyph::{Font as _, GlyphId, PxScale, ScaleFont as _};
+use std::ops::Range;
+use unicode_bidi::{BidiClass, ParagraphBidiInfo};
+use unicode_script::{Script, UnicodeScript};
+
+/// A glyph placed on a line: `x` from the line's left edge, `y` down
+/// from its baseline.
+pub struct PlacedGlyph<'a> {
+    pub face: &'a Face,
+    pub id: GlyphId,
+    pub scale: PxScale,
+    pub x: f32,
+    pub y: f32,
+}
+
+/// One displayed line, glyphs in visual order.
+pub struct Line<'a> {
+    pub glyphs: Vec<PlacedGlyph<'a>>,
+    /// Advance width, not counting trailing spaces.
+    pub width: f32,
+    /// Whether its paragraph runs right to left.
+    pub rtl: bool,
+    /// The characters it shows, in logical order.
+    pub text: String,
+}
+
+impl Line<'_> {
+    /// Offset of the line's left edge in a box `box_width` wide.
+    pub fn indent(&self, align: TextAlign, box_width: f32) -> f32 {
+        let slack = box_width - self.width;
+        match (align, self.rtl) {
+            (TextAlign::Left, _) | (TextAlign::Start, false) | (TextAlign::End, true) => 0.0,
+            (TextAlign::Right, _) | (TextAlign::Start, true) | (TextAlign::End, false) => slack,
+            (TextAlign::Center, _) => slack / 2.0,
+        }
+    }
+}
+
+/// Scale at which `face` draws text `size` px to the em, as CSS sizes it.
+pub fn em_scale(face: &Face, size: f32) -> PxScale {
+    face.font
+        .pt_to_px_scale(size)
+        .unwrap_or(PxScale::from(size))
+}
+
+/// Extra advance and stroke offset for faces emboldened synthetically.
+pub fn synthetic_bold(face: &Face, scale: PxScale) -> f32 {
+    if face.bold {
+        0.0
+    } else {
+        (scale.y / 30.0).max(0.5)
+    }
+}
+
+/// Lay out `text` at `size` px, wrapping lines at `max_width` if given.
+pub fn layout<'a>(
+    chain: &'a FontChain,
+    text: &str,
+    size: f32,
+    max_width: Option<f32>,
+) -> Vec<Line<'a>> {
+    text.split('\n')
+        .flat_map(|para| {
+            let para = para.strip_suffix('\r').unwrap_or(para);
+            layout_paragraph(chain, para, size, max_width)
+        })
+        .collect()
+}
+
+/// A shaped glyph in pixels, still in logical order.
+struct Glyph<'a> {
+    face: &'a Face,
+    id: GlyphId,
+    scale: PxScale,
+    advance: f32,
+    offset: (f32, f32),
+    cluster: usize,
+}
+
+fn layout_paragraph<'a>(
+    chain: &'a FontChain,
+    para: &str,
+    size: f32,
+    max_width: Option<f32>,
+) -> Vec<Line<'a>> {
+    let chars: Vec<char> = para.chars().collect();
+    let bidi = ParagraphBidiInfo::new(para, None);
+    let rtl = bidi.paragraph_level.is_rtl();
+    if chars.is_empty() {
+        return vec![Line {
+            glyphs: Vec::new(),
+            width: 0.0,
+            rtl,
+            text: String::new(),
+        }];
+    }
+    // Byte offset of each character, and of the paragraph's end.
+    let offsets: Vec<usize> = para
+        .char_indices()
+        .map(|(at, _)| at)
+        .chain([para.len()])
+        .collect();
+    let levels: Vec<u8> = offsets[..chars.len()]
+        .iter()
+        .map(|&at| bidi.levels[at].number())
+        .collect();
+    let glyphs = shape_paragraph(chain, &chars, &levels, size);
+
+    // Advance per character, for measuring candidate lines; a ligature's
+    // whole advance sits on its first character.
+    let mut advances = vec![0.0; chars.len()];
+    let mut cluster_start = vec![false; chars.len()];
+    for g in &glyphs {
+        advances[g.cluster] += g.advance;
+        cluster_start[g.cluster] = true;
+    }
+    let breaks = match max_width {
+        Some(max) => break_lines(&chars, &advances, &cluster_start, max),
+        None => std::iter::once(0..chars.len()).collect(),
+    };
+
+    breaks
+        .into_iter()
+        .map(|range| {
+            // Trailing spaces neither draw nor count towards the width.
+            let mut end = range.end;
+            while end > range.start && chars[end - 1].is_whitespace() {
+                end -= 1;
+            }
+            let line: Vec<&Glyph> = glyphs
+                .iter()
+                .filter(|g| (range.start..end).contains(&g.cluster))
+                .collect();
+            // Levels with the line rules applied, per character of the line.
+            let line_levels = bidi.reordered_levels_per_char(offsets[range.start]..offsets[end]);
+            let glyph_levels: Vec<_> = line
+                .iter()
+                .map(|g| line_levels[g.cluster - range.start])
+                .collect();
+            let mut pen = 0.0;
+            let placed = ParagraphBidiInfo::reorder_visual(&glyph_levels)
+                .into_iter()
+                .map(|i| {
+                    let g = line[i];
+                    let placed = PlacedGlyph {
+                        face: g.face,
+                        id: g.id,
+                        scale: g.scale,
+                        x: pen + g.offset.0,
+                        y: g.offset.1,
+                    };
+                    pen += g.advance;
+                    placed
+                })
+                .collect();
+            Line {
+                glyphs: placed,
+                width: pen,
+                rtl,
+                text: chars[range.start..end].iter().collect(),
+            }
+        })
+        .collect()
+}
+
+/// Split the paragraph into runs and shape each one.
+fn shape_paragraph<'a>(
+    chain: &'a FontChain,
+    chars: &[char],
+    levels: &[u8],
+    size: f32,
+) -> Vec<Glyph<'a>> {
+    // Marks and joiners stay in the face of the character they follow;
+    // characters of no particular script belong to their neighbours'.
+    let mut faces: Vec<&Face> = Vec::with_capacity(chars.len());
+    for (i, &c) in chars.iter().enumerate() {
+        let joins_previous = is_mark(c) || matches!(c, '\u{200C}' | '\u{200D}');
+        let face = match faces.last() {
+            Some(&prev) if i > 0 && joins_previous => prev,
+            _ => chain.face_for(c),
+        };
+        faces.push(face);
+    }
+    // Common and inherited characters take the script of the text they
+    // sit in, so punctuation doesn't split a run.
+    let mut scripts: Vec<Option<Script>> = chars
+        .iter()
+        .map(|c| Some(c.script()).filter(|s| !matches!(s, Script::Common | Script::Inherited)))
+        .collect();
+    let first = scripts.iter().flatten().next().copied();
+    let mut current = first;
+    for script in scripts.iter_mut() {
+        if script.is_none() {
+            *script = current;
+        }
+        current = *script;
+    }
+
+    let mut glyphs = Vec::new();
+    let mut start = 0;
+    while start < chars.len() {
+        let same_run = |i: usize| {
+            std::ptr::eq(faces[i], faces[start])
+                && scripts[i] == scripts[start]
+                && levels[i] == levels[start]
+        };
+        let end = (start..chars.len())
+            .find(|&i| !same_run(i))
+            .unwrap_or(chars.len());
+        let rtl = levels[start] % 2 == 1;
+        shape_run(&mut glyphs, faces[start], chars, start..end, rtl, size);
+        start = end;
+    }
+    glyphs
+}
+
+/// Shape one run in `face` with the font's OpenType features, appending
+/// its glyphs in logical order: a right-to-left run comes out of the
+/// shaper in display order and is reversed back, to be reversed again
+/// when its line is reordered.
+fn shape_run<'a>(
+    glyphs: &mut Vec<Glyph<'a>>,
+    face: &'a Face,
+    chars: &[char],
+    run: Range<usize>,
+    rtl: bool,
+    size: f32,
+) {
+    let Some(font) = rustybuzz::Face::from_slice(face.data, face.index) else {
+        return;
+    };
+    let mut buffer = rustybuzz::UnicodeBuffer::new();
+    for i in run {
+        buffer.add(chars[i], i as u32);
+    }
+    buffer.set_direction(if rtl {
+        rustybuzz::Direction::RightToLeft
+    } else {
+        rustybuzz::Direction::LeftToRight
+    });
+    buffer.guess_segment_properties();
+    let shaped = rustybuzz::shape(&font, &[], buffer);
+
+    let scale = em_scale(face, size);
+    let scaled = face.font.as_scaled(scale);
+    let (sx, sy) = (scaled.h_scale_factor(), scaled.v_scale_factor());
+    let embolden = synthetic_bold(face, scale);
+    let mut run_glyphs: Vec<Glyph> = shaped
+        .glyph_infos()
+        .iter()
+        .zip(shaped.glyph_positions())
+        .map(|(info, pos)| {
+            let advance = pos.x_advance as f32 * sx;
+            Glyph {
+                face,
+                id: GlyphId(info.glyph_id as u16),
+                scale,
+                advance: if advance > 0.0 {
+                    advance + embolden
+                } else {
+                    advance
+                },
+                offset: (pos.x_offset as f32 * sx, -pos.y_offset as f32 * sy),
+                cluster: info.cluster as usize,
+            }
+        })
+        .collect();
+    if rtl {
+        run_glyphs.reverse();
+    }
+    glyphs.extend(run_glyphs);
+}
+
+/// Non-spacing marks, which draw on the character before them.
+fn is_mark(c: char) -> bool {
+    unicode_bidi::bidi_class(c) == BidiClass::NSM
+}
+
+/// Greedy line breaking: the longest run of words that fits in `max`,
+/// breaking inside a word only when the word alone doesn't fit.
+fn break_lines(
+    chars: &[char],
+    advances: &[f32],
+    cluster_start: &[bool],
+    max: f32,
+) -> Vec<Range<usize>> {
+    let width = |range: Range<usize>| {
+        let mut end = range.end;
+        while end > range.start && chars[end - 1].is_whitespace() {
+            end -= 1;
+        }
+        advances[range.start..end].iter().sum::<f32>()
+    };
+    let opportunities: Vec<usize> = (1..chars.len())
+        .filter(|&i| cluster_start[i] && can_break(chars[i - 1], chars[i]))
+        .chain([chars.len()])
+        .collect();
+
+    let mut lines = Vec::new();
+    let mut start = 0;
+    while start < chars.len() {
+        let fitting = opportunities
+            .iter()
+            .copied()
+            .filter(|&end| end > start)
+            .take_while(|&end| width(start..end) <= max)
+            .last();
+        let end = fitting.unwrap_or_else(|| {
+            // One word wider than the box: break between its clusters.
+            let mut ends =
+                (start + 1..=chars.len()).filter(|&i| i == chars.len() || cluster_start[i]);
+            let first = ends.next().unwrap_or(chars.len());
+            ends.take_while(|&end| width(start..end) <= max)
+                .last()
+                .unwrap_or(first)
+        });
+        lines.push(start..end);
+        start = end;
+    }
+    lines
+}
+
+/// Whether a line may break between `before` and `after`: after spaces
+/// and hyphens, and around ideographs, except before closing or after
+/// opening punctuation.
+fn can_break(before: char, after: char) -> bool {
+    if after.is_whitespace() || is_mark(after) {
+        return false;
+    }
+    if before.is_whitespace() {
+        return true;
+    }
+    if matches!(before, '-' | '\u{2010}' | '\u{2013}' | '/') {
+        return after.is_alphanumeric();
+    }
+    let closing = "、。，．！？：；）」』】〉》〕ー々…!?,.:;)]}";
+    let opening = "（「『【〈《〔([{";
+    (is_cjk(before) || is_cjk(after)) && !closing.contains(after) && !opening.contains(before)
+}
+
+/// Scripts written without spaces, where any character boundary breaks.
+fn is_cjk(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x2E80..=0x9FFF | 0xAC00..=0xD7AF | 0xF900..=0xFAFF | 0xFF00..=0xFFEF | 0x20000..=0x2FFFF
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fonts::FontStyle;
+    use super::*;
+
+    fn chain() -> FontChain {
+        FontChain::resolve("No Such Family", FontStyle::default())
+    }
+
+    fn widths(lines: &[Line]) -> Vec<f32> {
+        lines.iter().map(|l| l.width).collect()
+    }
+
+    #[test]
+    fn wraps_at_spaces_within_the_width() {
+        let chain = chain();
+        let one = layout(&chain, "aaaa bbbb cccc", 20.0, None);
+        assert_eq!(one.len(), 1);
+        let word = layout(&chain, "aaaa", 20.0, None)[0].width;
+
+        let lines = layout(&chain, "aaaa bbbb cccc", 20.0, Some(word * 2.5));
+        assert_eq!(lines.len(), 2);
+        assert!(widths(&lines).iter().all(|w| *w <= word * 2.5));
+        // The space the line broke at isn't part of either line.
+        assert!(lines[1].width < word * 1.5);
+    }
+
+    #[test]
+    fn breaks_overlong_words_and_keeps_hard_breaks() {
+        let chain = chain();
+        let lines = layout(&chain, "abcdefghij", 20.0, Some(30.0));
+        assert!(lines.len() > 2);
+        assert!(lines.iter().all(|l| l.width <= 30.0 || l.glyphs.len() == 1));
+        assert_eq!(layout(&chain, "a\n\nb", 20.0, None).len(), 3);
+    }
+
+    #[test]
+    fn can_break_rules() {
+        assert!(can_break(' ', 'a'));
+        assert!(!can_break('a', ' '));
+        assert!(!can_break('a', 'b'));
+        assert!(can_break('-', 'b'));
+        assert!(can_break('中', '文'));
+        assert!(!can_break('中', '。'));
+        assert!(!can_break('「', '中'));
+    }
+
+    #[test]
+    fn alignment_follows_paragraph_direction() {
+        let line = |rtl| Line {
+            glyphs: Vec::new(),
+            width: 40.0,
+            rtl,
+            text: String::new(),
+        };
+        assert_eq!(line(false).indent(TextAlign::Start, 100.0), 0.0);
+        assert_eq!(line(true).indent(TextAlign::Start, 100.0), 60.0);
+        assert_eq!(line(true).indent(TextAlign::End, 100.0), 0.0);
+        assert_eq!(line(false).indent(TextAlign::Center, 100.0), 30.0);
+        assert_eq!(line(true).indent(TextAlign::Left, 100.0), 0.0);
+    }
+
+    #[test]
+    fn rtl_lines_are_laid_out_right_to_left() {
+        let chain = chain();
+        // Latin inside a right-to-left paragraph: the Hebrew, first in
+        // logical order, ends up rightmost.
+        let lines = layout(&chain, "\u{05D0} ab", 20.0, None);
+        assert!(lines[0].rtl);
+        let xs: Vec<f32> = lines[0].glyphs.iter().map(|g| g.x).collect();
+        assert!(xs.windows(2).all(|w| w[0] <= w[1]));
+        let a = chain.face_for('a').font.glyph_id('a');
+        // Visual order: "ab", space, alef.
+        assert_eq!(lines[0].glyphs[0].id, a);
+    }
+
+    #[test]
+    fn numbers_and_mirrored_brackets_in_rtl_text() {
+        let chain = chain();
+        let id = |c| chain.face_for(c).font.glyph_id(c);
+        // Digits keep their order: "123 גבא" on screen.
+        let lines = layout(&chain, "\u{05D0}\u{05D1}\u{05D2} 123", 20.0, None);
+        let ids: Vec<GlyphId> = lines[0].glyphs.iter().map(|g| g.id).collect();
+        assert_eq!(ids[..3], [id('1'), id('2'), id('3')]);
+        assert_eq!(ids[4..], [id('\u{05D2}'), id('\u{05D1}'), id('\u{05D0}')]);
+        // The closing bracket, mirrored, opens the line on the left.
+        let lines = layout(&chain, "\u{05D0} (\u{05D1})", 20.0, None);
+        assert_eq!(lines[0].glyphs[0].id, id('('));
+    }
+}
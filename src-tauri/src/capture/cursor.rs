@@ -0,0 +1,174 @@
+/// Mouse pointer compositing for `CaptureSettings::include_mouse_cursor`.
+///
+/// Backends fetch the current cursor bitmap, hotspot and position in desktop
+/// coordinates, then blend it into the captured image with
+/// [`composite_cursor`]. The resulting pointer position (in image
+/// coordinates) is recorded in `CaptureMetadata::cursor`.
+use image::{Rgba, RgbaImage};
+
+pub struct CursorImage {
+    /// Straight-alpha cursor bitmap.
+    pub image: RgbaImage,
+    /// Offset of the pointer tip inside `image`.
+    pub hotspot: (u32, u32),
+    /// Pointer tip in desktop coordinates.
+    pub position: (i32, i32),
+}
+
+/// Blend `cursor` into `target`, where `origin` is the desktop coordinate of
+/// the target's top-left pixel.
+///
+/// Returns the pointer tip in image coordinates, or `None` when the pointer
+/// lies outside the captured area (nothing is drawn in that case).
+pub fn composite_cursor(
+    target: &mut RgbaImage,
+    cursor: &CursorImage,
+    origin: (i32, i32),
+) -> Option<(i32, i32)> {
+    let tip_x = cursor.position.0 - origin.0;
+    let tip_y = cursor.position.1 - origin.1;
+    if tip_x < 0 || tip_y < 0 || tip_x >= target.width() as i32 || tip_y >= target.height() as i32 {
+        return None;
+    }
+
+    let left = tip_x - cursor.hotspot.0 as i32;
+    let top = tip_y - cursor.hotspot.1 as i32;
+    for (cx, cy, src) in cursor.image.enumerate_pixels() {
+        let px = left + cx as i32;
+        let py = top + cy as i32;
+        if px < 0 || py < 0 || px >= target.width() as i32 || py >= target.height() as i32 {
+            continue;
+        }
+        blend_over(target.get_pixel_mut(px as u32, py as u32), *src);
+    }
+    Some((tip_x, tip_y))
+}
+
+/// Source-over blend of a straight-alpha pixel onto an opaque-ish base.
+fn blend_over(dst: &mut Rgba<u8>, src: Rgba<u8>) {
+    let a = src[3] as u32;
+    if a == 0 {
+        return;
+    }
+    for c in 0..3 {
+        dst[c] = ((src[c] as u32 * a + dst[c] as u32 * (255 - a)) / 255) as u8;
+    }
+    dst[3] = (a + dst[3] as u32 * (255 - a) / 255) as u8;
+}
+
+/// Convert XFixes' premultiplied ARGB32 pixels to a straight-alpha RGBA image.
+pub fn from_premultiplied_argb(width: u32, height: u32, pixels: &[u32]) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| {
+        let argb = pixels
+            .get((y * width + x) as usize)
+            .copied()
+            .unwrap_or_default();
+        let a = (argb >> 24) & 0xff;
+        if a == 0 {
+            return Rgba([0, 0, 0, 0]);
+        }
+        let unmul = |c: u32| ((c * 255 + a / 2) / a).min(255) as u8;
+        Rgba([
+            unmul((argb >> 16) & 0xff),
+            unmul((argb >> 8) & 0xff),
+            unmul(argb & 0xff),
+            a as u8,
+        ])
+    })
+}
+
+/// Fetch the current pointer from the X server via XFixes.
+///
+/// Returns `Ok(None)` when there is no X display (e.g. a pure Wayland
+/// session without XWayland).
+#[cfg(target_os = "linux")]
+pub fn fetch_x11_cursor() -> anyhow::Result<Option<CursorImage>> {
+    use x11rb::protocol::xfixes::ConnectionExt as _;
+
+    if std::env::var_os("DISPLAY").is_none() {
+        return Ok(None);
+    }
+    let (conn, _screen) = x11rb::connect(None)?;
+    // XFixes requires version negotiation before any other request.
+    conn.xfixes_query_version(4, 0)?.reply()?;
+    let reply = conn.xfixes_get_cursor_image()?.reply()?;
+
+    Ok(Some(CursorImage {
+        image: from_premultiplied_argb(
+            reply.width as u32,
+            reply.height as u32,
+            &reply.cursor_image,
+        ),
+        hotspot: (reply.xhot as u32, reply.yhot as u32),
+        position: (reply.x as i32, reply.y as i32),
+    }))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn fetch_x11_cursor() -> anyhow::Result<Option<CursorImage>> {
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid_cursor(position: (i32, i32), hotspot: (u32, u32)) -> CursorImage {
+        CursorImage {
+            image: RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255])),
+            hotspot,
+            position,
+        }
+    }
+
+    #[test]
+    fn cursor_is_drawn_at_hotspot_adjusted_position() {
+        let mut img = RgbaImage::from_pixel(20, 20, Rgba([0, 0, 0, 255]));
+        let cursor = solid_cursor((15, 15), (1, 1));
+        let pos = composite_cursor(&mut img, &cursor, (5, 5));
+        assert_eq!(pos, Some((10, 10)));
+        assert_eq!(*img.get_pixel(9, 9), Rgba([255, 0, 0, 255]));
+        assert_eq!(*img.get_pixel(12, 12), Rgba([255, 0, 0, 255]));
+        assert_eq!(*img.get_pixel(8, 8), Rgba([0, 0, 0, 255]));
+        assert_eq!(*img.get_pixel(13, 13), Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn cursor_outside_capture_is_skipped() {
+        let mut img = RgbaImage::from_pixel(10, 10, Rgba([0, 0, 0, 255]));
+        let before = img.clone();
+        let cursor = solid_cursor((50, 50), (0, 0));
+        assert_eq!(composite_cursor(&mut img, &cursor, (0, 0)), None);
+        assert_eq!(img, before);
+    }
+
+    #[test]
+    fn cursor_clipped_at_image_edge() {
+        let mut img = RgbaImage::from_pixel(10, 10, Rgba([0, 0, 0, 255]));
+        let cursor = solid_cursor((9, 9), (0, 0));
+        assert_eq!(composite_cursor(&mut img, &cursor, (0, 0)), Some((9, 9)));
+        assert_eq!(*img.get_pixel(9, 9), Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn translucent_cursor_pixels_are_blended() {
+        let mut img = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, 255]));
+        let cursor = CursorImage {
+            image: RgbaImage::from_pixel(1, 1, Rgba([255, 255, 255, 128])),
+            hotspot: (0, 0),
+            position: (1, 1),
+        };
+        composite_cursor(&mut img, &cursor, (0, 0));
+        let px = img.get_pixel(1, 1);
+        assert!(px[0] > 100 && px[0] < 160, "expected ~50% grey, got {px:?}");
+        assert_eq!(px[3], 255);
+    }
+
+    #[test]
+    fn premultiplied_argb_is_unpremultiplied() {
+        // 50% alpha white, premultiplied → 0x80808080.
+        let img = from_premultiplied_argb(2, 1, &[0x8080_8080, 0x0000_0000]);
+        assert_eq!(*img.get_pixel(0, 0), Rgba([255, 255, 255, 128]));
+        assert_eq!(*img.get_pixel(1, 0), Rgba([0, 0, 0, 0]));
+    }
+}
@@ -0,0 +1,466 @@
+/// Persistent screenshot library.
+///
+/// Every image put in the [`ImageStore`](super::ImageStore) is also written
+/// here as `images/<id>.png`, with its metadata appended to `index.jsonl`, so
+/// ids stay valid across restarts. Images are only read back when asked for.
+///
+/// Writes happen on a background thread in submission order; metadata is
+/// available immediately. The index is an append-only log of add/remove
+/// records, compacted each time the library is opened.
+use super::{CaptureMetadata, ScreenshotInfo};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+const INDEX_FILE: &str = "index.jsonl";
+const IMAGES_DIR: &str = "images";
+
+/// How long screenshots are kept. `None` means no limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Retention {
+    pub max_age: Option<chrono::Duration>,
+    pub max_count: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum IndexRecord {
+    Add {
+        id: Uuid,
+        metadata: Box<CaptureMetadata>,
+    },
+    Remove {
+        id: Uuid,
+    },
+}
+
+enum WriteOp {
+    Save(Uuid, Arc<DynamicImage>, Box<CaptureMetadata>),
+    Delete(Uuid),
+    Flush(mpsc::SyncSender<()>),
+}
+
+pub struct Library {
+    dir: PathBuf,
+    entries: Mutex<HashMap<Uuid, CaptureMetadata>>,
+    retention: Mutex<Retention>,
+    writer: Mutex<Sender<WriteOp>>,
+}
+
+impl Library {
+    /// Open (or create) the library in `dir`, dropping index entries whose
+    /// image file is missing and applying `retention`.
+    pub fn open(dir: impl Into<PathBuf>, retention: Retention) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(dir.join(IMAGES_DIR))
+            .with_context(|| format!("Failed to create library at {}", dir.display()))?;
+
+        let mut entries = read_index(&dir.join(INDEX_FILE))?;
+        entries.retain(|id, _| image_path(&dir, id).exists());
+        write_index(&dir, &entries)?;
+        remove_orphans(&dir, &entries);
+
+        let (tx, rx) = mpsc::channel();
+        let writer_dir = dir.clone();
+        std::thread::Builder::new()
+            .name("fotos-library".into())
+            .spawn(move || {
+                for op in rx {
+                    if let Err(e) = apply(&writer_dir, op) {
+                        tracing::error!("library: {e:#}");
+                    }
+                }
+            })
+            .context("Failed to start library writer")?;
+
+        tracing::info!(
+            "library: {} screenshots in {}",
+            entries.len(),
+            dir.display()
+        );
+        let library = Self {
+            dir,
+            entries: Mutex::new(entries),
+            retention: Mutex::new(Retention::default()),
+            writer: Mutex::new(tx),
+        };
+        library.set_retention(retention);
+        Ok(library)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Persist `image` under `id`. Returns ids dropped by the retention
+    /// policy as a result.
+    pub fn save(&self, id: Uuid, image: Arc<DynamicImage>, metadata: CaptureMetadata) -> Vec<Uuid> {
+        self.lock_entries().insert(id, metadata.clone());
+        self.send(WriteOp::Save(id, image, Box::new(metadata)));
+        self.enforce_retention(Utc::now())
+    }
+
+    /// Read a persisted image from disk.
+    pub fn load(&self, id: &Uuid) -> Result<Option<DynamicImage>> {
+        if !self.contains(id) {
+            return Ok(None);
+        }
+        let path = image_path(&self.dir, id);
+        if !path.exists() {
+            // Saved moments ago and still queued for writing.
+            self.flush();
+        }
+        let image =
+            image::open(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        Ok(Some(image))
+    }
+
+    pub fn contains(&self, id: &Uuid) -> bool {
+        self.lock_entries().contains_key(id)
+    }
+
+    pub fn metadata(&self, id: &Uuid) -> Option<CaptureMetadata> {
+        self.lock_entries().get(id).cloned()
+    }
+
+    /// Every persisted screenshot, newest first.
+    pub fn list(&self) -> Vec<ScreenshotInfo> {
+        let mut all: Vec<ScreenshotInfo> = self
+            .lock_entries()
+            .iter()
+            .map(|(id, metadata)| ScreenshotInfo {
+                id: *id,
+                metadata: metadata.clone(),
+            })
+            .collect();
+        all.sort_by_key(|info| std::cmp::Reverse(info.metadata.timestamp));
+        all
+    }
+
+    /// Delete a screenshot. Returns `false` if it was not in the library.
+    pub fn remove(&self, id: &Uuid) -> bool {
+        let removed = self.lock_entries().remove(id).is_some();
+        if removed {
+            self.send(WriteOp::Delete(*id));
+        }
+        removed
+    }
+
+    /// Replace the retention policy and apply it. Returns the dropped ids.
+    pub fn set_retention(&self, retention: Retention) -> Vec<Uuid> {
+        *self.retention.lock().unwrap_or_else(|e| e.into_inner()) = retention;
+        self.enforce_retention(Utc::now())
+    }
+
+    /// Block until every queued write has reached disk.
+    pub fn flush(&self) {
+        let (tx, rx) = mpsc::sync_channel(1);
+        self.send(WriteOp::Flush(tx));
+        let _ = rx.recv();
+    }
+
+    fn enforce_retention(&self, now: DateTime<Utc>) -> Vec<Uuid> {
+        let retention = *self.retention.lock().unwrap_or_else(|e| e.into_inner());
+        let expired = {
+            let entries = self.lock_entries();
+            expired_ids(&entries, retention, now)
+        };
+        for id in &expired {
+            self.remove(id);
+        }
+        if !expired.is_empty() {
+            tracing::info!("library: retention removed {} screenshots", expired.len());
+        }
+        expired
+    }
+
+    fn send(&self, op: WriteOp) {
+        let writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        if writer.send(op).is_err() {
+            tracing::error!("library: writer thread has stopped");
+        }
+    }
+
+    fn lock_entries(&self) -> std::sync::MutexGuard<'_, HashMap<Uuid, CaptureMetadata>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Ids that fall outside `retention`: older than the age limit, or beyond
+/// the newest `max_count`.
+fn expired_ids(
+    entries: &HashMap<Uuid, CaptureMetadata>,
+    retention: Retention,
+    now: DateTime<Utc>,
+) -> Vec<Uuid> {
+    let mut by_age: Vec<(&Uuid, DateTime<Utc>)> =
+        entries.iter().map(|(id, m)| (id, m.timestamp)).collect();
+    by_age.sort_by_key(|(_, ts)| std::cmp::Reverse(*ts));
+    by_age
+        .into_iter()
+        .enumerate()
+        .filter(|(rank, (_, ts))| {
+            retention.max_count.is_some_and(|max| *rank >= max)
+                || retention.max_age.is_some_and(|age| now - *ts > age)
+        })
+        .map(|(_, (id, _))| *id)
+        .collect()
+}
+
+fn image_path(dir: &Path, id: &Uuid) -> PathBuf {
+    dir.join(IMAGES_DIR).join(format!("{id}.png"))
+}
+
+fn apply(dir: &Path, op: WriteOp) -> Result<()> {
+    match op {
+        WriteOp::Save(id, image, metadata) => {
+            let path = image_path(dir, &id);
+            let tmp = path.with_extension("png.tmp");
+            image
+                .save_with_format(&tmp, image::ImageFormat::Png)
+                .with_context(|| format!("Failed to write {}", tmp.display()))?;
+            fs::rename(&tmp, &path)?;
+            append_record(dir, &IndexRecord::Add { id, metadata })
+        }
+        WriteOp::Delete(id) => {
+            let path = image_path(dir, &id);
+            if let Err(e) = fs::remove_file(&path) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    return Err(e).with_context(|| format!("Failed to delete {}", path.display()));
+                }
+            }
+            append_record(dir, &IndexRecord::Remove { id })
+        }
+        WriteOp::Flush(done) => {
+            let _ = done.send(());
+            Ok(())
+        }
+    }
+}
+
+fn append_record(dir: &Path, record: &IndexRecord) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(INDEX_FILE))?;
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    file.write_all(&line)?;
+    Ok(())
+}
+
+/// Replay the index log. Unreadable lines (e.g. a write cut short by a
+/// crash) are skipped.
+fn read_index(path: &Path) -> Result<HashMap<Uuid, CaptureMetadata>> {
+    let mut entries = HashMap::new();
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(entries),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    for (n, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(IndexRecord::Add { id, metadata }) => {
+                entries.insert(id, *metadata);
+            }
+            Ok(IndexRecord::Remove { id }) => {
+                entries.remove(&id);
+            }
+            Err(e) => tracing::warn!("library: skipping index line {}: {e}", n + 1),
+        }
+    }
+    Ok(entries)
+}
+
+/// Rewrite the index with one add record per entry.
+fn write_index(dir: &Path, entries: &HashMap<Uuid, CaptureMetadata>) -> Result<()> {
+    let tmp = dir.join(format!("{INDEX_FILE}.tmp"));
+    {
+        let mut out = BufWriter::new(File::create(&tmp)?);
+        for (id, metadata) in entries {
+            let record = IndexRecord::Add {
+                id: *id,
+                metadata: Box::new(metadata.clone()),
+            };
+            serde_json::to_writer(&mut out, &record)?;
+            out.write_all(b"\n")?;
+        }
+        out.flush()?;
+    }
+    fs::rename(&tmp, dir.join(INDEX_FILE))?;
+    Ok(())
+}
+
+/// Delete image files the index no longer references (e.g. left behind by a
+/// crash between writing the image and its index record).
+fn remove_orphans(dir: &Path, entries: &HashMap<Uuid, CaptureMetadata>) {
+    let Ok(files) = fs::read_dir(dir.join(IMAGES_DIR)) else {
+        return;
+    };
+    for file in files.flatten() {
+        let path = file.path();
+        let known = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(|s| Uuid::parse_str(s).ok())
+            .is_some_and(|id| {
+                entries.contains_key(&id) && path.extension() == Some("png".as_ref())
+            });
+        if !known {
+            let _ = fs::remove_file(&path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::CaptureMode;
+    use image::{Rgba, RgbaImage};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fotos-library-{name}-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn image(v: u8) -> Arc<DynamicImage> {
+        Arc::new(DynamicImage::ImageRgba8(RgbaImage::from_pixel(
+            6,
+            4,
+            Rgba([v, v, v, 255]),
+        )))
+    }
+
+    fn metadata_at(ts: DateTime<Utc>) -> CaptureMetadata {
+        CaptureMetadata {
+            timestamp: ts,
+            ..CaptureMetadata::new(CaptureMode::Fullscreen, (6, 4))
+        }
+    }
+
+    #[test]
+    fn survives_reopen_and_loads_on_demand() {
+        let dir = temp_dir("reopen");
+        let id = Uuid::new_v4();
+        {
+            let lib = Library::open(&dir, Retention::default()).unwrap();
+            lib.save(
+                id,
+                image(42),
+                CaptureMetadata {
+                    window_title: Some("Terminal".into()),
+                    ..metadata_at(Utc::now())
+                },
+            );
+            lib.flush();
+        }
+        let lib = Library::open(&dir, Retention::default()).unwrap();
+        assert_eq!(
+            lib.metadata(&id).unwrap().window_title.as_deref(),
+            Some("Terminal")
+        );
+        let loaded = lib.load(&id).unwrap().unwrap().to_rgba8();
+        assert_eq!(loaded.dimensions(), (6, 4));
+        assert_eq!(*loaded.get_pixel(0, 0), Rgba([42, 42, 42, 255]));
+        assert!(lib.load(&Uuid::new_v4()).unwrap().is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn removal_is_persisted_and_deletes_file() {
+        let dir = temp_dir("remove");
+        let (keep, gone) = (Uuid::new_v4(), Uuid::new_v4());
+        {
+            let lib = Library::open(&dir, Retention::default()).unwrap();
+            lib.save(keep, image(1), metadata_at(Utc::now()));
+            lib.save(gone, image(2), metadata_at(Utc::now()));
+            assert!(lib.remove(&gone));
+            assert!(!lib.remove(&gone));
+            lib.flush();
+        }
+        assert!(!image_path(&dir, &gone).exists());
+        let lib = Library::open(&dir, Retention::default()).unwrap();
+        assert!(lib.contains(&keep));
+        assert!(!lib.contains(&gone));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn retention_keeps_newest_within_age_and_count() {
+        let dir = temp_dir("retention");
+        let lib = Library::open(&dir, Retention::default()).unwrap();
+        let now = Utc::now();
+        let ids: Vec<Uuid> = (0..4).map(|_| Uuid::new_v4()).collect();
+        for (i, id) in ids.iter().enumerate() {
+            lib.save(
+                *id,
+                image(i as u8),
+                metadata_at(now - chrono::Duration::days(i as i64 * 10)),
+            );
+        }
+        // ids[3] is 30 days old.
+        let dropped = lib.set_retention(Retention {
+            max_age: Some(chrono::Duration::days(25)),
+            max_count: None,
+        });
+        assert_eq!(dropped, vec![ids[3]]);
+        let dropped = lib.set_retention(Retention {
+            max_age: Some(chrono::Duration::days(25)),
+            max_count: Some(2),
+        });
+        assert_eq!(dropped, vec![ids[2]]);
+        let listed: Vec<Uuid> = lib.list().iter().map(|i| i.id).collect();
+        assert_eq!(listed, vec![ids[0], ids[1]]);
+        lib.flush();
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn corrupt_index_lines_and_missing_files_are_dropped() {
+        let dir = temp_dir("corrupt");
+        let id = Uuid::new_v4();
+        {
+            let lib = Library::open(&dir, Retention::default()).unwrap();
+            lib.save(id, image(9), metadata_at(Utc::now()));
+            lib.save(Uuid::new_v4(), image(8), metadata_at(Utc::now()));
+            lib.flush();
+        }
+        let mut index = OpenOptions::new()
+            .append(true)
+            .open(dir.join(INDEX_FILE))
+            .unwrap();
+        index
+            .write_all(b"{\"op\":\"add\",\"id\":\"trunc\n")
+            .unwrap();
+        let missing = Uuid::new_v4();
+        append_record(
+            &dir,
+            &IndexRecord::Add {
+                id: missing,
+                metadata: Box::new(metadata_at(Utc::now())),
+            },
+        )
+        .unwrap();
+        fs::write(dir.join(IMAGES_DIR).join("stray.png"), b"x").unwrap();
+
+        let lib = Library::open(&dir, Retention::default()).unwrap();
+        assert_eq!(lib.list().len(), 2);
+        assert!(lib.contains(&id));
+        assert!(!lib.contains(&missing));
+        assert!(!dir.join(IMAGES_DIR).join("stray.png").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,123 @@
+/// Derivation lineage between stored images.
+///
+/// Every image produced from another one (a crop, a PII redaction, an
+/// annotated copy) records its parent and the [`Operation`] that made it in
+/// its [`CaptureMetadata`](super::CaptureMetadata). The store walks those
+/// links to answer "where did this come from" and "what was made from this",
+/// and the recorded chain can be replayed on a fresh capture.
+use super::ScreenshotInfo;
+use crate::annotate::RedactStyle;
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+
+/// An edit that turned a parent image into a derived one, with the
+/// parameters needed to repeat it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Operation {
+    /// Crop to a rectangle in the parent's pixel coordinates.
+    Crop {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+    /// OCR the image and redact every detected piece of PII. Replaying it
+    /// re-runs detection, so a fresh capture gets its own PII redacted.
+    /// Records from before redaction styles existed replay with the secure
+    /// default.
+    RedactPii {
+        lang: String,
+        #[serde(default = "secure_redact_style")]
+        style: RedactStyle,
+    },
+    /// Composite annotations (the editor's JSON shape) onto the image.
+    Annotate { annotations: serde_json::Value },
+}
+
+fn secure_redact_style() -> RedactStyle {
+    RedactStyle::SECURE
+}
+
+/// Crop `image`, clamping the rectangle to its bounds. Returns the cropped
+/// image and the operation as actually applied.
+pub fn crop(
+    image: &DynamicImage,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+) -> (DynamicImage, Operation) {
+    let x = x.min(image.width().saturating_sub(1));
+    let y = y.min(image.height().saturating_sub(1));
+    let width = width.min(image.width() - x);
+    let height = height.min(image.height() - y);
+    (
+        image.crop_imm(x, y, width, height),
+        Operation::Crop {
+            x,
+            y,
+            width,
+            height,
+        },
+    )
+}
+
+/// Where an image sits in the derivation graph.
+#[derive(Debug, Clone, Serialize)]
+pub struct Lineage {
+    pub image: ScreenshotInfo,
+    /// Parent, grandparent, … up to the original capture (or the oldest
+    /// ancestor still stored).
+    pub ancestors: Vec<ScreenshotInfo>,
+    /// Every image derived from this one, directly or transitively, one
+    /// generation at a time and oldest first within a generation.
+    pub derivatives: Vec<ScreenshotInfo>,
+    /// The operations that lead from the oldest ancestor to this image, in
+    /// the order they were applied.
+    pub operations: Vec<Operation>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbaImage;
+
+    #[test]
+    fn crop_clamps_to_image_bounds() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(10, 8));
+        let (cropped, op) = crop(&image, 6, 20, 10, 10);
+        assert_eq!((cropped.width(), cropped.height()), (4, 1));
+        assert_eq!(
+            op,
+            Operation::Crop {
+                x: 6,
+                y: 7,
+                width: 4,
+                height: 1
+            }
+        );
+    }
+
+    #[test]
+    fn operations_serialize_with_an_op_tag() {
+        let op = Operation::RedactPii {
+            lang: "eng".to_owned(),
+            style: RedactStyle::Noise,
+        };
+        let json = serde_json::to_value(&op).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({ "op": "redact_pii", "lang": "eng", "style": "noise" })
+        );
+        assert_eq!(serde_json::from_value::<Operation>(json).unwrap(), op);
+        let legacy = serde_json::json!({ "op": "redact_pii", "lang": "eng" });
+        assert_eq!(
+            serde_json::from_value::<Operation>(legacy).unwrap(),
+            Operation::RedactPii {
+                lang: "eng".to_owned(),
+                style: RedactStyle::SECURE,
+            }
+        );
+    }
+}
@@ -0,0 +1,442 @@
+/// Deterministic in-memory capture backend.
+///
+/// Selected with `FOTOS_CAPTURE_BACKEND=mock` so capture-dependent commands
+/// and IPC handlers can run on a headless CI box. Every pixel is a pure
+/// function of its desktop coordinate (see [`pixel_at`]), so tests can verify
+/// crops and composites without reference images.
+use super::cursor::{self, CursorImage};
+use super::region;
+use super::{CaptureBackend, CaptureMode, CaptureOptions, CaptureResult, MonitorInfo, WindowInfo};
+use anyhow::Result;
+use async_trait::async_trait;
+use image::{DynamicImage, Rgba, RgbaImage};
+
+pub struct MockBackend {
+    monitors: Vec<MonitorInfo>,
+    windows: Vec<WindowInfo>,
+    pointer: (i32, i32),
+    /// Integer HiDPI scale per monitor index; missing entries mean 1.
+    scale_factors: Vec<u32>,
+}
+
+impl MockBackend {
+    /// Two monitors side by side (the second one offset downwards so the
+    /// fullscreen composite has an uncovered gap) and two windows.
+    pub fn new() -> Self {
+        Self::with_layout(
+            vec![
+                MonitorInfo {
+                    id: 1,
+                    name: "MOCK-1".to_string(),
+                    x: 0,
+                    y: 0,
+                    width: 800,
+                    height: 600,
+                    is_primary: true,
+                },
+                MonitorInfo {
+                    id: 2,
+                    name: "MOCK-2".to_string(),
+                    x: 800,
+                    y: 120,
+                    width: 640,
+                    height: 480,
+                    is_primary: false,
+                },
+            ],
+            vec![
+                WindowInfo {
+                    id: 101,
+                    title: "Mock Terminal".to_string(),
+                    app_name: "mock-terminal".to_string(),
+                    x: 40,
+                    y: 30,
+                    width: 320,
+                    height: 200,
+                },
+                WindowInfo {
+                    id: 102,
+                    title: "Mock Browser — Fotos".to_string(),
+                    app_name: "mock-browser".to_string(),
+                    x: 900,
+                    y: 200,
+                    width: 400,
+                    height: 300,
+                },
+            ],
+        )
+    }
+
+    pub fn with_layout(monitors: Vec<MonitorInfo>, windows: Vec<WindowInfo>) -> Self {
+        Self {
+            monitors,
+            windows,
+            pointer: (100, 100),
+            scale_factors: Vec::new(),
+        }
+    }
+
+    /// Give monitor `index` an integer scale factor. Only region captures
+    /// honour it, by rendering that monitor's slice at `factor` pixels per
+    /// desktop unit like a HiDPI output would.
+    pub fn with_scale_factor(mut self, index: usize, factor: u32) -> Self {
+        if self.scale_factors.len() <= index {
+            self.scale_factors.resize(index + 1, 1);
+        }
+        self.scale_factors[index] = factor.max(1);
+        self
+    }
+
+    /// Move the simulated pointer (desktop coordinates).
+    pub fn with_pointer(mut self, x: i32, y: i32) -> Self {
+        self.pointer = (x, y);
+        self
+    }
+
+    /// The simulated pointer: a solid white 8×8 square with its hotspot at
+    /// the top-left corner.
+    pub fn cursor(&self) -> CursorImage {
+        CursorImage {
+            image: RgbaImage::from_pixel(8, 8, Rgba([255, 255, 255, 255])),
+            hotspot: (0, 0),
+            position: self.pointer,
+        }
+    }
+
+    fn render_scaled(&self, x: i32, y: i32, w: u32, h: u32, scale: u32) -> RgbaImage {
+        if scale == 1 {
+            return self.render(x, y, w, h);
+        }
+        let base = self.render(x, y, w, h);
+        RgbaImage::from_fn(w * scale, h * scale, |px, py| {
+            *base.get_pixel(px / scale, py / scale)
+        })
+    }
+
+    /// Render the desktop rectangle at `(x, y)` of size `w×h`. Pixels not
+    /// covered by any monitor are opaque black, like the xcap composite.
+    fn render(&self, x: i32, y: i32, w: u32, h: u32) -> RgbaImage {
+        RgbaImage::from_fn(w, h, |px, py| {
+            let gx = x + px as i32;
+            let gy = y + py as i32;
+            let covered = self.monitors.iter().any(|m| {
+                gx >= m.x && gy >= m.y && gx < m.x + m.width as i32 && gy < m.y + m.height as i32
+            });
+            if covered {
+                pixel_at(gx, gy)
+            } else {
+                Rgba([0, 0, 0, 255])
+            }
+        })
+    }
+}
+
+impl Default for MockBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The colour the mock desktop shows at desktop coordinate `(x, y)`.
+pub fn pixel_at(x: i32, y: i32) -> Rgba<u8> {
+    Rgba([
+        (x & 0xff) as u8,
+        (y & 0xff) as u8,
+        (((x >> 8) & 0x0f) | ((y >> 4) & 0xf0)) as u8,
+        255,
+    ])
+}
+
+#[async_trait]
+impl CaptureBackend for MockBackend {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    async fn capture(&self, mode: CaptureMode, options: CaptureOptions) -> Result<CaptureResult> {
+        let (mut image, origin, monitor, window_title) = match mode {
+            CaptureMode::Fullscreen => {
+                if self.monitors.is_empty() {
+                    anyhow::bail!("No monitors detected");
+                }
+                let min_x = self.monitors.iter().map(|m| m.x).min().unwrap();
+                let min_y = self.monitors.iter().map(|m| m.y).min().unwrap();
+                let max_x = self
+                    .monitors
+                    .iter()
+                    .map(|m| m.x + m.width as i32)
+                    .max()
+                    .unwrap();
+                let max_y = self
+                    .monitors
+                    .iter()
+                    .map(|m| m.y + m.height as i32)
+                    .max()
+                    .unwrap();
+                let image =
+                    self.render(min_x, min_y, (max_x - min_x) as u32, (max_y - min_y) as u32);
+                (image, (min_x, min_y), None, None)
+            }
+            CaptureMode::Monitor(index) => {
+                let m = self
+                    .monitors
+                    .get(index as usize)
+                    .ok_or_else(|| anyhow::anyhow!("Monitor index {} out of range", index))?;
+                let image = self.render(m.x, m.y, m.width, m.height);
+                (image, (m.x, m.y), Some(m.name.clone()), None)
+            }
+            CaptureMode::Window(id) => {
+                let w = self
+                    .windows
+                    .iter()
+                    .find(|w| w.id as u64 == id)
+                    .ok_or_else(|| anyhow::anyhow!("No window found with id {}", id))?;
+                let image = self.render(w.x, w.y, w.width, w.height);
+                (image, (w.x, w.y), None, Some(w.title.clone()))
+            }
+            CaptureMode::Region { x, y, w, h } => {
+                let slices = region::plan_region(x, y, w, h, &self.monitors)?;
+                let names: Vec<&str> = slices
+                    .iter()
+                    .map(|s| self.monitors[s.monitor].name.as_str())
+                    .collect();
+                let monitor = Some(names.join("+"));
+                let pieces = slices
+                    .iter()
+                    .map(|s| {
+                        let m = &self.monitors[s.monitor];
+                        let scale = self.scale_factors.get(s.monitor).copied().unwrap_or(1);
+                        let gx = m.x + s.local_x as i32;
+                        let gy = m.y + s.local_y as i32;
+                        (*s, self.render_scaled(gx, gy, s.width, s.height, scale))
+                    })
+                    .collect();
+                (region::assemble_region(w, h, pieces), (x, y), monitor, None)
+            }
+        };
+
+        let unscaled = match mode {
+            CaptureMode::Region { w, h, .. } => image.dimensions() == (w, h),
+            _ => true,
+        };
+        let cursor_pos = if options.include_cursor && unscaled {
+            cursor::composite_cursor(&mut image, &self.cursor(), origin)
+        } else {
+            None
+        };
+
+        // Only region slices are rendered at their monitor's scale factor.
+        let scale_factor = match mode {
+            CaptureMode::Region { w, .. } => image.width() as f32 / w as f32,
+            _ => 1.0,
+        };
+        let app_name = match mode {
+            CaptureMode::Window(id) => self
+                .windows
+                .iter()
+                .find(|w| w.id as u64 == id)
+                .map(|w| w.app_name.clone()),
+            _ => None,
+        };
+
+        let mut result =
+            CaptureResult::new(DynamicImage::ImageRgba8(image), mode, monitor, window_title);
+        result.metadata.cursor = cursor_pos;
+        result.metadata.app_name = app_name;
+        result.metadata.scale_factor = Some(scale_factor);
+        Ok(result)
+    }
+
+    async fn list_monitors(&self) -> Result<Vec<MonitorInfo>> {
+        Ok(self.monitors.clone())
+    }
+
+    async fn list_windows(&self) -> Result<Vec<WindowInfo>> {
+        Ok(self.windows.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn fullscreen_covers_bounding_box_of_all_monitors() {
+        let backend = MockBackend::new();
+        let result = backend
+            .capture(CaptureMode::Fullscreen, CaptureOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(result.metadata.dimensions, (1440, 600));
+        let img = result.image.to_rgba8();
+        assert_eq!(*img.get_pixel(10, 20), pixel_at(10, 20));
+        assert_eq!(*img.get_pixel(900, 300), pixel_at(900, 300));
+        // Above the second monitor is not covered by any output.
+        assert_eq!(*img.get_pixel(900, 10), Rgba([0, 0, 0, 255]));
+    }
+
+    #[tokio::test]
+    async fn monitor_capture_uses_monitor_origin() {
+        let backend = MockBackend::new();
+        let result = backend
+            .capture(CaptureMode::Monitor(1), CaptureOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(result.metadata.dimensions, (640, 480));
+        assert_eq!(result.metadata.monitor.as_deref(), Some("MOCK-2"));
+        assert_eq!(*result.image.to_rgba8().get_pixel(0, 0), pixel_at(800, 120));
+    }
+
+    #[tokio::test]
+    async fn monitor_index_out_of_range_is_error() {
+        let backend = MockBackend::new();
+        let err = backend
+            .capture(CaptureMode::Monitor(5), CaptureOptions::default())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("out of range"));
+    }
+
+    #[tokio::test]
+    async fn window_capture_records_title_and_app() {
+        let backend = MockBackend::new();
+        let result = backend
+            .capture(CaptureMode::Window(101), CaptureOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(result.metadata.dimensions, (320, 200));
+        assert_eq!(
+            result.metadata.window_title.as_deref(),
+            Some("Mock Terminal")
+        );
+        assert_eq!(result.metadata.app_name.as_deref(), Some("mock-terminal"));
+        assert_eq!(*result.image.to_rgba8().get_pixel(5, 5), pixel_at(45, 35));
+    }
+
+    #[tokio::test]
+    async fn unknown_window_is_error() {
+        let backend = MockBackend::new();
+        assert!(backend
+            .capture(CaptureMode::Window(999), CaptureOptions::default())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn enumeration_is_deterministic() {
+        let backend = MockBackend::new();
+        let monitors = backend.list_monitors().await.unwrap();
+        let windows = backend.list_windows().await.unwrap();
+        assert_eq!(monitors.len(), 2);
+        assert!(monitors[0].is_primary);
+        assert_eq!(windows.len(), 2);
+        assert_eq!(windows[1].app_name, "mock-browser");
+    }
+
+    #[tokio::test]
+    async fn cursor_is_composited_when_requested() {
+        let backend = MockBackend::new().with_pointer(60, 50);
+        let options = CaptureOptions {
+            include_cursor: true,
+            ..CaptureOptions::default()
+        };
+        let result = backend
+            .capture(CaptureMode::Window(101), options)
+            .await
+            .unwrap();
+        // Window 101 sits at (40, 30), so the pointer lands at (20, 20).
+        assert_eq!(result.metadata.cursor, Some((20, 20)));
+        let img = result.image.to_rgba8();
+        assert_eq!(*img.get_pixel(20, 20), Rgba([255, 255, 255, 255]));
+        assert_eq!(*img.get_pixel(27, 27), Rgba([255, 255, 255, 255]));
+        assert_eq!(*img.get_pixel(28, 28), pixel_at(68, 58));
+    }
+
+    #[tokio::test]
+    async fn cursor_is_omitted_by_default_and_when_off_screen() {
+        let backend = MockBackend::new().with_pointer(60, 50);
+        let plain = backend
+            .capture(CaptureMode::Window(101), CaptureOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(plain.metadata.cursor, None);
+        assert_eq!(*plain.image.to_rgba8().get_pixel(20, 20), pixel_at(60, 50));
+
+        let options = CaptureOptions {
+            include_cursor: true,
+            ..CaptureOptions::default()
+        };
+        let elsewhere = backend
+            .capture(CaptureMode::Monitor(1), options)
+            .await
+            .unwrap();
+        assert_eq!(elsewhere.metadata.cursor, None);
+    }
+
+    #[tokio::test]
+    async fn region_capture_spans_monitors() {
+        let backend = MockBackend::new();
+        let mode = CaptureMode::Region {
+            x: 700,
+            y: 100,
+            w: 200,
+            h: 100,
+        };
+        let result = backend
+            .capture(mode, CaptureOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(result.metadata.dimensions, (200, 100));
+        assert_eq!(result.metadata.monitor.as_deref(), Some("MOCK-1+MOCK-2"));
+        let img = result.image.to_rgba8();
+        assert_eq!(*img.get_pixel(0, 0), pixel_at(700, 100));
+        assert_eq!(*img.get_pixel(150, 50), pixel_at(850, 150));
+        // MOCK-2 starts at y=120, so its top rows are uncovered.
+        assert_eq!(*img.get_pixel(150, 5), Rgba([0, 0, 0, 255]));
+    }
+
+    #[tokio::test]
+    async fn region_capture_with_mixed_scale_factors_uses_highest_density() {
+        let backend = MockBackend::new().with_scale_factor(1, 2);
+        let mode = CaptureMode::Region {
+            x: 780,
+            y: 200,
+            w: 40,
+            h: 10,
+        };
+        let result = backend
+            .capture(mode, CaptureOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(result.metadata.dimensions, (80, 20));
+        assert_eq!(result.metadata.scale_factor, Some(2.0));
+        let img = result.image.to_rgba8();
+        // Left half (MOCK-1, scale 1) is resampled; right half is native 2x.
+        let resampled = img.get_pixel(20, 10);
+        let expected = pixel_at(790, 205);
+        for c in 0..4 {
+            assert!(
+                resampled[c].abs_diff(expected[c]) <= 2,
+                "{resampled:?} vs {expected:?}"
+            );
+        }
+        assert_eq!(*img.get_pixel(41, 1), pixel_at(800, 200));
+        assert_eq!(*img.get_pixel(79, 19), pixel_at(819, 209));
+    }
+
+    #[tokio::test]
+    async fn region_outside_monitors_is_error() {
+        let backend = MockBackend::new();
+        let mode = CaptureMode::Region {
+            x: 5000,
+            y: 5000,
+            w: 10,
+            h: 10,
+        };
+        let err = backend
+            .capture(mode, CaptureOptions::default())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("does not intersect"));
+    }
+}
@@ -1,15 +1,33 @@
+pub mod cursor;
 pub mod detect;
+pub mod library;
+pub mod lineage;
+pub mod mock;
+pub mod phash;
 #[cfg(target_os = "linux")]
 pub mod portal;
+pub mod record;
+pub mod region;
+pub mod schedule;
+pub mod scroll;
+pub mod session;
+pub mod store;
 pub mod xcap_backend;
 
+use anyhow::Result;
+use async_trait::async_trait;
 use chrono::{DateTime, Utc};
+use detect::Platform;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use uuid::Uuid;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+pub use lineage::{Lineage, Operation};
+pub use phash::ImageHash;
+pub use store::{ImageStore, ScreenshotInfo, SimilarScreenshot, StoreStats};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum CaptureMode {
     Fullscreen,
     Monitor(u32),
@@ -17,6 +35,18 @@ pub enum CaptureMode {
     Window(u64),
 }
 
+impl CaptureMode {
+    /// The mode's name as used by the `mode` parameter of capture commands.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Fullscreen => "fullscreen",
+            Self::Monitor(_) => "monitor",
+            Self::Region { .. } => "region",
+            Self::Window(_) => "window",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CaptureMetadata {
     pub timestamp: DateTime<Utc>,
@@ -24,6 +54,74 @@ pub struct CaptureMetadata {
     pub monitor: Option<String>,
     pub window_title: Option<String>,
     pub dimensions: (u32, u32),
+    /// Pointer tip in image coordinates when the cursor was composited into
+    /// the capture, `None` if it was not requested or lay outside the image.
+    #[serde(default)]
+    pub cursor: Option<(i32, i32)>,
+    /// Application owning the captured window (window captures only).
+    #[serde(default)]
+    pub app_name: Option<String>,
+    /// Physical pixels per desktop unit of the capture, when the backend
+    /// knows it. Regions spanning mixed-DPI monitors report the density they
+    /// were stitched at.
+    #[serde(default)]
+    pub scale_factor: Option<f32>,
+    /// The image this one was derived from (crop, redaction, annotation).
+    #[serde(default)]
+    pub parent_id: Option<Uuid>,
+    /// How this image was made from `parent_id`; the derivation time is
+    /// `timestamp`.
+    #[serde(default)]
+    pub operation: Option<Operation>,
+    /// Perceptual hash of the image, filled in by the store on insert.
+    #[serde(default)]
+    pub phash: Option<ImageHash>,
+}
+
+impl CaptureMetadata {
+    /// Metadata for a new image of `dimensions` captured with `mode`,
+    /// timestamped now.
+    pub fn new(mode: CaptureMode, dimensions: (u32, u32)) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            mode,
+            monitor: None,
+            window_title: None,
+            dimensions,
+            cursor: None,
+            app_name: None,
+            scale_factor: None,
+            parent_id: None,
+            operation: None,
+            phash: None,
+        }
+    }
+
+    /// Metadata for an image derived from `parent_id` (described by `self`)
+    /// by `operation`: source details are kept, the timestamp and dimensions
+    /// are the new image's.
+    pub fn derive(&self, parent_id: Uuid, operation: Operation, dimensions: (u32, u32)) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            dimensions,
+            // Pointer coordinates don't survive a crop or transform.
+            cursor: None,
+            parent_id: Some(parent_id),
+            operation: Some(operation),
+            phash: None,
+            ..self.clone()
+        }
+    }
+}
+
+/// Per-capture options derived from `CaptureSettings`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CaptureOptions {
+    /// Blend the mouse pointer into the captured image.
+    pub include_cursor: bool,
+    /// If the new frame is a near-duplicate of the most recent capture,
+    /// return that capture instead of storing another copy.
+    pub reuse_duplicate: bool,
 }
 
 #[derive(Debug)]
@@ -31,99 +129,337 @@ pub struct CaptureResult {
     pub id: Uuid,
     pub image: Arc<image::DynamicImage>,
     pub metadata: CaptureMetadata,
+    /// `true` when `id` is an earlier capture returned because of
+    /// [`CaptureOptions::reuse_duplicate`]; nothing new was stored.
+    pub reused: bool,
+}
+
+impl CaptureResult {
+    /// Wrap a freshly captured image, assigning a new id and timestamp.
+    pub fn new(
+        image: image::DynamicImage,
+        mode: CaptureMode,
+        monitor: Option<String>,
+        window_title: Option<String>,
+    ) -> Self {
+        let dimensions = (image.width(), image.height());
+        Self {
+            id: Uuid::new_v4(),
+            image: Arc::new(image),
+            metadata: CaptureMetadata {
+                monitor,
+                window_title,
+                ..CaptureMetadata::new(mode, dimensions)
+            },
+            reused: false,
+        }
+    }
 }
 
-/// Global image store shared across the application.
-/// Used by capture, AI processing, and file operations.
-#[derive(Clone)]
-pub struct ImageStore {
-    images: Arc<RwLock<HashMap<Uuid, Arc<image::DynamicImage>>>>,
+#[derive(Debug, Clone, Serialize)]
+pub struct MonitorInfo {
+    pub id: u32,
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub is_primary: bool,
 }
 
-impl ImageStore {
-    pub fn new() -> Self {
-        Self {
-            images: Arc::new(RwLock::new(HashMap::new())),
+#[derive(Debug, Clone, Serialize)]
+pub struct WindowInfo {
+    pub id: u32,
+    pub title: String,
+    pub app_name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// A platform screenshot backend.
+///
+/// One backend is chosen at startup by [`detect_backend`] and shared by the
+/// Tauri commands, the global shortcuts and the IPC server, so none of them
+/// needs to know which platform it is running on.
+#[async_trait]
+pub trait CaptureBackend: Send + Sync {
+    /// Short identifier used in logs (`"xcap"`, `"portal"`, `"mock"`).
+    fn name(&self) -> &'static str;
+
+    /// Capture the screen according to `mode`.
+    async fn capture(&self, mode: CaptureMode, options: CaptureOptions) -> Result<CaptureResult>;
+
+    /// Enumerate the connected monitors, in capture-index order.
+    async fn list_monitors(&self) -> Result<Vec<MonitorInfo>>;
+
+    /// Enumerate the visible top-level windows.
+    async fn list_windows(&self) -> Result<Vec<WindowInfo>>;
+}
+
+/// The backend instance managed as Tauri state.
+pub type SharedBackend = Arc<dyn CaptureBackend>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackendKind {
+    Xcap,
+    Portal,
+    Mock,
+}
+
+/// Pure backend selection logic, separated for testability.
+///
+/// `override_name` comes from `FOTOS_CAPTURE_BACKEND` and wins when it names a
+/// known backend. Otherwise Wayland sessions and the Flatpak sandbox use the
+/// portal, and X11 / Windows use xcap.
+pub fn select_backend_kind(
+    platform: &Platform,
+    in_flatpak: bool,
+    override_name: Option<&str>,
+) -> BackendKind {
+    match override_name.map(str::to_ascii_lowercase).as_deref() {
+        Some("mock") => return BackendKind::Mock,
+        Some("xcap") => return BackendKind::Xcap,
+        Some("portal") => return BackendKind::Portal,
+        _ => {}
+    }
+    match platform {
+        Platform::Windows => BackendKind::Xcap,
+        _ if in_flatpak => BackendKind::Portal,
+        Platform::LinuxWaylandGnome | Platform::LinuxWaylandKde | Platform::LinuxWaylandOther => {
+            BackendKind::Portal
         }
+        Platform::LinuxX11 => BackendKind::Xcap,
+    }
+}
+
+/// Instantiate the backend for `kind`.
+pub fn backend_for(kind: BackendKind) -> SharedBackend {
+    match kind {
+        BackendKind::Xcap => Arc::new(xcap_backend::XcapBackend),
+        #[cfg(target_os = "linux")]
+        BackendKind::Portal => Arc::new(portal::PortalBackend),
+        #[cfg(not(target_os = "linux"))]
+        BackendKind::Portal => Arc::new(xcap_backend::XcapBackend),
+        BackendKind::Mock => Arc::new(mock::MockBackend::new()),
     }
+}
+
+/// Detect the platform and choose the capture backend once at startup.
+pub fn detect_backend() -> SharedBackend {
+    let platform = detect::detect_platform();
+    let in_flatpak = std::env::var("FLATPAK_ID").is_ok();
+    let override_name = std::env::var("FOTOS_CAPTURE_BACKEND").ok();
+    let kind = select_backend_kind(&platform, in_flatpak, override_name.as_deref());
+    let backend = backend_for(kind);
+    tracing::info!(
+        "capture backend: {} (platform={platform:?}, in_flatpak={in_flatpak})",
+        backend.name()
+    );
+    backend
+}
 
-    pub fn insert(&self, id: Uuid, image: Arc<image::DynamicImage>) {
-        self.images
-            .write()
-            .unwrap_or_else(|e| e.into_inner())
-            .insert(id, image);
+/// Capture with `backend` and insert the image into `store`.
+///
+/// With `options.reuse_duplicate`, a frame that is a near-duplicate of the
+/// most recent capture is dropped and that capture is returned instead.
+pub async fn capture_and_store(
+    backend: &dyn CaptureBackend,
+    store: &ImageStore,
+    mode: CaptureMode,
+    options: CaptureOptions,
+) -> Result<CaptureResult> {
+    let mut result = backend.capture(mode, options).await?;
+    let hash = ImageHash::of(&result.image);
+    result.metadata.phash = Some(hash);
+    if options.reuse_duplicate {
+        if let Some(existing) = store.latest_duplicate(&hash, result.metadata.dimensions) {
+            if let Some(image) = store.get(&existing.id) {
+                return Ok(CaptureResult {
+                    id: existing.id,
+                    image,
+                    metadata: existing.metadata,
+                    reused: true,
+                });
+            }
+        }
     }
+    store.insert(
+        result.id,
+        Arc::clone(&result.image),
+        result.metadata.clone(),
+    );
+    Ok(result)
+}
+
+/// Start/stop control for a long-running capture (scrolling capture,
+/// recording) so only one runs at a time and another call can end it early.
+#[derive(Default)]
+pub struct SessionControl {
+    running: AtomicBool,
+    stop: AtomicBool,
+}
 
-    pub fn get(&self, id: &Uuid) -> Option<Arc<image::DynamicImage>> {
-        self.images
-            .read()
-            .unwrap_or_else(|e| e.into_inner())
-            .get(id)
-            .cloned()
+impl SessionControl {
+    /// Claim the session. Returns `false` if one is already running.
+    pub fn try_begin(&self) -> bool {
+        let claimed = self
+            .running
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok();
+        if claimed {
+            self.stop.store(false, Ordering::SeqCst);
+        }
+        claimed
     }
 
-    pub fn ids(&self) -> Vec<Uuid> {
-        self.images
-            .read()
-            .unwrap_or_else(|e| e.into_inner())
-            .keys()
-            .copied()
-            .collect()
+    pub fn end(&self) {
+        self.running.store(false, Ordering::SeqCst);
     }
 
-    pub fn remove(&self, id: &Uuid) -> Option<Arc<image::DynamicImage>> {
-        self.images
-            .write()
-            .unwrap_or_else(|e| e.into_inner())
-            .remove(id)
+    pub fn request_stop(&self) {
+        self.stop.store(true, Ordering::SeqCst);
     }
-}
 
-impl Default for ImageStore {
-    fn default() -> Self {
-        Self::new()
+    /// The flag the capture loop polls.
+    pub fn stop_flag(&self) -> &AtomicBool {
+        &self.stop
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use image::{DynamicImage, RgbaImage};
 
-    fn dummy_image() -> Arc<DynamicImage> {
-        Arc::new(DynamicImage::ImageRgba8(RgbaImage::new(10, 10)))
+    // ── backend selection ────────────────────────────────────────────────────
+
+    #[test]
+    fn x11_selects_xcap() {
+        assert_eq!(
+            select_backend_kind(&Platform::LinuxX11, false, None),
+            BackendKind::Xcap
+        );
     }
 
     #[test]
-    fn image_store_insert_and_get() {
-        let store = ImageStore::new();
-        let id = Uuid::new_v4();
-        let img = dummy_image();
-        store.insert(id, img.clone());
-        assert!(store.get(&id).is_some());
+    fn wayland_selects_portal() {
+        for p in [
+            Platform::LinuxWaylandGnome,
+            Platform::LinuxWaylandKde,
+            Platform::LinuxWaylandOther,
+        ] {
+            assert_eq!(select_backend_kind(&p, false, None), BackendKind::Portal);
+        }
     }
 
     #[test]
-    fn image_store_get_missing_returns_none() {
-        let store = ImageStore::new();
-        assert!(store.get(&Uuid::new_v4()).is_none());
+    fn flatpak_selects_portal_even_on_x11() {
+        assert_eq!(
+            select_backend_kind(&Platform::LinuxX11, true, None),
+            BackendKind::Portal
+        );
     }
 
     #[test]
-    fn image_store_remove_clears_entry() {
-        let store = ImageStore::new();
-        let id = Uuid::new_v4();
-        store.insert(id, dummy_image());
-        assert!(store.remove(&id).is_some());
-        assert!(store.get(&id).is_none());
+    fn windows_selects_xcap() {
+        assert_eq!(
+            select_backend_kind(&Platform::Windows, false, None),
+            BackendKind::Xcap
+        );
     }
 
     #[test]
-    fn image_store_get_after_remove_is_none() {
+    fn override_wins_and_unknown_override_is_ignored() {
+        assert_eq!(
+            select_backend_kind(&Platform::LinuxWaylandGnome, true, Some("MOCK")),
+            BackendKind::Mock
+        );
+        assert_eq!(
+            select_backend_kind(&Platform::LinuxWaylandGnome, false, Some("xcap")),
+            BackendKind::Xcap
+        );
+        assert_eq!(
+            select_backend_kind(&Platform::LinuxX11, false, Some("bogus")),
+            BackendKind::Xcap
+        );
+    }
+
+    #[test]
+    fn session_control_allows_one_session_at_a_time() {
+        let session = SessionControl::default();
+        assert!(session.try_begin());
+        assert!(!session.try_begin());
+        session.request_stop();
+        assert!(session.stop_flag().load(Ordering::SeqCst));
+        session.end();
+        assert!(session.try_begin());
+        assert!(!session.stop_flag().load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn capture_and_store_inserts_image() {
+        let backend = mock::MockBackend::new();
+        let store = ImageStore::new();
+        let result = capture_and_store(
+            &backend,
+            &store,
+            CaptureMode::Monitor(0),
+            CaptureOptions::default(),
+        )
+        .await
+        .unwrap();
+        let stored = store.get(&result.id).expect("image should be stored");
+        assert_eq!(
+            (stored.width(), stored.height()),
+            result.metadata.dimensions
+        );
+        let meta = store
+            .metadata(&result.id)
+            .expect("metadata should be stored");
+        assert_eq!(meta.monitor.as_deref(), Some("MOCK-1"));
+        assert_eq!(meta.scale_factor, Some(1.0));
+        assert!(meta.phash.is_some());
+        assert!(!result.reused);
+    }
+
+    #[tokio::test]
+    async fn capture_and_store_reuses_duplicate_only_when_asked() {
+        let backend = mock::MockBackend::new();
         let store = ImageStore::new();
-        let id = Uuid::new_v4();
-        store.insert(id, dummy_image());
-        store.remove(&id);
-        assert!(store.get(&id).is_none());
+        let mode = CaptureMode::Monitor(0);
+        let first = capture_and_store(&backend, &store, mode.clone(), CaptureOptions::default())
+            .await
+            .unwrap();
+
+        let again = capture_and_store(&backend, &store, mode.clone(), CaptureOptions::default())
+            .await
+            .unwrap();
+        assert_ne!(again.id, first.id);
+        assert_eq!(store.ids().len(), 2);
+
+        let options = CaptureOptions {
+            reuse_duplicate: true,
+            ..CaptureOptions::default()
+        };
+        let reused = capture_and_store(&backend, &store, mode, options)
+            .await
+            .unwrap();
+        assert!(reused.reused);
+        assert_eq!(reused.id, again.id);
+        assert_eq!(store.ids().len(), 2);
+
+        // A different-sized frame is never a duplicate.
+        let region = CaptureMode::Region {
+            x: 0,
+            y: 0,
+            w: 50,
+            h: 40,
+        };
+        let fresh = capture_and_store(&backend, &store, region, options)
+            .await
+            .unwrap();
+        assert!(!fresh.reused);
+        assert_eq!(store.ids().len(), 3);
     }
 }
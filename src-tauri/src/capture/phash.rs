@@ -0,0 +1,134 @@
+/// Perceptual hashing for near-duplicate detection.
+///
+/// A 64-bit difference hash (dHash): the image is shrunk to 9×8 greyscale
+/// and each bit records whether a cell is brighter than its right-hand
+/// neighbour. Re-captures of an unchanged screen hash identically or within
+/// a few bits; unrelated screens land far apart.
+use image::{DynamicImage, GenericImageView};
+use serde::{Deserialize, Serialize};
+
+/// Largest Hamming distance at which a new capture counts as a duplicate of
+/// the previous one (a blinking caret or ticking clock stays under it).
+pub const DUPLICATE_DISTANCE: u32 = 3;
+
+/// Default distance for "find similar" queries.
+pub const SIMILAR_DISTANCE: u32 = 10;
+
+/// A dHash, serialised as 16 hex digits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct ImageHash(pub u64);
+
+impl ImageHash {
+    pub fn of(image: &DynamicImage) -> Self {
+        let cells = shrink(image);
+        let mut bits = 0u64;
+        for row in cells.chunks_exact(9) {
+            for pair in row.windows(2) {
+                bits = (bits << 1) | u64::from(pair[0] > pair[1]);
+            }
+        }
+        Self(bits)
+    }
+
+    /// Number of differing bits (0 = perceptually identical, 64 = opposite).
+    pub fn distance(&self, other: &Self) -> u32 {
+        (self.0 ^ other.0).count_ones()
+    }
+}
+
+/// Mean luma of each cell of a 9×8 grid, row by row. Each cell is sampled
+/// on at most a 32×32 lattice so hashing a 4K capture stays cheap.
+fn shrink(image: &DynamicImage) -> [u32; 72] {
+    const SAMPLES: u32 = 32;
+    let (width, height) = image.dimensions();
+    let mut cells = [0u32; 72];
+    if width == 0 || height == 0 {
+        return cells;
+    }
+    for (i, cell) in cells.iter_mut().enumerate() {
+        let (cx, cy) = (i as u32 % 9, i as u32 / 9);
+        let (x0, x1) = (
+            cx * width / 9,
+            ((cx + 1) * width / 9).max(cx * width / 9 + 1),
+        );
+        let (y0, y1) = (
+            cy * height / 8,
+            ((cy + 1) * height / 8).max(cy * height / 8 + 1),
+        );
+        let step_x = ((x1 - x0) / SAMPLES).max(1);
+        let step_y = ((y1 - y0) / SAMPLES).max(1);
+        let (mut sum, mut count) = (0u32, 0u32);
+        for y in (y0..y1.min(height)).step_by(step_y as usize) {
+            for x in (x0..x1.min(width)).step_by(step_x as usize) {
+                let [r, g, b, _] = image.get_pixel(x, y).0;
+                sum += (u32::from(r) * 299 + u32::from(g) * 587 + u32::from(b) * 114) / 1000;
+                count += 1;
+            }
+        }
+        *cell = sum / count.max(1);
+    }
+    cells
+}
+
+impl From<ImageHash> for String {
+    fn from(hash: ImageHash) -> Self {
+        format!("{:016x}", hash.0)
+    }
+}
+
+impl TryFrom<String> for ImageHash {
+    type Error = std::num::ParseIntError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        u64::from_str_radix(&s, 16).map(Self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    /// Smooth waves, so a rescaled copy shrinks to the same thumbnail.
+    fn gradient(width: u32, height: u32, flip: bool) -> DynamicImage {
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, y| {
+            let phase = x as f32 / width as f32 * 9.0 + y as f32 / height as f32 * 3.0;
+            let v = 128.0 + 100.0 * phase.sin();
+            let v = if flip { 255.0 - v } else { v } as u8;
+            Rgba([v, v, v, 255])
+        }))
+    }
+
+    #[test]
+    fn identical_and_rescaled_images_match() {
+        let a = ImageHash::of(&gradient(320, 200, false));
+        assert_eq!(a.distance(&ImageHash::of(&gradient(320, 200, false))), 0);
+        let scaled = gradient(320, 200, false).resize_exact(640, 400, image::imageops::Nearest);
+        assert!(a.distance(&ImageHash::of(&scaled)) <= DUPLICATE_DISTANCE);
+    }
+
+    #[test]
+    fn small_edits_stay_close_and_different_images_are_far() {
+        let base = gradient(320, 200, false);
+        let mut edited = base.to_rgba8();
+        // A caret-sized change.
+        for y in 50..60 {
+            edited.put_pixel(100, y, Rgba([0, 0, 0, 255]));
+        }
+        let a = ImageHash::of(&base);
+        assert!(
+            a.distance(&ImageHash::of(&DynamicImage::ImageRgba8(edited))) <= DUPLICATE_DISTANCE
+        );
+        assert!(a.distance(&ImageHash::of(&gradient(320, 200, true))) > SIMILAR_DISTANCE);
+    }
+
+    #[test]
+    fn hash_round_trips_through_hex() {
+        let hash = ImageHash(0x00ff_1234_abcd_0001);
+        let json = serde_json::to_value(hash).unwrap();
+        assert_eq!(json, "00ff1234abcd0001");
+        assert_eq!(serde_json::from_value::<ImageHash>(json).unwrap(), hash);
+        assert!(serde_json::from_value::<ImageHash>("zz".into()).is_err());
+    }
+}
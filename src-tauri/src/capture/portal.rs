@@ -2,7 +2,61 @@
 ///
 /// Used on GNOME Wayland, KDE Wayland, and other Wayland compositors
 /// that implement the Screenshot portal.
+use super::{
+    xcap_backend, CaptureBackend, CaptureMode, CaptureOptions, CaptureResult, MonitorInfo,
+    WindowInfo,
+};
 use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use std::sync::Once;
+
+/// Capture backend for Wayland sessions and the Flatpak sandbox.
+///
+/// The Screenshot portal can only grab the whole desktop, so monitor and
+/// window modes, and monitor/window enumeration, fall through to xcap.
+///
+/// `include_cursor` is not supported: the Screenshot portal has no way to
+/// include the pointer, and the XFixes pointer the xcap modes would overlay
+/// is XWayland's, so it lands in the wrong place. Captures are taken
+/// without it and record no pointer position. Drawing it would take a
+/// ScreenCast portal session in embedded or metadata cursor mode plus a
+/// PipeWire reader for its frames, which this backend doesn't have.
+pub struct PortalBackend;
+
+#[async_trait]
+impl CaptureBackend for PortalBackend {
+    fn name(&self) -> &'static str {
+        "portal"
+    }
+
+    async fn capture(&self, mode: CaptureMode, options: CaptureOptions) -> Result<CaptureResult> {
+        if options.include_cursor {
+            static WARNED: Once = Once::new();
+            WARNED.call_once(|| {
+                tracing::warn!("portal: the mouse cursor can't be captured here; leaving it out");
+            });
+        }
+        let options = CaptureOptions {
+            include_cursor: false,
+            ..options
+        };
+        match mode {
+            CaptureMode::Fullscreen => {
+                let image = capture_via_portal().await?;
+                Ok(CaptureResult::new(image, mode, None, None))
+            }
+            other => xcap_backend::XcapBackend.capture(other, options).await,
+        }
+    }
+
+    async fn list_monitors(&self) -> Result<Vec<MonitorInfo>> {
+        xcap_backend::list_monitors().await
+    }
+
+    async fn list_windows(&self) -> Result<Vec<WindowInfo>> {
+        xcap_backend::list_windows().await
+    }
+}
 
 pub async fn capture_via_portal() -> Result<image::DynamicImage> {
     use ashpd::desktop::screenshot::Screenshot;
@@ -0,0 +1,521 @@
+/// Screen recording to animated GIF, APNG or animated WebP.
+///
+/// [`record`] samples a capture mode at a fixed frame rate through the
+/// capture backend. Consecutive identical frames are merged by extending the
+/// previous frame's delay, so an idle screen costs one frame rather than one
+/// per tick. [`encode_animation`] then writes the frames in the requested
+/// container.
+///
+/// Every sample is a full capture. With xcap that is a cheap grab, but the
+/// portal backend has no streaming path here: each frame is a Screenshot
+/// portal round trip, written to disk as a PNG and decoded again, which
+/// manages a frame or two per second at best. Ticks missed meanwhile are
+/// skipped, so such recordings keep real-time delays but show far fewer
+/// frames than `fps` asks for.
+use super::{CaptureBackend, CaptureMode, CaptureOptions};
+use anyhow::{Context, Result};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, ExtendedColorType, ImageEncoder, RgbaImage};
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::time::{Instant, MissedTickBehavior};
+
+/// Longest recording accepted from any entry point.
+pub const MAX_DURATION: Duration = Duration::from_secs(60);
+pub const MAX_FPS: u32 = 30;
+pub const DEFAULT_FPS: u32 = 10;
+/// Raw frame memory budget; recording stops early once it is used up.
+const MAX_BUFFERED_BYTES: usize = 1 << 30;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AnimationFormat {
+    Gif,
+    Apng,
+    WebP,
+}
+
+impl AnimationFormat {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "gif" => Ok(Self::Gif),
+            "apng" | "png" => Ok(Self::Apng),
+            "webp" => Ok(Self::WebP),
+            other => {
+                anyhow::bail!("Unsupported recording format '{other}' (use gif, apng or webp)")
+            }
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Gif => "gif",
+            Self::Apng => "apng",
+            Self::WebP => "webp",
+        }
+    }
+
+    /// File extension; APNG uses `.png` so every PNG viewer can open it.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Gif => "gif",
+            Self::Apng => "png",
+            Self::WebP => "webp",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RecordOptions {
+    pub fps: u32,
+    pub duration: Duration,
+    pub include_cursor: bool,
+}
+
+impl RecordOptions {
+    /// Clamp the frame rate to `1..=MAX_FPS` and the duration to
+    /// [`MAX_DURATION`].
+    pub fn clamped(self) -> Self {
+        Self {
+            fps: self.fps.clamp(1, MAX_FPS),
+            duration: self.duration.min(MAX_DURATION),
+            ..self
+        }
+    }
+}
+
+/// One distinct frame and how long it stays on screen.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub image: RgbaImage,
+    pub delay_ms: u32,
+}
+
+#[derive(Debug)]
+pub struct Recording {
+    pub frames: Vec<Frame>,
+    /// Number of samples taken, before deduplication.
+    pub samples: u32,
+}
+
+impl Recording {
+    pub fn duration_ms(&self) -> u64 {
+        self.frames.iter().map(|f| f.delay_ms as u64).sum()
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.frames
+            .first()
+            .map(|f| f.image.dimensions())
+            .unwrap_or((0, 0))
+    }
+}
+
+/// Progress reported after every sample.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RecordProgress {
+    pub elapsed_ms: u64,
+    pub frames: u32,
+}
+
+/// Sample `mode` at `opts.fps` until `opts.duration` elapses or `stop` is set.
+pub async fn record(
+    backend: &dyn CaptureBackend,
+    mode: CaptureMode,
+    opts: RecordOptions,
+    stop: &AtomicBool,
+    on_progress: Option<&(dyn Fn(RecordProgress) + Send + Sync)>,
+) -> Result<Recording> {
+    let opts = opts.clamped();
+    if backend.name() == "portal" {
+        tracing::info!("record: portal captures run well below {} fps", opts.fps);
+    }
+    let capture_opts = CaptureOptions {
+        include_cursor: opts.include_cursor,
+        ..CaptureOptions::default()
+    };
+    let mut ticker = tokio::time::interval(Duration::from_secs_f64(1.0 / opts.fps as f64));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    let start = Instant::now();
+    let mut frames: Vec<Frame> = Vec::new();
+    let mut last_sample: Option<Instant> = None;
+    let mut samples = 0;
+    let mut buffered = 0usize;
+
+    loop {
+        ticker.tick().await;
+        if stop.load(Ordering::SeqCst) || start.elapsed() >= opts.duration {
+            break;
+        }
+
+        let at = Instant::now();
+        let image = backend
+            .capture(mode.clone(), capture_opts)
+            .await?
+            .image
+            .to_rgba8();
+        samples += 1;
+
+        // The previous distinct frame stays on screen until this sample.
+        if let (Some(prev), Some(t)) = (frames.last_mut(), last_sample) {
+            prev.delay_ms += (at - t).as_millis() as u32;
+        }
+        last_sample = Some(at);
+
+        match frames.last() {
+            Some(prev) if prev.image.dimensions() != image.dimensions() => {
+                tracing::debug!("record: frame size changed, sample {samples} dropped");
+            }
+            Some(prev) if prev.image == image => {}
+            _ => {
+                buffered += image.as_raw().len();
+                frames.push(Frame { image, delay_ms: 0 });
+            }
+        }
+
+        if let Some(cb) = on_progress {
+            cb(RecordProgress {
+                elapsed_ms: start.elapsed().as_millis() as u64,
+                frames: frames.len() as u32,
+            });
+        }
+        if buffered > MAX_BUFFERED_BYTES {
+            tracing::warn!("record: frame buffer full after {samples} samples, stopping early");
+            break;
+        }
+    }
+
+    let Some(t) = last_sample else {
+        anyhow::bail!("Recording stopped before the first frame was captured");
+    };
+    let tail = (Instant::now() - t).as_millis() as u32;
+    let min_delay = 1000 / opts.fps;
+    if let Some(last) = frames.last_mut() {
+        last.delay_ms = (last.delay_ms + tail).max(min_delay);
+    }
+    tracing::info!(
+        "record: {} distinct frames from {samples} samples",
+        frames.len()
+    );
+    Ok(Recording { frames, samples })
+}
+
+/// Encode `frames` as a looping animation.
+pub fn encode_animation(frames: &[Frame], format: AnimationFormat) -> Result<Vec<u8>> {
+    let first = frames.first().context("No frames to encode")?;
+    let (w, h) = first.image.dimensions();
+    if frames.iter().any(|f| f.image.dimensions() != (w, h)) {
+        anyhow::bail!("All frames must have the same size");
+    }
+    match format {
+        AnimationFormat::Gif => encode_gif(frames),
+        AnimationFormat::Apng => encode_apng(frames, w, h),
+        AnimationFormat::WebP => encode_webp(frames, w, h),
+    }
+}
+
+fn encode_gif(frames: &[Frame]) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    {
+        let mut encoder = GifEncoder::new_with_speed(&mut buf, 10);
+        encoder.set_repeat(Repeat::Infinite)?;
+        for f in frames {
+            encoder.encode_frame(image::Frame::from_parts(
+                f.image.clone(),
+                0,
+                0,
+                Delay::from_numer_denom_ms(f.delay_ms, 1),
+            ))?;
+        }
+    }
+    Ok(buf)
+}
+
+fn encode_apng(frames: &[Frame], w: u32, h: u32) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    let mut encoder = png::Encoder::new(&mut buf, w, h);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(frames.len() as u32, 0)?;
+    let mut writer = encoder.write_header()?;
+    for f in frames {
+        let delay = f.delay_ms.min(u16::MAX as u32) as u16;
+        writer.set_frame_delay(delay, 1000)?;
+        writer.write_image_data(f.image.as_raw())?;
+    }
+    writer.finish()?;
+    Ok(buf)
+}
+
+/// Animated WebP: each frame is encoded as a lossless VP8L still by the
+/// `image` crate and wrapped in an `ANMF` chunk of an extended container.
+fn encode_webp(frames: &[Frame], w: u32, h: u32) -> Result<Vec<u8>> {
+    fn chunk(out: &mut Vec<u8>, tag: &[u8; 4], data: &[u8]) {
+        out.extend_from_slice(tag);
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        out.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            out.push(0);
+        }
+    }
+    fn u24(out: &mut Vec<u8>, v: u32) {
+        out.extend_from_slice(&v.to_le_bytes()[..3]);
+    }
+
+    let mut body = Vec::new();
+
+    let mut vp8x = Vec::new();
+    vp8x.push(0x10 | 0x02); // alpha + animation
+    vp8x.extend_from_slice(&[0; 3]);
+    u24(&mut vp8x, w - 1);
+    u24(&mut vp8x, h - 1);
+    chunk(&mut body, b"VP8X", &vp8x);
+
+    // Transparent background, loop forever.
+    chunk(&mut body, b"ANIM", &[0, 0, 0, 0, 0, 0]);
+
+    for f in frames {
+        let mut still = Vec::new();
+        image::codecs::webp::WebPEncoder::new_lossless(&mut still).write_image(
+            f.image.as_raw(),
+            w,
+            h,
+            ExtendedColorType::Rgba8,
+        )?;
+        // Skip the 12-byte RIFF/WEBP header to get the bare VP8L chunk.
+        let bitstream = still
+            .get(12..)
+            .filter(|c| c.starts_with(b"VP8L"))
+            .context("Unexpected WebP encoder output")?;
+
+        let mut anmf = Vec::with_capacity(16 + bitstream.len());
+        u24(&mut anmf, 0); // x / 2
+        u24(&mut anmf, 0); // y / 2
+        u24(&mut anmf, w - 1);
+        u24(&mut anmf, h - 1);
+        u24(&mut anmf, f.delay_ms.min(0xff_ffff));
+        anmf.push(0x02); // do not blend, do not dispose
+        anmf.extend_from_slice(bitstream);
+        chunk(&mut body, b"ANMF", &anmf);
+    }
+
+    let mut out = Vec::with_capacity(12 + body.len());
+    out.extend_from_slice(b"RIFF");
+    out.write_all(&(body.len() as u32 + 4).to_le_bytes())?;
+    out.extend_from_slice(b"WEBP");
+    out.extend_from_slice(&body);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::mock::MockBackend;
+    use crate::capture::{CaptureResult, MonitorInfo, WindowInfo};
+    use async_trait::async_trait;
+    use image::{AnimationDecoder, DynamicImage, Rgba};
+    use std::io::Cursor;
+    use std::sync::atomic::AtomicU32;
+
+    fn solid(v: u8) -> RgbaImage {
+        RgbaImage::from_pixel(8, 6, Rgba([v, 255 - v, v / 2, 255]))
+    }
+
+    fn sample_frames() -> Vec<Frame> {
+        vec![
+            Frame {
+                image: solid(10),
+                delay_ms: 100,
+            },
+            Frame {
+                image: solid(200),
+                delay_ms: 250,
+            },
+        ]
+    }
+
+    fn decoded_delays(frames: Vec<image::Frame>) -> Vec<u32> {
+        frames
+            .iter()
+            .map(|f| {
+                let (n, d) = f.delay().numer_denom_ms();
+                n / d
+            })
+            .collect()
+    }
+
+    #[test]
+    fn format_names_parse() {
+        assert_eq!(AnimationFormat::parse("GIF").unwrap(), AnimationFormat::Gif);
+        assert_eq!(
+            AnimationFormat::parse("apng").unwrap(),
+            AnimationFormat::Apng
+        );
+        assert_eq!(
+            AnimationFormat::parse("webp").unwrap(),
+            AnimationFormat::WebP
+        );
+        assert!(AnimationFormat::parse("mp4").is_err());
+    }
+
+    #[test]
+    fn options_are_clamped() {
+        let opts = RecordOptions {
+            fps: 500,
+            duration: Duration::from_secs(3600),
+            include_cursor: false,
+        }
+        .clamped();
+        assert_eq!(opts.fps, MAX_FPS);
+        assert_eq!(opts.duration, MAX_DURATION);
+        assert_eq!(RecordOptions { fps: 0, ..opts }.clamped().fps, 1);
+    }
+
+    #[test]
+    fn gif_round_trips_frames_and_delays() {
+        let bytes = encode_animation(&sample_frames(), AnimationFormat::Gif).unwrap();
+        let decoder = image::codecs::gif::GifDecoder::new(Cursor::new(bytes)).unwrap();
+        let frames = decoder.into_frames().collect_frames().unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(decoded_delays(frames), vec![100, 250]);
+    }
+
+    #[test]
+    fn apng_round_trips_frames_and_delays() {
+        let bytes = encode_animation(&sample_frames(), AnimationFormat::Apng).unwrap();
+        let decoder = image::codecs::png::PngDecoder::new(Cursor::new(bytes)).unwrap();
+        assert!(decoder.is_apng().unwrap());
+        let frames = decoder
+            .apng()
+            .unwrap()
+            .into_frames()
+            .collect_frames()
+            .unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(
+            *frames[1].buffer().get_pixel(0, 0),
+            *solid(200).get_pixel(0, 0)
+        );
+        assert_eq!(decoded_delays(frames), vec![100, 250]);
+    }
+
+    #[test]
+    fn webp_round_trips_frames_and_delays() {
+        let bytes = encode_animation(&sample_frames(), AnimationFormat::WebP).unwrap();
+        let decoder = image::codecs::webp::WebPDecoder::new(Cursor::new(bytes)).unwrap();
+        assert!(decoder.has_animation());
+        let frames = decoder.into_frames().collect_frames().unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(
+            *frames[0].buffer().get_pixel(3, 3),
+            *solid(10).get_pixel(3, 3)
+        );
+        assert_eq!(decoded_delays(frames), vec![100, 250]);
+    }
+
+    #[test]
+    fn encoding_rejects_empty_and_mixed_sizes() {
+        assert!(encode_animation(&[], AnimationFormat::Gif).is_err());
+        let mixed = vec![
+            Frame {
+                image: solid(1),
+                delay_ms: 10,
+            },
+            Frame {
+                image: RgbaImage::new(3, 3),
+                delay_ms: 10,
+            },
+        ];
+        assert!(encode_animation(&mixed, AnimationFormat::Apng).is_err());
+    }
+
+    #[tokio::test]
+    async fn static_screen_collapses_to_one_frame() {
+        let backend = MockBackend::new();
+        let opts = RecordOptions {
+            fps: 20,
+            duration: Duration::from_millis(200),
+            include_cursor: false,
+        };
+        let stop = AtomicBool::new(false);
+        let rec = record(&backend, CaptureMode::Monitor(0), opts, &stop, None)
+            .await
+            .unwrap();
+        assert!(rec.samples > 1);
+        assert_eq!(rec.frames.len(), 1);
+        assert!(rec.duration_ms() >= 150, "got {}ms", rec.duration_ms());
+    }
+
+    /// Alternates between two images every `period` captures.
+    struct BlinkingBackend {
+        count: AtomicU32,
+        period: u32,
+    }
+
+    #[async_trait]
+    impl CaptureBackend for BlinkingBackend {
+        fn name(&self) -> &'static str {
+            "blinking"
+        }
+
+        async fn capture(&self, mode: CaptureMode, _: CaptureOptions) -> Result<CaptureResult> {
+            let n = self.count.fetch_add(1, Ordering::SeqCst);
+            let v = if (n / self.period).is_multiple_of(2) {
+                0
+            } else {
+                255
+            };
+            Ok(CaptureResult::new(
+                DynamicImage::ImageRgba8(solid(v)),
+                mode,
+                None,
+                None,
+            ))
+        }
+
+        async fn list_monitors(&self) -> Result<Vec<MonitorInfo>> {
+            Ok(vec![])
+        }
+
+        async fn list_windows(&self) -> Result<Vec<WindowInfo>> {
+            Ok(vec![])
+        }
+    }
+
+    #[tokio::test]
+    async fn changing_screen_keeps_distinct_frames() {
+        let backend = BlinkingBackend {
+            count: AtomicU32::new(0),
+            period: 2,
+        };
+        let opts = RecordOptions {
+            fps: 50,
+            duration: Duration::from_millis(300),
+            include_cursor: false,
+        };
+        let stop = AtomicBool::new(false);
+        let rec = record(&backend, CaptureMode::Fullscreen, opts, &stop, None)
+            .await
+            .unwrap();
+        assert!(rec.frames.len() >= 2);
+        assert!((rec.frames.len() as u32) < rec.samples);
+        for pair in rec.frames.windows(2) {
+            assert_ne!(pair[0].image, pair[1].image);
+        }
+    }
+
+    #[tokio::test]
+    async fn stop_flag_set_before_start_yields_error() {
+        let backend = MockBackend::new();
+        let stop = AtomicBool::new(true);
+        let opts = RecordOptions {
+            fps: 10,
+            duration: Duration::from_secs(1),
+            include_cursor: false,
+        };
+        assert!(record(&backend, CaptureMode::Fullscreen, opts, &stop, None)
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,216 @@
+/// Region capture across one or more monitors.
+///
+/// A region is given in desktop coordinates. [`plan_region`] splits it into
+/// one slice per intersecting monitor so backends only grab the pixels they
+/// need, and [`assemble_region`] stitches the captured slices back together.
+///
+/// Monitors with different scale factors return slices with different pixel
+/// densities for the same desktop area. The output uses the densest slice's
+/// scale and resamples the others to match, so text on a HiDPI monitor is
+/// never downsampled.
+use super::MonitorInfo;
+use anyhow::Result;
+use image::{imageops, Rgba, RgbaImage};
+
+/// One monitor's share of a capture region.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegionSlice {
+    /// Index into the monitor list passed to [`plan_region`].
+    pub monitor: usize,
+    /// Top-left of the slice relative to the monitor's origin.
+    pub local_x: u32,
+    pub local_y: u32,
+    /// Top-left of the slice relative to the region's origin.
+    pub offset_x: u32,
+    pub offset_y: u32,
+    /// Size of the slice in desktop units.
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Intersect the region at `(x, y)` of size `w×h` with each monitor.
+///
+/// Errors if the region is empty or does not touch any monitor.
+pub fn plan_region(
+    x: i32,
+    y: i32,
+    w: u32,
+    h: u32,
+    monitors: &[MonitorInfo],
+) -> Result<Vec<RegionSlice>> {
+    if w == 0 || h == 0 {
+        anyhow::bail!("Region must have a non-zero size");
+    }
+    let (rx0, ry0) = (x as i64, y as i64);
+    let (rx1, ry1) = (rx0 + w as i64, ry0 + h as i64);
+
+    let slices: Vec<RegionSlice> = monitors
+        .iter()
+        .enumerate()
+        .filter_map(|(i, m)| {
+            let (mx0, my0) = (m.x as i64, m.y as i64);
+            let (mx1, my1) = (mx0 + m.width as i64, my0 + m.height as i64);
+            let (ix0, iy0) = (rx0.max(mx0), ry0.max(my0));
+            let (ix1, iy1) = (rx1.min(mx1), ry1.min(my1));
+            (ix0 < ix1 && iy0 < iy1).then(|| RegionSlice {
+                monitor: i,
+                local_x: (ix0 - mx0) as u32,
+                local_y: (iy0 - my0) as u32,
+                offset_x: (ix0 - rx0) as u32,
+                offset_y: (iy0 - ry0) as u32,
+                width: (ix1 - ix0) as u32,
+                height: (iy1 - iy0) as u32,
+            })
+        })
+        .collect();
+
+    if slices.is_empty() {
+        anyhow::bail!("Region {w}x{h}+{x}+{y} does not intersect any monitor");
+    }
+    Ok(slices)
+}
+
+/// Pixels per desktop unit of a captured slice.
+fn slice_scale(slice: &RegionSlice, image: &RgbaImage) -> f64 {
+    image.width() as f64 / slice.width as f64
+}
+
+/// Stitch captured slices into one image for a `w×h` region.
+///
+/// Areas not covered by any monitor are opaque black, like the fullscreen
+/// composite.
+pub fn assemble_region(w: u32, h: u32, pieces: Vec<(RegionSlice, RgbaImage)>) -> RgbaImage {
+    let scale = pieces
+        .iter()
+        .map(|(s, img)| slice_scale(s, img))
+        .fold(1.0_f64, f64::max);
+    let out_w = (w as f64 * scale).round() as u32;
+    let out_h = (h as f64 * scale).round() as u32;
+    let mut out = RgbaImage::from_pixel(out_w, out_h, Rgba([0, 0, 0, 255]));
+
+    for (slice, image) in pieces {
+        let target_w = (slice.width as f64 * scale).round() as u32;
+        let target_h = (slice.height as f64 * scale).round() as u32;
+        let image = if image.dimensions() == (target_w, target_h) {
+            image
+        } else {
+            imageops::resize(&image, target_w, target_h, imageops::FilterType::CatmullRom)
+        };
+        let ox = (slice.offset_x as f64 * scale).round() as i64;
+        let oy = (slice.offset_y as f64 * scale).round() as i64;
+        imageops::replace(&mut out, &image, ox, oy);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(x: i32, y: i32, width: u32, height: u32) -> MonitorInfo {
+        MonitorInfo {
+            id: 0,
+            name: String::new(),
+            x,
+            y,
+            width,
+            height,
+            is_primary: false,
+        }
+    }
+
+    #[test]
+    fn region_inside_one_monitor_yields_one_slice() {
+        let monitors = [monitor(0, 0, 800, 600), monitor(800, 0, 800, 600)];
+        let slices = plan_region(100, 50, 200, 100, &monitors).unwrap();
+        assert_eq!(
+            slices,
+            vec![RegionSlice {
+                monitor: 0,
+                local_x: 100,
+                local_y: 50,
+                offset_x: 0,
+                offset_y: 0,
+                width: 200,
+                height: 100,
+            }]
+        );
+    }
+
+    #[test]
+    fn region_spanning_monitors_is_split() {
+        let monitors = [monitor(0, 0, 800, 600), monitor(800, 100, 800, 600)];
+        let slices = plan_region(700, 50, 200, 100, &monitors).unwrap();
+        assert_eq!(slices.len(), 2);
+        assert_eq!((slices[0].width, slices[0].height), (100, 100));
+        assert_eq!(
+            (slices[1].local_x, slices[1].local_y, slices[1].offset_x),
+            (0, 0, 100)
+        );
+        assert_eq!((slices[1].width, slices[1].height), (100, 50));
+        assert_eq!(slices[1].offset_y, 50);
+    }
+
+    #[test]
+    fn region_with_negative_origin_is_clipped() {
+        let monitors = [monitor(-1920, 0, 1920, 1080)];
+        let slices = plan_region(-100, -10, 200, 50, &monitors).unwrap();
+        assert_eq!(slices.len(), 1);
+        let s = slices[0];
+        assert_eq!((s.local_x, s.local_y), (1820, 0));
+        assert_eq!((s.offset_x, s.offset_y), (0, 10));
+        assert_eq!((s.width, s.height), (100, 40));
+    }
+
+    #[test]
+    fn region_outside_all_monitors_is_error() {
+        let monitors = [monitor(0, 0, 800, 600)];
+        assert!(plan_region(900, 0, 10, 10, &monitors).is_err());
+        assert!(plan_region(0, 0, 0, 10, &monitors).is_err());
+    }
+
+    #[test]
+    fn assemble_upscales_low_density_slices_to_match() {
+        let low = RegionSlice {
+            monitor: 0,
+            local_x: 0,
+            local_y: 0,
+            offset_x: 0,
+            offset_y: 0,
+            width: 10,
+            height: 10,
+        };
+        let high = RegionSlice {
+            monitor: 1,
+            offset_x: 10,
+            ..low
+        };
+        let red = RgbaImage::from_pixel(10, 10, Rgba([255, 0, 0, 255]));
+        // The second monitor has a scale factor of 2.
+        let blue = RgbaImage::from_pixel(20, 20, Rgba([0, 0, 255, 255]));
+        let out = assemble_region(20, 10, vec![(low, red), (high, blue)]);
+        assert_eq!(out.dimensions(), (40, 20));
+        assert_eq!(*out.get_pixel(5, 5), Rgba([255, 0, 0, 255]));
+        assert_eq!(*out.get_pixel(19, 19), Rgba([255, 0, 0, 255]));
+        assert_eq!(*out.get_pixel(20, 0), Rgba([0, 0, 255, 255]));
+        assert_eq!(*out.get_pixel(39, 19), Rgba([0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn assemble_fills_uncovered_area_with_black() {
+        let slice = RegionSlice {
+            monitor: 0,
+            local_x: 0,
+            local_y: 0,
+            offset_x: 0,
+            offset_y: 0,
+            width: 5,
+            height: 5,
+        };
+        let white = RgbaImage::from_pixel(5, 5, Rgba([255, 255, 255, 255]));
+        let out = assemble_region(10, 5, vec![(slice, white)]);
+        assert_eq!(out.dimensions(), (10, 5));
+        assert_eq!(*out.get_pixel(4, 4), Rgba([255, 255, 255, 255]));
+        assert_eq!(*out.get_pixel(5, 0), Rgba([0, 0, 0, 255]));
+    }
+}
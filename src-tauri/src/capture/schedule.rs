@@ -0,0 +1,283 @@
+/// When an interval capture session fires.
+///
+/// Either a fixed period, or a five-field cron expression
+/// (`minute hour day-of-month month day-of-week`) evaluated in local time.
+/// Cron fields accept `*`, numbers, ranges (`1-5`), steps (`*/15`, `0-30/10`)
+/// and comma-separated lists; day-of-week is `0-6` with Sunday as `0` (`7` is
+/// also Sunday). As in classic cron, when both day fields are restricted a
+/// time matches if either does.
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, TimeZone, Timelike};
+use std::time::Duration;
+
+/// Shortest accepted interval, to keep a runaway session from flooding the
+/// image store.
+pub const MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schedule {
+    Every(Duration),
+    Cron(CronSpec),
+}
+
+impl Schedule {
+    pub fn every(interval: Duration) -> Result<Self> {
+        if interval < MIN_INTERVAL {
+            anyhow::bail!("Interval must be at least {}s", MIN_INTERVAL.as_secs());
+        }
+        Ok(Self::Every(interval))
+    }
+
+    pub fn cron(expr: &str) -> Result<Self> {
+        Ok(Self::Cron(CronSpec::parse(expr)?))
+    }
+
+    /// The first firing strictly after `last` (or at `start` for the first
+    /// frame of an interval schedule). `start` anchors interval schedules so
+    /// they don't drift when captures are slow.
+    pub fn next_after<Tz: TimeZone>(
+        &self,
+        start: &DateTime<Tz>,
+        last: Option<&DateTime<Tz>>,
+    ) -> Option<DateTime<Tz>> {
+        match self {
+            Self::Every(interval) => {
+                let Some(last) = last else {
+                    return Some(start.clone());
+                };
+                let period = chrono::Duration::from_std(*interval).ok()?;
+                let elapsed = last.clone() - start.clone();
+                let n = elapsed.num_milliseconds() / period.num_milliseconds() + 1;
+                Some(start.clone() + period * n as i32)
+            }
+            Self::Cron(spec) => spec.next_after(last.unwrap_or(start)),
+        }
+    }
+}
+
+/// A parsed cron expression; each field is a bitmask of allowed values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CronSpec {
+    minutes: u64,
+    hours: u32,
+    days_of_month: u32,
+    months: u16,
+    days_of_week: u8,
+    dom_restricted: bool,
+    dow_restricted: bool,
+}
+
+impl CronSpec {
+    pub fn parse(expr: &str) -> Result<Self> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [min, hour, dom, month, dow] = fields[..] else {
+            anyhow::bail!(
+                "Cron expression must have 5 fields (minute hour day month weekday), got {}",
+                fields.len()
+            );
+        };
+        let mut days_of_week = parse_field(dow, 0, 7).context("weekday field")? as u8;
+        // 7 is an alias for Sunday.
+        if days_of_week & 0x80 != 0 {
+            days_of_week = (days_of_week & 0x7f) | 1;
+        }
+        let spec = Self {
+            minutes: parse_field(min, 0, 59).context("minute field")?,
+            hours: parse_field(hour, 0, 23).context("hour field")? as u32,
+            days_of_month: parse_field(dom, 1, 31).context("day field")? as u32,
+            months: parse_field(month, 1, 12).context("month field")? as u16,
+            days_of_week,
+            dom_restricted: dom != "*",
+            dow_restricted: dow != "*",
+        };
+        if !spec.has_a_day() {
+            anyhow::bail!("day field never falls in the months given, e.g. 30 February");
+        }
+        Ok(spec)
+    }
+
+    /// Whether some day of some allowed month matches. Only a restricted
+    /// day of month on its own can rule out every day; any weekday comes
+    /// round in every month.
+    fn has_a_day(&self) -> bool {
+        const MONTH_DAYS: [u32; 12] = [31, 29, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+        if !self.dom_restricted || self.dow_restricted {
+            return true;
+        }
+        (1..=12).any(|month| {
+            let days = ((1u64 << (MONTH_DAYS[month - 1] + 1)) - 2) as u32;
+            self.months & (1 << month) != 0 && self.days_of_month & days != 0
+        })
+    }
+
+    fn matches<Tz: TimeZone>(&self, t: &DateTime<Tz>) -> bool {
+        let dom = self.days_of_month & (1 << t.day()) != 0;
+        let dow = self.days_of_week & (1 << t.weekday().num_days_from_sunday()) != 0;
+        let day = match (self.dom_restricted, self.dow_restricted) {
+            (true, true) => dom || dow,
+            (true, false) => dom,
+            (false, true) => dow,
+            (false, false) => true,
+        };
+        self.minutes & (1 << t.minute()) != 0
+            && self.hours & (1 << t.hour()) != 0
+            && self.months & (1 << t.month()) != 0
+            && day
+    }
+
+    /// The first matching minute strictly after `t`, searching up to a
+    /// little over four years ahead (enough for `0 0 29 2 *`).
+    pub fn next_after<Tz: TimeZone>(&self, t: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let mut candidate =
+            t.clone().with_second(0)?.with_nanosecond(0)? + chrono::Duration::minutes(1);
+        const LIMIT: u32 = 60 * 24 * (366 * 4 + 1);
+        for _ in 0..LIMIT {
+            if self.matches(&candidate) {
+                return Some(candidate);
+            }
+            candidate += chrono::Duration::minutes(1);
+        }
+        None
+    }
+}
+
+/// Parse one cron field into a bitmask over `min..=max`.
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64> {
+    let mut mask = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((r, s)) => (
+                r,
+                s.parse::<u32>()
+                    .with_context(|| format!("bad step '{s}'"))?,
+            ),
+            None => (part, 1),
+        };
+        if step == 0 {
+            anyhow::bail!("step must be positive in '{part}'");
+        }
+        let (lo, hi) = if range == "*" {
+            (min, max)
+        } else if let Some((a, b)) = range.split_once('-') {
+            (parse_value(a, min, max)?, parse_value(b, min, max)?)
+        } else {
+            let v = parse_value(range, min, max)?;
+            // `5/10` means "from 5 to the end, every 10".
+            (v, if part.contains('/') { max } else { v })
+        };
+        if lo > hi {
+            anyhow::bail!("range {lo}-{hi} is reversed");
+        }
+        for v in (lo..=hi).step_by(step as usize) {
+            mask |= 1 << v;
+        }
+    }
+    Ok(mask)
+}
+
+fn parse_value(s: &str, min: u32, max: u32) -> Result<u32> {
+    let v: u32 = s
+        .parse()
+        .with_context(|| format!("'{s}' is not a number"))?;
+    if v < min || v > max {
+        anyhow::bail!("{v} is outside {min}-{max}");
+    }
+    Ok(v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn interval_is_anchored_to_start() {
+        let schedule = Schedule::every(Duration::from_secs(10)).unwrap();
+        let start = at("2026-01-01T00:00:00Z");
+        assert_eq!(schedule.next_after(&start, None), Some(start));
+        // A slow capture that finished at +13s fires next at +20s, not +23s.
+        let late = at("2026-01-01T00:00:13Z");
+        assert_eq!(
+            schedule.next_after(&start, Some(&late)),
+            Some(at("2026-01-01T00:00:20Z"))
+        );
+    }
+
+    #[test]
+    fn interval_below_minimum_is_rejected() {
+        assert!(Schedule::every(Duration::from_millis(200)).is_err());
+    }
+
+    #[test]
+    fn cron_every_fifteen_minutes() {
+        let schedule = Schedule::cron("*/15 * * * *").unwrap();
+        let start = at("2026-03-10T09:07:30Z");
+        assert_eq!(
+            schedule.next_after(&start, None),
+            Some(at("2026-03-10T09:15:00Z"))
+        );
+        let first = at("2026-03-10T09:15:00Z");
+        assert_eq!(
+            schedule.next_after(&start, Some(&first)),
+            Some(at("2026-03-10T09:30:00Z"))
+        );
+    }
+
+    #[test]
+    fn cron_weekday_working_hours() {
+        // 2026-03-13 is a Friday.
+        let spec = CronSpec::parse("0 9-17/4 * * 1-5").unwrap();
+        assert_eq!(
+            spec.next_after(&at("2026-03-13T17:30:00Z")),
+            Some(at("2026-03-16T09:00:00Z"))
+        );
+        assert_eq!(
+            spec.next_after(&at("2026-03-16T09:00:00Z")),
+            Some(at("2026-03-16T13:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn cron_sunday_alias_and_day_or_weekday() {
+        let sunday = CronSpec::parse("0 12 * * 7").unwrap();
+        // 2026-03-15 is a Sunday.
+        assert_eq!(
+            sunday.next_after(&at("2026-03-13T00:00:00Z")),
+            Some(at("2026-03-15T12:00:00Z"))
+        );
+        // Restricted day-of-month and weekday match if either does.
+        let either = CronSpec::parse("0 0 1 * 1").unwrap();
+        assert_eq!(
+            either.next_after(&at("2026-03-13T00:00:00Z")),
+            Some(at("2026-03-16T00:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn cron_rejects_malformed_expressions() {
+        for bad in [
+            "* * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "a * * * *",
+        ] {
+            assert!(CronSpec::parse(bad).is_err(), "{bad} should be rejected");
+        }
+    }
+
+    #[test]
+    fn cron_rejects_days_that_never_come() {
+        assert!(CronSpec::parse("0 0 30 2 *").is_err());
+        assert!(CronSpec::parse("0 0 31 4,6,9,11 *").is_err());
+        assert!(CronSpec::parse("0 0 31 4-5 *").is_ok());
+        assert!(CronSpec::parse("0 0 29 2 *").is_ok());
+        // Either day field may match, and Mondays come every February.
+        assert!(CronSpec::parse("0 0 30 2 1").is_ok());
+    }
+}
@@ -0,0 +1,453 @@
+/// Scrolling ("long") screenshot capture.
+///
+/// [`scroll_capture`] repeatedly captures a window or region while the user
+/// scrolls it, and a [`Stitcher`] appends each frame's newly revealed rows to
+/// one tall image. Consecutive frames are aligned by comparing row
+/// fingerprints, so the overlap is found without any knowledge of how far the
+/// content scrolled. Rows that stay put at the bottom of the frame (sticky
+/// footers, status bars) are detected and appended only once, at the end.
+use super::{CaptureBackend, CaptureMode, CaptureOptions};
+use anyhow::Result;
+use image::RgbaImage;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// Frames must share at least this fraction of their height to be aligned.
+const MIN_OVERLAP_RATIO: f64 = 0.1;
+/// Fraction of overlapping rows that must match for an alignment to count;
+/// the slack absorbs sticky headers, blinking carets and hover effects.
+const MIN_MATCH_RATIO: f64 = 0.9;
+
+/// What [`Stitcher::push`] did with a frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PushOutcome {
+    /// The frame showed `rows` new rows, which were appended.
+    Appended { rows: u32 },
+    /// The frame is identical to the previous one (the user paused).
+    Unchanged,
+    /// No overlap with the previous frame was found (scrolled too far
+    /// between captures, or scrolled upwards); the frame was skipped.
+    NoOverlap,
+}
+
+/// Incrementally stitches vertically scrolled frames of equal size.
+pub struct Stitcher {
+    body: RgbaImage,
+    last: RgbaImage,
+    last_rows: Vec<u64>,
+    footer: Option<u32>,
+    frames: u32,
+}
+
+impl Stitcher {
+    pub fn new(first: RgbaImage) -> Self {
+        Self {
+            last_rows: row_fingerprints(&first),
+            body: first.clone(),
+            last: first,
+            footer: None,
+            frames: 1,
+        }
+    }
+
+    /// Number of frames that contributed rows, including the first.
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    /// Current stitched height, not counting a detected sticky footer.
+    pub fn height(&self) -> u32 {
+        self.body.height()
+    }
+
+    pub fn push(&mut self, frame: RgbaImage) -> Result<PushOutcome> {
+        if frame.dimensions() != self.last.dimensions() {
+            anyhow::bail!(
+                "Frame size changed from {:?} to {:?} during scrolling capture",
+                self.last.dimensions(),
+                frame.dimensions()
+            );
+        }
+        let rows = row_fingerprints(&frame);
+        if rows == self.last_rows {
+            return Ok(PushOutcome::Unchanged);
+        }
+
+        // The first real scroll tells us which bottom rows never move. The
+        // body currently ends with them, so cut them off until `finish`.
+        let footer = match self.footer {
+            Some(f) => f,
+            None => {
+                let f = static_footer_rows(&self.last_rows, &rows);
+                let body_h = self.body.height() - f;
+                self.body = image::imageops::crop_imm(&self.body, 0, 0, self.body.width(), body_h)
+                    .to_image();
+                self.footer = Some(f);
+                f
+            }
+        };
+
+        let content_h = frame.height() - footer;
+        let Some(shift) = find_scroll_shift(
+            &self.last_rows[..content_h as usize],
+            &rows[..content_h as usize],
+        ) else {
+            return Ok(PushOutcome::NoOverlap);
+        };
+
+        // The new frame's last `shift` content rows were not visible before.
+        let fresh = image::imageops::crop_imm(&frame, 0, content_h - shift, frame.width(), shift)
+            .to_image();
+        let old_h = self.body.height();
+        let mut grown = RgbaImage::new(self.body.width(), old_h + shift);
+        image::imageops::replace(&mut grown, &self.body, 0, 0);
+        image::imageops::replace(&mut grown, &fresh, 0, old_h as i64);
+        self.body = grown;
+
+        self.last = frame;
+        self.last_rows = rows;
+        self.frames += 1;
+        Ok(PushOutcome::Appended { rows: shift })
+    }
+
+    /// The stitched image, with the sticky footer (if any) re-attached once.
+    pub fn finish(self) -> RgbaImage {
+        let footer = self.footer.unwrap_or(0);
+        if footer == 0 {
+            return self.body;
+        }
+        let h = self.last.height();
+        let tail = image::imageops::crop_imm(&self.last, 0, h - footer, self.last.width(), footer)
+            .to_image();
+        let mut out = RgbaImage::new(self.body.width(), self.body.height() + footer);
+        image::imageops::replace(&mut out, &self.body, 0, 0);
+        image::imageops::replace(&mut out, &tail, 0, self.body.height() as i64);
+        out
+    }
+}
+
+/// Hash each row after dropping the low bits of every channel, so JPEG-like
+/// noise and dithering don't break matching.
+fn row_fingerprints(image: &RgbaImage) -> Vec<u64> {
+    image
+        .rows()
+        .map(|row| {
+            let mut hasher = DefaultHasher::new();
+            for px in row {
+                [px[0] >> 2, px[1] >> 2, px[2] >> 2].hash(&mut hasher);
+            }
+            hasher.finish()
+        })
+        .collect()
+}
+
+/// Rows at the bottom that are identical in both frames. Capped at a third of
+/// the height so a frame that barely changed isn't mistaken for all-footer.
+fn static_footer_rows(prev: &[u64], next: &[u64]) -> u32 {
+    let max = prev.len() / 3;
+    prev.iter()
+        .rev()
+        .zip(next.iter().rev())
+        .take(max)
+        .take_while(|(a, b)| a == b)
+        .count() as u32
+}
+
+/// How many rows the content moved up between `prev` and `next`.
+///
+/// For each candidate shift `d`, `prev[d..]` should line up with `next[..]`.
+/// Returns the shift with the highest fraction of matching rows, preferring
+/// smaller shifts on ties, or `None` if no shift passes the thresholds.
+pub fn find_scroll_shift(prev: &[u64], next: &[u64]) -> Option<u32> {
+    let h = prev.len().min(next.len());
+    let min_overlap = ((h as f64 * MIN_OVERLAP_RATIO).ceil() as usize).max(1);
+    let mut best: Option<(usize, f64)> = None;
+
+    for d in 1..h {
+        let overlap = h - d;
+        if overlap < min_overlap {
+            break;
+        }
+        let matches = prev[d..h]
+            .iter()
+            .zip(&next[..overlap])
+            .filter(|(a, b)| a == b)
+            .count();
+        let ratio = matches as f64 / overlap as f64;
+        if ratio >= MIN_MATCH_RATIO && best.is_none_or(|(_, r)| ratio > r) {
+            best = Some((d, ratio));
+        }
+    }
+    best.map(|(d, _)| d as u32)
+}
+
+/// Limits and pacing for [`scroll_capture`].
+#[derive(Debug, Clone, Copy)]
+pub struct ScrollOptions {
+    /// Delay between frames.
+    pub interval: Duration,
+    /// Stop after this many captured frames.
+    pub max_frames: u32,
+    /// Stop once the stitched image reaches this height in pixels.
+    pub max_height: u32,
+    /// Stop after this many consecutive unchanged frames (user stopped
+    /// scrolling). `0` disables the idle stop.
+    pub idle_frames: u32,
+}
+
+impl Default for ScrollOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_millis(250),
+            max_frames: 200,
+            max_height: 30_000,
+            idle_frames: 8,
+        }
+    }
+}
+
+/// Progress reported after every captured frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScrollProgress {
+    pub frames: u32,
+    pub height: u32,
+}
+
+/// Capture `mode` repeatedly and stitch the frames until `stop` is set, the
+/// user stops scrolling, or a limit in `opts` is hit.
+pub async fn scroll_capture(
+    backend: &dyn CaptureBackend,
+    mode: CaptureMode,
+    opts: ScrollOptions,
+    stop: &AtomicBool,
+    on_progress: Option<&(dyn Fn(ScrollProgress) + Send + Sync)>,
+) -> Result<RgbaImage> {
+    if !matches!(mode, CaptureMode::Window(_) | CaptureMode::Region { .. }) {
+        anyhow::bail!("Scrolling capture needs a window or region");
+    }
+
+    let first = backend
+        .capture(mode.clone(), CaptureOptions::default())
+        .await?;
+    let mut stitcher = Stitcher::new(first.image.to_rgba8());
+    let mut captured = 1;
+    let mut idle = 0;
+    if let Some(cb) = on_progress {
+        cb(ScrollProgress {
+            frames: stitcher.frames(),
+            height: stitcher.height(),
+        });
+    }
+
+    while !stop.load(Ordering::SeqCst)
+        && captured < opts.max_frames
+        && stitcher.height() < opts.max_height
+        && (opts.idle_frames == 0 || idle < opts.idle_frames)
+    {
+        tokio::time::sleep(opts.interval).await;
+        let frame = backend
+            .capture(mode.clone(), CaptureOptions::default())
+            .await?;
+        captured += 1;
+        match stitcher.push(frame.image.to_rgba8())? {
+            PushOutcome::Appended { .. } => idle = 0,
+            PushOutcome::Unchanged => idle += 1,
+            PushOutcome::NoOverlap => {
+                tracing::debug!("scroll_capture: frame {captured} has no overlap, skipped");
+            }
+        }
+        if let Some(cb) = on_progress {
+            cb(ScrollProgress {
+                frames: stitcher.frames(),
+                height: stitcher.height(),
+            });
+        }
+    }
+
+    tracing::info!(
+        "scroll_capture: stitched {} of {captured} frames into {}px",
+        stitcher.frames(),
+        stitcher.height()
+    );
+    Ok(stitcher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::{CaptureResult, MonitorInfo, WindowInfo};
+    use async_trait::async_trait;
+    use image::{DynamicImage, Rgba};
+    use std::sync::Mutex;
+
+    /// A tall "document" whose rows are all distinct.
+    fn document(w: u32, h: u32) -> RgbaImage {
+        RgbaImage::from_fn(w, h, |x, y| {
+            Rgba([
+                (y * 7 % 256) as u8,
+                (y / 3 % 256) as u8,
+                (x * 16) as u8,
+                255,
+            ])
+        })
+    }
+
+    fn viewport(doc: &RgbaImage, top: u32, h: u32) -> RgbaImage {
+        image::imageops::crop_imm(doc, 0, top, doc.width(), h).to_image()
+    }
+
+    #[test]
+    fn stitches_frames_back_into_the_document() {
+        let doc = document(16, 400);
+        let mut s = Stitcher::new(viewport(&doc, 0, 100));
+        for top in [30, 95, 170, 240, 300] {
+            assert!(matches!(
+                s.push(viewport(&doc, top, 100)).unwrap(),
+                PushOutcome::Appended { .. }
+            ));
+        }
+        let out = s.finish();
+        assert_eq!(out, doc);
+    }
+
+    #[test]
+    fn identical_frames_are_reported_unchanged() {
+        let doc = document(16, 200);
+        let mut s = Stitcher::new(viewport(&doc, 0, 100));
+        assert_eq!(
+            s.push(viewport(&doc, 0, 100)).unwrap(),
+            PushOutcome::Unchanged
+        );
+        assert_eq!(s.height(), 100);
+    }
+
+    #[test]
+    fn frames_without_overlap_are_skipped() {
+        let doc = document(16, 400);
+        let mut s = Stitcher::new(viewport(&doc, 0, 100));
+        assert_eq!(
+            s.push(viewport(&doc, 250, 100)).unwrap(),
+            PushOutcome::NoOverlap
+        );
+    }
+
+    #[test]
+    fn sticky_footer_is_appended_once() {
+        let doc = document(16, 300);
+        let footer = RgbaImage::from_pixel(16, 10, Rgba([9, 9, 9, 255]));
+        let framed = |top: u32| {
+            let mut f = viewport(&doc, top, 100);
+            image::imageops::replace(&mut f, &footer, 0, 90);
+            f
+        };
+        let mut s = Stitcher::new(framed(0));
+        s.push(framed(40)).unwrap();
+        s.push(framed(100)).unwrap();
+        let out = s.finish();
+        // 190 content rows (0..190) plus the 10-row footer.
+        assert_eq!(out.height(), 200);
+        assert_eq!(
+            viewport(&out, 0, 190),
+            viewport(&doc, 0, 190),
+            "content must be continuous"
+        );
+        assert_eq!(*out.get_pixel(0, 195), Rgba([9, 9, 9, 255]));
+    }
+
+    #[test]
+    fn mismatched_frame_size_is_error() {
+        let mut s = Stitcher::new(RgbaImage::new(10, 10));
+        assert!(s.push(RgbaImage::new(10, 12)).is_err());
+    }
+
+    #[test]
+    fn shift_search_tolerates_a_few_differing_rows() {
+        let prev: Vec<u64> = (0..100).collect();
+        let mut next: Vec<u64> = (20..120).collect();
+        next[5] = 9999;
+        assert_eq!(find_scroll_shift(&prev, &next), Some(20));
+    }
+
+    /// Serves successive viewports of a document, then keeps repeating the
+    /// last one as if the user stopped scrolling.
+    struct ScriptedBackend {
+        frames: Mutex<Vec<RgbaImage>>,
+    }
+
+    #[async_trait]
+    impl CaptureBackend for ScriptedBackend {
+        fn name(&self) -> &'static str {
+            "scripted"
+        }
+
+        async fn capture(&self, mode: CaptureMode, _: CaptureOptions) -> Result<CaptureResult> {
+            let mut frames = self.frames.lock().unwrap();
+            let frame = if frames.len() > 1 {
+                frames.remove(0)
+            } else {
+                frames[0].clone()
+            };
+            Ok(CaptureResult::new(
+                DynamicImage::ImageRgba8(frame),
+                mode,
+                None,
+                None,
+            ))
+        }
+
+        async fn list_monitors(&self) -> Result<Vec<MonitorInfo>> {
+            Ok(vec![])
+        }
+
+        async fn list_windows(&self) -> Result<Vec<WindowInfo>> {
+            Ok(vec![])
+        }
+    }
+
+    #[tokio::test]
+    async fn capture_loop_stops_when_scrolling_stops() {
+        let doc = document(16, 260);
+        let backend = ScriptedBackend {
+            frames: Mutex::new([0, 60, 120, 160].map(|t| viewport(&doc, t, 100)).to_vec()),
+        };
+        let opts = ScrollOptions {
+            interval: Duration::ZERO,
+            idle_frames: 3,
+            ..ScrollOptions::default()
+        };
+        let progress = Mutex::new(Vec::new());
+        let record = |p: ScrollProgress| progress.lock().unwrap().push(p);
+        let stop = AtomicBool::new(false);
+        let out = scroll_capture(&backend, CaptureMode::Window(1), opts, &stop, Some(&record))
+            .await
+            .unwrap();
+        assert_eq!(out, doc);
+        let progress = progress.into_inner().unwrap();
+        assert_eq!(progress.first().unwrap().height, 100);
+        assert_eq!(
+            *progress.last().unwrap(),
+            ScrollProgress {
+                frames: 4,
+                height: 260
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn capture_loop_rejects_fullscreen() {
+        let backend = crate::capture::mock::MockBackend::new();
+        let stop = AtomicBool::new(false);
+        let err = scroll_capture(
+            &backend,
+            CaptureMode::Fullscreen,
+            ScrollOptions::default(),
+            &stop,
+            None,
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("window or region"));
+    }
+}
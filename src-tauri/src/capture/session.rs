@@ -0,0 +1,477 @@
+/// Interval / timelapse capture sessions.
+///
+/// A session captures one mode on a [`Schedule`] until its duration or frame
+/// budget runs out or it is stopped, inserting every kept frame into the
+/// [`ImageStore`] and recording its [`CaptureMetadata`]. With
+/// `skip_unchanged`, frames that look the same as the last kept frame are
+/// dropped so an idle dashboard doesn't fill the store.
+///
+/// Sessions run in the background; [`SessionRegistry`] keeps their status so
+/// callers can poll or stop them by id.
+use super::schedule::Schedule;
+use super::{CaptureBackend, SharedBackend};
+use super::{CaptureMetadata, CaptureMode, CaptureOptions, ImageStore};
+use chrono::{DateTime, Local, Utc};
+use image::imageops::FilterType;
+use image::DynamicImage;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+/// Longest session accepted.
+pub const MAX_SESSION_DURATION: Duration = Duration::from_secs(24 * 60 * 60);
+/// Ended sessions kept for polling; older ones are dropped as new sessions
+/// start.
+const KEPT_ENDED_SESSIONS: usize = 16;
+/// Frames are compared on thumbnails of this size.
+const COMPARE_SIZE: u32 = 64;
+/// Mean per-channel difference (0–255) below which a frame counts as
+/// unchanged. Absorbs compression noise and a ticking clock, not real change.
+const UNCHANGED_THRESHOLD: f64 = 1.0;
+
+#[derive(Debug, Clone)]
+pub struct SessionSpec {
+    pub mode: CaptureMode,
+    pub schedule: Schedule,
+    pub duration: Duration,
+    pub max_frames: Option<u32>,
+    pub skip_unchanged: bool,
+    pub options: CaptureOptions,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionState {
+    Running,
+    Finished,
+    Stopped,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionFrame {
+    pub id: Uuid,
+    pub index: u32,
+    pub metadata: CaptureMetadata,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionStatus {
+    pub id: Uuid,
+    pub state: SessionState,
+    pub started_at: DateTime<Utc>,
+    pub frames: Vec<SessionFrame>,
+    /// Captures dropped because they matched the previous frame.
+    pub skipped: u32,
+    pub error: Option<String>,
+}
+
+pub struct Session {
+    spec: SessionSpec,
+    status: Mutex<SessionStatus>,
+    stop_requested: AtomicBool,
+    wake: Notify,
+}
+
+impl Session {
+    fn new(spec: SessionSpec) -> Self {
+        Self {
+            spec,
+            status: Mutex::new(SessionStatus {
+                id: Uuid::new_v4(),
+                state: SessionState::Running,
+                started_at: Utc::now(),
+                frames: Vec::new(),
+                skipped: 0,
+                error: None,
+            }),
+            stop_requested: AtomicBool::new(false),
+            wake: Notify::new(),
+        }
+    }
+
+    pub fn id(&self) -> Uuid {
+        self.status().id
+    }
+
+    pub fn status(&self) -> SessionStatus {
+        self.status
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn stop(&self) {
+        self.stop_requested.store(true, Ordering::SeqCst);
+        self.wake.notify_one();
+    }
+
+    fn update(&self, f: impl FnOnce(&mut SessionStatus)) {
+        f(&mut self.status.lock().unwrap_or_else(|e| e.into_inner()));
+    }
+}
+
+/// Validate `spec` before a session is started.
+pub fn validate(spec: &SessionSpec) -> anyhow::Result<()> {
+    if spec.duration.is_zero() {
+        anyhow::bail!("Session duration must be positive");
+    }
+    if spec.duration > MAX_SESSION_DURATION {
+        anyhow::bail!(
+            "Session duration is limited to {}h",
+            MAX_SESSION_DURATION.as_secs() / 3600
+        );
+    }
+    if spec.max_frames == Some(0) {
+        anyhow::bail!("max_frames must be positive");
+    }
+    Ok(())
+}
+
+/// Sessions started in this process: every running one, plus the most
+/// recently started of those that have ended.
+#[derive(Default)]
+pub struct SessionRegistry {
+    sessions: Mutex<HashMap<Uuid, Arc<Session>>>,
+}
+
+impl SessionRegistry {
+    /// Register a session for `spec`. The caller spawns [`run_session`].
+    pub fn create(&self, spec: SessionSpec) -> anyhow::Result<Arc<Session>> {
+        validate(&spec)?;
+        let session = Arc::new(Session::new(spec));
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        let mut ended: Vec<SessionStatus> = sessions
+            .values()
+            .map(|s| s.status())
+            .filter(|s| s.state != SessionState::Running)
+            .collect();
+        ended.sort_by_key(|s| std::cmp::Reverse(s.started_at));
+        for status in ended.iter().skip(KEPT_ENDED_SESSIONS - 1) {
+            sessions.remove(&status.id);
+        }
+        sessions.insert(session.id(), Arc::clone(&session));
+        Ok(session)
+    }
+
+    pub fn get(&self, id: &Uuid) -> Option<Arc<Session>> {
+        self.sessions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(id)
+            .cloned()
+    }
+
+    /// Status of every session, newest first.
+    pub fn list(&self) -> Vec<SessionStatus> {
+        let mut all: Vec<SessionStatus> = self
+            .sessions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .map(|s| s.status())
+            .collect();
+        all.sort_by_key(|s| std::cmp::Reverse(s.started_at));
+        all
+    }
+}
+
+/// Run `session` to completion. `on_frame` is called for each kept frame.
+pub async fn run_session(
+    session: Arc<Session>,
+    backend: SharedBackend,
+    store: ImageStore,
+    on_frame: impl Fn(Uuid, &SessionFrame) + Send,
+) -> SessionStatus {
+    let result = run_loop(&session, backend.as_ref(), &store, &on_frame).await;
+    session.update(|s| match result {
+        Ok(()) if session.stop_requested.load(Ordering::SeqCst) => {
+            s.state = SessionState::Stopped;
+        }
+        Ok(()) => s.state = SessionState::Finished,
+        Err(e) => {
+            tracing::error!("capture session {}: {e}", s.id);
+            s.state = SessionState::Failed;
+            s.error = Some(e.to_string());
+        }
+    });
+    let status = session.status();
+    tracing::info!(
+        "capture session {}: {:?} with {} frames ({} skipped)",
+        status.id,
+        status.state,
+        status.frames.len(),
+        status.skipped
+    );
+    status
+}
+
+async fn run_loop(
+    session: &Session,
+    backend: &dyn CaptureBackend,
+    store: &ImageStore,
+    on_frame: &(impl Fn(Uuid, &SessionFrame) + Send),
+) -> anyhow::Result<()> {
+    let spec = &session.spec;
+    let id = session.id();
+    let start = Local::now();
+    let deadline = start + chrono::Duration::from_std(spec.duration)?;
+    let mut last_fire: Option<DateTime<Local>> = None;
+    let mut last_kept: Option<Arc<DynamicImage>> = None;
+    let mut kept = 0u32;
+
+    while let Some(next) = spec.schedule.next_after(&start, last_fire.as_ref()) {
+        if next > deadline {
+            break;
+        }
+        let wait = (next - Local::now()).to_std().unwrap_or_default();
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = session.wake.notified() => {}
+        }
+        if session.stop_requested.load(Ordering::SeqCst) {
+            break;
+        }
+        let result = backend.capture(spec.mode.clone(), spec.options).await?;
+        // Slots missed while a slow capture ran are dropped, not replayed.
+        last_fire = Some(next.max(Local::now()));
+        if spec.skip_unchanged
+            && last_kept
+                .as_deref()
+                .is_some_and(|prev| visually_unchanged(prev, &result.image))
+        {
+            session.update(|s| s.skipped += 1);
+            continue;
+        }
+        store.insert(
+            result.id,
+            Arc::clone(&result.image),
+            result.metadata.clone(),
+        );
+
+        let frame = SessionFrame {
+            id: result.id,
+            index: kept,
+            metadata: result.metadata,
+        };
+        on_frame(id, &frame);
+        session.update(|s| s.frames.push(frame));
+        last_kept = Some(result.image);
+        kept += 1;
+        if spec.max_frames.is_some_and(|max| kept >= max) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Whether two captures look the same, comparing small thumbnails so noise
+/// and sub-pixel changes don't count.
+pub fn visually_unchanged(a: &DynamicImage, b: &DynamicImage) -> bool {
+    if a.width() != b.width() || a.height() != b.height() {
+        return false;
+    }
+    let ta = a.resize_exact(COMPARE_SIZE, COMPARE_SIZE, FilterType::Triangle);
+    let tb = b.resize_exact(COMPARE_SIZE, COMPARE_SIZE, FilterType::Triangle);
+    let (ra, rb) = (ta.to_rgb8(), tb.to_rgb8());
+    let total: u64 = ra
+        .as_raw()
+        .iter()
+        .zip(rb.as_raw())
+        .map(|(x, y)| x.abs_diff(*y) as u64)
+        .sum();
+    let mean = total as f64 / ra.as_raw().len() as f64;
+    mean < UNCHANGED_THRESHOLD
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::mock::MockBackend;
+    use crate::capture::{CaptureResult, MonitorInfo, WindowInfo};
+    use async_trait::async_trait;
+    use image::{Rgba, RgbaImage};
+    use std::sync::atomic::AtomicU32;
+
+    fn spec(schedule: Schedule, duration_ms: u64) -> SessionSpec {
+        SessionSpec {
+            mode: CaptureMode::Monitor(0),
+            schedule,
+            duration: Duration::from_millis(duration_ms),
+            max_frames: None,
+            skip_unchanged: false,
+            options: CaptureOptions::default(),
+        }
+    }
+
+    fn fast() -> Schedule {
+        // Bypass the 1s minimum to keep tests quick.
+        Schedule::Every(Duration::from_millis(20))
+    }
+
+    #[tokio::test]
+    async fn captures_until_frame_budget_is_spent() {
+        let registry = SessionRegistry::default();
+        let store = ImageStore::new();
+        let session = registry
+            .create(SessionSpec {
+                max_frames: Some(3),
+                ..spec(fast(), 10_000)
+            })
+            .unwrap();
+        let backend: SharedBackend = Arc::new(MockBackend::new());
+        let seen = Mutex::new(Vec::new());
+        let status = run_session(session, backend, store.clone(), |_, f| {
+            seen.lock().unwrap().push(f.id)
+        })
+        .await;
+        assert_eq!(status.state, SessionState::Finished);
+        assert_eq!(status.frames.len(), 3);
+        assert_eq!(
+            *seen.lock().unwrap(),
+            status.frames.iter().map(|f| f.id).collect::<Vec<_>>()
+        );
+        for f in &status.frames {
+            assert!(store.get(&f.id).is_some());
+            assert_eq!(f.metadata.monitor.as_deref(), Some("MOCK-1"));
+        }
+        assert_eq!(status.frames[2].index, 2);
+    }
+
+    #[tokio::test]
+    async fn unchanged_frames_are_skipped_and_not_stored() {
+        let store = ImageStore::new();
+        let session = Arc::new(Session::new(SessionSpec {
+            skip_unchanged: true,
+            ..spec(fast(), 150)
+        }));
+        let backend: SharedBackend = Arc::new(MockBackend::new());
+        let status = run_session(session, backend, store.clone(), |_, _| {}).await;
+        assert_eq!(status.frames.len(), 1);
+        assert!(status.skipped >= 2);
+        assert_eq!(store.ids().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn stop_ends_session_early() {
+        let session = Arc::new(Session::new(spec(
+            Schedule::Every(Duration::from_secs(30)),
+            60_000,
+        )));
+        let backend: SharedBackend = Arc::new(MockBackend::new());
+        let runner = tokio::spawn(run_session(
+            Arc::clone(&session),
+            backend,
+            ImageStore::new(),
+            |_, _| {},
+        ));
+        // First frame fires immediately; the second would be 30s later.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        session.stop();
+        let status = runner.await.unwrap();
+        assert_eq!(status.state, SessionState::Stopped);
+        assert_eq!(status.frames.len(), 1);
+    }
+
+    struct FailingBackend {
+        calls: AtomicU32,
+    }
+
+    #[async_trait]
+    impl CaptureBackend for FailingBackend {
+        fn name(&self) -> &'static str {
+            "failing"
+        }
+
+        async fn capture(
+            &self,
+            mode: CaptureMode,
+            _: CaptureOptions,
+        ) -> anyhow::Result<CaptureResult> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) > 0 {
+                anyhow::bail!("display went away");
+            }
+            Ok(CaptureResult::new(
+                DynamicImage::ImageRgba8(RgbaImage::new(4, 4)),
+                mode,
+                None,
+                None,
+            ))
+        }
+
+        async fn list_monitors(&self) -> anyhow::Result<Vec<MonitorInfo>> {
+            Ok(vec![])
+        }
+
+        async fn list_windows(&self) -> anyhow::Result<Vec<WindowInfo>> {
+            Ok(vec![])
+        }
+    }
+
+    #[tokio::test]
+    async fn capture_error_fails_session_but_keeps_frames() {
+        let session = Arc::new(Session::new(spec(fast(), 10_000)));
+        let backend: SharedBackend = Arc::new(FailingBackend {
+            calls: AtomicU32::new(0),
+        });
+        let status = run_session(session, backend, ImageStore::new(), |_, _| {}).await;
+        assert_eq!(status.state, SessionState::Failed);
+        assert_eq!(status.frames.len(), 1);
+        assert!(status.error.unwrap().contains("display went away"));
+    }
+
+    #[test]
+    fn validation_rejects_unbounded_or_empty_sessions() {
+        assert!(validate(&spec(fast(), 0)).is_err());
+        assert!(validate(&spec(fast(), 25 * 3600 * 1000)).is_err());
+        assert!(validate(&SessionSpec {
+            max_frames: Some(0),
+            ..spec(fast(), 1000)
+        })
+        .is_err());
+        assert!(validate(&spec(fast(), 1000)).is_ok());
+    }
+
+    #[test]
+    fn registry_drops_the_oldest_ended_sessions() {
+        let registry = SessionRegistry::default();
+        let running = registry.create(spec(fast(), 1000)).unwrap();
+        for _ in 0..KEPT_ENDED_SESSIONS + 4 {
+            let session = registry.create(spec(fast(), 1000)).unwrap();
+            session.update(|s| s.state = SessionState::Finished);
+        }
+        let newest = registry.create(spec(fast(), 1000)).unwrap();
+        let ended = registry
+            .list()
+            .iter()
+            .filter(|s| s.state != SessionState::Running)
+            .count();
+        assert_eq!(ended, KEPT_ENDED_SESSIONS - 1);
+        assert!(registry.get(&running.id()).is_some());
+        assert!(registry.get(&newest.id()).is_some());
+    }
+
+    #[test]
+    fn small_differences_count_as_unchanged() {
+        let base = RgbaImage::from_pixel(200, 100, Rgba([100, 100, 100, 255]));
+        let mut noisy = base.clone();
+        noisy.put_pixel(10, 10, Rgba([101, 100, 99, 255]));
+        let mut changed = base.clone();
+        for x in 0..100 {
+            for y in 0..100 {
+                changed.put_pixel(x, y, Rgba([0, 0, 0, 255]));
+            }
+        }
+        let a = DynamicImage::ImageRgba8(base);
+        assert!(visually_unchanged(&a, &DynamicImage::ImageRgba8(noisy)));
+        assert!(!visually_unchanged(&a, &DynamicImage::ImageRgba8(changed)));
+        assert!(!visually_unchanged(
+            &a,
+            &DynamicImage::ImageRgba8(RgbaImage::new(10, 10))
+        ));
+    }
+}
@@ -0,0 +1,1076 @@
+/// The in-memory image store with a memory budget.
+///
+/// Decoded images are kept in RAM up to a byte budget; past it the least
+/// recently used ones are evicted. Evicted images that are in the persistent
+/// [`Library`] are simply dropped (they reload from there); anything else is
+/// spilled to a fast-compressed PNG in a per-process cache directory. Both
+/// come back transparently on [`ImageStore::get`].
+use super::library::{Library, Retention};
+use super::lineage::{Lineage, Operation};
+use super::phash::{ImageHash, DUPLICATE_DISTANCE};
+use super::{CaptureMetadata, CaptureMode};
+use anyhow::{Context, Result};
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock, RwLock};
+use uuid::Uuid;
+
+/// Memory budget used until settings say otherwise.
+pub const DEFAULT_MEMORY_BUDGET: u64 = 1024 * 1024 * 1024;
+
+/// Store listing entry: an image id with its metadata.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScreenshotInfo {
+    pub id: Uuid,
+    #[serde(flatten)]
+    pub metadata: CaptureMetadata,
+}
+
+impl ScreenshotInfo {
+    /// The entry as Tauri and IPC commands return it: metadata flattened
+    /// to `width`, `height` and the mode's name, plus the `region` of
+    /// region captures.
+    pub fn to_json(&self) -> Value {
+        let m = &self.metadata;
+        let mut entry = serde_json::json!({
+            "id": self.id.to_string(),
+            "timestamp": m.timestamp.to_rfc3339(),
+            "mode": m.mode.name(),
+            "monitor": m.monitor,
+            "window_title": m.window_title,
+            "app_name": m.app_name,
+            "width": m.dimensions.0,
+            "height": m.dimensions.1,
+            "scale_factor": m.scale_factor,
+            "parent_id": m.parent_id.map(|id| id.to_string()),
+            "operation": m.operation,
+        });
+        if let CaptureMode::Region { x, y, w, h } = m.mode {
+            entry["region"] = serde_json::json!({ "x": x, "y": y, "width": w, "height": h });
+        }
+        entry
+    }
+}
+
+/// A "find similar" hit: a stored image and its perceptual-hash distance
+/// from the query image.
+#[derive(Debug, Clone, Serialize)]
+pub struct SimilarScreenshot {
+    #[serde(flatten)]
+    pub info: ScreenshotInfo,
+    pub distance: u32,
+}
+
+/// Memory and cache usage, for the settings UI.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StoreStats {
+    pub memory_budget_bytes: u64,
+    pub resident_images: usize,
+    pub resident_bytes: u64,
+    pub spilled_images: usize,
+    /// Size of the spill files on disk.
+    pub spilled_bytes: u64,
+    pub library_images: usize,
+    /// `get` calls answered from memory.
+    pub hits: u64,
+    /// `get` calls that had to read the spill cache or the library.
+    pub disk_loads: u64,
+    pub evictions: u64,
+}
+
+/// An image in the store with the metadata describing where it came from.
+struct Entry {
+    /// `None` once spilled to disk.
+    image: Option<Arc<DynamicImage>>,
+    metadata: CaptureMetadata,
+    /// Decoded size in bytes.
+    bytes: u64,
+    /// Size of the spill file, when spilled.
+    spilled_bytes: u64,
+    last_used: AtomicU64,
+}
+
+/// Called with the id of every newly inserted image, or of every image
+/// deleted or expired.
+type Listener = Box<dyn Fn(Uuid) + Send + Sync>;
+
+struct Inner {
+    entries: RwLock<HashMap<Uuid, Entry>>,
+    insert_listeners: RwLock<Vec<Listener>>,
+    remove_listeners: RwLock<Vec<Listener>>,
+    /// The newest capture without a parent, for duplicate checks. The outer
+    /// `None` means not yet known: before the first check and after that
+    /// capture is removed.
+    latest_root: RwLock<Option<Option<ScreenshotInfo>>>,
+    library: OnceLock<Arc<Library>>,
+    spill_dir: OnceLock<PathBuf>,
+    budget: AtomicU64,
+    clock: AtomicU64,
+    hits: AtomicU64,
+    disk_loads: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        if let Some(dir) = self.spill_dir.get() {
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+}
+
+/// Global image store shared across the application.
+/// Used by capture, AI processing, and file operations.
+///
+/// Once a [`Library`] is attached, every insert is also persisted there, and
+/// ids from earlier sessions are loaded from disk on first access.
+#[derive(Clone)]
+pub struct ImageStore {
+    inner: Arc<Inner>,
+}
+
+impl ImageStore {
+    pub fn new() -> Self {
+        Self::with_memory_budget(DEFAULT_MEMORY_BUDGET)
+    }
+
+    pub fn with_memory_budget(bytes: u64) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                entries: RwLock::new(HashMap::new()),
+                insert_listeners: RwLock::new(Vec::new()),
+                remove_listeners: RwLock::new(Vec::new()),
+                latest_root: RwLock::new(None),
+                library: OnceLock::new(),
+                spill_dir: OnceLock::new(),
+                budget: AtomicU64::new(bytes),
+                clock: AtomicU64::new(0),
+                hits: AtomicU64::new(0),
+                disk_loads: AtomicU64::new(0),
+                evictions: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Back the store with `library`. Only the first call takes effect.
+    pub fn attach_library(&self, library: Arc<Library>) {
+        if self.inner.library.set(library).is_err() {
+            tracing::warn!("image store: library already attached");
+        }
+    }
+
+    pub fn library(&self) -> Option<&Arc<Library>> {
+        self.inner.library.get()
+    }
+
+    /// Change the memory budget, evicting right away if now over it.
+    pub fn set_memory_budget(&self, bytes: u64) {
+        self.inner.budget.store(bytes, Ordering::Relaxed);
+        self.evict_to_budget(None);
+    }
+
+    pub fn insert(&self, id: Uuid, image: Arc<DynamicImage>, mut metadata: CaptureMetadata) {
+        if metadata.phash.is_none() {
+            metadata.phash = Some(ImageHash::of(&image));
+        }
+        if let Some(library) = self.library() {
+            let expired = library.save(id, Arc::clone(&image), metadata.clone());
+            self.forget(&expired);
+        }
+        if metadata.parent_id.is_none() {
+            self.track_latest_root(ScreenshotInfo {
+                id,
+                metadata: metadata.clone(),
+            });
+        }
+        self.put_resident(id, image, metadata);
+        self.evict_to_budget(Some(id));
+        notify(&self.inner.insert_listeners, &[id]);
+    }
+
+    /// Run `listener` after every insert (captures and derived images).
+    /// It is called on the inserting thread, so it should only hand the id
+    /// off, e.g. to a channel.
+    pub fn on_insert(&self, listener: impl Fn(Uuid) + Send + Sync + 'static) {
+        self.inner
+            .insert_listeners
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(Box::new(listener));
+    }
+
+    /// Run `listener` after every image is deleted or expired by the
+    /// library's retention policy, on the thread that removed it.
+    pub fn on_remove(&self, listener: impl Fn(Uuid) + Send + Sync + 'static) {
+        self.inner
+            .remove_listeners
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(Box::new(listener));
+    }
+
+    /// Insert `image` as made from `parent` by `operation`, inheriting the
+    /// parent's metadata. Returns the new entry's metadata, or `None` if
+    /// `parent` is not stored.
+    pub fn insert_derived(
+        &self,
+        id: Uuid,
+        image: Arc<DynamicImage>,
+        parent: &Uuid,
+        operation: Operation,
+    ) -> Option<CaptureMetadata> {
+        let metadata =
+            self.metadata(parent)?
+                .derive(*parent, operation, (image.width(), image.height()));
+        self.insert(id, image, metadata.clone());
+        Some(metadata)
+    }
+
+    /// Stored images whose perceptual hash is within `max_distance` bits of
+    /// `id`'s, closest first (newest first on ties). `None` if `id` is not
+    /// stored. Images saved before hashing existed have no hash and never
+    /// match.
+    pub fn find_similar(&self, id: &Uuid, max_distance: u32) -> Option<Vec<SimilarScreenshot>> {
+        let hash = self.metadata(id)?.phash?;
+        let mut hits: Vec<SimilarScreenshot> = self
+            .list()
+            .into_iter()
+            .filter(|info| info.id != *id)
+            .filter_map(|info| {
+                let distance = hash.distance(&info.metadata.phash?);
+                (distance <= max_distance).then_some(SimilarScreenshot { info, distance })
+            })
+            .collect();
+        // `list()` is newest first and the sort is stable.
+        hits.sort_by_key(|hit| hit.distance);
+        Some(hits)
+    }
+
+    /// The most recent capture (derived images don't count) if it has the
+    /// same `dimensions` and a hash within [`DUPLICATE_DISTANCE`] of `hash`.
+    pub fn latest_duplicate(
+        &self,
+        hash: &ImageHash,
+        dimensions: (u32, u32),
+    ) -> Option<ScreenshotInfo> {
+        // Held while looking it up so a concurrent insert can't be missed.
+        let mut cached = self.latest_root();
+        let latest = cached
+            .get_or_insert_with(|| {
+                self.list()
+                    .into_iter()
+                    .find(|info| info.metadata.parent_id.is_none())
+            })
+            .clone();
+        drop(cached);
+        latest.filter(|info| {
+            info.metadata.dimensions == dimensions
+                && info
+                    .metadata
+                    .phash
+                    .is_some_and(|h| h.distance(hash) <= DUPLICATE_DISTANCE)
+        })
+    }
+
+    /// The ancestors and derivatives of `id`, or `None` if it is not stored.
+    ///
+    /// Links to deleted images are cut: an ancestor chain stops at the first
+    /// parent no longer stored, and derivatives of a deleted intermediate
+    /// are not reachable from above it.
+    pub fn lineage(&self, id: &Uuid) -> Option<Lineage> {
+        let all = self.list();
+        let by_id: HashMap<Uuid, &ScreenshotInfo> = all.iter().map(|i| (i.id, i)).collect();
+        let image = (*by_id.get(id)?).clone();
+
+        let mut ancestors = Vec::new();
+        let mut operations: Vec<Operation> = image.metadata.operation.iter().cloned().collect();
+        let mut seen = HashSet::from([*id]);
+        let mut next = image.metadata.parent_id;
+        while let Some(parent) = next.filter(|p| seen.insert(*p)) {
+            let Some(info) = by_id.get(&parent) else {
+                break;
+            };
+            operations.extend(info.metadata.operation.iter().cloned());
+            ancestors.push((*info).clone());
+            next = info.metadata.parent_id;
+        }
+        operations.reverse();
+
+        // `all` is newest first; walk it backwards so siblings come out
+        // oldest first.
+        let mut children: HashMap<Uuid, Vec<&ScreenshotInfo>> = HashMap::new();
+        for info in all.iter().rev() {
+            if let Some(parent) = info.metadata.parent_id {
+                children.entry(parent).or_default().push(info);
+            }
+        }
+        let mut derivatives = Vec::new();
+        let mut queue = VecDeque::from([*id]);
+        while let Some(current) = queue.pop_front() {
+            for child in children.get(&current).into_iter().flatten() {
+                if seen.insert(child.id) {
+                    derivatives.push((*child).clone());
+                    queue.push_back(child.id);
+                }
+            }
+        }
+
+        Some(Lineage {
+            image,
+            ancestors,
+            derivatives,
+            operations,
+        })
+    }
+
+    pub fn get(&self, id: &Uuid) -> Option<Arc<DynamicImage>> {
+        let spilled_metadata = {
+            let entries = self.read();
+            match entries.get(id) {
+                Some(entry) => {
+                    entry.last_used.store(self.tick(), Ordering::Relaxed);
+                    if let Some(image) = &entry.image {
+                        self.inner.hits.fetch_add(1, Ordering::Relaxed);
+                        return Some(Arc::clone(image));
+                    }
+                    Some(entry.metadata.clone())
+                }
+                None => None,
+            }
+        };
+
+        let (image, metadata) = self.load(id, spilled_metadata)?;
+        self.inner.disk_loads.fetch_add(1, Ordering::Relaxed);
+        let image = Arc::new(image);
+        self.put_resident(*id, Arc::clone(&image), metadata);
+        self.evict_to_budget(Some(*id));
+        Some(image)
+    }
+
+    /// Like [`get`](Self::get), but an image not in memory is read from disk
+    /// without being cached or evicting anything. For background passes
+    /// over the whole library.
+    pub fn peek(&self, id: &Uuid) -> Option<Arc<DynamicImage>> {
+        let spilled_metadata = match self.read().get(id) {
+            Some(Entry {
+                image: Some(image), ..
+            }) => return Some(Arc::clone(image)),
+            Some(entry) => Some(entry.metadata.clone()),
+            None => None,
+        };
+        self.load(id, spilled_metadata)
+            .map(|(image, _)| Arc::new(image))
+    }
+
+    pub fn metadata(&self, id: &Uuid) -> Option<CaptureMetadata> {
+        self.read()
+            .get(id)
+            .map(|e| e.metadata.clone())
+            .or_else(|| self.library()?.metadata(id))
+    }
+
+    /// Every stored image with its metadata, newest first.
+    pub fn list(&self) -> Vec<ScreenshotInfo> {
+        let mut all = self.library().map(|l| l.list()).unwrap_or_default();
+        {
+            let entries = self.read();
+            let persisted: HashSet<Uuid> = all.iter().map(|i| i.id).collect();
+            all.extend(
+                entries
+                    .iter()
+                    .filter(|(id, _)| !persisted.contains(id))
+                    .map(|(id, e)| ScreenshotInfo {
+                        id: *id,
+                        metadata: e.metadata.clone(),
+                    }),
+            );
+        }
+        all.sort_by_key(|info| std::cmp::Reverse(info.metadata.timestamp));
+        all
+    }
+
+    /// Stored ids, newest first.
+    pub fn ids(&self) -> Vec<Uuid> {
+        self.list().into_iter().map(|info| info.id).collect()
+    }
+
+    /// Delete an image from memory, the spill cache and the library.
+    /// Returns `false` if it was not stored.
+    pub fn remove(&self, id: &Uuid) -> bool {
+        let entry = self.write().remove(id);
+        if entry.as_ref().is_some_and(|e| e.image.is_none()) {
+            self.remove_spill(id);
+        }
+        let persisted = self.library().is_some_and(|l| l.remove(id));
+        let removed = entry.is_some() || persisted;
+        if removed {
+            self.untrack_latest_root(&[*id]);
+            notify(&self.inner.remove_listeners, &[*id]);
+        }
+        removed
+    }
+
+    /// Apply a new retention policy to the attached library.
+    pub fn set_retention(&self, retention: Retention) {
+        if let Some(library) = self.library() {
+            let expired = library.set_retention(retention);
+            self.forget(&expired);
+        }
+    }
+
+    pub fn stats(&self) -> StoreStats {
+        let entries = self.read();
+        let mut stats = StoreStats {
+            memory_budget_bytes: self.inner.budget.load(Ordering::Relaxed),
+            library_images: self.library().map_or(0, |l| l.list().len()),
+            hits: self.inner.hits.load(Ordering::Relaxed),
+            disk_loads: self.inner.disk_loads.load(Ordering::Relaxed),
+            evictions: self.inner.evictions.load(Ordering::Relaxed),
+            ..StoreStats::default()
+        };
+        for entry in entries.values() {
+            if entry.image.is_some() {
+                stats.resident_images += 1;
+                stats.resident_bytes += entry.bytes;
+            } else {
+                stats.spilled_images += 1;
+                stats.spilled_bytes += entry.spilled_bytes;
+            }
+        }
+        stats
+    }
+
+    fn put_resident(&self, id: Uuid, image: Arc<DynamicImage>, metadata: CaptureMetadata) {
+        let bytes = decoded_size(&image);
+        let entry = Entry {
+            image: Some(image),
+            metadata,
+            bytes,
+            spilled_bytes: 0,
+            last_used: AtomicU64::new(self.tick()),
+        };
+        if let Some(old) = self.write().insert(id, entry) {
+            if old.image.is_none() {
+                self.remove_spill(&id);
+            }
+        }
+    }
+
+    /// Drop `ids` from memory after the library expired them.
+    fn forget(&self, ids: &[Uuid]) {
+        if ids.is_empty() {
+            return;
+        }
+        {
+            let mut entries = self.write();
+            for id in ids {
+                entries.remove(id);
+            }
+        }
+        self.untrack_latest_root(ids);
+        notify(&self.inner.remove_listeners, ids);
+    }
+
+    /// Remember `info` as the newest capture if it is newer than the one
+    /// known. Nothing to do while that is still unknown.
+    fn track_latest_root(&self, info: ScreenshotInfo) {
+        let mut latest = self.latest_root();
+        if let Some(known) = latest.as_mut() {
+            if known
+                .as_ref()
+                .is_none_or(|k| k.metadata.timestamp <= info.metadata.timestamp)
+            {
+                *known = Some(info);
+            }
+        }
+    }
+
+    /// Forget the newest capture if it is among `ids`; the next duplicate
+    /// check looks it up again.
+    fn untrack_latest_root(&self, ids: &[Uuid]) {
+        let mut latest = self.latest_root();
+        if latest
+            .as_ref()
+            .and_then(Option::as_ref)
+            .is_some_and(|info| ids.contains(&info.id))
+        {
+            *latest = None;
+        }
+    }
+
+    fn latest_root(&self) -> std::sync::RwLockWriteGuard<'_, Option<Option<ScreenshotInfo>>> {
+        self.inner
+            .latest_root
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Evict least-recently-used images until resident memory fits the
+    /// budget. `keep` (the image just inserted or loaded) is never evicted.
+    fn evict_to_budget(&self, keep: Option<Uuid>) {
+        let budget = self.inner.budget.load(Ordering::Relaxed);
+        let victims: Vec<(Uuid, Arc<DynamicImage>, u64)> = {
+            let entries = self.read();
+            let mut resident: Vec<(&Uuid, &Entry)> =
+                entries.iter().filter(|(_, e)| e.image.is_some()).collect();
+            let mut used: u64 = resident.iter().map(|(_, e)| e.bytes).sum();
+            if used <= budget {
+                return;
+            }
+            resident.sort_by_key(|(_, e)| e.last_used.load(Ordering::Relaxed));
+            let mut victims = Vec::new();
+            for (id, entry) in resident {
+                if used <= budget {
+                    break;
+                }
+                if Some(*id) == keep {
+                    continue;
+                }
+                used -= entry.bytes;
+                let image = Arc::clone(entry.image.as_ref().expect("resident"));
+                victims.push((*id, image, entry.last_used.load(Ordering::Relaxed)));
+            }
+            victims
+        };
+
+        for (id, image, last_used) in victims {
+            let in_library = self.library().is_some_and(|l| l.contains(&id));
+            let spilled_bytes = if in_library {
+                0
+            } else {
+                match self.write_spill(&id, &image) {
+                    Ok(size) => size,
+                    Err(e) => {
+                        tracing::error!("image store: keeping {id} in memory: {e:#}");
+                        continue;
+                    }
+                }
+            };
+
+            let mut entries = self.write();
+            let Some(entry) = entries.get_mut(&id) else {
+                // Removed meanwhile.
+                drop(entries);
+                self.remove_spill(&id);
+                continue;
+            };
+            if entry.last_used.load(Ordering::Relaxed) != last_used {
+                // Used again while we were writing; leave it resident.
+                continue;
+            }
+            if in_library {
+                entries.remove(&id);
+            } else {
+                entry.image = None;
+                entry.spilled_bytes = spilled_bytes;
+            }
+            self.inner.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Read `id` from the spill cache if it was spilled with
+    /// `spilled_metadata`, else from the library.
+    fn load(
+        &self,
+        id: &Uuid,
+        spilled_metadata: Option<CaptureMetadata>,
+    ) -> Option<(DynamicImage, CaptureMetadata)> {
+        let loaded = match spilled_metadata {
+            Some(metadata) => self.read_spill(id).map(|image| Some((image, metadata))),
+            None => {
+                let library = self.library()?;
+                let metadata = library.metadata(id)?;
+                library
+                    .load(id)
+                    .map(|image| image.map(|image| (image, metadata)))
+            }
+        };
+        match loaded {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                tracing::error!("image store: {e:#}");
+                None
+            }
+        }
+    }
+
+    fn spill_dir(&self) -> Result<&PathBuf> {
+        if let Some(dir) = self.inner.spill_dir.get() {
+            return Ok(dir);
+        }
+        let dir = std::env::temp_dir().join(format!(
+            "fotos-spill-{}-{}",
+            std::process::id(),
+            Uuid::new_v4().simple()
+        ));
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create spill cache {}", dir.display()))?;
+        if let Err(dir) = self.inner.spill_dir.set(dir) {
+            let _ = fs::remove_dir_all(dir);
+        }
+        Ok(self.inner.spill_dir.get().expect("just set"))
+    }
+
+    /// Write `image` to the spill cache, returning the file size.
+    fn write_spill(&self, id: &Uuid, image: &DynamicImage) -> Result<u64> {
+        let path = self.spill_dir()?.join(format!("{id}.png"));
+        let file = BufWriter::new(File::create(&path)?);
+        // Spilling is on the capture path; favour speed over size.
+        let encoder = PngEncoder::new_with_quality(file, CompressionType::Fast, FilterType::Sub);
+        image
+            .write_with_encoder(encoder)
+            .with_context(|| format!("Failed to spill {}", path.display()))?;
+        Ok(fs::metadata(&path)?.len())
+    }
+
+    fn read_spill(&self, id: &Uuid) -> Result<DynamicImage> {
+        let path = self.spill_dir()?.join(format!("{id}.png"));
+        image::open(&path).with_context(|| format!("Failed to reload {}", path.display()))
+    }
+
+    fn remove_spill(&self, id: &Uuid) {
+        if let Some(dir) = self.inner.spill_dir.get() {
+            let _ = fs::remove_file(dir.join(format!("{id}.png")));
+        }
+    }
+
+    fn tick(&self) -> u64 {
+        self.inner.clock.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, HashMap<Uuid, Entry>> {
+        self.inner.entries.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, HashMap<Uuid, Entry>> {
+        self.inner
+            .entries
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for ImageStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn decoded_size(image: &DynamicImage) -> u64 {
+    image.width() as u64 * image.height() as u64 * image.color().bytes_per_pixel() as u64
+}
+
+fn notify(listeners: &RwLock<Vec<Listener>>, ids: &[Uuid]) {
+    let listeners = listeners.read().unwrap_or_else(|e| e.into_inner());
+    for id in ids {
+        for listener in listeners.iter() {
+            listener(*id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::CaptureMode;
+    use chrono::Utc;
+    use image::{Rgba, RgbaImage};
+
+    fn dummy_image() -> Arc<DynamicImage> {
+        Arc::new(DynamicImage::ImageRgba8(RgbaImage::new(10, 10)))
+    }
+
+    fn dummy_metadata() -> CaptureMetadata {
+        CaptureMetadata::new(CaptureMode::Fullscreen, (10, 10))
+    }
+
+    fn crop_op() -> Operation {
+        Operation::Crop {
+            x: 0,
+            y: 0,
+            width: 4,
+            height: 2,
+        }
+    }
+
+    /// A 10×10 RGBA image (400 bytes decoded) filled with `v`.
+    fn filled(v: u8) -> Arc<DynamicImage> {
+        Arc::new(DynamicImage::ImageRgba8(RgbaImage::from_pixel(
+            10,
+            10,
+            Rgba([v, v, v, 255]),
+        )))
+    }
+
+    #[test]
+    fn image_store_insert_and_get() {
+        let store = ImageStore::new();
+        let id = Uuid::new_v4();
+        let img = dummy_image();
+        store.insert(id, img.clone(), dummy_metadata());
+        assert!(store.get(&id).is_some());
+    }
+
+    #[test]
+    fn image_store_get_missing_returns_none() {
+        let store = ImageStore::new();
+        assert!(store.get(&Uuid::new_v4()).is_none());
+    }
+
+    #[test]
+    fn image_store_remove_clears_entry() {
+        let store = ImageStore::new();
+        let id = Uuid::new_v4();
+        store.insert(id, dummy_image(), dummy_metadata());
+        assert!(store.remove(&id));
+        assert!(store.get(&id).is_none());
+    }
+
+    #[test]
+    fn image_store_get_after_remove_is_none() {
+        let store = ImageStore::new();
+        let id = Uuid::new_v4();
+        store.insert(id, dummy_image(), dummy_metadata());
+        store.remove(&id);
+        assert!(store.get(&id).is_none());
+    }
+
+    #[test]
+    fn image_store_lists_newest_first() {
+        let store = ImageStore::new();
+        let base = Utc::now();
+        let ids: Vec<Uuid> = (0..5).map(|_| Uuid::new_v4()).collect();
+        // Insert out of order so HashMap order can't pass by accident.
+        for (i, id) in ids.iter().enumerate().rev() {
+            let metadata = CaptureMetadata {
+                timestamp: base + chrono::Duration::seconds(i as i64),
+                ..dummy_metadata()
+            };
+            store.insert(*id, dummy_image(), metadata);
+        }
+        let expected: Vec<Uuid> = ids.iter().rev().copied().collect();
+        assert_eq!(store.ids(), expected);
+        assert_eq!(store.list()[0].id, ids[4]);
+    }
+
+    #[test]
+    fn derived_image_inherits_source_metadata() {
+        let store = ImageStore::new();
+        let parent = Uuid::new_v4();
+        store.insert(
+            parent,
+            dummy_image(),
+            CaptureMetadata {
+                window_title: Some("Editor".into()),
+                app_name: Some("gedit".into()),
+                scale_factor: Some(2.0),
+                cursor: Some((3, 3)),
+                ..CaptureMetadata::new(CaptureMode::Window(7), (10, 10))
+            },
+        );
+        let child = Uuid::new_v4();
+        let cropped = Arc::new(DynamicImage::ImageRgba8(RgbaImage::new(4, 2)));
+        store
+            .insert_derived(child, cropped, &parent, crop_op())
+            .unwrap();
+
+        let meta = store.metadata(&child).unwrap();
+        assert_eq!(meta.parent_id, Some(parent));
+        assert_eq!(meta.dimensions, (4, 2));
+        assert_eq!(meta.mode, CaptureMode::Window(7));
+        assert_eq!(meta.app_name.as_deref(), Some("gedit"));
+        assert_eq!(meta.scale_factor, Some(2.0));
+        assert_eq!(meta.cursor, None);
+        assert_eq!(meta.operation, Some(crop_op()));
+        assert!(store
+            .insert_derived(Uuid::new_v4(), dummy_image(), &Uuid::new_v4(), crop_op())
+            .is_none());
+    }
+
+    #[test]
+    fn insert_listeners_see_every_new_id() {
+        let store = ImageStore::new();
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = Arc::clone(&seen);
+        store.on_insert(move |id| sink.lock().unwrap().push(id));
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        store.insert(a, dummy_image(), dummy_metadata());
+        store.insert_derived(b, dummy_image(), &a, crop_op());
+        assert_eq!(*seen.lock().unwrap(), vec![a, b]);
+    }
+
+    #[test]
+    fn remove_listeners_see_deleted_and_expired_ids() {
+        let dir = std::env::temp_dir().join(format!("fotos-store-{}", Uuid::new_v4()));
+        let store = ImageStore::new();
+        store.attach_library(Arc::new(Library::open(&dir, Retention::default()).unwrap()));
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = Arc::clone(&seen);
+        store.on_remove(move |id| sink.lock().unwrap().push(id));
+        let (deleted, expired) = (Uuid::new_v4(), Uuid::new_v4());
+        store.insert(deleted, dummy_image(), dummy_metadata());
+        store.insert(expired, dummy_image(), dummy_metadata());
+        assert!(store.remove(&deleted));
+        assert!(!store.remove(&deleted));
+        store.set_retention(Retention {
+            max_age: None,
+            max_count: Some(0),
+        });
+        assert_eq!(*seen.lock().unwrap(), vec![deleted, expired]);
+        store.library().unwrap().flush();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn to_json_flattens_metadata() {
+        let parent = Uuid::new_v4();
+        let info = ScreenshotInfo {
+            id: Uuid::new_v4(),
+            metadata: CaptureMetadata {
+                monitor: Some("DP-1".into()),
+                scale_factor: Some(1.5),
+                parent_id: Some(parent),
+                operation: Some(crate::capture::Operation::Crop {
+                    x: 0,
+                    y: 0,
+                    width: 45,
+                    height: 60,
+                }),
+                ..CaptureMetadata::new(
+                    CaptureMode::Region {
+                        x: 10,
+                        y: 20,
+                        w: 30,
+                        h: 40,
+                    },
+                    (45, 60),
+                )
+            },
+        };
+        let json = info.to_json();
+        assert_eq!(json["mode"], "region");
+        assert_eq!(json["monitor"], "DP-1");
+        assert_eq!(json["width"], 45);
+        assert_eq!(json["height"], 60);
+        assert_eq!(json["scale_factor"], 1.5);
+        assert_eq!(json["parent_id"], parent.to_string());
+        assert_eq!(json["operation"]["op"], "crop");
+        assert_eq!(json["window_title"], Value::Null);
+        assert_eq!(json["region"]["width"], 30);
+    }
+
+    #[test]
+    fn find_similar_ranks_by_hash_distance() {
+        let store = ImageStore::new();
+        let dark = Uuid::new_v4();
+        store.insert(dark, filled(10), dummy_metadata());
+        assert!(store.metadata(&dark).unwrap().phash.is_some());
+
+        let near = Uuid::new_v4();
+        let far = Uuid::new_v4();
+        for (id, hash) in [(near, 0b101), (far, u64::MAX)] {
+            let metadata = CaptureMetadata {
+                phash: Some(ImageHash(hash)),
+                ..dummy_metadata()
+            };
+            store.insert(id, filled(10), metadata);
+        }
+
+        // A flat image hashes to all zeros.
+        let hits = store.find_similar(&dark, 10).unwrap();
+        let ids: Vec<(Uuid, u32)> = hits.iter().map(|h| (h.info.id, h.distance)).collect();
+        assert_eq!(ids, vec![(near, 2)]);
+        assert_eq!(store.find_similar(&dark, 64).unwrap().len(), 2);
+        assert!(store.find_similar(&Uuid::new_v4(), 10).is_none());
+    }
+
+    #[test]
+    fn latest_duplicate_only_considers_the_newest_capture() {
+        let store = ImageStore::new();
+        let hash = ImageHash::of(&filled(10));
+        let first = Uuid::new_v4();
+        store.insert(first, filled(10), dummy_metadata());
+        assert_eq!(store.latest_duplicate(&hash, (10, 10)).unwrap().id, first);
+        assert!(store.latest_duplicate(&hash, (20, 10)).is_none());
+
+        // Derived images are skipped when looking for the latest capture.
+        store.insert_derived(Uuid::new_v4(), filled(10), &first, crop_op());
+        assert_eq!(store.latest_duplicate(&hash, (10, 10)).unwrap().id, first);
+
+        let different = CaptureMetadata {
+            phash: Some(ImageHash(u64::MAX)),
+            ..dummy_metadata()
+        };
+        let newest = Uuid::new_v4();
+        store.insert(newest, filled(10), different);
+        assert!(store.latest_duplicate(&hash, (10, 10)).is_none());
+
+        // Once the newest capture is deleted, the one before it counts again.
+        store.remove(&newest);
+        assert_eq!(store.latest_duplicate(&hash, (10, 10)).unwrap().id, first);
+    }
+
+    #[test]
+    fn lineage_links_ancestors_and_derivatives() {
+        let store = ImageStore::new();
+        let original = Uuid::new_v4();
+        store.insert(original, dummy_image(), dummy_metadata());
+        let cropped = Uuid::new_v4();
+        store.insert_derived(cropped, dummy_image(), &original, crop_op());
+        let redacted = Uuid::new_v4();
+        let redact = Operation::RedactPii {
+            lang: "eng".into(),
+            style: crate::annotate::RedactStyle::SECURE,
+        };
+        store.insert_derived(redacted, dummy_image(), &cropped, redact.clone());
+        let sibling = Uuid::new_v4();
+        store.insert_derived(sibling, dummy_image(), &original, crop_op());
+
+        let lineage = store.lineage(&redacted).unwrap();
+        let ancestors: Vec<Uuid> = lineage.ancestors.iter().map(|i| i.id).collect();
+        assert_eq!(ancestors, vec![cropped, original]);
+        assert!(lineage.derivatives.is_empty());
+        assert_eq!(lineage.operations, vec![crop_op(), redact]);
+
+        let lineage = store.lineage(&original).unwrap();
+        assert!(lineage.ancestors.is_empty());
+        assert!(lineage.operations.is_empty());
+        let derivatives: Vec<Uuid> = lineage.derivatives.iter().map(|i| i.id).collect();
+        assert_eq!(derivatives, vec![cropped, sibling, redacted]);
+
+        // Deleting the middle link cuts the chain.
+        store.remove(&cropped);
+        let lineage = store.lineage(&redacted).unwrap();
+        assert!(lineage.ancestors.is_empty());
+        assert_eq!(lineage.operations.len(), 1);
+        assert!(store.lineage(&cropped).is_none());
+    }
+
+    #[test]
+    fn image_store_loads_persisted_images_after_restart() {
+        let dir = std::env::temp_dir().join(format!("fotos-store-{}", Uuid::new_v4()));
+        let id = Uuid::new_v4();
+        {
+            let store = ImageStore::new();
+            store.attach_library(Arc::new(Library::open(&dir, Retention::default()).unwrap()));
+            store.insert(id, dummy_image(), dummy_metadata());
+            store.library().unwrap().flush();
+        }
+        let store = ImageStore::new();
+        store.attach_library(Arc::new(Library::open(&dir, Retention::default()).unwrap()));
+        assert_eq!(store.ids(), vec![id]);
+        assert_eq!(store.metadata(&id).unwrap().dimensions, (10, 10));
+        assert_eq!(store.get(&id).unwrap().width(), 10);
+        assert!(store.remove(&id));
+        assert!(store.get(&id).is_none());
+        store.library().unwrap().flush();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn over_budget_spills_least_recently_used_and_reloads() {
+        // Room for two 400-byte images.
+        let store = ImageStore::with_memory_budget(800);
+        let ids: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+        store.insert(ids[0], filled(10), dummy_metadata());
+        store.insert(ids[1], filled(20), dummy_metadata());
+        // Touch the first so the second becomes least recently used.
+        store.get(&ids[0]).unwrap();
+        store.insert(ids[2], filled(30), dummy_metadata());
+
+        let stats = store.stats();
+        assert_eq!(stats.resident_images, 2);
+        assert_eq!(stats.resident_bytes, 800);
+        assert_eq!(stats.spilled_images, 1);
+        assert!(stats.spilled_bytes > 0);
+        assert_eq!(stats.evictions, 1);
+        // Metadata of spilled images stays available without reloading.
+        assert!(store.metadata(&ids[1]).is_some());
+        assert_eq!(store.ids().len(), 3);
+
+        let reloaded = store.get(&ids[1]).unwrap().to_rgba8();
+        assert_eq!(*reloaded.get_pixel(5, 5), Rgba([20, 20, 20, 255]));
+        let stats = store.stats();
+        assert_eq!(stats.disk_loads, 1);
+        assert_eq!(stats.resident_images, 2);
+        // Reloading ids[1] pushed out ids[0], the least recently used now.
+        assert_eq!(stats.spilled_images, 1);
+        assert_eq!(
+            *store.get(&ids[0]).unwrap().to_rgba8().get_pixel(0, 0),
+            Rgba([10, 10, 10, 255])
+        );
+    }
+
+    #[test]
+    fn image_larger_than_budget_stays_until_replaced() {
+        let store = ImageStore::with_memory_budget(100);
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        store.insert(a, filled(1), dummy_metadata());
+        assert_eq!(store.stats().resident_images, 1);
+        store.insert(b, filled(2), dummy_metadata());
+        let stats = store.stats();
+        assert_eq!((stats.resident_images, stats.spilled_images), (1, 1));
+        assert!(store.remove(&a));
+        assert_eq!(store.stats().spilled_images, 0);
+    }
+
+    #[test]
+    fn library_backed_images_are_dropped_not_spilled() {
+        let dir = std::env::temp_dir().join(format!("fotos-store-{}", Uuid::new_v4()));
+        let store = ImageStore::with_memory_budget(400);
+        store.attach_library(Arc::new(Library::open(&dir, Retention::default()).unwrap()));
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        store.insert(a, filled(7), dummy_metadata());
+        store.insert(b, filled(8), dummy_metadata());
+        let stats = store.stats();
+        assert_eq!((stats.resident_images, stats.spilled_images), (1, 0));
+        assert_eq!(stats.library_images, 2);
+        assert_eq!(
+            *store.get(&a).unwrap().to_rgba8().get_pixel(0, 0),
+            Rgba([7, 7, 7, 255])
+        );
+        store.library().unwrap().flush();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn peek_reads_without_caching() {
+        let dir = std::env::temp_dir().join(format!("fotos-store-{}", Uuid::new_v4()));
+        let store = ImageStore::with_memory_budget(400);
+        store.attach_library(Arc::new(Library::open(&dir, Retention::default()).unwrap()));
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        store.insert(a, filled(7), dummy_metadata());
+        store.insert(b, filled(8), dummy_metadata());
+        let before = store.stats();
+
+        let peeked = store.peek(&a).unwrap().to_rgba8();
+        assert_eq!(*peeked.get_pixel(0, 0), Rgba([7, 7, 7, 255]));
+        assert_eq!(store.peek(&b).unwrap().to_rgba8().get_pixel(0, 0)[0], 8);
+        let after = store.stats();
+        assert_eq!(after.resident_images, 1);
+        assert_eq!(after.evictions, before.evictions);
+        assert_eq!(after.disk_loads, before.disk_loads);
+        assert!(store.peek(&Uuid::new_v4()).is_none());
+        store.library().unwrap().flush();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn lowering_the_budget_evicts_immediately() {
+        let store = ImageStore::new();
+        for v in 0..4 {
+            store.insert(Uuid::new_v4(), filled(v), dummy_metadata());
+        }
+        assert_eq!(store.stats().resident_images, 4);
+        store.set_memory_budget(800);
+        let stats = store.stats();
+        assert_eq!((stats.resident_images, stats.spilled_images), (2, 2));
+    }
+}
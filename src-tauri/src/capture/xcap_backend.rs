@@ -2,11 +2,122 @@
 ///
 /// Used on X11 Linux and Windows where direct capture APIs are available
 /// without requiring a portal.
+use super::{
+    cursor, region, CaptureBackend, CaptureMode, CaptureOptions, CaptureResult, MonitorInfo,
+    WindowInfo,
+};
 use anyhow::Result;
+use async_trait::async_trait;
 use image::{DynamicImage, ImageBuffer, Rgba};
 use xcap::{Monitor, Window};
 
-pub async fn capture_fullscreen() -> Result<DynamicImage> {
+/// Capture backend driving `xcap` directly.
+pub struct XcapBackend;
+
+#[async_trait]
+impl CaptureBackend for XcapBackend {
+    fn name(&self) -> &'static str {
+        "xcap"
+    }
+
+    async fn capture(&self, mode: CaptureMode, options: CaptureOptions) -> Result<CaptureResult> {
+        let grab = match mode {
+            CaptureMode::Fullscreen => capture_fullscreen().await?,
+            CaptureMode::Monitor(index) => capture_monitor(index).await?,
+            CaptureMode::Window(id) => {
+                let id = u32::try_from(id)
+                    .map_err(|_| anyhow::anyhow!("Window id {id} is out of range"))?;
+                capture_window(id).await?
+            }
+            CaptureMode::Region { x, y, w, h } => capture_region(x, y, w, h).await?,
+        };
+        let Grab {
+            image,
+            origin,
+            monitor,
+            window_title,
+            app_name,
+            scale_factor,
+        } = grab;
+
+        // The pointer is positioned in desktop units, so it can only be placed
+        // when the capture has one pixel per unit (always the case on X11).
+        let unscaled = match mode {
+            CaptureMode::Region { w, h, .. } => (image.width(), image.height()) == (w, h),
+            _ => true,
+        };
+        let (image, cursor_pos) = if options.include_cursor && unscaled {
+            tokio::task::spawn_blocking(move || overlay_pointer(image, origin))
+                .await
+                .map_err(|e| anyhow::anyhow!("Task join error: {}", e))?
+        } else {
+            (image, None)
+        };
+
+        let mut result = CaptureResult::new(image, mode, monitor, window_title);
+        result.metadata.cursor = cursor_pos;
+        result.metadata.app_name = app_name;
+        result.metadata.scale_factor = scale_factor;
+        Ok(result)
+    }
+
+    async fn list_monitors(&self) -> Result<Vec<MonitorInfo>> {
+        list_monitors().await
+    }
+
+    async fn list_windows(&self) -> Result<Vec<WindowInfo>> {
+        list_windows().await
+    }
+}
+
+/// A captured image with what xcap reported about its source.
+pub struct Grab {
+    pub image: DynamicImage,
+    /// Desktop coordinate of the top-left pixel.
+    pub origin: (i32, i32),
+    pub monitor: Option<String>,
+    pub window_title: Option<String>,
+    pub app_name: Option<String>,
+    pub scale_factor: Option<f32>,
+}
+
+impl Grab {
+    fn new(image: DynamicImage, origin: (i32, i32)) -> Self {
+        Self {
+            image,
+            origin,
+            monitor: None,
+            window_title: None,
+            app_name: None,
+            scale_factor: None,
+        }
+    }
+}
+
+/// Blend the current X11 pointer into `image`, whose top-left pixel sits at
+/// desktop coordinate `origin`. Failing to read the pointer only loses the
+/// cursor, never the capture.
+fn overlay_pointer(image: DynamicImage, origin: (i32, i32)) -> (DynamicImage, Option<(i32, i32)>) {
+    match cursor::fetch_x11_cursor() {
+        Ok(Some(pointer)) => {
+            let mut rgba = image.into_rgba8();
+            let pos = cursor::composite_cursor(&mut rgba, &pointer, origin);
+            (DynamicImage::ImageRgba8(rgba), pos)
+        }
+        Ok(None) => {
+            tracing::debug!("xcap: no X11 display, capturing without cursor");
+            (image, None)
+        }
+        Err(e) => {
+            tracing::warn!("xcap: failed to read cursor image: {e}");
+            (image, None)
+        }
+    }
+}
+
+/// Capture all monitors composited into one image, positioned at its
+/// desktop origin. The scale factor is the highest of the monitors'.
+pub async fn capture_fullscreen() -> Result<Grab> {
     // Run xcap in a blocking task to avoid nested runtime issues
     // xcap uses zbus which creates a tokio runtime internally on Wayland
     tokio::task::spawn_blocking(|| {
@@ -28,6 +139,10 @@ pub async fn capture_fullscreen() -> Result<DynamicImage> {
             image: ImageBuffer<Rgba<u8>, Vec<u8>>,
         }
 
+        let scale_factor = monitors
+            .iter()
+            .filter_map(|m| m.scale_factor().ok())
+            .reduce(f32::max);
         let mut geoms: Vec<MonitorGeom> = Vec::with_capacity(monitors.len());
         for monitor in monitors {
             geoms.push(MonitorGeom {
@@ -56,27 +171,39 @@ pub async fn capture_fullscreen() -> Result<DynamicImage> {
             image::imageops::overlay(&mut composite, &geom.image, offset_x, offset_y);
         }
 
-        Ok(DynamicImage::ImageRgba8(composite))
+        Ok(Grab {
+            scale_factor,
+            ..Grab::new(DynamicImage::ImageRgba8(composite), (min_x, min_y))
+        })
     })
     .await
     .map_err(|e| anyhow::anyhow!("Task join error: {}", e))?
 }
 
-pub async fn capture_monitor(index: u32) -> Result<DynamicImage> {
+/// Capture a single monitor by index.
+pub async fn capture_monitor(index: u32) -> Result<Grab> {
     tokio::task::spawn_blocking(move || {
         let monitors = Monitor::all()?;
         let monitor = monitors
             .into_iter()
             .nth(index as usize)
             .ok_or_else(|| anyhow::anyhow!("Monitor index {} out of range", index))?;
+        let name = monitor.name().unwrap_or_default();
+        let origin = (monitor.x()?, monitor.y()?);
         let image = monitor.capture_image()?;
-        Ok(DynamicImage::ImageRgba8(image))
+        Ok(Grab {
+            monitor: Some(name),
+            scale_factor: monitor.scale_factor().ok(),
+            ..Grab::new(DynamicImage::ImageRgba8(image), origin)
+        })
     })
     .await
     .map_err(|e| anyhow::anyhow!("Task join error: {}", e))?
 }
 
-pub async fn capture_window(window_id: u32) -> Result<DynamicImage> {
+/// Capture a single window by id, with its title, application and the scale
+/// factor of the monitor it is on.
+pub async fn capture_window(window_id: u32) -> Result<Grab> {
     tokio::task::spawn_blocking(move || {
         let windows = Window::all()?;
         let window = windows
@@ -86,8 +213,93 @@ pub async fn capture_window(window_id: u32) -> Result<DynamicImage> {
         if window.is_minimized()? {
             anyhow::bail!("Window {} is minimized and cannot be captured", window_id);
         }
+        let title = window.title().unwrap_or_default();
+        let origin = (window.x()?, window.y()?);
         let image = window.capture_image()?;
-        Ok(DynamicImage::ImageRgba8(image))
+        Ok(Grab {
+            window_title: Some(title),
+            app_name: window.app_name().ok().filter(|n| !n.is_empty()),
+            scale_factor: window.current_monitor().and_then(|m| m.scale_factor()).ok(),
+            ..Grab::new(DynamicImage::ImageRgba8(image), origin)
+        })
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("Task join error: {}", e))?
+}
+
+/// Capture the desktop rectangle at `(x, y)` of size `w×h`, grabbing only
+/// the intersecting part of each monitor. The monitor name lists every
+/// monitor involved, joined with `+` when the region spans several.
+pub async fn capture_region(x: i32, y: i32, w: u32, h: u32) -> Result<Grab> {
+    tokio::task::spawn_blocking(move || {
+        let monitors = Monitor::all()?;
+        let infos = monitors
+            .iter()
+            .map(monitor_info)
+            .collect::<Result<Vec<_>>>()?;
+        let slices = region::plan_region(x, y, w, h, &infos)?;
+
+        let mut names = Vec::with_capacity(slices.len());
+        let mut pieces = Vec::with_capacity(slices.len());
+        for slice in slices {
+            let monitor = &monitors[slice.monitor];
+            names.push(infos[slice.monitor].name.clone());
+            let image =
+                monitor.capture_region(slice.local_x, slice.local_y, slice.width, slice.height)?;
+            pieces.push((slice, image));
+        }
+
+        let image = region::assemble_region(w, h, pieces);
+        Ok(Grab {
+            monitor: Some(names.join("+")),
+            scale_factor: Some(image.width() as f32 / w as f32),
+            ..Grab::new(DynamicImage::ImageRgba8(image), (x, y))
+        })
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("Task join error: {}", e))?
+}
+
+fn monitor_info(m: &Monitor) -> Result<MonitorInfo> {
+    Ok(MonitorInfo {
+        id: m.id()?,
+        name: m.name()?,
+        x: m.x()?,
+        y: m.y()?,
+        width: m.width()?,
+        height: m.height()?,
+        is_primary: m.is_primary()?,
+    })
+}
+
+pub async fn list_monitors() -> Result<Vec<MonitorInfo>> {
+    tokio::task::spawn_blocking(|| {
+        let monitors =
+            Monitor::all().map_err(|e| anyhow::anyhow!("Failed to enumerate monitors: {e}"))?;
+        monitors.iter().map(monitor_info).collect()
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("Task join error: {}", e))?
+}
+
+pub async fn list_windows() -> Result<Vec<WindowInfo>> {
+    tokio::task::spawn_blocking(|| {
+        let windows =
+            Window::all().map_err(|e| anyhow::anyhow!("Failed to enumerate windows: {e}"))?;
+        windows
+            .into_iter()
+            .map(|w| {
+                Ok(WindowInfo {
+                    id: w.id()?,
+                    title: w.title()?,
+                    app_name: w.app_name()?,
+                    x: w.x()?,
+                    y: w.y()?,
+                    width: w.width()?,
+                    height: w.height()?,
+                })
+            })
+            .collect()
     })
     .await
     .map_err(|e| anyhow::anyhow!("Task join error: {}", e))?
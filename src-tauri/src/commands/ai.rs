@@ -2,7 +2,7 @@ use crate::ai::ocr::OcrOptions;
 use crate::capture::ImageStore;
 use serde::Serialize;
 use std::path::PathBuf;
-use tauri::Emitter;
+use tauri::{Emitter, Manager};
 use uuid::Uuid;
 
 #[derive(Serialize)]
@@ -201,6 +201,9 @@ pub fn run_ocr(
     };
     let output = crate::ai::ocr::run_ocr(&image, &opts, Some(&on_progress))
         .map_err(|e| format!("OCR failed: {e}"))?;
+    if let Some(index) = app.try_state::<crate::commands::search::SharedIndex>() {
+        index.add_ocr(uuid, &output.regions);
+    }
 
     let regions = output
         .regions
@@ -258,6 +261,34 @@ pub fn auto_blur_pii(
     Ok(blur_regions)
 }
 
+/// OCR `image` in `lang` and redact every piece of PII found in `style`.
+/// Returns the redacted image and the matches.
+pub(crate) fn redact_pii(
+    app: &tauri::AppHandle,
+    image: &image::DynamicImage,
+    lang: &str,
+    style: crate::annotate::RedactStyle,
+) -> Result<(image::RgbaImage, Vec<crate::ai::pii::PiiMatch>), String> {
+    let tessdata_path = resolve_tessdata_path(app, lang)?;
+    let opts = OcrOptions {
+        lang: lang.to_owned(),
+        tessdata_path,
+    };
+    let ocr_output =
+        crate::ai::ocr::run_ocr(image, &opts, None).map_err(|e| format!("OCR failed: {e}"))?;
+    let pii_matches = crate::ai::pii::detect_pii(&ocr_output.regions)
+        .map_err(|e| format!("PII detection failed: {e}"))?;
+
+    let blurs: Vec<_> = pii_matches
+        .iter()
+        .map(|m| crate::annotate::Annotation::blur(m.x, m.y, m.w, m.h, style))
+        .collect();
+    let store = app.state::<crate::capture::ImageStore>();
+    let redacted =
+        crate::annotate::render_annotations(image, &blurs, &store).map_err(|e| e.to_string())?;
+    Ok((redacted, pii_matches))
+}
+
 #[tauri::command]
 pub async fn analyze_llm(
     app: tauri::AppHandle,
@@ -1,11 +1,26 @@
-use crate::capture::ImageStore;
+use crate::capture::lineage::{self, Lineage, Operation};
+use crate::capture::phash::SIMILAR_DISTANCE;
+use crate::capture::record::{
+    encode_animation, record, AnimationFormat, RecordOptions, RecordProgress, Recording,
+    DEFAULT_FPS,
+};
+use crate::capture::schedule::Schedule;
+use crate::capture::scroll::{scroll_capture, ScrollOptions, ScrollProgress};
+use crate::capture::session::{run_session, SessionRegistry, SessionSpec, SessionStatus};
+use crate::capture::{
+    capture_and_store, CaptureMetadata, CaptureMode, CaptureOptions, ImageStore, ScreenshotInfo,
+    SessionControl, SharedBackend, SimilarScreenshot, StoreStats,
+};
+use crate::commands::files::{expand_tilde, generate_default_path};
 use base64::prelude::*;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::io::Cursor;
 use std::sync::Arc;
-use tauri::Emitter;
+use tauri::{Emitter, Manager};
 use uuid::Uuid;
-use xcap::{Monitor, Window};
+
+pub use crate::capture::{MonitorInfo, WindowInfo};
 
 #[derive(Serialize, Clone)]
 pub struct ScreenshotResponse {
@@ -22,137 +37,97 @@ struct ScreenshotReadyEvent {
     height: u32,
 }
 
-#[derive(Serialize)]
-pub struct MonitorInfo {
-    pub id: u32,
-    pub name: String,
+/// Desktop rectangle for `mode: "region"`, in desktop coordinates.
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct CaptureRegion {
     pub x: i32,
     pub y: i32,
     pub width: u32,
     pub height: u32,
-    pub is_primary: bool,
 }
 
-#[derive(Serialize)]
-pub struct WindowInfo {
-    pub id: u32,
-    pub title: String,
-    pub app_name: String,
-    pub x: i32,
-    pub y: i32,
-    pub width: u32,
-    pub height: u32,
+/// Map the `mode` string and its mode-specific arguments to a [`CaptureMode`].
+fn parse_capture_mode(
+    mode: &str,
+    monitor: Option<u32>,
+    window_id: Option<u32>,
+    region: Option<CaptureRegion>,
+) -> Result<CaptureMode, String> {
+    match mode {
+        "fullscreen" => Ok(CaptureMode::Fullscreen),
+        "monitor" => {
+            let index = monitor.ok_or("monitor index required for mode 'monitor'")?;
+            tracing::info!("capture: monitor index={index}");
+            Ok(CaptureMode::Monitor(index))
+        }
+        "window" => {
+            let wid = window_id.ok_or("window_id required for mode 'window'")?;
+            tracing::info!("capture: window_id={wid}");
+            Ok(CaptureMode::Window(wid as u64))
+        }
+        "region" => {
+            let r = region.ok_or("region required for mode 'region'")?;
+            tracing::info!("capture: region {}x{}+{}+{}", r.width, r.height, r.x, r.y);
+            Ok(CaptureMode::Region {
+                x: r.x,
+                y: r.y,
+                w: r.width,
+                h: r.height,
+            })
+        }
+        other => Err(format!("Unknown capture mode '{}'", other)),
+    }
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn take_screenshot(
     mode: String,
     monitor: Option<u32>,
     window_id: Option<u32>,
+    region: Option<CaptureRegion>,
+    dedupe: Option<bool>,
     store: tauri::State<'_, ImageStore>,
+    backend: tauri::State<'_, SharedBackend>,
     app: tauri::AppHandle,
     window: tauri::WebviewWindow,
 ) -> Result<ScreenshotResponse, String> {
-    tracing::info!("take_screenshot: mode={mode}");
+    tracing::info!("take_screenshot: mode={mode} backend={}", backend.name());
 
-    let image = match mode.as_str() {
-        "fullscreen" => {
-            // Hide the app window so it doesn't appear in the screenshot.
-            tracing::info!("take_screenshot: hiding window");
-            let _ = window.hide();
-            // Give the compositor a moment to actually hide the window.
-            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
-
-            #[cfg(target_os = "linux")]
-            let in_flatpak = std::env::var("FLATPAK_ID").is_ok();
-            #[cfg(target_os = "linux")]
-            tracing::info!("take_screenshot: in_flatpak={in_flatpak}");
-            #[cfg(target_os = "linux")]
-            let result = if in_flatpak {
-                tracing::info!("take_screenshot: routing to portal backend");
-                crate::capture::portal::capture_via_portal()
-                    .await
-                    .map_err(|e| {
-                        tracing::error!("take_screenshot: portal failed: {e}");
-                        format!("Portal capture failed: {}", e)
-                    })
-            } else {
-                tracing::info!("take_screenshot: routing to xcap backend");
-                crate::capture::xcap_backend::capture_fullscreen()
-                    .await
-                    .map_err(|e| {
-                        tracing::error!("take_screenshot: xcap failed: {e}");
-                        format!("Capture failed: {}", e)
-                    })
-            };
-            #[cfg(not(target_os = "linux"))]
-            let result = {
-                tracing::info!("take_screenshot: routing to xcap backend");
-                crate::capture::xcap_backend::capture_fullscreen()
-                    .await
-                    .map_err(|e| {
-                        tracing::error!("take_screenshot: xcap failed: {e}");
-                        format!("Capture failed: {}", e)
-                    })
-            };
-
-            // Always restore the window before returning.
-            tracing::info!("take_screenshot: restoring window");
-            let _ = window.show();
-            let _ = window.set_focus();
+    let capture_mode = parse_capture_mode(&mode, monitor, window_id, region)?;
 
-            result?
-        }
-        "monitor" => {
-            let index = monitor.ok_or("monitor index required for mode 'monitor'")?;
-            tracing::info!("take_screenshot: monitor index={index}");
-
-            let _ = window.hide();
-            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
-
-            let result = crate::capture::xcap_backend::capture_monitor(index)
-                .await
-                .map_err(|e| {
-                    tracing::error!("take_screenshot: monitor capture failed: {e}");
-                    format!("Monitor capture failed: {}", e)
-                });
-
-            let _ = window.show();
-            let _ = window.set_focus();
+    // Hide the app window so it doesn't appear in desktop-wide captures.
+    let hide = !matches!(capture_mode, CaptureMode::Window(_));
+    if hide {
+        tracing::info!("take_screenshot: hiding window");
+        let _ = window.hide();
+        // Give the compositor a moment to actually hide the window.
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    }
 
-            result?
-        }
-        "window" => {
-            let wid = window_id.ok_or("window_id required for mode 'window'")?;
-            tracing::info!("take_screenshot: window_id={wid}");
-
-            crate::capture::xcap_backend::capture_window(wid)
-                .await
-                .map_err(|e| {
-                    tracing::error!("take_screenshot: window capture failed: {e}");
-                    format!("Window capture failed: {}", e)
-                })?
-        }
-        "region" => {
-            return Err("Region capture is handled in-app; use fullscreen + crop_image".into());
-        }
-        other => {
-            return Err(format!("Unknown capture mode '{}'", other));
-        }
+    let options = CaptureOptions {
+        reuse_duplicate: dedupe.unwrap_or(false),
+        ..crate::commands::settings::load_capture_settings(&app).capture_options()
     };
-    tracing::info!(
-        "take_screenshot: image captured ({}x{})",
-        image.width(),
-        image.height()
-    );
+    let result = capture_and_store(backend.as_ref(), &store, capture_mode, options)
+        .await
+        .map_err(|e| {
+            tracing::error!("take_screenshot: {} capture failed: {e}", backend.name());
+            format!("Capture failed: {}", e)
+        });
 
-    let image = Arc::new(image);
-    let width = image.width();
-    let height = image.height();
+    // Always restore the window before returning.
+    if hide {
+        tracing::info!("take_screenshot: restoring window");
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
 
-    // Generate UUID and store image (Arc clone, no pixel copy)
-    let id = Uuid::new_v4();
-    store.insert(id, Arc::clone(&image));
+    let result = result?;
+    let image = result.image;
+    let id = result.id;
+    let (width, height) = result.metadata.dimensions;
+    tracing::info!("take_screenshot: image captured ({width}x{height})");
 
     // Convert to base64 PNG data URL
     let mut png_data = Vec::new();
@@ -180,6 +155,64 @@ pub async fn take_screenshot(
     })
 }
 
+/// Metadata for a stored image: when and how it was captured, its source
+/// monitor/window, dimensions, scale factor and the image it was derived from.
+#[tauri::command]
+pub fn get_screenshot_info(
+    image_id: String,
+    store: tauri::State<'_, ImageStore>,
+) -> Result<Value, String> {
+    let id = Uuid::parse_str(&image_id).map_err(|_| format!("Invalid image ID: {image_id}"))?;
+    let metadata = store
+        .metadata(&id)
+        .ok_or_else(|| format!("No image found for ID: {image_id}"))?;
+    Ok(ScreenshotInfo { id, metadata }.to_json())
+}
+
+/// Screenshots in the library, newest first, including earlier sessions'.
+#[tauri::command]
+pub fn list_screenshots(limit: Option<usize>, store: tauri::State<'_, ImageStore>) -> Vec<Value> {
+    let mut all = store.list();
+    if let Some(limit) = limit {
+        all.truncate(limit);
+    }
+    all.iter().map(ScreenshotInfo::to_json).collect()
+}
+
+/// Delete a screenshot from memory and the on-disk library.
+#[tauri::command]
+pub fn delete_screenshot(
+    image_id: String,
+    store: tauri::State<'_, ImageStore>,
+) -> Result<(), String> {
+    let id = Uuid::parse_str(&image_id).map_err(|_| format!("Invalid image ID: {image_id}"))?;
+    if store.remove(&id) {
+        Ok(())
+    } else {
+        Err(format!("No image found for ID: {image_id}"))
+    }
+}
+
+/// Memory and disk cache usage of the image store.
+#[tauri::command]
+pub fn get_store_stats(store: tauri::State<'_, ImageStore>) -> StoreStats {
+    store.stats()
+}
+
+/// Stored screenshots that look like `image_id`, closest first.
+/// `max_distance` is in perceptual-hash bits (default 10 of 64).
+#[tauri::command]
+pub fn find_similar_screenshots(
+    image_id: String,
+    max_distance: Option<u32>,
+    store: tauri::State<'_, ImageStore>,
+) -> Result<Vec<SimilarScreenshot>, String> {
+    let id = Uuid::parse_str(&image_id).map_err(|_| format!("Invalid image ID: {image_id}"))?;
+    store
+        .find_similar(&id, max_distance.unwrap_or(SIMILAR_DISTANCE))
+        .ok_or_else(|| format!("No image found for ID: {image_id}"))
+}
+
 #[tauri::command]
 pub fn crop_image(
     image_id: String,
@@ -194,17 +227,13 @@ pub fn crop_image(
         .get(&id)
         .ok_or_else(|| format!("No image found for ID: {image_id}"))?;
 
-    // Clamp to image bounds to avoid panic
-    let img_w = base.width();
-    let img_h = base.height();
-    let x = x.min(img_w.saturating_sub(1));
-    let y = y.min(img_h.saturating_sub(1));
-    let width = width.min(img_w - x);
-    let height = height.min(img_h - y);
-
-    let cropped = Arc::new(base.crop_imm(x, y, width, height));
+    let (cropped, operation) = lineage::crop(&base, x, y, width, height);
+    let (width, height) = (cropped.width(), cropped.height());
+    let cropped = Arc::new(cropped);
     let new_id = Uuid::new_v4();
-    store.insert(new_id, Arc::clone(&cropped));
+    store
+        .insert_derived(new_id, Arc::clone(&cropped), &id, operation)
+        .ok_or_else(|| format!("No image found for ID: {image_id}"))?;
 
     let mut png_data = Vec::new();
     cropped
@@ -223,50 +252,558 @@ pub fn crop_image(
     })
 }
 
+/// Where an image came from and what has been derived from it.
 #[tauri::command]
-pub async fn list_monitors() -> Result<Vec<MonitorInfo>, String> {
-    tokio::task::spawn_blocking(|| {
-        let monitors = Monitor::all().map_err(|e| format!("Failed to enumerate monitors: {e}"))?;
-        monitors
-            .into_iter()
-            .map(|m| {
-                Ok(MonitorInfo {
-                    id: m.id().map_err(|e| format!("monitor.id: {e}"))?,
-                    name: m.name().map_err(|e| format!("monitor.name: {e}"))?,
-                    x: m.x().map_err(|e| format!("monitor.x: {e}"))?,
-                    y: m.y().map_err(|e| format!("monitor.y: {e}"))?,
-                    width: m.width().map_err(|e| format!("monitor.width: {e}"))?,
-                    height: m.height().map_err(|e| format!("monitor.height: {e}"))?,
-                    is_primary: m
-                        .is_primary()
-                        .map_err(|e| format!("monitor.is_primary: {e}"))?,
-                })
-            })
-            .collect()
+pub fn get_lineage(
+    image_id: String,
+    store: tauri::State<'_, ImageStore>,
+) -> Result<Lineage, String> {
+    let id = Uuid::parse_str(&image_id).map_err(|_| format!("Invalid image ID: {image_id}"))?;
+    store
+        .lineage(&id)
+        .ok_or_else(|| format!("No image found for ID: {image_id}"))
+}
+
+/// Replay the operations that produced `source_id` (from its original
+/// capture onwards) on `target_id`, storing each step as a derivative.
+/// Returns the final image.
+#[tauri::command]
+pub fn reapply_operations(
+    app: tauri::AppHandle,
+    source_id: String,
+    target_id: String,
+    store: tauri::State<'_, ImageStore>,
+) -> Result<Value, String> {
+    let source =
+        Uuid::parse_str(&source_id).map_err(|_| format!("Invalid image ID: {source_id}"))?;
+    let target =
+        Uuid::parse_str(&target_id).map_err(|_| format!("Invalid image ID: {target_id}"))?;
+    let operations = store
+        .lineage(&source)
+        .ok_or_else(|| format!("No image found for ID: {source_id}"))?
+        .operations;
+    replay_operations(&app, &store, target, &operations).map(|info| info.to_json())
+}
+
+/// Apply `operations` in order starting from `target`, inserting every
+/// intermediate result so the new chain has the same lineage shape.
+pub(crate) fn replay_operations(
+    app: &tauri::AppHandle,
+    store: &ImageStore,
+    target: Uuid,
+    operations: &[Operation],
+) -> Result<ScreenshotInfo, String> {
+    let mut current = target;
+    let mut image = store
+        .get(&current)
+        .ok_or_else(|| format!("No image found for ID: {target}"))?;
+    for operation in operations {
+        let (derived, applied) = apply_operation(app, store, &image, operation)?;
+        let id = Uuid::new_v4();
+        image = Arc::new(derived);
+        store
+            .insert_derived(id, Arc::clone(&image), &current, applied)
+            .ok_or_else(|| format!("No image found for ID: {current}"))?;
+        current = id;
+    }
+    let metadata = store
+        .metadata(&current)
+        .ok_or_else(|| format!("No image found for ID: {current}"))?;
+    Ok(ScreenshotInfo {
+        id: current,
+        metadata,
     })
+}
+
+/// Run one recorded operation on `image`. Returns the result and the
+/// operation as applied (a crop is clamped to the new image's bounds).
+fn apply_operation(
+    app: &tauri::AppHandle,
+    store: &ImageStore,
+    image: &image::DynamicImage,
+    operation: &Operation,
+) -> Result<(image::DynamicImage, Operation), String> {
+    match operation {
+        Operation::Crop {
+            x,
+            y,
+            width,
+            height,
+        } => Ok(lineage::crop(image, *x, *y, *width, *height)),
+        Operation::RedactPii { lang, style } => {
+            let (redacted, _) = crate::commands::ai::redact_pii(app, image, lang, *style)?;
+            Ok((image::DynamicImage::ImageRgba8(redacted), operation.clone()))
+        }
+        Operation::Annotate { annotations } => {
+            let parsed = crate::annotate::parse_annotations(annotations.clone())
+                .map_err(|e| e.to_string())?;
+            let rendered = crate::annotate::render_annotations(image, &parsed, store)
+                .map_err(|e| e.to_string())?;
+            Ok((image::DynamicImage::ImageRgba8(rendered), operation.clone()))
+        }
+    }
+}
+
+/// Run state of the scrolling capture, managed as Tauri state so
+/// `stop_scrolling_capture` can end a capture started by another call.
+#[derive(Default)]
+pub struct ScrollCaptureState(pub SessionControl);
+
+#[derive(Serialize, Clone)]
+pub struct ScrollProgressPayload {
+    pub frames: u32,
+    pub height: u32,
+}
+
+/// Capture a window or region repeatedly while the user scrolls it and
+/// stitch the frames into one tall screenshot.
+///
+/// Ends when `stop_scrolling_capture` is called, when the content stops
+/// moving for a couple of seconds, or when a frame/height limit is reached.
+/// Emits `scroll-capture:progress` events with `{ frames, height }` after
+/// every frame.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn start_scrolling_capture(
+    mode: String,
+    window_id: Option<u32>,
+    region: Option<CaptureRegion>,
+    interval_ms: Option<u64>,
+    max_frames: Option<u32>,
+    store: tauri::State<'_, ImageStore>,
+    backend: tauri::State<'_, SharedBackend>,
+    scroll: tauri::State<'_, ScrollCaptureState>,
+    app: tauri::AppHandle,
+    window: tauri::WebviewWindow,
+) -> Result<ScreenshotResponse, String> {
+    if !matches!(mode.as_str(), "window" | "region") {
+        return Err(format!(
+            "Scrolling capture supports 'window' and 'region', not '{mode}'"
+        ));
+    }
+    let capture_mode = parse_capture_mode(&mode, None, window_id, region)?;
+
+    if !scroll.0.try_begin() {
+        return Err("A scrolling capture is already running".into());
+    }
+
+    let mut opts = ScrollOptions::default();
+    if let Some(ms) = interval_ms {
+        opts.interval = std::time::Duration::from_millis(ms.max(50));
+    }
+    if let Some(n) = max_frames {
+        opts.max_frames = n.max(1);
+    }
+    tracing::info!("start_scrolling_capture: mode={mode} {opts:?}");
+
+    let hide = !matches!(capture_mode, CaptureMode::Window(_));
+    if hide {
+        let _ = window.hide();
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    }
+
+    let progress_app = app.clone();
+    let on_progress = move |p: ScrollProgress| {
+        let _ = progress_app.emit(
+            "scroll-capture:progress",
+            ScrollProgressPayload {
+                frames: p.frames,
+                height: p.height,
+            },
+        );
+    };
+    let stitched = scroll_capture(
+        backend.as_ref(),
+        capture_mode.clone(),
+        opts,
+        scroll.0.stop_flag(),
+        Some(&on_progress),
+    )
     .await
-    .map_err(|e| format!("Task join error: {e}"))?
+    .map_err(|e| format!("Scrolling capture failed: {e}"));
+
+    if hide {
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+    scroll.0.end();
+
+    let image = Arc::new(image::DynamicImage::ImageRgba8(stitched?));
+    let id = Uuid::new_v4();
+    let (width, height) = (image.width(), image.height());
+    store.insert(
+        id,
+        Arc::clone(&image),
+        CaptureMetadata::new(capture_mode, (width, height)),
+    );
+
+    let mut png_data = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png_data), image::ImageFormat::Png)
+        .map_err(|e| format!("PNG encoding failed: {e}"))?;
+    let data_url = format!(
+        "data:image/png;base64,{}",
+        BASE64_STANDARD.encode(&png_data)
+    );
+
+    app.emit(
+        "screenshot-ready",
+        ScreenshotReadyEvent {
+            id: id.to_string(),
+            width,
+            height,
+        },
+    )
+    .map_err(|e| format!("Failed to emit event: {e}"))?;
+
+    Ok(ScreenshotResponse {
+        id: id.to_string(),
+        width,
+        height,
+        data_url,
+    })
 }
 
+/// Ask the running scrolling capture to finish with the frames so far.
 #[tauri::command]
-pub async fn list_windows() -> Result<Vec<WindowInfo>, String> {
-    tokio::task::spawn_blocking(|| {
-        let windows = Window::all().map_err(|e| format!("Failed to enumerate windows: {e}"))?;
-        windows
-            .into_iter()
-            .map(|w| {
-                Ok(WindowInfo {
-                    id: w.id().map_err(|e| format!("window.id: {e}"))?,
-                    title: w.title().map_err(|e| format!("window.title: {e}"))?,
-                    app_name: w.app_name().map_err(|e| format!("window.app_name: {e}"))?,
-                    x: w.x().map_err(|e| format!("window.x: {e}"))?,
-                    y: w.y().map_err(|e| format!("window.y: {e}"))?,
-                    width: w.width().map_err(|e| format!("window.width: {e}"))?,
-                    height: w.height().map_err(|e| format!("window.height: {e}"))?,
-                })
-            })
-            .collect()
+pub fn stop_scrolling_capture(scroll: tauri::State<'_, ScrollCaptureState>) {
+    scroll.0.request_stop();
+}
+
+/// Run state of the screen recorder, shared by the Tauri commands and the
+/// IPC `record_screen` handler.
+#[derive(Default)]
+pub struct RecordingState(pub SessionControl);
+
+#[derive(Serialize, Clone)]
+pub struct RecordingProgressPayload {
+    pub elapsed_ms: u64,
+    pub frames: u32,
+}
+
+#[derive(Serialize, Clone)]
+pub struct RecordingResponse {
+    pub path: String,
+    pub format: String,
+    pub frames: u32,
+    pub width: u32,
+    pub height: u32,
+    pub duration_ms: u64,
+    pub size_bytes: u64,
+}
+
+/// Encode `recording` and write it to `path`, or to a timestamped file in
+/// `~/Pictures/Fotos` when `path` is empty.
+pub(crate) fn write_recording(
+    recording: &Recording,
+    format: AnimationFormat,
+    path: &str,
+) -> Result<RecordingResponse, String> {
+    let bytes = encode_animation(&recording.frames, format)
+        .map_err(|e| format!("Failed to encode {}: {e}", format.name()))?;
+    let out_path = if path.is_empty() {
+        generate_default_path(format.extension())?
+    } else {
+        expand_tilde(path)?
+    };
+    if let Some(parent) = out_path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create directory: {e}"))?;
+    }
+    std::fs::write(&out_path, &bytes).map_err(|e| format!("Failed to write recording: {e}"))?;
+
+    let (width, height) = recording.dimensions();
+    Ok(RecordingResponse {
+        path: out_path.to_string_lossy().to_string(),
+        format: format.name().to_string(),
+        frames: recording.frames.len() as u32,
+        width,
+        height,
+        duration_ms: recording.duration_ms(),
+        size_bytes: bytes.len() as u64,
     })
+}
+
+/// Record the screen to an animated GIF, APNG or WebP file.
+///
+/// Samples `mode` at `fps` (default 10, max 30) for `duration_ms` (max 60 s)
+/// or until `stop_recording` is called. Identical consecutive frames are
+/// merged. Emits `recording:progress` events with `{ elapsed_ms, frames }`.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn start_recording(
+    mode: String,
+    monitor: Option<u32>,
+    window_id: Option<u32>,
+    region: Option<CaptureRegion>,
+    format: String,
+    fps: Option<u32>,
+    duration_ms: u64,
+    path: Option<String>,
+    backend: tauri::State<'_, SharedBackend>,
+    recording: tauri::State<'_, RecordingState>,
+    app: tauri::AppHandle,
+    window: tauri::WebviewWindow,
+) -> Result<RecordingResponse, String> {
+    let capture_mode = parse_capture_mode(&mode, monitor, window_id, region)?;
+    let format = AnimationFormat::parse(&format).map_err(|e| e.to_string())?;
+    let opts = RecordOptions {
+        fps: fps.unwrap_or(DEFAULT_FPS),
+        duration: std::time::Duration::from_millis(duration_ms),
+        include_cursor: crate::commands::settings::load_capture_settings(&app).include_mouse_cursor,
+    };
+
+    if !recording.0.try_begin() {
+        return Err("A recording is already running".into());
+    }
+    tracing::info!(
+        "start_recording: mode={mode} format={} {opts:?}",
+        format.name()
+    );
+
+    let hide = !matches!(capture_mode, CaptureMode::Window(_));
+    if hide {
+        let _ = window.hide();
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    }
+
+    let progress_app = app.clone();
+    let on_progress = move |p: RecordProgress| {
+        let _ = progress_app.emit(
+            "recording:progress",
+            RecordingProgressPayload {
+                elapsed_ms: p.elapsed_ms,
+                frames: p.frames,
+            },
+        );
+    };
+    let result = record(
+        backend.as_ref(),
+        capture_mode,
+        opts,
+        recording.0.stop_flag(),
+        Some(&on_progress),
+    )
     .await
-    .map_err(|e| format!("Task join error: {e}"))?
+    .map_err(|e| format!("Recording failed: {e}"));
+
+    if hide {
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+    recording.0.end();
+
+    let rec = result?;
+    let path = path.unwrap_or_default();
+    tokio::task::spawn_blocking(move || write_recording(&rec, format, &path))
+        .await
+        .map_err(|e| format!("Task join error: {e}"))?
+}
+
+/// Ask the running recording to finish early; the frames so far are kept.
+#[tauri::command]
+pub fn stop_recording(recording: tauri::State<'_, RecordingState>) {
+    recording.0.request_stop();
+}
+
+#[derive(Serialize, Clone)]
+struct SessionFramePayload {
+    session_id: String,
+    screenshot_id: String,
+    index: u32,
+    timestamp: String,
+}
+
+/// Build a session spec from command arguments. Exactly one of
+/// `interval_secs` and `cron` must be given.
+pub(crate) fn build_session_spec(
+    mode: CaptureMode,
+    interval_secs: Option<f64>,
+    cron: Option<&str>,
+    duration_secs: u64,
+    max_frames: Option<u32>,
+    skip_unchanged: bool,
+    options: CaptureOptions,
+) -> Result<SessionSpec, String> {
+    let schedule = match (interval_secs, cron) {
+        (Some(secs), None) => {
+            let interval = std::time::Duration::try_from_secs_f64(secs)
+                .map_err(|e| format!("Invalid interval: {e}"))?;
+            Schedule::every(interval)
+        }
+        (None, Some(expr)) => Schedule::cron(expr),
+        _ => return Err("Specify either interval_secs or cron".into()),
+    }
+    .map_err(|e| format!("Invalid schedule: {e:#}"))?;
+    Ok(SessionSpec {
+        mode,
+        schedule,
+        duration: std::time::Duration::from_secs(duration_secs),
+        max_frames,
+        skip_unchanged,
+        options,
+    })
+}
+
+/// Register a session and run it in the background, emitting
+/// `capture-session:frame` per stored frame and `capture-session:finished`
+/// with the final status.
+pub(crate) fn spawn_capture_session(
+    app: &tauri::AppHandle,
+    spec: SessionSpec,
+) -> Result<SessionStatus, String> {
+    let registry = app.state::<SessionRegistry>();
+    let session = registry.create(spec).map_err(|e| e.to_string())?;
+    let status = session.status();
+    tracing::info!("start_capture_session: {}", status.id);
+
+    let backend: SharedBackend = Arc::new(HidingBackend {
+        app: app.clone(),
+        inner: app.state::<SharedBackend>().inner().clone(),
+    });
+    let store = app.state::<ImageStore>().inner().clone();
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let frame_app = app.clone();
+        let on_frame = move |session_id: Uuid, frame: &crate::capture::session::SessionFrame| {
+            let _ = frame_app.emit(
+                "capture-session:frame",
+                SessionFramePayload {
+                    session_id: session_id.to_string(),
+                    screenshot_id: frame.id.to_string(),
+                    index: frame.index,
+                    timestamp: frame.metadata.timestamp.to_rfc3339(),
+                },
+            );
+        };
+        let status = run_session(session, backend, store, on_frame).await;
+        let _ = app.emit("capture-session:finished", status);
+    });
+    Ok(status)
+}
+
+/// Backend for scheduled captures: hides the main window, if shown, around
+/// each desktop-wide capture so Fotos stays out of the frames, without
+/// keeping it hidden for the whole session.
+struct HidingBackend {
+    app: tauri::AppHandle,
+    inner: SharedBackend,
+}
+
+#[async_trait::async_trait]
+impl crate::capture::CaptureBackend for HidingBackend {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    async fn capture(
+        &self,
+        mode: CaptureMode,
+        options: CaptureOptions,
+    ) -> anyhow::Result<crate::capture::CaptureResult> {
+        let window = self
+            .app
+            .get_webview_window("main")
+            .filter(|w| !matches!(mode, CaptureMode::Window(_)) && w.is_visible().unwrap_or(false));
+        if let Some(window) = &window {
+            let _ = window.hide();
+            tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+        }
+        let result = self.inner.capture(mode, options).await;
+        if let Some(window) = &window {
+            let _ = window.show();
+        }
+        result
+    }
+
+    async fn list_monitors(&self) -> anyhow::Result<Vec<MonitorInfo>> {
+        self.inner.list_monitors().await
+    }
+
+    async fn list_windows(&self) -> anyhow::Result<Vec<WindowInfo>> {
+        self.inner.list_windows().await
+    }
+}
+
+fn parse_session_id(session_id: &str) -> Result<Uuid, String> {
+    Uuid::parse_str(session_id).map_err(|e| format!("Invalid session ID: {e}"))
+}
+
+/// Start an interval or cron-scheduled capture session that runs for
+/// `duration_secs` (max 24 h) in the background. Returns immediately with
+/// the session status; poll `get_capture_session` or listen for
+/// `capture-session:frame` / `capture-session:finished` events.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn start_capture_session(
+    mode: String,
+    monitor: Option<u32>,
+    window_id: Option<u32>,
+    region: Option<CaptureRegion>,
+    interval_secs: Option<f64>,
+    cron: Option<String>,
+    duration_secs: u64,
+    max_frames: Option<u32>,
+    skip_unchanged: Option<bool>,
+    app: tauri::AppHandle,
+) -> Result<SessionStatus, String> {
+    let capture_mode = parse_capture_mode(&mode, monitor, window_id, region)?;
+    let spec = build_session_spec(
+        capture_mode,
+        interval_secs,
+        cron.as_deref(),
+        duration_secs,
+        max_frames,
+        skip_unchanged.unwrap_or(false),
+        crate::commands::settings::load_capture_settings(&app).capture_options(),
+    )?;
+    spawn_capture_session(&app, spec)
+}
+
+/// Stop a capture session; frames captured so far stay in the store.
+#[tauri::command]
+pub fn stop_capture_session(
+    session_id: String,
+    sessions: tauri::State<'_, SessionRegistry>,
+) -> Result<(), String> {
+    let id = parse_session_id(&session_id)?;
+    let session = sessions.get(&id).ok_or("Capture session not found")?;
+    session.stop();
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_capture_session(
+    session_id: String,
+    sessions: tauri::State<'_, SessionRegistry>,
+) -> Result<SessionStatus, String> {
+    let id = parse_session_id(&session_id)?;
+    sessions
+        .get(&id)
+        .map(|s| s.status())
+        .ok_or_else(|| "Capture session not found".into())
+}
+
+#[tauri::command]
+pub fn list_capture_sessions(sessions: tauri::State<'_, SessionRegistry>) -> Vec<SessionStatus> {
+    sessions.list()
+}
+
+#[tauri::command]
+pub async fn list_monitors(
+    backend: tauri::State<'_, SharedBackend>,
+) -> Result<Vec<MonitorInfo>, String> {
+    backend
+        .list_monitors()
+        .await
+        .map_err(|e| format!("Failed to enumerate monitors: {e}"))
+}
+
+#[tauri::command]
+pub async fn list_windows(
+    backend: tauri::State<'_, SharedBackend>,
+) -> Result<Vec<WindowInfo>, String> {
+    backend
+        .list_windows()
+        .await
+        .map_err(|e| format!("Failed to enumerate windows: {e}"))
 }
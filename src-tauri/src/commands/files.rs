@@ -1,73 +1,51 @@
+use crate::ai::ocr::OcrOptions;
+use crate::ai::search::IndexedWord;
+use crate::annotate::{
+    parse_annotations, read_annotation_file, render_annotations, render_svg, Annotation,
+    AnnotationFile,
+};
 use crate::capture::ImageStore;
-use ab_glyph::{Font as _, FontVec, PxScale, ScaleFont as _};
+use crate::commands::capture::ScreenshotResponse;
+use crate::commands::search::SharedIndex;
+use crate::commands::settings::load_capture_settings;
+use crate::encode::{self, EncodeOptions};
+use crate::pdf;
+use crate::project::{self, Project};
 use base64::Engine;
 use chrono::Local;
 use directories::UserDirs;
-use image::Rgba;
-use imageproc::drawing::{
-    draw_filled_circle_mut, draw_filled_ellipse_mut, draw_hollow_ellipse_mut, draw_hollow_rect_mut,
-    draw_line_segment_mut, draw_text_mut,
-};
-use imageproc::rect::Rect;
-use serde::{Deserialize, Serialize};
 use std::fs::File;
-use std::io::{BufWriter, Cursor};
-use std::path::PathBuf;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tauri::Manager;
 use uuid::Uuid;
 
-#[derive(Deserialize, Serialize)]
-pub struct Point {
-    pub x: f64,
-    pub y: f64,
-}
-
-#[derive(Deserialize, Serialize)]
-#[serde(rename_all = "camelCase")]
-pub struct Annotation {
-    pub id: String,
-    #[serde(rename = "type")]
-    pub annotation_type: String,
-    pub x: f64,
-    pub y: f64,
-    pub width: Option<f64>,
-    pub height: Option<f64>,
-    pub stroke_color: Option<String>,
-    pub fill_color: Option<String>,
-    pub stroke_width: Option<f64>,
-    pub opacity: Option<f64>,
-    pub text: Option<String>,
-    pub font_size: Option<f64>,
-    pub font_family: Option<String>,
-    pub points: Option<Vec<Point>>,
-    pub step_number: Option<u32>,
-    pub blur_radius: Option<f64>,
-    pub highlight_color: Option<String>,
-    pub created_at: Option<String>,
-    pub locked: Option<bool>,
-}
-
+/// Save `image_id` with its annotations to `path`, or to a timestamped file
+/// in `~/Pictures/Fotos`, encoded with the quality and PNG optimisation from
+/// the capture settings.
 #[tauri::command]
 pub fn save_image(
+    app: tauri::AppHandle,
     image_id: String,
-    annotations: Vec<Annotation>,
+    annotations: serde_json::Value,
     format: String,
     path: String,
     store: tauri::State<'_, ImageStore>,
 ) -> Result<String, String> {
+    let annotations = parse_annotations(annotations).map_err(|e| e.to_string())?;
     let uuid = Uuid::parse_str(&image_id).map_err(|e| format!("Invalid image ID: {}", e))?;
 
     let base_image = store
         .get(&uuid)
         .ok_or_else(|| format!("Image not found: {}", image_id))?;
 
-    let mut composite = base_image.to_rgba8();
-
-    for anno in &annotations {
-        composite_annotation(&mut composite, anno);
-    }
-
     let (save_path, user_chosen) = if path.is_empty() {
-        (generate_default_path()?, false)
+        let ext = if format.eq_ignore_ascii_case("svg") {
+            "svg"
+        } else {
+            "png"
+        };
+        (generate_default_path(ext)?, false)
     } else {
         (expand_tilde(&path)?, true)
     };
@@ -98,60 +76,207 @@ pub fn save_image(
             .map_err(|e| format!("Failed to create directory: {}", e))?;
     }
 
+    if wants_svg(&save_path, &format) {
+        let svg = render_svg(&base_image, &annotations, &store).map_err(|e| e.to_string())?;
+        std::fs::write(&save_path, svg).map_err(|e| format!("Failed to write SVG: {e}"))?;
+        return Ok(save_path.to_string_lossy().to_string());
+    }
+
+    let composite =
+        render_annotations(&base_image, &annotations, &store).map_err(|e| e.to_string())?;
     let fmt = detect_format(&save_path, &format);
-    write_image_to_file(composite, &save_path, fmt, JPEG_QUALITY_DEFAULT)?;
+    let options = load_capture_settings(&app).encode_options();
+    write_image_to_file(composite, &save_path, fmt, &options)?;
 
     Ok(save_path.to_string_lossy().to_string())
 }
 
 /// Composite annotations onto an image and return as a base64-encoded image.
 ///
-/// `format` controls the output encoding: `"png"` (default), `"jpeg"`, or `"webp"`.
+/// Malformed annotations (unknown `type`, missing or out-of-range fields)
+/// fail the whole call with one message per problem.
+///
+/// `format` controls the output encoding: `"png"` (default), `"jpeg"`, `"webp"`,
+/// `"avif"`, `"tiff"`, `"qoi"`, or `"svg"` for the screenshot with each
+/// annotation as an editable vector layer.
+/// Quality and PNG optimisation come from the capture settings.
 #[tauri::command]
 pub fn composite_image(
+    app: tauri::AppHandle,
     image_id: String,
-    annotations: Vec<Annotation>,
+    annotations: serde_json::Value,
     format: Option<String>,
     store: tauri::State<'_, ImageStore>,
 ) -> Result<String, String> {
+    let annotations = parse_annotations(annotations).map_err(|e| e.to_string())?;
     let uuid = Uuid::parse_str(&image_id).map_err(|e| format!("Invalid image ID: {}", e))?;
 
     let base_image = store
         .get(&uuid)
         .ok_or_else(|| format!("Image not found: {}", image_id))?;
 
-    let mut composite = base_image.to_rgba8();
-
-    for anno in &annotations {
-        composite_annotation(&mut composite, anno);
+    let hint = format.as_deref().unwrap_or("png");
+    if hint.eq_ignore_ascii_case("svg") {
+        let svg = render_svg(&base_image, &annotations, &store).map_err(|e| e.to_string())?;
+        return Ok(base64::engine::general_purpose::STANDARD.encode(svg));
     }
 
-    let hint = format.as_deref().unwrap_or("png");
+    let composite =
+        render_annotations(&base_image, &annotations, &store).map_err(|e| e.to_string())?;
     let fmt = format_from_hint(hint);
-    let bytes = encode_to_bytes(composite, fmt, JPEG_QUALITY_DEFAULT)?;
+    let options = load_capture_settings(&app).encode_options();
+    let bytes = encode_to_bytes(composite, fmt, &options)?;
 
     Ok(base64::engine::general_purpose::STANDARD.encode(bytes))
 }
 
+/// One page of a PDF export: a stored screenshot and the annotations to
+/// composite onto it.
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfPage {
+    pub image_id: String,
+    #[serde(default)]
+    pub annotations: Option<serde_json::Value>,
+}
+
+/// Write `pages` to one PDF at `path` (or a timestamped file in
+/// `~/Pictures/Fotos`), one page per screenshot with its annotations
+/// composited.
+///
+/// With `ocr_text`, each page gets an invisible layer of its OCR words so
+/// the document can be searched and copied from. Unannotated pages use the
+/// words already in the search index; annotated pages are OCR'd as
+/// composited, so text under a redaction never reaches the file.
+#[tauri::command]
+pub async fn export_pdf(
+    app: tauri::AppHandle,
+    pages: Vec<PdfPage>,
+    path: String,
+    ocr_text: Option<bool>,
+    store: tauri::State<'_, ImageStore>,
+) -> Result<String, String> {
+    let mut sources = Vec::with_capacity(pages.len());
+    for page in pages {
+        let id = Uuid::parse_str(&page.image_id)
+            .map_err(|e| format!("Invalid image ID {}: {e}", page.image_id))?;
+        let annotations = match page.annotations {
+            Some(value) => {
+                parse_annotations(value).map_err(|e| format!("Page {}: {e}", sources.len() + 1))?
+            }
+            None => Vec::new(),
+        };
+        sources.push((id, annotations));
+    }
+    if sources.is_empty() {
+        return Err("No screenshots to export".to_string());
+    }
+
+    let save_path = if path.is_empty() {
+        generate_default_path("pdf")?
+    } else {
+        expand_tilde(&path)?
+    };
+    if let Some(parent) = save_path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create directory: {}", e))?;
+    }
+
+    let store = store.inner().clone();
+    let ocr_text = ocr_text.unwrap_or(false);
+    let target = save_path.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut pages = Vec::with_capacity(sources.len());
+        for (id, annotations) in sources {
+            let base = store
+                .get(&id)
+                .ok_or_else(|| format!("Image not found: {id}"))?;
+            let image =
+                render_annotations(&base, &annotations, &store).map_err(|e| e.to_string())?;
+            let words = if ocr_text {
+                page_words(&app, id, &image, !annotations.is_empty())?
+            } else {
+                Vec::new()
+            };
+            pages.push(pdf::Page { image, words });
+        }
+        let bytes = pdf::write(&pages).map_err(|e| format!("{e:#}"))?;
+        std::fs::write(&target, bytes).map_err(|e| format!("Failed to write PDF: {e}"))
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+
+    Ok(save_path.to_string_lossy().to_string())
+}
+
+/// OCR words for a PDF page: the search index's for an unannotated
+/// screenshot, OCR'ing it (and indexing the result) if they are missing,
+/// or a fresh OCR of an annotated page as it appears, in the OCR language
+/// from settings.
+fn page_words(
+    app: &tauri::AppHandle,
+    id: Uuid,
+    page: &image::RgbaImage,
+    annotated: bool,
+) -> Result<Vec<IndexedWord>, String> {
+    let index = if annotated {
+        None
+    } else {
+        app.try_state::<SharedIndex>()
+    };
+    if let Some(words) = index.as_ref().and_then(|index| index.words(&id)) {
+        return Ok(words);
+    }
+
+    let lang = crate::commands::settings::load_ai_settings(app).ocr_language;
+    let opts = OcrOptions {
+        tessdata_path: crate::commands::ai::resolve_tessdata_path(app, &lang)?,
+        lang,
+    };
+    let output =
+        crate::ai::ocr::run_ocr(&image::DynamicImage::ImageRgba8(page.clone()), &opts, None)
+            .map_err(|e| format!("OCR failed: {e}"))?;
+    if let Some(index) = index {
+        index.add_ocr(id, &output.regions);
+    }
+    Ok(output.regions.iter().map(IndexedWord::from).collect())
+}
+
+/// The picture an image annotation shows (a stored screenshot or an image
+/// file), as a base64-encoded PNG for the editor to draw.
+#[tauri::command]
+pub fn load_annotation_image(
+    image_id: Option<String>,
+    path: Option<String>,
+    store: tauri::State<'_, ImageStore>,
+) -> Result<String, String> {
+    let picture = crate::annotate::load_image(image_id.as_deref(), path.as_deref(), &store)?;
+    let bytes = encode_to_bytes(
+        picture.to_rgba8(),
+        image::ImageFormat::Png,
+        &EncodeOptions::default(),
+    )?;
+    Ok(base64::engine::general_purpose::STANDARD.encode(bytes))
+}
+
 #[tauri::command]
 pub fn copy_to_clipboard(
     app: tauri::AppHandle,
     image_id: String,
-    annotations: Vec<Annotation>,
+    annotations: serde_json::Value,
     store: tauri::State<'_, ImageStore>,
 ) -> Result<(), String> {
     use tauri_plugin_clipboard_manager::ClipboardExt;
 
+    let annotations = parse_annotations(annotations).map_err(|e| e.to_string())?;
     let uuid = Uuid::parse_str(&image_id).map_err(|e| format!("Invalid image ID: {}", e))?;
 
     let base_image = store
         .get(&uuid)
         .ok_or_else(|| format!("Image not found: {}", image_id))?;
 
-    let mut composite = base_image.to_rgba8();
-    for anno in &annotations {
-        composite_annotation(&mut composite, anno);
-    }
+    let composite =
+        render_annotations(&base_image, &annotations, &store).map_err(|e| e.to_string())?;
 
     let (width, height) = composite.dimensions();
     let rgba_bytes = composite.into_raw();
@@ -162,11 +287,14 @@ pub fn copy_to_clipboard(
         .map_err(|e| format!("Failed to copy to clipboard: {}", e))
 }
 
+/// Save `annotations` to a JSON file the user picks, in an envelope with
+/// the schema version and the size of the image they were drawn on.
 #[tauri::command]
 pub async fn export_annotations(
     app: tauri::AppHandle,
     image_id: String,
     annotations: Vec<Annotation>,
+    store: tauri::State<'_, ImageStore>,
 ) -> Result<String, String> {
     use tauri_plugin_dialog::DialogExt;
     use tokio::sync::oneshot;
@@ -188,16 +316,28 @@ pub async fn export_annotations(
         .into_path()
         .map_err(|e| format!("invalid path: {e}"))?;
 
-    let json = serde_json::to_string_pretty(&annotations)
-        .map_err(|e| format!("serialization error: {e}"))?;
+    let image_size = Uuid::parse_str(&image_id)
+        .ok()
+        .and_then(|id| store.metadata(&id))
+        .map(|m| m.dimensions.into());
+    let file = AnnotationFile::new(annotations, image_size);
+    let json =
+        serde_json::to_string_pretty(&file).map_err(|e| format!("serialization error: {e}"))?;
 
     std::fs::write(&path, json).map_err(|e| format!("write error: {e}"))?;
 
     Ok(path.to_string_lossy().to_string())
 }
 
+/// Load annotations from a JSON file the user picks, migrating files from
+/// older versions and rescaling them onto `image_id` if it has a different
+/// size than the image they were drawn on.
 #[tauri::command]
-pub async fn import_annotations(app: tauri::AppHandle) -> Result<Vec<Annotation>, String> {
+pub async fn import_annotations(
+    app: tauri::AppHandle,
+    image_id: Option<String>,
+    store: tauri::State<'_, ImageStore>,
+) -> Result<Vec<Annotation>, String> {
     use tauri_plugin_dialog::DialogExt;
     use tokio::sync::oneshot;
 
@@ -218,19 +358,140 @@ pub async fn import_annotations(app: tauri::AppHandle) -> Result<Vec<Annotation>
 
     let content = std::fs::read_to_string(&path).map_err(|e| format!("read error: {e}"))?;
 
-    serde_json::from_str(&content).map_err(|e| format!("invalid JSON: {e}"))
+    let value = serde_json::from_str(&content).map_err(|e| format!("invalid JSON: {e}"))?;
+    let target = image_id
+        .and_then(|id| Uuid::parse_str(&id).ok())
+        .and_then(|id| store.metadata(&id))
+        .map(|m| m.dimensions.into());
+    read_annotation_file(value, target)
 }
 
-// ── Image encoding helpers ────────────────────────────────────────────────────
+/// A project bundle opened into the store, ready for the editor.
+#[derive(serde::Serialize)]
+pub struct OpenedProject {
+    #[serde(flatten)]
+    pub screenshot: ScreenshotResponse,
+    pub annotations: Vec<Annotation>,
+}
 
-/// Default JPEG quality (matches `CaptureSettings::jpeg_quality` default).
-const JPEG_QUALITY_DEFAULT: u8 = 85;
+/// Save the image with its annotations, metadata, OCR words and lineage as
+/// a `.fotos` project bundle at `path`, or where the user picks when
+/// `path` is not given.
+#[tauri::command]
+pub async fn save_project(
+    app: tauri::AppHandle,
+    image_id: String,
+    annotations: serde_json::Value,
+    path: Option<String>,
+    store: tauri::State<'_, ImageStore>,
+) -> Result<String, String> {
+    let annotations = parse_annotations(annotations).map_err(|e| e.to_string())?;
+    let uuid = Uuid::parse_str(&image_id).map_err(|e| format!("Invalid image ID: {}", e))?;
+    let index = app.try_state::<SharedIndex>();
+    let project = Project::from_store(&store, index.as_deref().map(|i| &**i), uuid, annotations)
+        .ok_or_else(|| format!("Image not found: {}", image_id))?;
+
+    let path = match path.filter(|p| !p.is_empty()) {
+        Some(path) => expand_tilde(&path)?,
+        None => {
+            let default_name = format!(
+                "fotos-{}.{}",
+                &image_id[..image_id.len().min(8)],
+                project::EXTENSION
+            );
+            pick_project_path(&app, Some(default_name)).await?
+        }
+    };
+    tokio::task::spawn_blocking({
+        let path = path.clone();
+        move || project::save(&project, &path)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| format!("{e:#}"))?;
+
+    Ok(path.to_string_lossy().to_string())
+}
+
+/// Open a `.fotos` project bundle from `path`, or one the user picks, and
+/// put its image back into the store under its original id (or a new one,
+/// if a different image already has that id).
+#[tauri::command]
+pub async fn open_project(
+    app: tauri::AppHandle,
+    path: Option<String>,
+    store: tauri::State<'_, ImageStore>,
+) -> Result<OpenedProject, String> {
+    let path = match path.filter(|p| !p.is_empty()) {
+        Some(path) => expand_tilde(&path)?,
+        None => pick_project_path(&app, None).await?,
+    };
+    let mut project = tokio::task::spawn_blocking(move || project::open(&path))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| format!("{e:#}"))?;
+
+    let index = app.try_state::<SharedIndex>();
+    project.restore(&store, index.as_deref().map(|i| &**i));
+
+    let bytes = encode_to_bytes(
+        project.image.to_rgba8(),
+        image::ImageFormat::Png,
+        &EncodeOptions::default(),
+    )?;
+    Ok(OpenedProject {
+        screenshot: ScreenshotResponse {
+            id: project.image_id.to_string(),
+            width: project.image.width(),
+            height: project.image.height(),
+            data_url: format!(
+                "data:image/png;base64,{}",
+                base64::engine::general_purpose::STANDARD.encode(bytes)
+            ),
+        },
+        annotations: project.annotations,
+    })
+}
+
+/// Ask the user for a project file: a save dialog when `save_as` names a
+/// default file, an open dialog otherwise.
+async fn pick_project_path(
+    app: &tauri::AppHandle,
+    save_as: Option<String>,
+) -> Result<PathBuf, String> {
+    use tauri_plugin_dialog::DialogExt;
+    use tokio::sync::oneshot;
+
+    let (tx, rx) = oneshot::channel();
+    let dialog = app
+        .dialog()
+        .file()
+        .add_filter("Fotos project", &[project::EXTENSION]);
+    let on_pick = move |path| {
+        let _ = tx.send(path);
+    };
+    match save_as {
+        Some(name) => dialog.set_file_name(&name).save_file(on_pick),
+        None => dialog.pick_file(on_pick),
+    }
+
+    rx.await
+        .map_err(|_| "dialog error".to_string())?
+        .ok_or_else(|| "cancelled".to_string())?
+        .into_path()
+        .map_err(|e| format!("invalid path: {e}"))
+}
+
+// ── Image encoding helpers ────────────────────────────────────────────────────
 
 /// Map a format hint string to an `ImageFormat`.
 fn format_from_hint(hint: &str) -> image::ImageFormat {
     match hint.to_ascii_lowercase().as_str() {
         "jpeg" | "jpg" => image::ImageFormat::Jpeg,
         "webp" => image::ImageFormat::WebP,
+        "avif" => image::ImageFormat::Avif,
+        "tiff" | "tif" => image::ImageFormat::Tiff,
+        "qoi" => image::ImageFormat::Qoi,
         _ => image::ImageFormat::Png,
     }
 }
@@ -240,523 +501,39 @@ fn detect_format(path: &PathBuf, hint: &str) -> image::ImageFormat {
     image::ImageFormat::from_path(path).unwrap_or_else(|_| format_from_hint(hint))
 }
 
+/// Whether to write SVG: a `.svg` path, or an `"svg"` hint for a path
+/// without an image extension.
+fn wants_svg(path: &Path, hint: &str) -> bool {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("svg") => true,
+        _ => image::ImageFormat::from_path(path).is_err() && hint.eq_ignore_ascii_case("svg"),
+    }
+}
+
 /// Encode `img` to bytes in the requested format.
-///
-/// JPEG strips the alpha channel (JPEG does not support transparency).
 fn encode_to_bytes(
     img: image::RgbaImage,
     fmt: image::ImageFormat,
-    jpeg_quality: u8,
+    options: &EncodeOptions,
 ) -> Result<Vec<u8>, String> {
-    let mut buf = Cursor::new(Vec::new());
-    match fmt {
-        image::ImageFormat::Jpeg => {
-            let rgb = image::DynamicImage::ImageRgba8(img).to_rgb8();
-            image::codecs::jpeg::JpegEncoder::new_with_quality(&mut buf, jpeg_quality)
-                .encode_image(&rgb)
-                .map_err(|e| format!("Failed to encode JPEG: {e}"))?;
-        }
-        _ => {
-            image::DynamicImage::ImageRgba8(img)
-                .write_to(&mut buf, fmt)
-                .map_err(|e| format!("Failed to encode image: {e}"))?;
-        }
-    }
-    Ok(buf.into_inner())
+    encode::encode_to_vec(img, fmt, options).map_err(|e| format!("{e:#}"))
 }
 
-/// Write `img` directly to a file with the given format and JPEG quality.
+/// Write `img` directly to a file with the given format and options.
 fn write_image_to_file(
     img: image::RgbaImage,
     path: &PathBuf,
     fmt: image::ImageFormat,
-    jpeg_quality: u8,
+    options: &EncodeOptions,
 ) -> Result<(), String> {
     let file = File::create(path).map_err(|e| format!("Failed to create file: {e}"))?;
     let mut w = BufWriter::new(file);
-    match fmt {
-        image::ImageFormat::Jpeg => {
-            let rgb = image::DynamicImage::ImageRgba8(img).to_rgb8();
-            image::codecs::jpeg::JpegEncoder::new_with_quality(&mut w, jpeg_quality)
-                .encode_image(&rgb)
-                .map_err(|e| format!("Failed to encode JPEG: {e}"))
-        }
-        _ => image::DynamicImage::ImageRgba8(img)
-            .write_to(&mut w, fmt)
-            .map_err(|e| format!("Failed to encode image: {e}")),
-    }
-}
-
-// ── Compositing dispatch ──────────────────────────────────────────────────────
-
-fn composite_annotation(composite: &mut image::RgbaImage, anno: &Annotation) {
-    match anno.annotation_type.as_str() {
-        "rect" => composite_rectangle(composite, anno),
-        "arrow" => composite_arrow(composite, anno),
-        "ellipse" => composite_ellipse(composite, anno),
-        "freehand" => composite_freehand(composite, anno),
-        "highlight" => composite_highlight(composite, anno),
-        "blur" => composite_blur(composite, anno),
-        "step" => composite_step(composite, anno),
-        "text" => composite_text(composite, anno),
-        other => tracing::debug!("unknown annotation type: {other}"),
-    }
-}
-
-// ── Per-type compositing ──────────────────────────────────────────────────────
-
-fn composite_rectangle(composite: &mut image::RgbaImage, anno: &Annotation) {
-    let x = anno.x as i32;
-    let y = anno.y as i32;
-    let width = anno.width.unwrap_or(0.0) as i32;
-    let height = anno.height.unwrap_or(0.0) as i32;
-
-    if width <= 0 || height <= 0 {
-        return;
-    }
-
-    let opacity = (anno.opacity.unwrap_or(1.0).clamp(0.0, 1.0) * 255.0) as u8;
-
-    // Fill (drawn first so stroke overlays it).
-    let fill_str = anno.fill_color.as_deref().unwrap_or("transparent");
-    if let Ok(mut fill) = parse_color(fill_str) {
-        if fill[3] > 0 {
-            fill[3] = ((fill[3] as f64 / 255.0) * opacity as f64) as u8;
-            for py in y..(y + height) {
-                for px in x..(x + width) {
-                    if px >= 0
-                        && py >= 0
-                        && (px as u32) < composite.width()
-                        && (py as u32) < composite.height()
-                    {
-                        let base = composite.get_pixel_mut(px as u32, py as u32);
-                        blend_pixel(base, fill);
-                    }
-                }
-            }
-        }
-    }
-
-    // Stroke — centered on the rect edge (half inside, half outside).
-    let stroke_color_str = anno.stroke_color.as_deref().unwrap_or("#FF0000");
-    let Ok(stroke_color) = parse_color(stroke_color_str) else {
-        return;
-    };
-    if stroke_color[3] == 0 {
-        return;
-    }
-
-    let sw = anno.stroke_width.unwrap_or(2.0).max(0.0) as i32;
-    let half = sw / 2;
-
-    for i in 0..sw {
-        let offset = i - half;
-        let rx = x - offset;
-        let ry = y - offset;
-        let rw = width + offset * 2;
-        let rh = height + offset * 2;
-
-        if rw <= 0 || rh <= 0 {
-            continue;
-        }
-
-        let rect = Rect::at(rx, ry).of_size(rw as u32, rh as u32);
-        draw_hollow_rect_mut(composite, rect, stroke_color);
-    }
-}
-
-fn composite_arrow(composite: &mut image::RgbaImage, anno: &Annotation) {
-    let points = match &anno.points {
-        Some(pts) if pts.len() >= 2 => pts,
-        _ => return,
-    };
-    let stroke_str = anno.stroke_color.as_deref().unwrap_or("#FF0000");
-    let Ok(color) = parse_color(stroke_str) else {
-        return;
-    };
-    let sw = anno.stroke_width.unwrap_or(2.0).max(1.0);
-
-    let p1 = &points[0];
-    let p2 = &points[1];
-
-    // Shaft.
-    draw_thick_line(
-        composite,
-        p1.x as f32,
-        p1.y as f32,
-        p2.x as f32,
-        p2.y as f32,
-        color,
-        sw as f32,
-    );
-
-    // Arrowhead — two lines from tip.
-    let head_len = (sw * 5.0).max(12.0);
-    let dx = p2.x - p1.x;
-    let dy = p2.y - p1.y;
-    let len = (dx * dx + dy * dy).sqrt();
-    if len < 0.001 {
-        return;
-    }
-    let angle = dy.atan2(dx);
-    let wing = std::f64::consts::PI / 6.0;
-
-    let wx1 = (p2.x - head_len * (angle - wing).cos()) as f32;
-    let wy1 = (p2.y - head_len * (angle - wing).sin()) as f32;
-    let wx2 = (p2.x - head_len * (angle + wing).cos()) as f32;
-    let wy2 = (p2.y - head_len * (angle + wing).sin()) as f32;
-
-    draw_thick_line(
-        composite,
-        p2.x as f32,
-        p2.y as f32,
-        wx1,
-        wy1,
-        color,
-        sw as f32,
-    );
-    draw_thick_line(
-        composite,
-        p2.x as f32,
-        p2.y as f32,
-        wx2,
-        wy2,
-        color,
-        sw as f32,
-    );
-}
-
-fn composite_ellipse(composite: &mut image::RgbaImage, anno: &Annotation) {
-    let x = anno.x as i32;
-    let y = anno.y as i32;
-    let width = anno.width.unwrap_or(0.0) as i32;
-    let height = anno.height.unwrap_or(0.0) as i32;
-
-    if width <= 0 || height <= 0 {
-        return;
-    }
-
-    let cx = x + width / 2;
-    let cy = y + height / 2;
-    let rx = width / 2;
-    let ry = height / 2;
-
-    let opacity = anno.opacity.unwrap_or(1.0).clamp(0.0, 1.0);
-
-    // Fill.
-    let fill_str = anno.fill_color.as_deref().unwrap_or("transparent");
-    if let Ok(mut fill) = parse_color(fill_str) {
-        if fill[3] > 0 {
-            fill[3] = (fill[3] as f64 * opacity) as u8;
-            draw_filled_ellipse_mut(composite, (cx, cy), rx, ry, fill);
-        }
-    }
-
-    // Stroke.
-    let stroke_str = anno.stroke_color.as_deref().unwrap_or("#FF0000");
-    if let Ok(mut stroke) = parse_color(stroke_str) {
-        if stroke[3] > 0 {
-            stroke[3] = (stroke[3] as f64 * opacity) as u8;
-            draw_hollow_ellipse_mut(composite, (cx, cy), rx, ry, stroke);
-        }
-    }
-}
-
-fn composite_freehand(composite: &mut image::RgbaImage, anno: &Annotation) {
-    let points = match &anno.points {
-        Some(pts) if pts.len() >= 2 => pts,
-        _ => return,
-    };
-    let stroke_str = anno.stroke_color.as_deref().unwrap_or("#FF0000");
-    let Ok(color) = parse_color(stroke_str) else {
-        return;
-    };
-    let sw = anno.stroke_width.unwrap_or(2.0).max(1.0);
-
-    for i in 0..points.len() - 1 {
-        let p1 = &points[i];
-        let p2 = &points[i + 1];
-        draw_thick_line(
-            composite,
-            p1.x as f32,
-            p1.y as f32,
-            p2.x as f32,
-            p2.y as f32,
-            color,
-            sw as f32,
-        );
-    }
-}
-
-fn composite_highlight(composite: &mut image::RgbaImage, anno: &Annotation) {
-    let x = anno.x as i32;
-    let y = anno.y as i32;
-    let width = anno.width.unwrap_or(0.0) as i32;
-    let height = anno.height.unwrap_or(0.0) as i32;
-
-    if width <= 0 || height <= 0 {
-        return;
-    }
-
-    let color_str = anno.highlight_color.as_deref().unwrap_or("#FFFF00");
-    let Ok(mut color) = parse_color(color_str) else {
-        return;
-    };
-    // Always 0.4 opacity per spec.
-    color[3] = (0.4 * 255.0) as u8;
-
-    let img_w = composite.width() as i32;
-    let img_h = composite.height() as i32;
-
-    for py in y..(y + height) {
-        for px in x..(x + width) {
-            if px >= 0 && py >= 0 && px < img_w && py < img_h {
-                let base = composite.get_pixel_mut(px as u32, py as u32);
-                blend_pixel(base, color);
-            }
-        }
-    }
-}
-
-fn composite_blur(composite: &mut image::RgbaImage, anno: &Annotation) {
-    let x = anno.x as u32;
-    let y = anno.y as u32;
-    let w = anno.width.unwrap_or(0.0) as u32;
-    let h = anno.height.unwrap_or(0.0) as u32;
-    let block_size = anno.blur_radius.unwrap_or(10.0).max(1.0) as u32;
-
-    if w == 0 || h == 0 {
-        return;
-    }
-
-    let img_w = composite.width();
-    let img_h = composite.height();
-
-    let x2 = (x + w).min(img_w);
-    let y2 = (y + h).min(img_h);
-    let x1 = x.min(x2);
-    let y1 = y.min(y2);
-
-    let mut bx = x1;
-    while bx < x2 {
-        let bx2 = (bx + block_size).min(x2);
-        let mut by = y1;
-        while by < y2 {
-            let by2 = (by + block_size).min(y2);
-            let count = (bx2 - bx) * (by2 - by);
-
-            let mut r_sum = 0u32;
-            let mut g_sum = 0u32;
-            let mut b_sum = 0u32;
-            let mut a_sum = 0u32;
-
-            for py in by..by2 {
-                for px in bx..bx2 {
-                    let p = composite.get_pixel(px, py);
-                    r_sum += p[0] as u32;
-                    g_sum += p[1] as u32;
-                    b_sum += p[2] as u32;
-                    a_sum += p[3] as u32;
-                }
-            }
-
-            let avg = Rgba([
-                (r_sum / count) as u8,
-                (g_sum / count) as u8,
-                (b_sum / count) as u8,
-                (a_sum / count) as u8,
-            ]);
-
-            for py in by..by2 {
-                for px in bx..bx2 {
-                    composite.put_pixel(px, py, avg);
-                }
-            }
-
-            by += block_size;
-        }
-        bx += block_size;
-    }
-}
-
-fn composite_step(composite: &mut image::RgbaImage, anno: &Annotation) {
-    let cx = anno.x as i32;
-    let cy = anno.y as i32;
-    let size = anno.font_size.unwrap_or(24.0) as i32;
-    let radius = size / 2;
-
-    let stroke_str = anno.stroke_color.as_deref().unwrap_or("#FF0000");
-    let Ok(color) = parse_color(stroke_str) else {
-        return;
-    };
-
-    draw_filled_circle_mut(composite, (cx, cy), radius, color);
-
-    // Draw the step number centered inside the circle.
-    let step = match anno.step_number {
-        Some(n) => n,
-        None => return,
-    };
-    let font = embedded_font();
-    let text = step.to_string();
-    let font_size = (size as f32 * 0.6).max(8.0);
-    let scale = PxScale {
-        x: font_size,
-        y: font_size,
-    };
-    let scaled = font.as_scaled(scale);
-    let text_width: f32 = text
-        .chars()
-        .map(|c| scaled.h_advance(font.glyph_id(c)))
-        .sum();
-    let ascent = scaled.ascent();
-    let tx = cx - (text_width / 2.0) as i32;
-    let ty = cy - (ascent / 2.0) as i32;
-    draw_text_mut(
-        composite,
-        Rgba([255, 255, 255, 255]),
-        tx,
-        ty,
-        scale,
-        &font,
-        &text,
-    );
-}
-
-fn embedded_font() -> FontVec {
-    static BYTES: &[u8] = include_bytes!("../../fonts/LiberationSans-Regular.ttf");
-    FontVec::try_from_vec(BYTES.to_vec()).expect("embedded font is valid")
-}
-
-fn composite_text(composite: &mut image::RgbaImage, anno: &Annotation) {
-    let text = match &anno.text {
-        Some(t) if !t.is_empty() => t,
-        _ => return,
-    };
-
-    let font = embedded_font();
-    let x = anno.x as i32;
-    let y = anno.y as i32;
-    let font_size = anno.font_size.unwrap_or(20.0) as f32;
-    let scale = PxScale {
-        x: font_size,
-        y: font_size,
-    };
-
-    let stroke_str = anno.stroke_color.as_deref().unwrap_or("#FF0000");
-    let Ok(color) = parse_color(stroke_str) else {
-        return;
-    };
-
-    let line_height = (font_size * 1.4) as i32;
-    for (i, line) in text.lines().enumerate() {
-        if line.is_empty() {
-            continue;
-        }
-        let line_y = y + (i as i32 * line_height);
-        draw_text_mut(composite, color, x, line_y, scale, &font, line);
-    }
-}
-
-// ── Line helpers ──────────────────────────────────────────────────────────────
-
-/// Draw a line with approximate stroke width by drawing parallel offset lines.
-fn draw_thick_line(
-    composite: &mut image::RgbaImage,
-    x1: f32,
-    y1: f32,
-    x2: f32,
-    y2: f32,
-    color: Rgba<u8>,
-    width: f32,
-) {
-    if width <= 1.0 {
-        draw_line_segment_mut(composite, (x1, y1), (x2, y2), color);
-        return;
-    }
-
-    let dx = x2 - x1;
-    let dy = y2 - y1;
-    let len = (dx * dx + dy * dy).sqrt();
-    if len < 0.001 {
-        return;
-    }
-    // Perpendicular unit vector.
-    let px = -dy / len;
-    let py = dx / len;
-
-    let half = width / 2.0;
-    let steps = width.ceil() as i32;
-
-    for i in 0..=steps {
-        let t = ((i as f32 / steps.max(1) as f32) - 0.5) * width;
-        let t = t.clamp(-half, half);
-        draw_line_segment_mut(
-            composite,
-            (x1 + px * t, y1 + py * t),
-            (x2 + px * t, y2 + py * t),
-            color,
-        );
-    }
-}
-
-// ── Shared pixel helpers ──────────────────────────────────────────────────────
-
-/// Alpha-composite `src` over `dst` in place (src-over).
-#[inline]
-fn blend_pixel(dst: &mut Rgba<u8>, src: Rgba<u8>) {
-    let sa = src[3] as f64 / 255.0;
-    let da = dst[3] as f64 / 255.0;
-    let out_a = sa + da * (1.0 - sa);
-    if out_a < f64::EPSILON {
-        *dst = Rgba([0, 0, 0, 0]);
-        return;
-    }
-    dst[0] = ((src[0] as f64 * sa + dst[0] as f64 * da * (1.0 - sa)) / out_a) as u8;
-    dst[1] = ((src[1] as f64 * sa + dst[1] as f64 * da * (1.0 - sa)) / out_a) as u8;
-    dst[2] = ((src[2] as f64 * sa + dst[2] as f64 * da * (1.0 - sa)) / out_a) as u8;
-    dst[3] = (out_a * 255.0) as u8;
+    encode::encode(img, fmt, options, &mut w).map_err(|e| format!("{e:#}"))?;
+    w.flush().map_err(|e| format!("Failed to write file: {e}"))
 }
 
-/// Parse a CSS color string into `Rgba<u8>`.
-///
-/// Supports:
-/// - `"transparent"` → `[0, 0, 0, 0]`
-/// - `#RRGGBB` → alpha 255
-/// - `#RRGGBBAA` → explicit alpha
-///
-/// Returns `Err` for unrecognized formats.
-fn parse_color(color: &str) -> Result<Rgba<u8>, String> {
-    if color.eq_ignore_ascii_case("transparent") {
-        return Ok(Rgba([0, 0, 0, 0]));
-    }
-
-    let hex = color
-        .strip_prefix('#')
-        .ok_or_else(|| format!("Unsupported color format: {color}"))?;
-
-    let parse_byte = |s: &str| {
-        u8::from_str_radix(s, 16).map_err(|_| format!("Invalid hex byte '{s}' in color {color}"))
-    };
-
-    match hex.len() {
-        6 => Ok(Rgba([
-            parse_byte(&hex[0..2])?,
-            parse_byte(&hex[2..4])?,
-            parse_byte(&hex[4..6])?,
-            255,
-        ])),
-        8 => Ok(Rgba([
-            parse_byte(&hex[0..2])?,
-            parse_byte(&hex[2..4])?,
-            parse_byte(&hex[4..6])?,
-            parse_byte(&hex[6..8])?,
-        ])),
-        _ => Err(format!("Unsupported color format: {color}")),
-    }
-}
-
-fn generate_default_path() -> Result<PathBuf, String> {
+/// `~/Pictures/Fotos/fotos-<timestamp>.<ext>`.
+pub(crate) fn generate_default_path(ext: &str) -> Result<PathBuf, String> {
     let user_dirs = UserDirs::new().ok_or("Could not find user directories")?;
     let pictures = user_dirs
         .picture_dir()
@@ -764,12 +541,12 @@ fn generate_default_path() -> Result<PathBuf, String> {
 
     let fotos_dir = pictures.join("Fotos");
     let timestamp = Local::now().format("%Y%m%d-%H%M%S");
-    let filename = format!("fotos-{}.png", timestamp);
+    let filename = format!("fotos-{}.{}", timestamp, ext);
 
     Ok(fotos_dir.join(filename))
 }
 
-fn expand_tilde(path: &str) -> Result<PathBuf, String> {
+pub(crate) fn expand_tilde(path: &str) -> Result<PathBuf, String> {
     if let Some(stripped) = path.strip_prefix("~/") {
         let home = UserDirs::new()
             .map(|dirs| dirs.home_dir().to_path_buf())
@@ -784,218 +561,6 @@ mod tests {
     use super::*;
     use image::{Rgba, RgbaImage};
 
-    fn make_annotation(
-        ann_type: &str,
-        x: f64,
-        y: f64,
-        w: f64,
-        h: f64,
-        stroke_color: Option<&str>,
-        fill_color: Option<&str>,
-        stroke_width: Option<f64>,
-        opacity: Option<f64>,
-    ) -> Annotation {
-        Annotation {
-            id: "test".into(),
-            annotation_type: ann_type.into(),
-            x,
-            y,
-            width: Some(w),
-            height: Some(h),
-            stroke_color: stroke_color.map(String::from),
-            fill_color: fill_color.map(String::from),
-            stroke_width,
-            opacity,
-            text: None,
-            font_size: None,
-            font_family: None,
-            points: None,
-            step_number: None,
-            blur_radius: None,
-            highlight_color: None,
-            created_at: None,
-            locked: None,
-        }
-    }
-
-    // ── parse_color ──────────────────────────────────────────────────────────
-
-    #[test]
-    fn parse_color_transparent() {
-        assert_eq!(parse_color("transparent").unwrap(), Rgba([0, 0, 0, 0]));
-        assert_eq!(parse_color("TRANSPARENT").unwrap(), Rgba([0, 0, 0, 0]));
-    }
-
-    #[test]
-    fn parse_color_rrggbb() {
-        assert_eq!(parse_color("#ff0000").unwrap(), Rgba([255, 0, 0, 255]));
-        assert_eq!(parse_color("#00FF00").unwrap(), Rgba([0, 255, 0, 255]));
-    }
-
-    #[test]
-    fn parse_color_rrggbbaa() {
-        assert_eq!(parse_color("#ff000080").unwrap(), Rgba([255, 0, 0, 128]));
-    }
-
-    #[test]
-    fn parse_color_invalid_returns_err() {
-        assert!(parse_color("red").is_err());
-        assert!(parse_color("#zzz").is_err());
-        assert!(parse_color("").is_err());
-    }
-
-    // ── compositing ──────────────────────────────────────────────────────────
-
-    #[test]
-    fn compositing_stroke_pixel_is_stroke_color() {
-        let mut img = RgbaImage::from_pixel(100, 100, Rgba([255, 255, 255, 255]));
-        let anno = make_annotation(
-            "rect",
-            10.0,
-            10.0,
-            30.0,
-            20.0,
-            Some("#ff0000"),
-            Some("transparent"),
-            Some(1.0),
-            Some(1.0),
-        );
-        composite_rectangle(&mut img, &anno);
-        assert_eq!(*img.get_pixel(10, 10), Rgba([255, 0, 0, 255]));
-    }
-
-    #[test]
-    fn compositing_outside_pixel_unchanged() {
-        let mut img = RgbaImage::from_pixel(100, 100, Rgba([255, 255, 255, 255]));
-        let anno = make_annotation(
-            "rect",
-            10.0,
-            10.0,
-            30.0,
-            20.0,
-            Some("#ff0000"),
-            Some("transparent"),
-            Some(1.0),
-            Some(1.0),
-        );
-        composite_rectangle(&mut img, &anno);
-        assert_eq!(*img.get_pixel(0, 0), Rgba([255, 255, 255, 255]));
-        assert_eq!(*img.get_pixel(99, 99), Rgba([255, 255, 255, 255]));
-    }
-
-    #[test]
-    fn compositing_fill_pixel_is_fill_color() {
-        let mut img = RgbaImage::from_pixel(100, 100, Rgba([255, 255, 255, 255]));
-        let anno = make_annotation(
-            "rect",
-            10.0,
-            10.0,
-            30.0,
-            20.0,
-            Some("transparent"),
-            Some("#0000ff"),
-            Some(0.0),
-            Some(1.0),
-        );
-        composite_rectangle(&mut img, &anno);
-        let px = img.get_pixel(20, 15);
-        assert_eq!(px[2], 255);
-        assert_eq!(px[0], 0);
-    }
-
-    #[test]
-    fn compositing_highlight_uses_fixed_opacity() {
-        let mut img = RgbaImage::from_pixel(100, 100, Rgba([0, 0, 0, 255]));
-        let mut anno = make_annotation(
-            "highlight",
-            10.0,
-            10.0,
-            20.0,
-            20.0,
-            None,
-            None,
-            None,
-            Some(1.0),
-        );
-        anno.highlight_color = Some("#FFFF00".into());
-        composite_highlight(&mut img, &anno);
-        // The yellow should be blended at 0.4 opacity over black.
-        let px = img.get_pixel(20, 20);
-        assert!(
-            px[0] > 0,
-            "yellow channel should be non-zero after highlight"
-        );
-        assert!(px[0] < 255, "should be blended, not fully opaque");
-    }
-
-    #[test]
-    fn compositing_blur_pixelates_region() {
-        // Fill top-left 20×20 with red, rest with blue.
-        let mut img = RgbaImage::from_pixel(100, 100, Rgba([0, 0, 255, 255]));
-        for y in 0..20u32 {
-            for x in 0..20u32 {
-                img.put_pixel(x, y, Rgba([255, 0, 0, 255]));
-            }
-        }
-        let mut anno = make_annotation("blur", 0.0, 0.0, 20.0, 20.0, None, None, None, None);
-        anno.blur_radius = Some(10.0);
-        composite_blur(&mut img, &anno);
-        // After blur, the 10×10 blocks should all have the same average red color.
-        let block1 = *img.get_pixel(0, 0);
-        let block2 = *img.get_pixel(5, 5);
-        assert_eq!(
-            block1, block2,
-            "pixels within a blur block should be identical"
-        );
-    }
-
-    #[test]
-    fn compositing_arrow_draws_something() {
-        let mut img = RgbaImage::from_pixel(100, 100, Rgba([255, 255, 255, 255]));
-        let mut anno = make_annotation(
-            "arrow",
-            0.0,
-            0.0,
-            0.0,
-            0.0,
-            Some("#ff0000"),
-            None,
-            Some(2.0),
-            Some(1.0),
-        );
-        anno.points = Some(vec![Point { x: 10.0, y: 50.0 }, Point { x: 80.0, y: 50.0 }]);
-        composite_arrow(&mut img, &anno);
-        // At least the shaft midpoint should be red.
-        let mid = img.get_pixel(45, 50);
-        assert_eq!(mid[0], 255, "arrow shaft should be red");
-        assert_eq!(mid[1], 0);
-    }
-
-    // ── Annotation serde (camelCase round-trip) ──────────────────────────────
-
-    #[test]
-    fn annotation_serde_camel_case_round_trip() {
-        let json = r##"{
-            "id": "abc",
-            "type": "rect",
-            "x": 5.0,
-            "y": 10.0,
-            "width": 100.0,
-            "height": 50.0,
-            "strokeColor": "#ff0000",
-            "fillColor": "transparent",
-            "strokeWidth": 2.0,
-            "opacity": 0.8
-        }"##;
-
-        let anno: Annotation = serde_json::from_str(json).expect("deserialization failed");
-        assert_eq!(anno.annotation_type, "rect");
-        assert_eq!(anno.stroke_color.as_deref(), Some("#ff0000"));
-        assert_eq!(anno.fill_color.as_deref(), Some("transparent"));
-        assert_eq!(anno.stroke_width, Some(2.0));
-        assert_eq!(anno.opacity, Some(0.8));
-    }
-
     // ── Format detection ──────────────────────────────────────────────────────
 
     #[test]
@@ -1010,6 +575,13 @@ mod tests {
         assert_eq!(format_from_hint("webp"), image::ImageFormat::WebP);
     }
 
+    #[test]
+    fn format_from_hint_avif_tiff_qoi() {
+        assert_eq!(format_from_hint("avif"), image::ImageFormat::Avif);
+        assert_eq!(format_from_hint("TIF"), image::ImageFormat::Tiff);
+        assert_eq!(format_from_hint("qoi"), image::ImageFormat::Qoi);
+    }
+
     #[test]
     fn format_from_hint_png_fallback() {
         assert_eq!(format_from_hint("png"), image::ImageFormat::Png);
@@ -1033,10 +605,20 @@ mod tests {
 
     // ── encode_to_bytes ───────────────────────────────────────────────────────
 
+    #[test]
+    fn wants_svg_follows_extension_then_hint() {
+        assert!(wants_svg(Path::new("/tmp/shot.svg"), "png"));
+        assert!(wants_svg(Path::new("/tmp/shot.SVG"), "png"));
+        assert!(wants_svg(Path::new("/tmp/shot"), "svg"));
+        assert!(!wants_svg(Path::new("/tmp/shot.png"), "svg"));
+        assert!(!wants_svg(Path::new("/tmp/shot"), "png"));
+    }
+
     #[test]
     fn encode_to_bytes_png_has_png_magic() {
         let img = RgbaImage::from_pixel(8, 8, Rgba([255, 0, 0, 255]));
-        let bytes = encode_to_bytes(img, image::ImageFormat::Png, 85).unwrap();
+        let bytes =
+            encode_to_bytes(img, image::ImageFormat::Png, &EncodeOptions::default()).unwrap();
         assert_eq!(
             &bytes[..8],
             b"\x89PNG\r\n\x1a\n",
@@ -1047,7 +629,8 @@ mod tests {
     #[test]
     fn encode_to_bytes_jpeg_has_jpeg_magic() {
         let img = RgbaImage::from_pixel(8, 8, Rgba([255, 0, 0, 255]));
-        let bytes = encode_to_bytes(img, image::ImageFormat::Jpeg, 85).unwrap();
+        let bytes =
+            encode_to_bytes(img, image::ImageFormat::Jpeg, &EncodeOptions::default()).unwrap();
         assert_eq!(
             &bytes[..2],
             b"\xff\xd8",
@@ -1058,27 +641,8 @@ mod tests {
     #[test]
     fn encode_to_bytes_webp_has_riff_magic() {
         let img = RgbaImage::from_pixel(8, 8, Rgba([0, 128, 0, 255]));
-        let bytes = encode_to_bytes(img, image::ImageFormat::WebP, 85).unwrap();
+        let bytes =
+            encode_to_bytes(img, image::ImageFormat::WebP, &EncodeOptions::default()).unwrap();
         assert_eq!(&bytes[..4], b"RIFF", "should start with RIFF header");
     }
-
-    #[test]
-    fn annotation_serde_new_fields() {
-        let json = r##"{
-            "id": "def",
-            "type": "step",
-            "x": 50.0,
-            "y": 50.0,
-            "strokeColor": "#ff0000",
-            "stepNumber": 3,
-            "fontSize": 24.0,
-            "highlightColor": "#FFFF00",
-            "blurRadius": 10.0
-        }"##;
-
-        let anno: Annotation = serde_json::from_str(json).expect("deserialization failed");
-        assert_eq!(anno.step_number, Some(3));
-        assert_eq!(anno.blur_radius, Some(10.0));
-        assert_eq!(anno.highlight_color.as_deref(), Some("#FFFF00"));
-    }
 }
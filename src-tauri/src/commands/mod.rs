@@ -1,6 +1,7 @@
 pub mod ai;
 pub mod capture;
 pub mod files;
+pub mod search;
 pub mod settings;
 
 /// Tracer-bullet: verify Tauri IPC round-trip
@@ -0,0 +1,212 @@
+use crate::ai::ocr::OcrOptions;
+use crate::ai::search::{IndexedWord, SearchIndex};
+use crate::capture::{ImageStore, ScreenshotInfo};
+use serde::Serialize;
+use std::sync::{mpsc, Arc};
+use tauri::{Emitter, Manager};
+use uuid::Uuid;
+
+/// The OCR search index as managed Tauri state.
+pub type SharedIndex = Arc<SearchIndex>;
+
+/// Default number of results for `search_screenshots`.
+pub const DEFAULT_SEARCH_LIMIT: usize = 20;
+
+/// A screenshot matching a search, with the boxes of the matching words.
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchResult {
+    #[serde(flatten)]
+    pub info: ScreenshotInfo,
+    pub hits: Vec<IndexedWord>,
+}
+
+#[derive(Clone, Serialize)]
+struct IndexedPayload {
+    screenshot_id: String,
+    words: usize,
+}
+
+/// Run `query` against the index, newest screenshot first among equally
+/// good matches. Entries for screenshots no longer in the store (deleted or
+/// expired) are dropped from the index on the way.
+pub(crate) fn search(
+    store: &ImageStore,
+    index: &SearchIndex,
+    query: &str,
+    limit: usize,
+) -> Vec<SearchResult> {
+    let mut results: Vec<SearchResult> = index
+        .search(query)
+        .into_iter()
+        .filter_map(|hit| match store.metadata(&hit.id) {
+            Some(metadata) => Some(SearchResult {
+                info: ScreenshotInfo {
+                    id: hit.id,
+                    metadata,
+                },
+                hits: hit.words,
+            }),
+            None => {
+                index.remove(&hit.id);
+                None
+            }
+        })
+        .collect();
+    results.sort_by(|a, b| {
+        b.hits
+            .len()
+            .cmp(&a.hits.len())
+            .then(b.info.metadata.timestamp.cmp(&a.info.metadata.timestamp))
+    });
+    results.truncate(limit);
+    results
+}
+
+/// Index OCR text of new captures in the background.
+///
+/// Every image inserted into the store is queued, as is anything already in
+/// the store that has not been indexed yet. A single worker thread OCRs the
+/// queue one image at a time, in the OCR language from settings, and emits
+/// `search:indexed` as each one lands.
+/// Images deleted or expired from the store leave the index straight away.
+pub fn start_indexer(app: &tauri::AppHandle, index: SharedIndex) {
+    let store = app.state::<ImageStore>().inner().clone();
+    let (tx, rx) = mpsc::channel::<Uuid>();
+
+    let worker_app = app.clone();
+    let worker_store = store.clone();
+    let worker_index = Arc::clone(&index);
+    let spawned = std::thread::Builder::new()
+        .name("fotos-indexer".into())
+        .spawn(move || {
+            for id in rx {
+                index_one(&worker_app, &worker_store, &worker_index, id);
+            }
+        });
+    if let Err(e) = spawned {
+        tracing::error!("search index: failed to start indexer: {e}");
+        return;
+    }
+
+    let backlog: Vec<Uuid> = store
+        .ids()
+        .into_iter()
+        .filter(|id| !index.contains(id))
+        .collect();
+    if !backlog.is_empty() {
+        tracing::info!("search index: {} screenshots to index", backlog.len());
+    }
+    for id in backlog {
+        let _ = tx.send(id);
+    }
+    let tx = std::sync::Mutex::new(tx);
+    store.on_insert(move |id| {
+        let _ = tx.lock().unwrap_or_else(|e| e.into_inner()).send(id);
+    });
+    store.on_remove(move |id| {
+        index.remove(&id);
+    });
+}
+
+fn index_one(app: &tauri::AppHandle, store: &ImageStore, index: &SearchIndex, id: Uuid) {
+    if index.contains(&id) {
+        return;
+    }
+    // Not `get`: a pass over the whole library must not push the user's
+    // working images out of memory.
+    let Some(image) = store.peek(&id) else {
+        return;
+    };
+    let lang = crate::commands::settings::load_ai_settings(app).ocr_language;
+    let tessdata_path = match crate::commands::ai::resolve_tessdata_path(app, &lang) {
+        Ok(path) => path,
+        Err(e) => {
+            tracing::warn!("search index: skipping {id}: {e}");
+            return;
+        }
+    };
+    let opts = OcrOptions {
+        lang,
+        tessdata_path,
+    };
+    match crate::ai::ocr::run_ocr(&image, &opts, None) {
+        Ok(output) => {
+            index.add_ocr(id, &output.regions);
+            let _ = app.emit(
+                "search:indexed",
+                IndexedPayload {
+                    screenshot_id: id.to_string(),
+                    words: output.regions.len(),
+                },
+            );
+        }
+        Err(e) => tracing::warn!("search index: OCR failed for {id}: {e}"),
+    }
+}
+
+/// Find screenshots whose OCR text contains every word of `query` (word
+/// prefixes match), with the boxes of the matching words.
+#[tauri::command]
+pub fn search_screenshots(
+    query: String,
+    limit: Option<usize>,
+    store: tauri::State<'_, ImageStore>,
+    index: tauri::State<'_, SharedIndex>,
+) -> Result<Vec<SearchResult>, String> {
+    Ok(search(
+        &store,
+        &index,
+        &query,
+        limit.unwrap_or(DEFAULT_SEARCH_LIMIT),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::{CaptureMetadata, CaptureMode};
+    use image::{DynamicImage, RgbaImage};
+
+    fn word(text: &str) -> IndexedWord {
+        IndexedWord {
+            text: text.to_owned(),
+            x: 0,
+            y: 0,
+            w: 10,
+            h: 10,
+        }
+    }
+
+    #[test]
+    fn search_ranks_by_hits_then_recency_and_prunes_deleted() {
+        let store = ImageStore::new();
+        let index = SearchIndex::in_memory();
+        let base = chrono::Utc::now();
+        let ids: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+        for (i, id) in ids.iter().enumerate() {
+            let metadata = CaptureMetadata {
+                timestamp: base + chrono::Duration::seconds(i as i64),
+                ..CaptureMetadata::new(CaptureMode::Fullscreen, (1, 1))
+            };
+            store.insert(
+                *id,
+                Arc::new(DynamicImage::ImageRgba8(RgbaImage::new(1, 1))),
+                metadata,
+            );
+        }
+        index.add(ids[0], vec![word("timeout"), word("timeout")]);
+        index.add(ids[1], vec![word("timeout")]);
+        index.add(ids[2], vec![word("timeout")]);
+
+        let found: Vec<Uuid> = search(&store, &index, "timeout", 10)
+            .iter()
+            .map(|r| r.info.id)
+            .collect();
+        assert_eq!(found, vec![ids[0], ids[2], ids[1]]);
+        assert_eq!(search(&store, &index, "timeout", 1).len(), 1);
+
+        store.remove(&ids[2]);
+        assert_eq!(search(&store, &index, "timeout", 10).len(), 2);
+        assert!(!index.contains(&ids[2]));
+    }
+}
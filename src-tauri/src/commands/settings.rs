@@ -1,5 +1,6 @@
 use reqwest::header;
 use serde::{Deserialize, Serialize};
+use tauri::Manager;
 use tauri_plugin_store::StoreExt;
 
 const STORE_PATH: &str = "prefs.json";
@@ -14,9 +15,35 @@ pub struct CaptureSettings {
     pub save_directory: String,
     pub default_format: String,
     pub jpeg_quality: u8,
+    /// Lossy WebP quality (1–100); ignored when `webp_lossless` is set.
+    #[serde(default = "default_webp_quality")]
+    pub webp_quality: u8,
+    #[serde(default = "default_webp_lossless")]
+    pub webp_lossless: bool,
+    #[serde(default = "default_avif_quality")]
+    pub avif_quality: u8,
+    /// PNG optimisation level, 0–[`crate::encode::MAX_PNG_LEVEL`].
+    #[serde(default = "default_png_optimization_level")]
+    pub png_optimization_level: u8,
     pub copy_to_clipboard_after_capture: bool,
 }
 
+fn default_webp_quality() -> u8 {
+    85
+}
+
+fn default_webp_lossless() -> bool {
+    true
+}
+
+fn default_avif_quality() -> u8 {
+    80
+}
+
+fn default_png_optimization_level() -> u8 {
+    2
+}
+
 impl Default for CaptureSettings {
     fn default() -> Self {
         Self {
@@ -24,6 +51,10 @@ impl Default for CaptureSettings {
             save_directory: "~/Pictures/Fotos".to_string(),
             default_format: "png".to_string(),
             jpeg_quality: 90,
+            webp_quality: default_webp_quality(),
+            webp_lossless: default_webp_lossless(),
+            avif_quality: default_avif_quality(),
+            png_optimization_level: default_png_optimization_level(),
             copy_to_clipboard_after_capture: true,
             include_mouse_cursor: false,
             delay_ms: 0,
@@ -131,12 +162,39 @@ impl Default for UiSettings {
     }
 }
 
+/// Screenshot library retention (`0` disables a limit) and how much decoded
+/// image data the store keeps in memory.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibrarySettings {
+    pub retention_days: u32,
+    pub max_screenshots: u32,
+    #[serde(default = "default_memory_budget_mb")]
+    pub memory_budget_mb: u32,
+}
+
+fn default_memory_budget_mb() -> u32 {
+    (crate::capture::store::DEFAULT_MEMORY_BUDGET / (1024 * 1024)) as u32
+}
+
+impl Default for LibrarySettings {
+    fn default() -> Self {
+        Self {
+            retention_days: 30,
+            max_screenshots: 0,
+            memory_budget_mb: default_memory_budget_mb(),
+        }
+    }
+}
+
 #[derive(Default, Serialize, Deserialize)]
 pub struct Settings {
     pub capture: CaptureSettings,
     pub annotation: AnnotationSettings,
     pub ai: AiSettings,
     pub ui: UiSettings,
+    #[serde(default)]
+    pub library: LibrarySettings,
 }
 
 fn load_section<T: serde::de::DeserializeOwned + Default>(
@@ -279,6 +337,87 @@ fn migrate_if_needed(store: &tauri_plugin_store::Store<tauri::Wry>) {
     }
 }
 
+/// Load the capture section, falling back to defaults if the store is
+/// unavailable. Used by the capture paths that run outside the settings UI.
+pub fn load_capture_settings(app: &tauri::AppHandle) -> CaptureSettings {
+    match app.store(STORE_PATH) {
+        Ok(store) => {
+            migrate_if_needed(&store);
+            load_section(&store, "capture")
+        }
+        Err(e) => {
+            tracing::warn!("settings: store unavailable, using capture defaults: {e}");
+            CaptureSettings::default()
+        }
+    }
+}
+
+impl CaptureSettings {
+    /// Per-capture options for the capture backend.
+    pub fn capture_options(&self) -> crate::capture::CaptureOptions {
+        crate::capture::CaptureOptions {
+            include_cursor: self.include_mouse_cursor,
+            ..Default::default()
+        }
+    }
+
+    /// How saved and exported images are encoded.
+    pub fn encode_options(&self) -> crate::encode::EncodeOptions {
+        crate::encode::EncodeOptions {
+            jpeg_quality: self.jpeg_quality.clamp(1, 100),
+            webp_quality: (!self.webp_lossless).then_some(self.webp_quality.clamp(1, 100)),
+            avif_quality: self.avif_quality.clamp(1, 100),
+            png_level: self
+                .png_optimization_level
+                .min(crate::encode::MAX_PNG_LEVEL),
+        }
+    }
+}
+
+/// Load the library section, falling back to defaults if the store is
+/// unavailable.
+pub fn load_library_settings(app: &tauri::AppHandle) -> LibrarySettings {
+    match app.store(STORE_PATH) {
+        Ok(store) => {
+            migrate_if_needed(&store);
+            load_section(&store, "library")
+        }
+        Err(e) => {
+            tracing::warn!("settings: store unavailable, using library defaults: {e}");
+            LibrarySettings::default()
+        }
+    }
+}
+
+/// Load the AI section, falling back to defaults if the store is
+/// unavailable.
+pub fn load_ai_settings(app: &tauri::AppHandle) -> AiSettings {
+    match app.store(STORE_PATH) {
+        Ok(store) => {
+            migrate_if_needed(&store);
+            load_section(&store, "ai")
+        }
+        Err(e) => {
+            tracing::warn!("settings: store unavailable, using AI defaults: {e}");
+            AiSettings::default()
+        }
+    }
+}
+
+impl LibrarySettings {
+    pub fn retention(&self) -> crate::capture::library::Retention {
+        crate::capture::library::Retention {
+            max_age: (self.retention_days > 0)
+                .then(|| chrono::Duration::days(self.retention_days.into())),
+            max_count: (self.max_screenshots > 0).then_some(self.max_screenshots as usize),
+        }
+    }
+
+    pub fn memory_budget_bytes(&self) -> u64 {
+        self.memory_budget_mb as u64 * 1024 * 1024
+    }
+}
+
 #[tauri::command]
 pub fn get_settings(app: tauri::AppHandle) -> Result<Settings, String> {
     let store = app
@@ -290,6 +429,7 @@ pub fn get_settings(app: tauri::AppHandle) -> Result<Settings, String> {
         annotation: load_section(&store, "annotation"),
         ai: load_section(&store, "ai"),
         ui: load_section(&store, "ui"),
+        library: load_section(&store, "library"),
     })
 }
 
@@ -314,8 +454,15 @@ pub fn set_settings(app: tauri::AppHandle, settings: Settings) -> Result<(), Str
         "ui",
         serde_json::to_value(&settings.ui).map_err(|e| e.to_string())?,
     );
+    store.set(
+        "library",
+        serde_json::to_value(&settings.library).map_err(|e| e.to_string())?,
+    );
     store.set("_schemaVersion", serde_json::json!(SCHEMA_VERSION));
     store.save().map_err(|e| format!("Save error: {e}"))?;
+    let images = app.state::<crate::capture::ImageStore>();
+    images.set_retention(settings.library.retention());
+    images.set_memory_budget(settings.library.memory_budget_bytes());
     Ok(())
 }
 
@@ -0,0 +1,415 @@
+/// Encoding of saved and exported images: JPEG and AVIF quality, lossless
+/// or lossy WebP, and PNG optimisation.
+///
+/// PNG optimisation follows oxipng's approach without its dependency tree:
+/// pixels are first stored in the smallest lossless colour type — RGB when
+/// nothing is transparent, greyscale when every pixel is grey, a palette of
+/// 1, 2, 4 or 8 bits when there are at most 256 colours — then deflated with
+/// per-row filtering. The highest level also tries every filter strategy and
+/// every reduction that applies, and keeps the smallest file.
+use anyhow::{Context, Result};
+use image::{ImageEncoder, ImageFormat, RgbaImage};
+use png::{BitDepth, ColorType, DeflateCompression, Filter};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::io::{Cursor, Write};
+
+/// Highest PNG optimisation level.
+pub const MAX_PNG_LEVEL: u8 = 3;
+
+/// rav1e speed for AVIF, 1 (slowest, smallest) to 10. Screenshots encode
+/// in a second or two at this speed.
+const AVIF_SPEED: u8 = 7;
+
+/// How images are encoded. The defaults favour speed, for images that never
+/// leave the app; saved and exported images use [`EncodeOptions`] built from
+/// the capture settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodeOptions {
+    /// JPEG quality, 1–100.
+    pub jpeg_quality: u8,
+    /// Lossy WebP quality, 1–100, or `None` for lossless WebP.
+    pub webp_quality: Option<u8>,
+    /// AVIF quality, 1–100.
+    pub avif_quality: u8,
+    /// PNG optimisation level, 0–[`MAX_PNG_LEVEL`]:
+    /// 0 writes RGBA with fast compression; 1 reduces the colour type and
+    /// uses balanced compression; 2 reduces with the best compression; 3 also
+    /// tries every filter strategy and reduction and keeps the smallest.
+    pub png_level: u8,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        Self {
+            jpeg_quality: 90,
+            webp_quality: None,
+            avif_quality: 80,
+            png_level: 0,
+        }
+    }
+}
+
+/// Encode `img` as `format` to `w`.
+///
+/// JPEG strips the alpha channel (JPEG does not support transparency).
+/// TIFF, QOI and other formats go through `image`'s encoders as they are.
+pub fn encode(
+    img: RgbaImage,
+    format: ImageFormat,
+    options: &EncodeOptions,
+    w: &mut (impl Write + std::io::Seek),
+) -> Result<()> {
+    match format {
+        ImageFormat::Jpeg => {
+            let rgb = image::DynamicImage::ImageRgba8(img).to_rgb8();
+            image::codecs::jpeg::JpegEncoder::new_with_quality(
+                w,
+                options.jpeg_quality.clamp(1, 100),
+            )
+            .encode_image(&rgb)
+            .context("Failed to encode JPEG")
+        }
+        ImageFormat::Avif => image::codecs::avif::AvifEncoder::new_with_speed_quality(
+            w,
+            AVIF_SPEED,
+            options.avif_quality.clamp(1, 100),
+        )
+        .write_image(
+            img.as_raw(),
+            img.width(),
+            img.height(),
+            image::ExtendedColorType::Rgba8,
+        )
+        .context("Failed to encode AVIF"),
+        ImageFormat::Png if options.png_level > 0 => {
+            let png = optimize_png(&img, options.png_level)?;
+            w.write_all(&png).context("Failed to write PNG")
+        }
+        ImageFormat::WebP => match options.webp_quality {
+            Some(quality) => {
+                let (width, height) = img.dimensions();
+                let webp = webp::Encoder::from_rgba(&img, width, height)
+                    .encode_simple(false, quality.clamp(1, 100) as f32)
+                    .map_err(|e| anyhow::anyhow!("Failed to encode WebP: {e:?}"))?;
+                w.write_all(&webp).context("Failed to write WebP")
+            }
+            None => image::DynamicImage::ImageRgba8(img)
+                .write_to(w, ImageFormat::WebP)
+                .context("Failed to encode WebP"),
+        },
+        _ => image::DynamicImage::ImageRgba8(img)
+            .write_to(w, format)
+            .context("Failed to encode image"),
+    }
+}
+
+/// Encode `img` as `format` into a byte vector.
+pub fn encode_to_vec(
+    img: RgbaImage,
+    format: ImageFormat,
+    options: &EncodeOptions,
+) -> Result<Vec<u8>> {
+    let mut buf = Cursor::new(Vec::new());
+    encode(img, format, options, &mut buf)?;
+    Ok(buf.into_inner())
+}
+
+/// `img` as a PNG optimised at `level` (1–[`MAX_PNG_LEVEL`]).
+pub fn optimize_png(img: &RgbaImage, level: u8) -> Result<Vec<u8>> {
+    let reductions = reductions(img);
+    if level < MAX_PNG_LEVEL {
+        // zlib's default and best levels, as png's `Balanced` and `High`.
+        let compression = DeflateCompression::Level(if level == 1 { 6 } else { 9 });
+        return reductions[0].write(img.dimensions(), compression, Filter::Adaptive);
+    }
+
+    const FILTERS: [Filter; 7] = [
+        Filter::Adaptive,
+        Filter::MinEntropy,
+        Filter::NoFilter,
+        Filter::Sub,
+        Filter::Up,
+        Filter::Avg,
+        Filter::Paeth,
+    ];
+    let trials: Vec<_> = reductions
+        .iter()
+        .flat_map(|r| FILTERS.iter().map(move |f| (r, *f)))
+        .collect();
+    trials
+        .into_par_iter()
+        .map(|(r, filter)| r.write(img.dimensions(), DeflateCompression::Level(9), filter))
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .min_by_key(Vec::len)
+        .context("no PNG encodings")
+}
+
+/// Pixels in one PNG colour type, with the palette if it is indexed.
+struct Reduction {
+    color: ColorType,
+    depth: BitDepth,
+    /// `PLTE` and `tRNS` chunk contents.
+    palette: Option<(Vec<u8>, Vec<u8>)>,
+    data: Vec<u8>,
+}
+
+impl Reduction {
+    fn write(
+        &self,
+        (width, height): (u32, u32),
+        compression: DeflateCompression,
+        filter: Filter,
+    ) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        let mut encoder = png::Encoder::new(&mut out, width, height);
+        encoder.set_color(self.color);
+        encoder.set_depth(self.depth);
+        if let Some((plte, trns)) = &self.palette {
+            encoder.set_palette(plte.as_slice());
+            if !trns.is_empty() {
+                encoder.set_trns(trns.as_slice());
+            }
+        }
+        encoder.set_deflate_compression(compression);
+        encoder.set_filter(filter);
+        let mut writer = encoder.write_header().context("Failed to encode PNG")?;
+        writer
+            .write_image_data(&self.data)
+            .context("Failed to encode PNG")?;
+        writer.finish().context("Failed to encode PNG")?;
+        Ok(out)
+    }
+}
+
+/// Every lossless representation of `img` worth trying, the one most likely
+/// to be smallest first. The last is always plain RGB or RGBA.
+fn reductions(img: &RgbaImage) -> Vec<Reduction> {
+    let opaque = img.pixels().all(|p| p[3] == 255);
+    let grey = img.pixels().all(|p| p[0] == p[1] && p[1] == p[2]);
+
+    let mut out = Vec::new();
+    if let Some(indexed) = indexed(img) {
+        out.push(indexed);
+    }
+    if grey {
+        let (color, channels): (_, &[usize]) = if opaque {
+            (ColorType::Grayscale, &[0])
+        } else {
+            (ColorType::GrayscaleAlpha, &[0, 3])
+        };
+        let grey = Reduction {
+            color,
+            depth: BitDepth::Eight,
+            palette: None,
+            data: img
+                .pixels()
+                .flat_map(|p| channels.iter().map(move |&c| p[c]))
+                .collect(),
+        };
+        // An 8-bit palette costs as many bytes per pixel as opaque grey, plus
+        // the palette itself.
+        match out.first() {
+            Some(indexed) if indexed.depth == BitDepth::Eight && opaque => out.insert(0, grey),
+            _ => out.push(grey),
+        }
+    }
+    out.push(if opaque {
+        Reduction {
+            color: ColorType::Rgb,
+            depth: BitDepth::Eight,
+            palette: None,
+            data: img.pixels().flat_map(|p| [p[0], p[1], p[2]]).collect(),
+        }
+    } else {
+        Reduction {
+            color: ColorType::Rgba,
+            depth: BitDepth::Eight,
+            palette: None,
+            data: img.as_raw().clone(),
+        }
+    });
+    out
+}
+
+/// `img` as palette indices, if it has at most 256 colours. Colours with
+/// transparency come first so the `tRNS` chunk can stop after them.
+fn indexed(img: &RgbaImage) -> Option<Reduction> {
+    let mut colors: HashMap<[u8; 4], u8> = HashMap::new();
+    let mut last = None;
+    for p in img.pixels() {
+        if last == Some(p.0) {
+            continue;
+        }
+        last = Some(p.0);
+        colors.insert(p.0, 0);
+        if colors.len() > 256 {
+            return None;
+        }
+    }
+
+    let mut palette: Vec<[u8; 4]> = colors.keys().copied().collect();
+    palette.sort_by_key(|c| (c[3] == 255, *c));
+    for (i, c) in palette.iter().enumerate() {
+        colors.insert(*c, i as u8);
+    }
+    let (depth, bits) = match palette.len() {
+        0..=2 => (BitDepth::One, 1),
+        3..=4 => (BitDepth::Two, 2),
+        5..=16 => (BitDepth::Four, 4),
+        _ => (BitDepth::Eight, 8),
+    };
+
+    let width = img.width() as usize;
+    let per_byte = 8 / bits;
+    let stride = width.div_ceil(per_byte);
+    let mut data = vec![0u8; stride * img.height() as usize];
+    for (y, row) in img.rows().enumerate() {
+        for (x, p) in row.enumerate() {
+            let shift = 8 - bits * (x % per_byte + 1);
+            data[y * stride + x / per_byte] |= colors[&p.0] << shift;
+        }
+    }
+
+    let plte = palette.iter().flat_map(|c| [c[0], c[1], c[2]]).collect();
+    let trns = palette
+        .iter()
+        .take_while(|c| c[3] < 255)
+        .map(|c| c[3])
+        .collect();
+    Some(Reduction {
+        color: ColorType::Indexed,
+        depth,
+        palette: Some((plte, trns)),
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn decode(bytes: &[u8]) -> RgbaImage {
+        image::load_from_memory(bytes).unwrap().to_rgba8()
+    }
+
+    fn color_type(png: &[u8]) -> ColorType {
+        let reader = png::Decoder::new(Cursor::new(png)).read_info().unwrap();
+        reader.info().color_type
+    }
+
+    /// A screenshot-like image: flat areas in a handful of colours.
+    fn flat(colors: &[Rgba<u8>]) -> RgbaImage {
+        RgbaImage::from_fn(37, 23, |x, y| {
+            colors[((x / 5 + y / 3) as usize) % colors.len()]
+        })
+    }
+
+    #[test]
+    fn optimised_pngs_are_lossless_at_every_level() {
+        let images = [
+            flat(&[Rgba([255, 255, 255, 255]), Rgba([0, 0, 0, 255])]),
+            flat(&[
+                Rgba([10, 20, 30, 0]),
+                Rgba([200, 0, 0, 128]),
+                Rgba([0, 200, 0, 255]),
+            ]),
+            flat(&[Rgba([40, 40, 40, 255]), Rgba([90, 90, 90, 200])]),
+            RgbaImage::from_fn(30, 20, |x, y| Rgba([x as u8 * 8, y as u8 * 12, 77, 255])),
+            RgbaImage::from_fn(30, 20, |x, y| Rgba([x as u8, y as u8, 0, (x * y) as u8])),
+        ];
+        for img in &images {
+            for level in 1..=MAX_PNG_LEVEL {
+                let png = optimize_png(img, level).unwrap();
+                assert_eq!(&decode(&png), img, "level {level}");
+            }
+        }
+    }
+
+    #[test]
+    fn colour_types_are_reduced() {
+        let two = flat(&[Rgba([255, 255, 255, 255]), Rgba([0, 90, 200, 255])]);
+        assert_eq!(
+            color_type(&optimize_png(&two, 2).unwrap()),
+            ColorType::Indexed
+        );
+
+        let grey = RgbaImage::from_fn(20, 20, |x, y| {
+            let v = (x * 13 + y * 7) as u8;
+            Rgba([v, v, v, 255])
+        });
+        assert_eq!(
+            color_type(&optimize_png(&grey, 2).unwrap()),
+            ColorType::Grayscale
+        );
+
+        let many = RgbaImage::from_fn(20, 20, |x, y| Rgba([x as u8 * 9, y as u8 * 11, 3, 255]));
+        assert_eq!(color_type(&optimize_png(&many, 2).unwrap()), ColorType::Rgb);
+    }
+
+    #[test]
+    fn optimised_pngs_are_smaller() {
+        let img = flat(&[
+            Rgba([250, 250, 250, 255]),
+            Rgba([30, 30, 30, 255]),
+            Rgba([0, 120, 215, 255]),
+        ]);
+        let plain =
+            encode_to_vec(img.clone(), ImageFormat::Png, &EncodeOptions::default()).unwrap();
+        let optimised = optimize_png(&img, 2).unwrap();
+        let max = optimize_png(&img, MAX_PNG_LEVEL).unwrap();
+        assert!(optimised.len() < plain.len());
+        assert!(max.len() <= optimised.len());
+    }
+
+    #[test]
+    fn webp_quality_selects_lossy_or_lossless() {
+        let img = RgbaImage::from_fn(32, 32, |x, y| Rgba([x as u8 * 8, y as u8 * 8, 100, 255]));
+        let lossless =
+            encode_to_vec(img.clone(), ImageFormat::WebP, &EncodeOptions::default()).unwrap();
+        assert_eq!(decode(&lossless), img);
+
+        let lossy = encode_to_vec(
+            img.clone(),
+            ImageFormat::WebP,
+            &EncodeOptions {
+                webp_quality: Some(50),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(&lossy[..4], b"RIFF");
+        assert_eq!(&lossy[12..16], b"VP8 ");
+    }
+
+    #[test]
+    fn avif_tiff_and_qoi_are_written() {
+        let img = RgbaImage::from_fn(16, 16, |x, y| Rgba([x as u8 * 16, y as u8 * 16, 0, 200]));
+        let options = EncodeOptions::default();
+        let avif = encode_to_vec(img.clone(), ImageFormat::Avif, &options).unwrap();
+        assert_eq!(&avif[4..12], b"ftypavif");
+        for format in [ImageFormat::Tiff, ImageFormat::Qoi] {
+            let bytes = encode_to_vec(img.clone(), format, &options).unwrap();
+            assert_eq!(decode(&bytes), img, "{format:?}");
+        }
+    }
+
+    #[test]
+    fn jpeg_quality_is_honoured() {
+        let img = RgbaImage::from_fn(64, 64, |x, y| {
+            Rgba([(x * y) as u8, x as u8 * 4, y as u8 * 4, 255])
+        });
+        let size = |quality| {
+            let options = EncodeOptions {
+                jpeg_quality: quality,
+                ..Default::default()
+            };
+            encode_to_vec(img.clone(), ImageFormat::Jpeg, &options)
+                .unwrap()
+                .len()
+        };
+        assert!(size(30) < size(95));
+    }
+}
@@ -6,13 +6,16 @@
 /// Protocol: each message is framed as a 4-byte big-endian u32 payload length
 /// followed by that many bytes of UTF-8 JSON.  Request: `{id, command, params}`.
 /// Response: `{id, ok}` on success or `{id, error: {code, message}}` on failure.
+use crate::annotate::{Annotation, AnnotationError, AnnotationErrors, Shape};
+use crate::capture::{
+    capture_and_store, CaptureBackend, CaptureMode, CaptureResult, ScreenshotInfo, SharedBackend,
+};
 use anyhow::Result;
 use base64::prelude::*;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::io::Cursor;
 use std::path::PathBuf;
-use std::sync::Arc;
 use tauri::Manager;
 use tracing::{error, info, warn};
 use uuid::Uuid;
@@ -45,6 +48,10 @@ struct IpcResponse {
 struct IpcError {
     code: String,
     message: String,
+    /// Machine-readable specifics, e.g. the per-annotation problems for
+    /// `invalid_annotations`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<Value>,
 }
 
 impl IpcResponse {
@@ -62,9 +69,23 @@ impl IpcResponse {
             error: Some(IpcError {
                 code: code.to_owned(),
                 message,
+                details: None,
             }),
         }
     }
+
+    /// Error response for a failed command. Annotation validation failures
+    /// get their own code and carry the individual problems as `details`.
+    fn command_err(id: String, e: anyhow::Error) -> Self {
+        let mut response = Self::err(id, "command_error", e.to_string());
+        if let Some(errors) = e.downcast_ref::<crate::annotate::AnnotationErrors>() {
+            if let Some(error) = response.error.as_mut() {
+                error.code = "invalid_annotations".to_owned();
+                error.details = serde_json::to_value(errors).ok();
+            }
+        }
+        response
+    }
 }
 
 // ─── socket path ─────────────────────────────────────────────────────────────
@@ -135,7 +156,7 @@ async fn handle_connection(mut stream: UnixStream, app: tauri::AppHandle) -> Res
                 let id = req.id.clone();
                 match dispatch(&app, &req.command, req.params).await {
                     Ok(v) => IpcResponse::ok(id, v),
-                    Err(e) => IpcResponse::err(id, "command_error", e.to_string()),
+                    Err(e) => IpcResponse::command_err(id, e),
                 }
             }
             Err(e) => {
@@ -169,78 +190,149 @@ async fn dispatch(app: &tauri::AppHandle, command: &str, params: Value) -> anyho
                 .and_then(Value::as_str)
                 .unwrap_or("fullscreen")
                 .to_owned();
-            let image = match mode.as_str() {
-                "fullscreen" => {
-                    if let Some(win) = app.get_webview_window("main") {
-                        let _ = win.hide();
-                    }
-                    tokio::time::sleep(tokio::time::Duration::from_millis(300)).await;
-                    let img = crate::capture::xcap_backend::capture_fullscreen()
-                        .await
-                        .map_err(|e| anyhow::anyhow!("Capture failed: {e}"))?;
-                    if let Some(win) = app.get_webview_window("main") {
-                        let _ = win.show();
-                    }
-                    img
-                }
-                "monitor" => {
-                    let idx = params
-                        .get("monitor_index")
-                        .and_then(Value::as_u64)
-                        .map(|v| v as u32)
-                        .ok_or_else(|| {
-                            anyhow::anyhow!("monitor_index required for mode 'monitor'")
-                        })?;
-                    if let Some(win) = app.get_webview_window("main") {
-                        let _ = win.hide();
-                    }
-                    tokio::time::sleep(tokio::time::Duration::from_millis(300)).await;
-                    let img = crate::capture::xcap_backend::capture_monitor(idx)
-                        .await
-                        .map_err(|e| anyhow::anyhow!("Monitor capture failed: {e}"))?;
-                    if let Some(win) = app.get_webview_window("main") {
-                        let _ = win.show();
-                    }
-                    img
-                }
-                "window" => {
-                    let title_sub = params
-                        .get("window_title")
-                        .and_then(Value::as_str)
-                        .ok_or_else(|| anyhow::anyhow!("window_title required for mode 'window'"))?
-                        .to_lowercase();
-                    let windows = crate::commands::capture::list_windows()
-                        .await
-                        .map_err(|e| anyhow::anyhow!("{e}"))?;
-                    let win = windows
-                        .into_iter()
-                        .find(|w| w.title.to_lowercase().contains(&title_sub))
-                        .ok_or_else(|| anyhow::anyhow!("No window matching title"))?;
-                    crate::capture::xcap_backend::capture_window(win.id)
-                        .await
-                        .map_err(|e| anyhow::anyhow!("Window capture failed: {e}"))?
-                }
-                other => anyhow::bail!("Unknown capture mode '{other}'"),
-            };
-            let image = Arc::new(image);
-            let (width, height) = (image.width(), image.height());
-            let id = Uuid::new_v4();
-            app.state::<crate::capture::ImageStore>()
-                .insert(id, Arc::clone(&image));
+            let backend = app.state::<SharedBackend>().inner().clone();
+            let capture_mode = capture_mode_from_params(backend.as_ref(), &params).await?;
+            let dedupe = params
+                .get("dedupe")
+                .and_then(Value::as_bool)
+                .unwrap_or(false);
+            let result = capture_hidden(app, capture_mode, dedupe).await?;
+            let image = result.image;
+            let (width, height) = result.metadata.dimensions;
             let mut png_data = Vec::new();
             image
                 .write_to(&mut Cursor::new(&mut png_data), image::ImageFormat::Png)
                 .map_err(|e| anyhow::anyhow!("PNG encoding failed: {e}"))?;
             Ok(serde_json::json!({
-                "id": id.to_string(),
+                "id": result.id.to_string(),
                 "image_b64": BASE64_STANDARD.encode(&png_data),
                 "width": width,
                 "height": height,
-                "timestamp": chrono::Utc::now().to_rfc3339(),
+                "timestamp": result.metadata.timestamp.to_rfc3339(),
                 "mode": mode,
+                "duplicate": result.reused,
             }))
         }
 
+        "record_screen" => {
+            use crate::capture::record::{self, AnimationFormat, RecordOptions};
+            use crate::commands::capture::{write_recording, RecordingState};
+
+            let format = AnimationFormat::parse(
+                params
+                    .get("format")
+                    .and_then(Value::as_str)
+                    .unwrap_or("gif"),
+            )?;
+            let duration_ms = params
+                .get("duration_ms")
+                .and_then(Value::as_u64)
+                .ok_or_else(|| anyhow::anyhow!("duration_ms is required"))?;
+            let fps = params
+                .get("fps")
+                .and_then(Value::as_u64)
+                .map(|v| v.min(u32::MAX as u64) as u32)
+                .unwrap_or(record::DEFAULT_FPS);
+            let path = params
+                .get("path")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_owned();
+            let opts = RecordOptions {
+                fps,
+                duration: std::time::Duration::from_millis(duration_ms),
+                include_cursor: crate::commands::settings::load_capture_settings(app)
+                    .include_mouse_cursor,
+            };
+
+            let backend = app.state::<SharedBackend>().inner().clone();
+            let capture_mode = capture_mode_from_params(backend.as_ref(), &params).await?;
+            let session = app.state::<RecordingState>();
+            if !session.0.try_begin() {
+                anyhow::bail!("A recording is already running");
+            }
+            let hide = !matches!(capture_mode, CaptureMode::Window(_));
+            if hide {
+                if let Some(win) = app.get_webview_window("main") {
+                    let _ = win.hide();
+                }
+                tokio::time::sleep(tokio::time::Duration::from_millis(300)).await;
+            }
+            let result = record::record(
+                backend.as_ref(),
+                capture_mode,
+                opts,
+                session.0.stop_flag(),
+                None,
+            )
+            .await;
+            if hide {
+                if let Some(win) = app.get_webview_window("main") {
+                    let _ = win.show();
+                }
+            }
+            session.0.end();
+
+            let rec = result?;
+            let saved = tokio::task::spawn_blocking(move || write_recording(&rec, format, &path))
+                .await?
+                .map_err(|e| anyhow::anyhow!(e))?;
+            Ok(serde_json::to_value(saved)?)
+        }
+
+        "start_capture_session" => {
+            use crate::commands::capture::{build_session_spec, spawn_capture_session};
+
+            let duration_secs = params
+                .get("duration_secs")
+                .and_then(Value::as_u64)
+                .ok_or_else(|| anyhow::anyhow!("duration_secs is required"))?;
+            let backend = app.state::<SharedBackend>().inner().clone();
+            let capture_mode = capture_mode_from_params(backend.as_ref(), &params).await?;
+            let spec = build_session_spec(
+                capture_mode,
+                params.get("interval_secs").and_then(Value::as_f64),
+                params.get("cron").and_then(Value::as_str),
+                duration_secs,
+                params
+                    .get("max_frames")
+                    .and_then(Value::as_u64)
+                    .map(|v| v.min(u32::MAX as u64) as u32),
+                params
+                    .get("skip_unchanged")
+                    .and_then(Value::as_bool)
+                    .unwrap_or(false),
+                crate::commands::settings::load_capture_settings(app).capture_options(),
+            )
+            .map_err(|e| anyhow::anyhow!(e))?;
+            let status = spawn_capture_session(app, spec).map_err(|e| anyhow::anyhow!(e))?;
+            Ok(serde_json::to_value(status)?)
+        }
+
+        "list_capture_sessions" => {
+            use crate::capture::session::SessionRegistry;
+
+            Ok(serde_json::to_value(app.state::<SessionRegistry>().list())?)
+        }
+
+        "get_capture_session" | "stop_capture_session" => {
+            use crate::capture::session::SessionRegistry;
+
+            let session_id = params
+                .get("session_id")
+                .and_then(Value::as_str)
+                .ok_or_else(|| anyhow::anyhow!("session_id is required"))?;
+            let id = Uuid::parse_str(session_id)?;
+            let session = app
+                .state::<SessionRegistry>()
+                .get(&id)
+                .ok_or_else(|| anyhow::anyhow!("capture session not found: {session_id}"))?;
+            if command == "stop_capture_session" {
+                session.stop();
+            }
+            Ok(serde_json::to_value(session.status())?)
+        }
+
         "ocr_screenshot" => {
             let lang = params
                 .get("language")
@@ -257,20 +349,8 @@ async fn dispatch(app: &tauri::AppHandle, command: &str, params: Value) -> anyho
                     (img, uuid)
                 }
                 None => {
-                    if let Some(win) = app.get_webview_window("main") {
-                        let _ = win.hide();
-                    }
-                    tokio::time::sleep(tokio::time::Duration::from_millis(300)).await;
-                    let img = crate::capture::xcap_backend::capture_fullscreen()
-                        .await
-                        .map_err(|e| anyhow::anyhow!("Capture failed: {e}"))?;
-                    if let Some(win) = app.get_webview_window("main") {
-                        let _ = win.show();
-                    }
-                    let img = Arc::new(img);
-                    let id = Uuid::new_v4();
-                    store.insert(id, Arc::clone(&img));
-                    (img, id)
+                    let result = capture_hidden(app, CaptureMode::Fullscreen, false).await?;
+                    (result.image, result.id)
                 }
             };
             let tessdata_path = crate::commands::ai::resolve_tessdata_path(app, &lang)
@@ -289,6 +369,9 @@ async fn dispatch(app: &tauri::AppHandle, command: &str, params: Value) -> anyho
             };
             let ocr = crate::ai::ocr::run_ocr(&image, &opts, Some(&on_progress))
                 .map_err(|e| anyhow::anyhow!("OCR failed: {e}"))?;
+            if let Some(index) = app.try_state::<crate::commands::search::SharedIndex>() {
+                index.add_ocr(screenshot_id, &ocr.regions);
+            }
             Ok(serde_json::json!({
                 "screenshot_id": screenshot_id.to_string(),
                 "text": ocr.full_text,
@@ -303,22 +386,33 @@ async fn dispatch(app: &tauri::AppHandle, command: &str, params: Value) -> anyho
             let id_str = params
                 .get("screenshot_id")
                 .and_then(Value::as_str)
-                .ok_or_else(|| anyhow::anyhow!("screenshot_id required"))?
-                .to_owned();
+                .ok_or_else(|| anyhow::anyhow!("screenshot_id required"))?;
+            let uuid = Uuid::parse_str(id_str).map_err(|e| anyhow::anyhow!("{e}"))?;
             let annotations_val = inject_annotation_ids(
                 params
                     .get("annotations")
                     .cloned()
                     .unwrap_or(Value::Array(vec![])),
             );
-            let annotations: Vec<crate::commands::files::Annotation> =
-                serde_json::from_value(annotations_val)
-                    .map_err(|e| anyhow::anyhow!("Invalid annotations: {e}"))?;
+            let annotations = ipc_annotations(annotations_val.clone())?;
             let store = app.state::<crate::capture::ImageStore>();
-            let image_b64 =
-                crate::commands::files::composite_image(id_str, annotations, None, store)
-                    .map_err(|e| anyhow::anyhow!("{e}"))?;
-            Ok(serde_json::json!({ "image_b64": image_b64 }))
+            let image = store
+                .get(&uuid)
+                .ok_or_else(|| anyhow::anyhow!("Screenshot not found: {id_str}"))?;
+            let annotated = image::DynamicImage::ImageRgba8(crate::annotate::render_annotations(
+                &image,
+                &annotations,
+                &store,
+            )?);
+            let operation = crate::capture::Operation::Annotate {
+                annotations: annotations_val,
+            };
+            let derived = store_derived(&store, &uuid, annotated, operation).await?;
+            Ok(serde_json::json!({
+                "screenshot_id": derived.id.to_string(),
+                "parent_id": id_str,
+                "image_b64": derived.image_b64,
+            }))
         }
 
         "analyze_screenshot" => {
@@ -345,20 +439,8 @@ async fn dispatch(app: &tauri::AppHandle, command: &str, params: Value) -> anyho
                     (img, uuid)
                 }
                 None => {
-                    if let Some(win) = app.get_webview_window("main") {
-                        let _ = win.hide();
-                    }
-                    tokio::time::sleep(tokio::time::Duration::from_millis(300)).await;
-                    let img = crate::capture::xcap_backend::capture_fullscreen()
-                        .await
-                        .map_err(|e| anyhow::anyhow!("Capture failed: {e}"))?;
-                    if let Some(win) = app.get_webview_window("main") {
-                        let _ = win.show();
-                    }
-                    let img = Arc::new(img);
-                    let id = Uuid::new_v4();
-                    store.insert(id, Arc::clone(&img));
-                    (img, id)
+                    let result = capture_hidden(app, CaptureMode::Fullscreen, false).await?;
+                    (result.image, result.id)
                 }
             };
 
@@ -434,53 +516,33 @@ async fn dispatch(app: &tauri::AppHandle, command: &str, params: Value) -> anyho
             let id_str = params
                 .get("screenshot_id")
                 .and_then(Value::as_str)
-                .ok_or_else(|| anyhow::anyhow!("screenshot_id required"))?
-                .to_owned();
+                .ok_or_else(|| anyhow::anyhow!("screenshot_id required"))?;
             let store = app.state::<crate::capture::ImageStore>();
-            let uuid = Uuid::parse_str(&id_str).map_err(|e| anyhow::anyhow!("{e}"))?;
+            let uuid = Uuid::parse_str(id_str).map_err(|e| anyhow::anyhow!("{e}"))?;
             let image = store
                 .get(&uuid)
                 .ok_or_else(|| anyhow::anyhow!("Screenshot not found: {id_str}"))?;
 
-            let tessdata_path = crate::commands::ai::resolve_tessdata_path(app, "eng")
-                .map_err(|e| anyhow::anyhow!("{e}"))?;
-            let opts = crate::ai::ocr::OcrOptions {
-                lang: "eng".to_owned(),
-                tessdata_path,
+            let style = match params.get("style") {
+                Some(v) if !v.is_null() => serde_json::from_value(v.clone())
+                    .map_err(|e| anyhow::anyhow!("Invalid style: {e}"))?,
+                _ => crate::annotate::RedactStyle::SECURE,
             };
-            let ocr = crate::ai::ocr::run_ocr(&image, &opts, None)
-                .map_err(|e| anyhow::anyhow!("OCR failed: {e}"))?;
-            let pii = crate::ai::pii::detect_pii(&ocr.regions)
-                .map_err(|e| anyhow::anyhow!("PII detection failed: {e}"))?;
 
-            let blur_annotations: Vec<crate::commands::files::Annotation> = pii
-                .iter()
-                .map(|m| crate::commands::files::Annotation {
-                    id: Uuid::new_v4().to_string(),
-                    annotation_type: "blur".to_owned(),
-                    x: m.x as f64,
-                    y: m.y as f64,
-                    width: Some(m.w as f64),
-                    height: Some(m.h as f64),
-                    stroke_color: None,
-                    fill_color: None,
-                    stroke_width: None,
-                    opacity: None,
-                    text: None,
-                    font_size: None,
-                    font_family: None,
-                    points: None,
-                    step_number: None,
-                    blur_radius: None,
-                    highlight_color: None,
-                    created_at: None,
-                    locked: None,
-                })
-                .collect();
-
-            let image_b64 =
-                crate::commands::files::composite_image(id_str, blur_annotations, None, store)
-                    .map_err(|e| anyhow::anyhow!("{e}"))?;
+            let lang = "eng";
+            let (redacted, pii) = crate::commands::ai::redact_pii(app, &image, lang, style)
+                .map_err(|e| anyhow::anyhow!("{e}"))?;
+            let operation = crate::capture::Operation::RedactPii {
+                lang: lang.to_owned(),
+                style,
+            };
+            let derived = store_derived(
+                &store,
+                &uuid,
+                image::DynamicImage::ImageRgba8(redacted),
+                operation,
+            )
+            .await?;
 
             let detections: Vec<Value> = pii
                 .into_iter()
@@ -490,16 +552,171 @@ async fn dispatch(app: &tauri::AppHandle, command: &str, params: Value) -> anyho
                     })
                 })
                 .collect();
-            Ok(serde_json::json!({ "image_b64": image_b64, "detections": detections }))
+            Ok(serde_json::json!({
+                "screenshot_id": derived.id.to_string(),
+                "parent_id": id_str,
+                "image_b64": derived.image_b64,
+                "detections": detections,
+            }))
+        }
+
+        "search_screenshots" => {
+            let query = params
+                .get("query")
+                .and_then(Value::as_str)
+                .ok_or_else(|| anyhow::anyhow!("query required"))?;
+            let limit = params
+                .get("limit")
+                .and_then(Value::as_u64)
+                .map_or(crate::commands::search::DEFAULT_SEARCH_LIMIT, |l| {
+                    l as usize
+                });
+            let store = app.state::<crate::capture::ImageStore>();
+            let index = app.state::<crate::commands::search::SharedIndex>();
+            let results: Vec<Value> = crate::commands::search::search(&store, &index, query, limit)
+                .iter()
+                .map(|result| {
+                    let mut entry = result.info.to_json();
+                    entry["hits"] = serde_json::json!(result.hits);
+                    entry
+                })
+                .collect();
+            Ok(serde_json::json!({
+                "query": query,
+                "indexed": index.len(),
+                "results": results,
+            }))
+        }
+
+        "find_similar_screenshots" => {
+            let id_str = params
+                .get("screenshot_id")
+                .and_then(Value::as_str)
+                .ok_or_else(|| anyhow::anyhow!("screenshot_id required"))?;
+            let id = Uuid::parse_str(id_str)?;
+            let max_distance = params
+                .get("max_distance")
+                .and_then(Value::as_u64)
+                .map_or(crate::capture::phash::SIMILAR_DISTANCE, |d| {
+                    d.min(64) as u32
+                });
+            let limit = params
+                .get("limit")
+                .and_then(Value::as_u64)
+                .map_or(usize::MAX, |l| l as usize);
+            let store = app.state::<crate::capture::ImageStore>();
+            let hits = store
+                .find_similar(&id, max_distance)
+                .ok_or_else(|| anyhow::anyhow!("Screenshot not found: {id_str}"))?;
+            let similar: Vec<Value> = hits
+                .iter()
+                .take(limit)
+                .map(|hit| {
+                    let mut entry = hit.info.to_json();
+                    entry["distance"] = Value::from(hit.distance);
+                    entry
+                })
+                .collect();
+            Ok(serde_json::json!({ "screenshot_id": id_str, "similar": similar }))
+        }
+
+        "get_screenshot_lineage" => {
+            let id_str = params
+                .get("screenshot_id")
+                .and_then(Value::as_str)
+                .ok_or_else(|| anyhow::anyhow!("screenshot_id required"))?;
+            let id = Uuid::parse_str(id_str)?;
+            let store = app.state::<crate::capture::ImageStore>();
+            let lineage = store
+                .lineage(&id)
+                .ok_or_else(|| anyhow::anyhow!("Screenshot not found: {id_str}"))?;
+            Ok(serde_json::json!({
+                "screenshot": lineage.image.to_json(),
+                "ancestors": lineage.ancestors.iter().map(ScreenshotInfo::to_json).collect::<Vec<_>>(),
+                "derivatives": lineage.derivatives.iter().map(ScreenshotInfo::to_json).collect::<Vec<_>>(),
+                "operations": lineage.operations,
+            }))
+        }
+
+        "reapply_operations" => {
+            let source_str = params
+                .get("source_id")
+                .and_then(Value::as_str)
+                .ok_or_else(|| anyhow::anyhow!("source_id required"))?;
+            let target = match params.get("screenshot_id").and_then(Value::as_str) {
+                Some(id_str) => Uuid::parse_str(id_str)?,
+                None => {
+                    capture_hidden(app, CaptureMode::Fullscreen, false)
+                        .await?
+                        .id
+                }
+            };
+            let source = Uuid::parse_str(source_str)?;
+            let store = app.state::<crate::capture::ImageStore>();
+            let operations = store
+                .lineage(&source)
+                .ok_or_else(|| anyhow::anyhow!("Screenshot not found: {source_str}"))?
+                .operations;
+            for operation in &operations {
+                if let crate::capture::Operation::Annotate { annotations } = operation {
+                    ipc_annotations(annotations.clone())?;
+                }
+            }
+            let info =
+                crate::commands::capture::replay_operations(app, &store, target, &operations)
+                    .map_err(|e| anyhow::anyhow!("{e}"))?;
+            let mut result = info.to_json();
+            result["source_id"] = Value::String(source_str.to_owned());
+            result["operations_applied"] = Value::from(operations.len());
+            Ok(result)
+        }
+
+        "get_screenshot" => {
+            let id_str = params
+                .get("id")
+                .and_then(Value::as_str)
+                .ok_or_else(|| anyhow::anyhow!("id is required"))?;
+            let id = Uuid::parse_str(id_str)?;
+            let store = app.state::<crate::capture::ImageStore>();
+            let metadata = store
+                .metadata(&id)
+                .ok_or_else(|| anyhow::anyhow!("Screenshot not found: {id_str}"))?;
+            let image = store
+                .get(&id)
+                .ok_or_else(|| anyhow::anyhow!("Screenshot not found: {id_str}"))?;
+            let png_data = tokio::task::spawn_blocking(move || {
+                let mut png_data = Vec::new();
+                image
+                    .write_to(&mut Cursor::new(&mut png_data), image::ImageFormat::Png)
+                    .map(|_| png_data)
+            })
+            .await?
+            .map_err(|e| anyhow::anyhow!("PNG encoding failed: {e}"))?;
+            let mut entry = ScreenshotInfo { id, metadata }.to_json();
+            entry["image_b64"] = Value::String(BASE64_STANDARD.encode(&png_data));
+            Ok(entry)
+        }
+
+        "delete_screenshot" => {
+            let id_str = params
+                .get("screenshot_id")
+                .and_then(Value::as_str)
+                .ok_or_else(|| anyhow::anyhow!("screenshot_id is required"))?;
+            let id = Uuid::parse_str(id_str)?;
+            if !app.state::<crate::capture::ImageStore>().remove(&id) {
+                anyhow::bail!("Screenshot not found: {id_str}");
+            }
+            Ok(serde_json::json!({ "deleted": id_str }))
         }
 
         "list_screenshots" => {
             let limit = params.get("limit").and_then(Value::as_u64).unwrap_or(10) as usize;
-            let ids = app.state::<crate::capture::ImageStore>().ids();
-            let entries: Vec<Value> = ids
-                .into_iter()
+            let entries: Vec<Value> = app
+                .state::<crate::capture::ImageStore>()
+                .list()
+                .iter()
                 .take(limit)
-                .map(|id| serde_json::json!({ "id": id.to_string() }))
+                .map(ScreenshotInfo::to_json)
                 .collect();
             Ok(Value::Array(entries))
         }
@@ -508,6 +725,151 @@ async fn dispatch(app: &tauri::AppHandle, command: &str, params: Value) -> anyho
     }
 }
 
+/// A newly derived image's id and PNG encoding.
+struct Derived {
+    id: Uuid,
+    image_b64: String,
+}
+
+/// Store `image` as derived from `parent` and encode it for the response.
+async fn store_derived(
+    store: &crate::capture::ImageStore,
+    parent: &Uuid,
+    image: image::DynamicImage,
+    operation: crate::capture::Operation,
+) -> anyhow::Result<Derived> {
+    let image = std::sync::Arc::new(image);
+    let id = Uuid::new_v4();
+    store
+        .insert_derived(id, std::sync::Arc::clone(&image), parent, operation)
+        .ok_or_else(|| anyhow::anyhow!("Screenshot not found: {parent}"))?;
+    let png_data = tokio::task::spawn_blocking(move || {
+        let mut png_data = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut png_data), image::ImageFormat::Png)
+            .map(|_| png_data)
+    })
+    .await?
+    .map_err(|e| anyhow::anyhow!("PNG encoding failed: {e}"))?;
+    Ok(Derived {
+        id,
+        image_b64: BASE64_STANDARD.encode(&png_data),
+    })
+}
+
+/// Translate `take_screenshot` IPC params into a [`CaptureMode`].
+///
+/// Window mode matches `window_title` case-insensitively against the titles
+/// reported by the backend and captures the first hit. Region mode takes
+/// `x`, `y`, `width` and `height` in desktop coordinates.
+async fn capture_mode_from_params(
+    backend: &dyn CaptureBackend,
+    params: &Value,
+) -> anyhow::Result<CaptureMode> {
+    let mode = params
+        .get("mode")
+        .and_then(Value::as_str)
+        .unwrap_or("fullscreen");
+    match mode {
+        "fullscreen" => Ok(CaptureMode::Fullscreen),
+        "monitor" => {
+            let idx = params
+                .get("monitor_index")
+                .and_then(Value::as_u64)
+                .map(|v| v as u32)
+                .ok_or_else(|| anyhow::anyhow!("monitor_index required for mode 'monitor'"))?;
+            Ok(CaptureMode::Monitor(idx))
+        }
+        "window" => {
+            let title_sub = params
+                .get("window_title")
+                .and_then(Value::as_str)
+                .ok_or_else(|| anyhow::anyhow!("window_title required for mode 'window'"))?
+                .to_lowercase();
+            let windows = backend.list_windows().await?;
+            let win = windows
+                .into_iter()
+                .find(|w| w.title.to_lowercase().contains(&title_sub))
+                .ok_or_else(|| anyhow::anyhow!("No window matching title"))?;
+            Ok(CaptureMode::Window(win.id as u64))
+        }
+        "region" => {
+            let coord = |key: &str| {
+                params
+                    .get(key)
+                    .and_then(Value::as_i64)
+                    .ok_or_else(|| anyhow::anyhow!("{key} required for mode 'region'"))
+            };
+            let (x, y) = (coord("x")?, coord("y")?);
+            let (w, h) = (coord("width")?, coord("height")?);
+            if w <= 0 || h <= 0 {
+                anyhow::bail!("width and height must be positive for mode 'region'");
+            }
+            Ok(CaptureMode::Region {
+                x: i32::try_from(x)?,
+                y: i32::try_from(y)?,
+                w: u32::try_from(w)?,
+                h: u32::try_from(h)?,
+            })
+        }
+        other => anyhow::bail!("Unknown capture mode '{other}'"),
+    }
+}
+
+/// Capture with the managed backend and store the result, hiding the main
+/// window first for desktop-wide modes so Fotos doesn't appear in the shot.
+async fn capture_hidden(
+    app: &tauri::AppHandle,
+    mode: CaptureMode,
+    reuse_duplicate: bool,
+) -> anyhow::Result<CaptureResult> {
+    let backend = app.state::<SharedBackend>().inner().clone();
+    let store = app.state::<crate::capture::ImageStore>();
+    let hide = !matches!(mode, CaptureMode::Window(_));
+    if hide {
+        if let Some(win) = app.get_webview_window("main") {
+            let _ = win.hide();
+        }
+        tokio::time::sleep(tokio::time::Duration::from_millis(300)).await;
+    }
+    let options = crate::capture::CaptureOptions {
+        reuse_duplicate,
+        ..crate::commands::settings::load_capture_settings(app).capture_options()
+    };
+    let result = capture_and_store(backend.as_ref(), &store, mode, options)
+        .await
+        .map_err(|e| anyhow::anyhow!("Capture failed: {e}"));
+    if hide {
+        if let Some(win) = app.get_webview_window("main") {
+            let _ = win.show();
+        }
+    }
+    result
+}
+
+/// Parse annotations sent over IPC. Image annotations may embed stored
+/// screenshots and stamps but not files by `path`, which would let any
+/// local client read images off disk through the app; files can only be
+/// picked in the editor.
+fn ipc_annotations(val: Value) -> Result<Vec<Annotation>, AnnotationErrors> {
+    let annotations = crate::annotate::parse_annotations(val)?;
+    let errors: Vec<AnnotationError> = annotations
+        .iter()
+        .enumerate()
+        .filter(|(_, anno)| matches!(anno.shape, Shape::Image { path: Some(_), .. }))
+        .map(|(index, anno)| AnnotationError {
+            index,
+            id: Some(anno.id.clone()),
+            kind: Some(anno.shape.kind().to_owned()),
+            message: "path: not accepted over IPC; use imageId or stamp".to_owned(),
+        })
+        .collect();
+    if !errors.is_empty() {
+        return Err(AnnotationErrors(errors));
+    }
+    Ok(annotations)
+}
+
 /// Inject a random `id` field into any annotation object that is missing one.
 fn inject_annotation_ids(mut val: Value) -> Value {
     if let Some(arr) = val.as_array_mut() {
@@ -521,3 +883,115 @@ fn inject_annotation_ids(mut val: Value) -> Value {
     }
     val
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::mock::MockBackend;
+
+    #[tokio::test]
+    async fn capture_mode_defaults_to_fullscreen() {
+        let backend = MockBackend::new();
+        let mode = capture_mode_from_params(&backend, &serde_json::json!({}))
+            .await
+            .unwrap();
+        assert_eq!(mode, CaptureMode::Fullscreen);
+    }
+
+    #[tokio::test]
+    async fn capture_mode_monitor_requires_index() {
+        let backend = MockBackend::new();
+        let params = serde_json::json!({ "mode": "monitor" });
+        assert!(capture_mode_from_params(&backend, &params).await.is_err());
+        let params = serde_json::json!({ "mode": "monitor", "monitor_index": 1 });
+        assert_eq!(
+            capture_mode_from_params(&backend, &params).await.unwrap(),
+            CaptureMode::Monitor(1)
+        );
+    }
+
+    #[tokio::test]
+    async fn capture_mode_window_matches_title_case_insensitively() {
+        let backend = MockBackend::new();
+        let params = serde_json::json!({ "mode": "window", "window_title": "BROWSER" });
+        assert_eq!(
+            capture_mode_from_params(&backend, &params).await.unwrap(),
+            CaptureMode::Window(102)
+        );
+        let params = serde_json::json!({ "mode": "window", "window_title": "nope" });
+        assert!(capture_mode_from_params(&backend, &params).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn capture_mode_region_requires_positive_rect() {
+        let backend = MockBackend::new();
+        let params =
+            serde_json::json!({ "mode": "region", "x": -10, "y": 20, "width": 300, "height": 200 });
+        assert_eq!(
+            capture_mode_from_params(&backend, &params).await.unwrap(),
+            CaptureMode::Region {
+                x: -10,
+                y: 20,
+                w: 300,
+                h: 200
+            }
+        );
+        let params = serde_json::json!({ "mode": "region", "x": 0, "y": 0, "width": 300 });
+        assert!(capture_mode_from_params(&backend, &params).await.is_err());
+        let params =
+            serde_json::json!({ "mode": "region", "x": 0, "y": 0, "width": 0, "height": 10 });
+        assert!(capture_mode_from_params(&backend, &params).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn capture_mode_unknown_is_error() {
+        let backend = MockBackend::new();
+        let params = serde_json::json!({ "mode": "scrolling" });
+        assert!(capture_mode_from_params(&backend, &params).await.is_err());
+    }
+
+    #[test]
+    fn annotation_errors_get_their_own_code_and_details() {
+        let e = crate::annotate::parse_annotations(serde_json::json!([
+            { "id": "a", "type": "rectangle", "x": 0, "y": 0 },
+        ]))
+        .unwrap_err();
+        let response =
+            serde_json::to_value(IpcResponse::command_err("1".into(), e.into())).unwrap();
+        assert_eq!(response["error"]["code"], "invalid_annotations");
+        assert_eq!(response["error"]["details"][0]["index"], 0);
+        assert_eq!(response["error"]["details"][0]["type"], "rectangle");
+
+        let response = serde_json::to_value(IpcResponse::command_err(
+            "2".into(),
+            anyhow::anyhow!("boom"),
+        ))
+        .unwrap();
+        assert_eq!(response["error"]["code"], "command_error");
+        assert!(response["error"].get("details").is_none());
+    }
+
+    #[test]
+    fn ipc_annotations_cannot_read_files() {
+        let e = ipc_annotations(serde_json::json!([
+            { "id": "a", "type": "image", "x": 0, "y": 0, "width": 10, "height": 10,
+              "stamp": "checkmark" },
+            { "id": "b", "type": "image", "x": 0, "y": 0, "width": 10, "height": 10,
+              "path": "/etc/passwd" },
+        ]))
+        .unwrap_err();
+        assert_eq!(e.0.len(), 1);
+        assert_eq!(e.0[0].index, 1);
+        assert!(e.0[0].message.starts_with("path:"));
+    }
+
+    #[test]
+    fn inject_annotation_ids_fills_missing_ids_only() {
+        let val = inject_annotation_ids(serde_json::json!([
+            { "type": "rect" },
+            { "id": "keep", "type": "rect" },
+        ]));
+        assert!(val[0]["id"].is_string());
+        assert_eq!(val[1]["id"], "keep");
+    }
+}
@@ -1,10 +1,14 @@
 pub mod ai;
+pub mod annotate;
 pub mod capture;
 pub mod commands;
 pub mod credentials;
 #[cfg(target_os = "linux")]
 mod dbus;
+pub mod encode;
 pub mod ipc;
+pub mod pdf;
+pub mod project;
 
 use base64::prelude::*;
 use std::io::Cursor;
@@ -14,7 +18,6 @@ use std::sync::{
 };
 use tauri::{Emitter, Manager};
 use tauri_plugin_global_shortcut::{GlobalShortcutExt, ShortcutState};
-use uuid::Uuid;
 
 fn init_logging() {
     use tracing_subscriber::{fmt, EnvFilter};
@@ -57,40 +60,25 @@ async fn do_capture_and_emit(
     tokio::time::sleep(std::time::Duration::from_millis(300)).await;
 
     let image_store = app.state::<capture::ImageStore>();
+    let backend = app.state::<capture::SharedBackend>();
     let result: Result<commands::capture::ScreenshotResponse, String> = async {
-        #[cfg(target_os = "linux")]
-        let in_flatpak = std::env::var("FLATPAK_ID").is_ok();
-        #[cfg(target_os = "linux")]
-        tracing::info!("do_capture_and_emit: in_flatpak={in_flatpak}");
-
-        #[cfg(target_os = "linux")]
-        let image = if in_flatpak {
-            tracing::info!("do_capture_and_emit: using portal backend");
-            capture::portal::capture_via_portal()
-                .await
-                .map_err(|e| e.to_string())?
-        } else {
-            tracing::info!("do_capture_and_emit: using xcap backend");
-            capture::xcap_backend::capture_fullscreen()
-                .await
-                .map_err(|e| e.to_string())?
-        };
-        #[cfg(not(target_os = "linux"))]
-        let image = {
-            tracing::info!("do_capture_and_emit: using xcap backend");
-            capture::xcap_backend::capture_fullscreen()
-                .await
-                .map_err(|e| e.to_string())?
-        };
-        let image = Arc::new(image);
-        let id = Uuid::new_v4();
-        image_store.insert(id, Arc::clone(&image));
+        tracing::info!("do_capture_and_emit: using {} backend", backend.name());
+        let options = commands::settings::load_capture_settings(app).capture_options();
+        let captured = capture::capture_and_store(
+            backend.as_ref(),
+            &image_store,
+            capture::CaptureMode::Fullscreen,
+            options,
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+        let image = captured.image;
         let mut png = Vec::new();
         image
             .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
             .map_err(|e| e.to_string())?;
         Ok(commands::capture::ScreenshotResponse {
-            id: id.to_string(),
+            id: captured.id.to_string(),
             width: image.width(),
             height: image.height(),
             data_url: format!("data:image/png;base64,{}", BASE64_STANDARD.encode(&png)),
@@ -126,9 +114,14 @@ pub fn run() {
     );
 
     let image_store = capture::ImageStore::new();
+    let capture_backend = capture::detect_backend();
 
     tauri::Builder::default()
         .manage(image_store)
+        .manage(capture_backend)
+        .manage(commands::capture::ScrollCaptureState::default())
+        .manage(commands::capture::RecordingState::default())
+        .manage(capture::session::SessionRegistry::default())
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
@@ -140,6 +133,38 @@ pub fn run() {
             let handle = app.handle().clone();
             let is_capturing = Arc::new(AtomicBool::new(false));
 
+            // Back the image store with the on-disk library so ids survive
+            // restarts. Without it captures still work, just in memory.
+            match app.path().app_data_dir() {
+                Ok(data_dir) => {
+                    let settings = commands::settings::load_library_settings(&handle);
+                    let images = app.state::<capture::ImageStore>();
+                    images.set_memory_budget(settings.memory_budget_bytes());
+                    match capture::library::Library::open(
+                        data_dir.join("library"),
+                        settings.retention(),
+                    ) {
+                        Ok(library) => images.attach_library(Arc::new(library)),
+                        Err(e) => tracing::error!("Screenshot library unavailable: {e:#}"),
+                    }
+                }
+                Err(e) => tracing::error!("No app data directory for the library: {e}"),
+            }
+
+            // Index OCR text of every capture for `search_screenshots`.
+            let index = app
+                .path()
+                .app_data_dir()
+                .map_err(anyhow::Error::from)
+                .and_then(|dir| ai::search::SearchIndex::open(dir.join("search-index.jsonl")))
+                .unwrap_or_else(|e| {
+                    tracing::error!("Search index not persisted: {e:#}");
+                    ai::search::SearchIndex::in_memory()
+                });
+            let index: commands::search::SharedIndex = Arc::new(index);
+            app.manage(Arc::clone(&index));
+            commands::search::start_indexer(&handle, index);
+
             // Start the IPC server so fotos-mcp can connect.
             let ipc_handle = handle.clone();
             tauri::async_runtime::spawn(async move {
@@ -229,18 +254,38 @@ pub fn run() {
             commands::ping,
             commands::capture::take_screenshot,
             commands::capture::crop_image,
+            commands::capture::get_screenshot_info,
+            commands::capture::list_screenshots,
+            commands::capture::delete_screenshot,
+            commands::capture::get_store_stats,
+            commands::capture::find_similar_screenshots,
+            commands::capture::get_lineage,
+            commands::capture::reapply_operations,
             commands::capture::list_monitors,
             commands::capture::list_windows,
+            commands::capture::start_scrolling_capture,
+            commands::capture::stop_scrolling_capture,
+            commands::capture::start_recording,
+            commands::capture::stop_recording,
+            commands::capture::start_capture_session,
+            commands::capture::stop_capture_session,
+            commands::capture::get_capture_session,
+            commands::capture::list_capture_sessions,
             commands::ai::run_ocr,
             commands::ai::auto_blur_pii,
             commands::ai::analyze_llm,
             commands::ai::tessdata_available,
             commands::ai::download_tessdata,
+            commands::search::search_screenshots,
             commands::files::save_image,
             commands::files::composite_image,
+            commands::files::export_pdf,
+            commands::files::load_annotation_image,
             commands::files::copy_to_clipboard,
             commands::files::export_annotations,
             commands::files::import_annotations,
+            commands::files::save_project,
+            commands::files::open_project,
             commands::settings::get_settings,
             commands::settings::set_settings,
             commands::settings::set_api_key,
@@ -264,6 +309,9 @@ pub fn run() {
             }
             tauri::WindowEvent::Destroyed => {
                 let _ = std::fs::remove_file(ipc::server::socket_path());
+                if let Some(library) = window.state::<capture::ImageStore>().library() {
+                    library.flush();
+                }
             }
             _ => {}
         })
@@ -0,0 +1,425 @@
+/// Multi-page PDF export: one page per screenshot, with an optional
+/// invisible text layer of OCR words so the document can be searched and
+/// its text selected and copied.
+///
+/// Pages are sized so the screenshot fills them at 96 dpi (one pixel is
+/// ¾ pt). Screenshots are embedded losslessly as Flate-compressed RGB, with
+/// a soft mask when they have transparency. The text layer is set in a
+/// subset of the bundled Liberation Sans with a `ToUnicode` map, so text in
+/// any script can be searched and copied; characters the font has no glyph
+/// for are drawn (invisibly) as its missing-glyph box.
+use crate::ai::search::IndexedWord;
+use anyhow::{Context, Result};
+use flate2::write::ZlibEncoder;
+use image::RgbaImage;
+use pdf_writer::types::{CidFontType, FontFlags, SystemInfo, TextRenderingMode, UnicodeCmap};
+use pdf_writer::{Content, Date, Filter, Finish, Name, Pdf, Rect, Ref, Str, TextStr};
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// PDF points per image pixel.
+const PT_PER_PX: f32 = 72.0 / 96.0;
+
+/// One page: the screenshot as it should appear, and the words to lay over
+/// it in image pixel coordinates.
+pub struct Page {
+    pub image: RgbaImage,
+    pub words: Vec<IndexedWord>,
+}
+
+/// Name of the text layer font; the tag marks it as a subset.
+const FONT_NAME: Name = Name(b"FOTOSA+LiberationSans");
+
+/// The text layer font: one CID per distinct character of the document's
+/// words, numbered from 1 in character order (CID 0 is the missing glyph).
+struct TextFont {
+    face: ttf_parser::Face<'static>,
+    cids: BTreeMap<char, u16>,
+}
+
+impl TextFont {
+    fn new(pages: &[Page]) -> Self {
+        let face = ttf_parser::Face::parse(crate::annotate::EMBEDDED_FONT, 0)
+            .expect("embedded font is valid");
+        let mut cids: BTreeMap<char, u16> = pages
+            .iter()
+            .flat_map(|page| &page.words)
+            .flat_map(|word| word.text.chars())
+            .map(|c| (c, 0))
+            .collect();
+        for (cid, n) in cids.values_mut().zip(1..=u16::MAX) {
+            *cid = n;
+        }
+        // Past 65535 distinct characters the rest keep CID 0, and words
+        // using them are left out.
+        Self { face, cids }
+    }
+
+    fn glyph(&self, c: char) -> u16 {
+        self.face.glyph_index(c).map_or(0, |g| g.0)
+    }
+
+    /// Advance of glyph `gid` in thousandths of an em.
+    fn width(&self, gid: u16) -> f32 {
+        let advance = self
+            .face
+            .glyph_hor_advance(ttf_parser::GlyphId(gid))
+            .unwrap_or(0);
+        self.units(advance.into())
+    }
+
+    fn units(&self, value: f32) -> f32 {
+        value * 1000.0 / self.face.units_per_em() as f32
+    }
+
+    /// `text` as two-byte CIDs, or `None` if a character has no CID.
+    fn encode(&self, text: &str) -> Option<(Vec<u8>, f32)> {
+        let mut bytes = Vec::with_capacity(text.len() * 2);
+        let mut width = 0.0;
+        for c in text.chars() {
+            let cid = self.cids.get(&c).copied().filter(|&cid| cid > 0)?;
+            bytes.extend(cid.to_be_bytes());
+            width += self.width(self.glyph(c));
+        }
+        Some((bytes, width))
+    }
+
+    /// Write the Type 0 font with its descendant, descriptor, embedded
+    /// subset and `ToUnicode` map.
+    fn write(&self, pdf: &mut Pdf, font: Ref, mut next: impl FnMut() -> Ref) -> Result<()> {
+        let (cid_font, descriptor, file, cid_map, to_unicode) =
+            (next(), next(), next(), next(), next());
+        let system_info = SystemInfo {
+            registry: Str(b"Adobe"),
+            ordering: Str(b"Identity"),
+            supplement: 0,
+        };
+
+        pdf.type0_font(font)
+            .base_font(FONT_NAME)
+            .encoding_predefined(Name(b"Identity-H"))
+            .descendant_font(cid_font)
+            .to_unicode(to_unicode);
+
+        let glyphs: Vec<u16> = std::iter::once(0)
+            .chain(
+                self.cids
+                    .iter()
+                    .filter(|(_, &cid)| cid > 0)
+                    .map(|(&c, _)| self.glyph(c)),
+            )
+            .collect();
+        let mut cid = pdf.cid_font(cid_font);
+        cid.subtype(CidFontType::Type2)
+            .base_font(FONT_NAME)
+            .system_info(system_info)
+            .font_descriptor(descriptor)
+            .default_width(self.width(0))
+            .cid_to_gid_map_stream(cid_map);
+        cid.widths()
+            .consecutive(1, glyphs[1..].iter().map(|&g| self.width(g)));
+        cid.finish();
+
+        let bbox = self.face.global_bounding_box();
+        pdf.font_descriptor(descriptor)
+            .name(FONT_NAME)
+            .flags(FontFlags::SYMBOLIC)
+            .bbox(Rect::new(
+                self.units(bbox.x_min.into()),
+                self.units(bbox.y_min.into()),
+                self.units(bbox.x_max.into()),
+                self.units(bbox.y_max.into()),
+            ))
+            .italic_angle(0.0)
+            .ascent(self.units(self.face.ascender().into()))
+            .descent(self.units(self.face.descender().into()))
+            .cap_height(self.units(self.face.capital_height().unwrap_or(0).into()))
+            .stem_v(80.0)
+            .font_file2(file);
+
+        let subset = subsetter::subset(
+            crate::annotate::EMBEDDED_FONT,
+            0,
+            subsetter::Profile::pdf(&glyphs),
+        )
+        .context("Failed to subset the text layer font")?;
+        pdf.stream(file, &deflate(&subset)?)
+            .filter(Filter::FlateDecode)
+            .pair(Name(b"Length1"), subset.len() as i32);
+
+        let map: Vec<u8> = glyphs.iter().flat_map(|g| g.to_be_bytes()).collect();
+        pdf.stream(cid_map, &deflate(&map)?)
+            .filter(Filter::FlateDecode);
+
+        let mut cmap = UnicodeCmap::new(Name(b"Fotos-UTF16"), system_info);
+        for (&c, &cid) in &self.cids {
+            if cid > 0 {
+                cmap.pair(cid, c);
+            }
+        }
+        pdf.cmap(to_unicode, &cmap.finish());
+        Ok(())
+    }
+}
+
+/// Write `pages` as a PDF document.
+pub fn write(pages: &[Page]) -> Result<Vec<u8>> {
+    anyhow::ensure!(!pages.is_empty(), "PDF export needs at least one page");
+
+    let mut pdf = Pdf::new();
+    let mut ids = (1..).map(Ref::new);
+    let mut next = || ids.next().expect("unbounded");
+    let catalog = next();
+    let tree = next();
+    let font = next();
+    let info = next();
+    let page_ids: Vec<Ref> = pages.iter().map(|_| next()).collect();
+
+    pdf.catalog(catalog).pages(tree);
+    pdf.pages(tree)
+        .kids(page_ids.iter().copied())
+        .count(pages.len() as i32);
+    let text_font = TextFont::new(pages);
+    if !text_font.cids.is_empty() {
+        text_font.write(&mut pdf, font, &mut next)?;
+    }
+
+    let now = chrono::Local::now();
+    let producer = format!("Fotos {}", env!("CARGO_PKG_VERSION"));
+    pdf.document_info(info)
+        .producer(TextStr(&producer))
+        .creation_date(pdf_date(&now));
+
+    for (page, &page_id) in pages.iter().zip(&page_ids) {
+        let image_id = next();
+        let content_id = next();
+        let (width, height) = page.image.dimensions();
+        let (page_w, page_h) = (width as f32 * PT_PER_PX, height as f32 * PT_PER_PX);
+
+        let mut writer = pdf.page(page_id);
+        writer
+            .parent(tree)
+            .media_box(Rect::new(0.0, 0.0, page_w, page_h))
+            .contents(content_id);
+        let mut resources = writer.resources();
+        resources.x_objects().pair(Name(b"Im"), image_id);
+        if !text_font.cids.is_empty() {
+            resources.fonts().pair(Name(b"F"), font);
+        }
+        resources.finish();
+        writer.finish();
+
+        let rgb: Vec<u8> = page
+            .image
+            .pixels()
+            .flat_map(|p| [p[0], p[1], p[2]])
+            .collect();
+        let mask = page
+            .image
+            .pixels()
+            .any(|p| p[3] < 255)
+            .then(|| -> Result<_> {
+                let alpha: Vec<u8> = page.image.pixels().map(|p| p[3]).collect();
+                Ok((next(), deflate(&alpha)?))
+            })
+            .transpose()?;
+        let rgb = deflate(&rgb)?;
+        let mut xobject = pdf.image_xobject(image_id, &rgb);
+        xobject
+            .width(width as i32)
+            .height(height as i32)
+            .bits_per_component(8)
+            .filter(Filter::FlateDecode);
+        xobject.color_space().device_rgb();
+        if let Some((mask_id, _)) = &mask {
+            xobject.s_mask(*mask_id);
+        }
+        xobject.finish();
+        if let Some((mask_id, alpha)) = &mask {
+            let mut smask = pdf.image_xobject(*mask_id, alpha);
+            smask
+                .width(width as i32)
+                .height(height as i32)
+                .bits_per_component(8)
+                .filter(Filter::FlateDecode);
+            smask.color_space().device_gray();
+        }
+
+        let mut content = Content::new();
+        content
+            .save_state()
+            .transform([page_w, 0.0, 0.0, page_h, 0.0, 0.0])
+            .x_object(Name(b"Im"))
+            .restore_state();
+        text_layer(&mut content, &page.words, &text_font, page_h);
+        pdf.stream(content_id, &content.finish());
+    }
+
+    Ok(pdf.finish())
+}
+
+/// Lay `words` over the page as invisible text, each stretched to the width
+/// of its box so selections line up with the words in the image.
+fn text_layer(content: &mut Content, words: &[IndexedWord], font: &TextFont, page_h: f32) {
+    let words: Vec<_> = words
+        .iter()
+        .filter(|w| w.w > 0 && w.h > 0 && !w.text.trim().is_empty())
+        .collect();
+    if words.is_empty() {
+        return;
+    }
+    content
+        .begin_text()
+        .set_text_rendering_mode(TextRenderingMode::Invisible);
+    for word in words {
+        let Some((text, width)) = font.encode(word.text.trim()) else {
+            continue;
+        };
+        let size = word.h as f32 * PT_PER_PX;
+        let natural = width / 1000.0 * size;
+        if natural <= 0.0 {
+            continue;
+        }
+        let x = word.x as f32 * PT_PER_PX;
+        // Liberation Sans's descender is about a fifth of the em, so this
+        // puts the glyphs' full height over the word's box.
+        let baseline = page_h - (word.y + word.h) as f32 * PT_PER_PX + 0.2 * size;
+        content
+            .set_font(Name(b"F"), size)
+            .set_horizontal_scaling(100.0 * word.w as f32 * PT_PER_PX / natural)
+            .set_text_matrix([1.0, 0.0, 0.0, 1.0, x, baseline])
+            .show(Str(&text));
+    }
+    content.end_text();
+}
+
+fn deflate(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data).context("Failed to compress page")?;
+    encoder.finish().context("Failed to compress page")
+}
+
+fn pdf_date(time: &chrono::DateTime<chrono::Local>) -> Date {
+    use chrono::{Datelike, Timelike};
+    let offset = time.offset().local_minus_utc() / 60;
+    Date::new(time.year() as u16)
+        .month(time.month() as u8)
+        .day(time.day() as u8)
+        .hour(time.hour() as u8)
+        .minute(time.minute() as u8)
+        .second(time.second() as u8)
+        .utc_offset_hour((offset / 60) as i8)
+        .utc_offset_minute((offset % 60).unsigned_abs() as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn word(text: &str, x: u32, y: u32, w: u32, h: u32) -> IndexedWord {
+        IndexedWord {
+            text: text.into(),
+            x,
+            y,
+            w,
+            h,
+        }
+    }
+
+    fn count(haystack: &[u8], needle: &[u8]) -> usize {
+        haystack
+            .windows(needle.len())
+            .filter(|w| *w == needle)
+            .count()
+    }
+
+    #[test]
+    fn writes_one_page_per_screenshot() {
+        let pages = [
+            Page {
+                image: RgbaImage::from_pixel(40, 20, Rgba([255, 0, 0, 255])),
+                words: vec![word("Invoice", 2, 2, 30, 10)],
+            },
+            Page {
+                image: RgbaImage::from_pixel(80, 60, Rgba([0, 0, 255, 128])),
+                words: vec![],
+            },
+        ];
+        let pdf = write(&pages).unwrap();
+        assert!(pdf.starts_with(b"%PDF-"));
+        assert_eq!(count(&pdf, b"/Type /Page\n"), 2);
+        assert!(count(&pdf, b"/MediaBox [0 0 30 15]") == 1);
+        assert!(count(&pdf, b"/MediaBox [0 0 60 45]") == 1);
+        // Only the transparent page needs a soft mask.
+        assert_eq!(count(&pdf, b"/SMask"), 1);
+        // Content streams are uncompressed, so the text layer is readable:
+        // the distinct letters I c e i n o v are CIDs 1 to 7.
+        assert_eq!(
+            count(
+                &pdf,
+                br"(\000\001\000\005\000\007\000\006\000\004\000\002\000\003) Tj"
+            ),
+            1
+        );
+        assert_eq!(count(&pdf, b"3 Tr"), 1);
+        assert_eq!(count(&pdf, b"/FontFile2"), 1);
+    }
+
+    #[test]
+    fn empty_documents_are_refused() {
+        assert!(write(&[]).is_err());
+    }
+
+    #[test]
+    fn text_is_stretched_over_its_box() {
+        let words = vec![word("ii", 0, 0, 24, 16)];
+        let font = TextFont::new(&[Page {
+            image: RgbaImage::new(1, 1),
+            words: words.clone(),
+        }]);
+        let mut content = Content::new();
+        // "ii" is 910/2048 em wide; at 12 pt (16 px) that is 5.332 pt, and
+        // the box is 24 px = 18 pt wide.
+        text_layer(&mut content, &words, &font, 100.0);
+        let ops = String::from_utf8(content.finish()).unwrap();
+        assert!(ops.contains("/F 12 Tf"), "{ops}");
+        let scaling: f32 = ops
+            .lines()
+            .find_map(|line| line.strip_suffix(" Tz"))
+            .unwrap()
+            .parse()
+            .unwrap();
+        let natural = 12.0 * 910.0 / 2048.0;
+        assert!((scaling - 100.0 * 18.0 / natural).abs() < 0.01, "{ops}");
+    }
+
+    #[test]
+    fn text_in_any_script_maps_back_to_unicode() {
+        let pages = [Page {
+            image: RgbaImage::new(40, 20),
+            words: vec![word("日本", 0, 0, 20, 10), word("café", 20, 0, 20, 10)],
+        }];
+        let pdf = write(&pages).unwrap();
+        // CIDs follow character order: a c f é 日 本.
+        for pair in [
+            "<0001> <0061>",
+            "<0004> <00E9>",
+            "<0005> <65E5>",
+            "<0006> <672C>",
+        ] {
+            assert_eq!(count(&pdf, pair.as_bytes()), 1, "{pair}");
+        }
+        assert_eq!(count(&pdf, b"Tj"), 2);
+    }
+
+    #[test]
+    fn pages_without_words_have_no_font() {
+        let pages = [Page {
+            image: RgbaImage::new(4, 4),
+            words: vec![],
+        }];
+        let pdf = write(&pages).unwrap();
+        assert_eq!(count(&pdf, b"/Font"), 0);
+    }
+}
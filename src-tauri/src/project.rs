@@ -0,0 +1,485 @@
+/// `.fotos` project bundles: a screenshot with everything needed to pick
+/// up editing it later or on another machine.
+///
+/// A bundle is a zip archive of
+///
+/// - `manifest.json`: format name, schema version and the image id;
+/// - `image.png`: the original screenshot, lossless;
+/// - `annotations.json`: the editor's annotations;
+/// - `metadata.json`: the image's [`CaptureMetadata`];
+/// - `ocr.json` (optional): OCR words from the search index, so opening
+///   the bundle doesn't run OCR again;
+/// - `lineage.json` (optional): the ancestors the image was derived from
+///   and the operations that made it.
+///
+/// Bundles written by older versions are migrated forward on open, one
+/// schema version at a time; bundles from newer versions are refused.
+use crate::ai::search::{IndexedWord, SearchIndex};
+use crate::annotate::{parse_annotations, Annotation};
+use crate::capture::{CaptureMetadata, ImageStore, Operation, ScreenshotInfo};
+use anyhow::{bail, Context, Result};
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Cursor, Read, Seek, Write};
+use std::path::Path;
+use std::sync::Arc;
+use uuid::Uuid;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+/// File extension of project bundles.
+pub const EXTENSION: &str = "fotos";
+
+/// `format` field of every bundle manifest.
+const FORMAT: &str = "fotos-project";
+
+/// Schema version written by this build. Bump it with every change to the
+/// bundle layout or to a part's JSON, and add the migration from the
+/// previous version to [`MIGRATIONS`].
+pub const VERSION: u32 = 1;
+
+/// `MIGRATIONS[n]` upgrades the parts of a version `n + 1` bundle to
+/// version `n + 2`.
+const MIGRATIONS: &[Migration] = &[];
+
+type Migration = fn(&mut Parts) -> Result<()>;
+
+/// Largest part read from a bundle, so a corrupt or hostile archive can't
+/// exhaust memory.
+const MAX_PART_BYTES: u64 = 512 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Manifest {
+    format: String,
+    version: u32,
+    image_id: Uuid,
+    /// Version of Fotos that wrote the bundle.
+    #[serde(default)]
+    generator: Option<String>,
+}
+
+/// Where a bundled image came from.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProjectLineage {
+    /// Parent, grandparent, … as they were stored when the bundle was
+    /// saved. Their images are not bundled.
+    pub ancestors: Vec<ScreenshotInfo>,
+    /// The operations from the oldest ancestor to the image.
+    pub operations: Vec<Operation>,
+}
+
+/// The contents of a bundle.
+#[derive(Debug, Clone)]
+pub struct Project {
+    pub image_id: Uuid,
+    pub image: Arc<DynamicImage>,
+    pub metadata: CaptureMetadata,
+    pub annotations: Vec<Annotation>,
+    /// `None` if the image hadn't been OCR'd yet.
+    pub ocr: Option<Vec<IndexedWord>>,
+    pub lineage: Option<ProjectLineage>,
+}
+
+/// A bundle's JSON parts, untyped so migrations can rewrite them.
+#[derive(Debug)]
+struct Parts {
+    annotations: Value,
+    metadata: Value,
+    ocr: Option<Value>,
+    lineage: Option<Value>,
+}
+
+impl Project {
+    /// Gather `image_id` from `store` (and its OCR words from `index`, if
+    /// it has been indexed) with `annotations`. `None` if it isn't stored.
+    pub fn from_store(
+        store: &ImageStore,
+        index: Option<&SearchIndex>,
+        image_id: Uuid,
+        annotations: Vec<Annotation>,
+    ) -> Option<Self> {
+        let image = store.get(&image_id)?;
+        let metadata = store.metadata(&image_id)?;
+        let lineage = store
+            .lineage(&image_id)
+            .filter(|l| !l.ancestors.is_empty() || !l.operations.is_empty())
+            .map(|l| ProjectLineage {
+                ancestors: l.ancestors,
+                operations: l.operations,
+            });
+        Some(Self {
+            image_id,
+            image,
+            metadata,
+            annotations,
+            ocr: index.and_then(|index| index.words(&image_id)),
+            lineage,
+        })
+    }
+
+    /// Put the bundled image back into `store` under its original id, so
+    /// annotations and lineage referring to it still resolve, and its OCR
+    /// words into `index`. An image already stored under that id with the
+    /// same pixels is the same screenshot and is left alone; a different
+    /// one (the bundle was edited elsewhere, or comes from another machine)
+    /// is kept, and the bundle's image is stored under a new id, written
+    /// back to `image_id`.
+    pub fn restore(&mut self, store: &ImageStore, index: Option<&SearchIndex>) {
+        if store.metadata(&self.image_id).is_some() && !self.matches_stored(store) {
+            self.image_id = Uuid::new_v4();
+        }
+        // Indexed first: the store's insert listener queues unindexed
+        // images for OCR.
+        if let (Some(index), Some(words)) = (index, &self.ocr) {
+            if !index.contains(&self.image_id) {
+                index.add(self.image_id, words.clone());
+            }
+        }
+        if store.metadata(&self.image_id).is_none() {
+            store.insert(
+                self.image_id,
+                Arc::clone(&self.image),
+                self.metadata.clone(),
+            );
+        }
+    }
+
+    /// Whether the image stored under `image_id` has the bundle's pixels.
+    fn matches_stored(&self, store: &ImageStore) -> bool {
+        let same_size = store
+            .metadata(&self.image_id)
+            .is_some_and(|m| m.dimensions == (self.image.width(), self.image.height()));
+        // `peek`: a one-off comparison shouldn't displace cached images.
+        same_size
+            && store
+                .peek(&self.image_id)
+                .is_some_and(|stored| stored.to_rgba8() == self.image.to_rgba8())
+    }
+}
+
+/// Write `project` as a bundle at `path`.
+pub fn save(project: &Project, path: &Path) -> Result<()> {
+    let file =
+        File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    write(project, BufWriter::new(file))
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Read the bundle at `path`, migrating it to the current schema.
+pub fn open(path: &Path) -> Result<Project> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    read(BufReader::new(file)).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+fn write(project: &Project, out: impl Write + Seek) -> Result<()> {
+    let mut zip = ZipWriter::new(out);
+    let json = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    // PNG is compressed already.
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+    let manifest = Manifest {
+        format: FORMAT.to_owned(),
+        version: VERSION,
+        image_id: project.image_id,
+        generator: Some(format!("fotos {}", env!("CARGO_PKG_VERSION"))),
+    };
+    zip.start_file("manifest.json", json)?;
+    serde_json::to_writer_pretty(&mut zip, &manifest)?;
+
+    let mut png = Vec::new();
+    project
+        .image
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .context("Failed to encode image")?;
+    zip.start_file("image.png", stored)?;
+    zip.write_all(&png)?;
+
+    zip.start_file("annotations.json", json)?;
+    serde_json::to_writer_pretty(&mut zip, &project.annotations)?;
+    zip.start_file("metadata.json", json)?;
+    serde_json::to_writer_pretty(&mut zip, &project.metadata)?;
+    if let Some(ocr) = &project.ocr {
+        zip.start_file("ocr.json", json)?;
+        serde_json::to_writer(&mut zip, ocr)?;
+    }
+    if let Some(lineage) = &project.lineage {
+        zip.start_file("lineage.json", json)?;
+        serde_json::to_writer_pretty(&mut zip, lineage)?;
+    }
+    zip.finish()?.flush()?;
+    Ok(())
+}
+
+fn read(input: impl Read + Seek) -> Result<Project> {
+    let mut zip = ZipArchive::new(input).context("Not a Fotos project")?;
+
+    let manifest: Manifest = serde_json::from_slice(
+        &part(&mut zip, "manifest.json")?.context("Not a Fotos project: no manifest.json")?,
+    )
+    .context("Invalid manifest.json")?;
+    if manifest.format != FORMAT {
+        bail!("Not a Fotos project: format is '{}'", manifest.format);
+    }
+    if manifest.version == 0 {
+        bail!("Invalid manifest.json: version 0");
+    }
+    if manifest.version > VERSION {
+        bail!(
+            "Project was saved by a newer version of Fotos (format version {}, this build reads up to {VERSION})",
+            manifest.version
+        );
+    }
+
+    let mut json = |name: &str| -> Result<Option<Value>> {
+        part(&mut zip, name)?
+            .map(|bytes| serde_json::from_slice(&bytes).with_context(|| format!("Invalid {name}")))
+            .transpose()
+    };
+    let mut parts = Parts {
+        annotations: json("annotations.json")?.unwrap_or_else(|| Value::Array(Vec::new())),
+        metadata: json("metadata.json")?.context("Missing metadata.json")?,
+        ocr: json("ocr.json")?,
+        lineage: json("lineage.json")?,
+    };
+    migrate(&mut parts, manifest.version, MIGRATIONS)?;
+
+    let png = part(&mut zip, "image.png")?.context("Missing image.png")?;
+    let image = image::load_from_memory(&png).context("Invalid image.png")?;
+    let annotations = parse_annotations(parts.annotations)
+        .map_err(|e| anyhow::anyhow!("Invalid annotations.json: {e}"))?;
+    Ok(Project {
+        image_id: manifest.image_id,
+        image: Arc::new(image),
+        metadata: serde_json::from_value(parts.metadata).context("Invalid metadata.json")?,
+        annotations,
+        ocr: parts
+            .ocr
+            .map(serde_json::from_value)
+            .transpose()
+            .context("Invalid ocr.json")?,
+        lineage: parts
+            .lineage
+            .map(serde_json::from_value)
+            .transpose()
+            .context("Invalid lineage.json")?,
+    })
+}
+
+/// Upgrade `parts` from schema `version` to [`VERSION`].
+fn migrate(parts: &mut Parts, version: u32, migrations: &[Migration]) -> Result<()> {
+    for (from, migration) in migrations.iter().enumerate().skip(version as usize - 1) {
+        migration(parts)
+            .with_context(|| format!("Failed to migrate project from version {}", from + 1))?;
+    }
+    Ok(())
+}
+
+/// The bytes of the entry `name`, `None` if the bundle has none.
+fn part(zip: &mut ZipArchive<impl Read + Seek>, name: &str) -> Result<Option<Vec<u8>>> {
+    let entry = match zip.by_name(name) {
+        Ok(entry) => entry,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {name}")),
+    };
+    if entry.size() > MAX_PART_BYTES {
+        bail!("{name} is too large ({} bytes)", entry.size());
+    }
+    let mut bytes = Vec::with_capacity(entry.size() as usize);
+    entry
+        .take(MAX_PART_BYTES)
+        .read_to_end(&mut bytes)
+        .with_context(|| format!("Failed to read {name}"))?;
+    Ok(Some(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::CaptureMode;
+    use image::{Rgba, RgbaImage};
+    use serde_json::json;
+
+    fn sample() -> Project {
+        let image = RgbaImage::from_fn(8, 6, |x, y| Rgba([x as u8 * 30, y as u8 * 40, 7, 200]));
+        let annotations = parse_annotations(json!([
+            { "id": "a1", "type": "rect", "x": 1, "y": 1, "width": 4, "height": 3 },
+            { "id": "a2", "type": "text", "x": 2, "y": 2, "text": "hi" },
+        ]))
+        .unwrap();
+        let parent = Uuid::new_v4();
+        let metadata = CaptureMetadata::new(CaptureMode::Fullscreen, (16, 12)).derive(
+            parent,
+            Operation::Crop {
+                x: 4,
+                y: 2,
+                width: 8,
+                height: 6,
+            },
+            (8, 6),
+        );
+        Project {
+            image_id: Uuid::new_v4(),
+            image: Arc::new(DynamicImage::ImageRgba8(image)),
+            metadata,
+            annotations,
+            ocr: Some(vec![IndexedWord {
+                text: "hi".into(),
+                x: 2,
+                y: 2,
+                w: 3,
+                h: 2,
+            }]),
+            lineage: None,
+        }
+    }
+
+    fn bundle(project: &Project) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write(project, Cursor::new(&mut bytes)).unwrap();
+        bytes
+    }
+
+    /// A bundle with `manifest.json` replaced.
+    fn with_manifest(project: &Project, manifest: Value) -> Vec<u8> {
+        let mut src = ZipArchive::new(Cursor::new(bundle(project))).unwrap();
+        let mut bytes = Vec::new();
+        let mut zip = ZipWriter::new(Cursor::new(&mut bytes));
+        for i in 0..src.len() {
+            let entry = src.by_index(i).unwrap();
+            if entry.name() == "manifest.json" {
+                zip.start_file("manifest.json", SimpleFileOptions::default())
+                    .unwrap();
+                serde_json::to_writer(&mut zip, &manifest).unwrap();
+            } else {
+                zip.raw_copy_file(entry).unwrap();
+            }
+        }
+        zip.finish().unwrap();
+        bytes
+    }
+
+    #[test]
+    fn bundles_round_trip() {
+        let project = sample();
+        let opened = read(Cursor::new(bundle(&project))).unwrap();
+        assert_eq!(opened.image_id, project.image_id);
+        assert_eq!(opened.image.to_rgba8(), project.image.to_rgba8());
+        assert_eq!(opened.annotations, project.annotations);
+        assert_eq!(opened.metadata.parent_id, project.metadata.parent_id);
+        assert_eq!(opened.metadata.operation, project.metadata.operation);
+        assert_eq!(opened.metadata.timestamp, project.metadata.timestamp);
+        assert_eq!(opened.ocr, project.ocr);
+        assert!(opened.lineage.is_none());
+    }
+
+    #[test]
+    fn restore_repopulates_store_and_index_under_the_original_id() {
+        let mut project = sample();
+        let id = project.image_id;
+        let store = ImageStore::new();
+        let index = SearchIndex::in_memory();
+        project.restore(&store, Some(&index));
+        // Restoring the same bundle again keeps the id.
+        project.restore(&store, Some(&index));
+        assert_eq!(project.image_id, id);
+        assert_eq!(
+            store.get(&project.image_id).unwrap().to_rgba8(),
+            project.image.to_rgba8()
+        );
+        assert_eq!(index.words(&project.image_id), project.ocr);
+
+        let again = Project::from_store(
+            &store,
+            Some(&index),
+            project.image_id,
+            project.annotations.clone(),
+        )
+        .unwrap();
+        assert_eq!(again.ocr, project.ocr);
+        // The parent isn't stored, but the operation that made the image is.
+        assert_eq!(
+            again.lineage.unwrap().operations,
+            vec![project.metadata.operation.clone().unwrap()]
+        );
+    }
+
+    #[test]
+    fn restore_gives_a_different_image_a_new_id() {
+        let store = ImageStore::new();
+        let mut project = sample();
+        let stored = RgbaImage::from_pixel(8, 6, Rgba([1, 2, 3, 255]));
+        store.insert(
+            project.image_id,
+            Arc::new(DynamicImage::ImageRgba8(stored.clone())),
+            project.metadata.clone(),
+        );
+        let original = project.image_id;
+
+        project.restore(&store, None);
+        assert_ne!(project.image_id, original);
+        assert_eq!(store.get(&original).unwrap().to_rgba8(), stored);
+        assert_eq!(
+            store.get(&project.image_id).unwrap().to_rgba8(),
+            project.image.to_rgba8()
+        );
+    }
+
+    #[test]
+    fn newer_and_foreign_bundles_are_refused() {
+        let project = sample();
+        let newer = with_manifest(
+            &project,
+            json!({ "format": FORMAT, "version": VERSION + 1, "imageId": project.image_id }),
+        );
+        let err = read(Cursor::new(newer)).unwrap_err().to_string();
+        assert!(err.contains("newer version of Fotos"), "{err}");
+
+        let foreign = with_manifest(
+            &project,
+            json!({ "format": "other", "version": 1, "imageId": project.image_id }),
+        );
+        assert!(read(Cursor::new(foreign)).is_err());
+        assert!(read(Cursor::new(b"not a zip".to_vec())).is_err());
+    }
+
+    #[test]
+    fn migrations_run_in_order_from_the_bundle_version() {
+        fn v1_to_v2(parts: &mut Parts) -> Result<()> {
+            parts.ocr = Some(json!(["v2"]));
+            Ok(())
+        }
+        fn v2_to_v3(parts: &mut Parts) -> Result<()> {
+            parts
+                .ocr
+                .as_mut()
+                .unwrap()
+                .as_array_mut()
+                .unwrap()
+                .push(json!("v3"));
+            Ok(())
+        }
+        let migrations: &[Migration] = &[v1_to_v2, v2_to_v3];
+        let parts = || Parts {
+            annotations: json!([]),
+            metadata: json!({}),
+            ocr: Some(json!(["v2"])),
+            lineage: None,
+        };
+
+        let mut from_v1 = parts();
+        from_v1.ocr = None;
+        migrate(&mut from_v1, 1, migrations).unwrap();
+        assert_eq!(from_v1.ocr, Some(json!(["v2", "v3"])));
+
+        let mut from_v2 = parts();
+        migrate(&mut from_v2, 2, migrations).unwrap();
+        assert_eq!(from_v2.ocr, Some(json!(["v2", "v3"])));
+
+        let mut current = parts();
+        migrate(&mut current, 3, migrations).unwrap();
+        assert_eq!(current.ocr, Some(json!(["v2"])));
+    }
+}
@@ -4,7 +4,7 @@
 import { store } from './state.js';
 import { CanvasEngine } from './canvas/engine.js';
 import { History } from './canvas/history.js';
-import { AddAnnotationCommand, CropCommand, BatchTransformCommand, BatchDeleteCommand, ZOrderCommand, LockCommand } from './canvas/commands.js';
+import { AddAnnotationCommand, CropCommand, TransformAnnotationCommand, BatchTransformCommand, BatchDeleteCommand, ZOrderCommand, LockCommand } from './canvas/commands.js';
 import { SelectionManager } from './canvas/selection.js';
 import { initToolbar } from './ui/toolbar.js';
 import { initColorPicker, notifyColorApplied } from './ui/color-picker.js';
@@ -12,7 +12,7 @@ import { initSizePicker } from './ui/size-picker.js';
 import { initAiPanel } from './ui/ai-panel.js';
 import { initSettings, showSettingsModal, applyThemeFromSettings } from './ui/settings.js';
 import { showExportDialog } from './ui/export-dialog.js';
-import { ping, takeScreenshot, cropImage, runOcr, saveImage, compositeImage, showSaveDialog, exportAnnotations, importAnnotations } from './tauri-bridge.js';
+import { ping, takeScreenshot, cropImage, runOcr, saveImage, compositeImage, exportPdf, loadAnnotationImage, showSaveDialog, exportAnnotations, importAnnotations, saveProject, openProject } from './tauri-bridge.js';
 import { RegionPicker } from './ui/region-picker.js';
 
 let messageTimeout = null;
@@ -177,6 +177,10 @@ async function init() {
   const container = document.getElementById('canvas-container');
 
   const engine = new CanvasEngine(baseCanvas, annoCanvas, activeCanvas);
+  engine.setPictureLoader(anno =>
+    loadAnnotationImage(anno.imageId ?? null, anno.path ?? null)
+      .then(base64PngToBlob)
+      .then(blob => createImageBitmap(blob)));
   const history = new History();
   const selectionManager = new SelectionManager();
   const regionPicker = new RegionPicker();
@@ -258,6 +262,23 @@ async function init() {
       engine.renderAnnotations(newAnnotations, isLocked ? target : null);
     }
 
+    // Switch a blur's redaction style; new blurs use the last one picked.
+    function doRedactStyle(style) {
+      const cmd = new TransformAnnotationCommand(target, { ...target, redactStyle: style });
+      const newAnnotations = history.execute(cmd, annotations);
+      store.set('annotations', newAnnotations);
+      store.set('redactStyle', style);
+      engine.renderAnnotations(newAnnotations, target);
+    }
+
+    // Turn a rect, ellipse or text box about its centre.
+    function doRotate(rotation) {
+      const cmd = new TransformAnnotationCommand(target, { ...target, rotation: ((rotation % 360) + 360) % 360 });
+      const newAnnotations = history.execute(cmd, annotations);
+      store.set('annotations', newAnnotations);
+      engine.renderAnnotations(newAnnotations, target);
+    }
+
     const items = [];
     if (!isLocked) {
       items.push(
@@ -267,6 +288,23 @@ async function init() {
         { label: 'Send to Back',   disabled: idx === 0,     action: () => doZOrder(0) },
         'separator',
       );
+      if (target.type === 'blur') {
+        const current = target.redactStyle || 'pixelate';
+        const styles = [['pixelate', 'Pixelate'], ['gaussian', 'Gaussian Blur'], ['noise', 'Noise'], ['solid', 'Solid Box']];
+        for (const [style, label] of styles) {
+          items.push({ label: `${style === current ? '✓ ' : ''}${label}`, disabled: style === current, action: () => doRedactStyle(style) });
+        }
+        items.push('separator');
+      }
+      if (['rect', 'ellipse', 'text', 'image'].includes(target.type)) {
+        const rotation = target.rotation || 0;
+        items.push(
+          { label: 'Rotate Right 15°', action: () => doRotate(rotation + 15) },
+          { label: 'Rotate Left 15°',  action: () => doRotate(rotation - 15) },
+          { label: 'Reset Rotation',   disabled: !rotation, action: () => doRotate(0) },
+          'separator',
+        );
+      }
     }
     items.push({ label: isLocked ? 'Unlock' : 'Lock', action: doToggleLock });
 
@@ -477,6 +515,14 @@ async function init() {
         await doImportAnnotations();
         break;
 
+      case 'save-project':
+        await doSaveProject();
+        break;
+
+      case 'open-project':
+        await doOpenProject();
+        break;
+
       case 'capture-window':
       case 'auto-blur':
       case 'ai-analyze':
@@ -558,6 +604,11 @@ async function init() {
         { name: 'PNG Image', extensions: ['png'] },
         { name: 'JPEG Image', extensions: ['jpg', 'jpeg'] },
         { name: 'WebP Image', extensions: ['webp'] },
+        { name: 'AVIF Image', extensions: ['avif'] },
+        { name: 'TIFF Image', extensions: ['tif', 'tiff'] },
+        { name: 'QOI Image', extensions: ['qoi'] },
+        { name: 'SVG (editable annotations)', extensions: ['svg'] },
+        { name: 'PDF (searchable text)', extensions: ['pdf'] },
       ],
       defaultPath: defaultName,
     });
@@ -565,12 +616,15 @@ async function init() {
 
     const ext = path.split('.').pop().toLowerCase();
     const format = ext === 'jpg' || ext === 'jpeg' ? 'jpeg'
-                 : ext === 'webp' ? 'webp'
+                 : ext === 'tif' || ext === 'tiff' ? 'tiff'
+                 : ['webp', 'avif', 'qoi', 'svg', 'pdf'].includes(ext) ? ext
                  : 'png';
 
     try {
       setStatusMessage('Saving...', false);
-      const savedPath = await saveImage(currentImageId, store.get('annotations'), format, path);
+      const savedPath = format === 'pdf'
+        ? await exportPdf([{ imageId: currentImageId, annotations: store.get('annotations') }], path, true)
+        : await saveImage(currentImageId, store.get('annotations'), format, path);
       setStatusMessage('');
       showToast(`Saved to ${savedPath}`);
     } catch (error) {
@@ -596,7 +650,7 @@ async function init() {
 
   async function doImportAnnotations() {
     try {
-      const imported = await importAnnotations();
+      const imported = await importAnnotations(store.get('currentImageId'));
       store.set('annotations', imported);
       engine.render(imported);
       showToast(`Imported ${imported.length} annotation${imported.length !== 1 ? 's' : ''}`);
@@ -607,6 +661,33 @@ async function init() {
     }
   }
 
+  async function doSaveProject() {
+    const currentImageId = store.get('currentImageId');
+    if (!currentImageId) { setStatusMessage('No image to save', false); return; }
+    try {
+      const savedPath = await saveProject(currentImageId, store.get('annotations'));
+      showToast(`Project saved to ${savedPath}`);
+    } catch (error) {
+      if (String(error) !== 'cancelled') {
+        showToast(`Save project failed: ${error}`, 'error');
+      }
+    }
+  }
+
+  async function doOpenProject() {
+    try {
+      const project = await openProject();
+      await loadImageAndUpdate(project.data_url, project.id);
+      store.set('annotations', project.annotations);
+      engine.render(project.annotations);
+      showToast('Project opened');
+    } catch (error) {
+      if (String(error) !== 'cancelled') {
+        showToast(`Open project failed: ${error}`, 'error');
+      }
+    }
+  }
+
   // Apply the crop tool selection: call backend, adjust annotations, record in history.
   async function applyCrop(cropRect) {
     const imageId = store.get('currentImageId');
@@ -728,6 +809,13 @@ async function init() {
       return;
     }
 
+    // Ctrl+O — open a .fotos project
+    if (e.ctrlKey && !e.shiftKey && e.key === 'o') {
+      e.preventDefault();
+      await doOpenProject();
+      return;
+    }
+
     // Ctrl+0 — fit to page
     if (e.ctrlKey && e.key === '0') {
       e.preventDefault();
@@ -1392,7 +1480,7 @@ function buildPreviewShape(tool, startImg, endImg, r) {
   switch (tool) {
     case 'rect':      return { ...base, type: 'rect' };
     case 'ellipse':   return { ...base, type: 'ellipse' };
-    case 'blur':      return { ...base, type: 'blur', blurRadius: 10 };
+    case 'blur':      return { ...base, type: 'blur', blurRadius: 10, redactStyle: store.get('redactStyle') };
     case 'highlight': return { ...base, type: 'highlight', highlightColor: '#FFFF00' };
     case 'arrow':
       return {
@@ -1422,7 +1510,7 @@ function buildCommittedShape(tool, startImg, endImg, r) {
   switch (tool) {
     case 'rect':      return { ...common, type: 'rect' };
     case 'ellipse':   return { ...common, type: 'ellipse' };
-    case 'blur':      return { ...common, type: 'blur', blurRadius: 10 };
+    case 'blur':      return { ...common, type: 'blur', blurRadius: 10, redactStyle: store.get('redactStyle') };
     case 'highlight': return { ...common, type: 'highlight', fillColor: 'transparent', highlightColor: '#FFFF00' };
     case 'arrow':
       return {
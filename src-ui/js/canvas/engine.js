@@ -12,6 +12,152 @@
 /// Transform: backing → CSS via ctx.setTransform(dpr,0,0,dpr,0,0)
 ///            CSS → image via translate(panX, panY) then scale(zoom, zoom)
 
+import { rotationOf } from '../utils/geometry.js';
+
+// Grey noise for previewing `noise` redaction, built once and tiled.
+let _noiseTile = null;
+function noiseTile() {
+  if (_noiseTile) return _noiseTile;
+  _noiseTile = new OffscreenCanvas(128, 128);
+  const tctx = _noiseTile.getContext('2d');
+  const img = tctx.createImageData(128, 128);
+  for (let i = 0; i < img.data.length; i += 4) {
+    const v = Math.floor(Math.random() * 256);
+    img.data[i] = img.data[i + 1] = img.data[i + 2] = v;
+    img.data[i + 3] = 255;
+  }
+  tctx.putImageData(img, 0, 0);
+  return _noiseTile;
+}
+
+// Canvas font shorthand for a text annotation.
+function textFont(shape) {
+  const style = `${shape.italic ? 'italic ' : ''}${shape.bold ? 'bold ' : ''}`;
+  return `${style}${shape.fontSize || 20}px ${shape.fontFamily || 'sans-serif'}`;
+}
+
+// Words (with their trailing spaces), single CJK characters, or spaces.
+const TEXT_TOKENS = /[\u2E80-\u9FFF\uAC00-\uD7AF\uF900-\uFAFF\uFF00-\uFFEF]\s*|[^\s\u2E80-\u9FFF\uAC00-\uD7AF\uF900-\uFAFF\uFF00-\uFFEF]+\s*|\s+/g;
+const RTL_CHAR = /[\u0590-\u08FF\uFB1D-\uFDFF\uFE70-\uFEFF]/;
+
+// Lines of a text annotation laid out as the exporter does: wrapped at
+// `width` when set, each paragraph in the direction of its first letter,
+// and aligned within the box. Needs `ctx.font` set to textFont(shape).
+function layoutText(ctx, shape) {
+  const lineHeight = (shape.fontSize || 20) * 1.4;
+  const maxWidth = shape.width > 0 ? shape.width : Infinity;
+  const fits = (text) => ctx.measureText(text.trimEnd()).width <= maxWidth;
+  const lines = [];
+  for (const para of String(shape.text || '').split('\n')) {
+    const letter = para.match(/\p{L}/u);
+    const rtl = !!letter && RTL_CHAR.test(letter[0]);
+    let line = '';
+    for (const token of para.match(TEXT_TOKENS) || []) {
+      if (line && !fits(line + token)) {
+        lines.push({ text: line.trimEnd(), rtl });
+        line = token.trimStart();
+      } else {
+        line += token;
+      }
+      // A word wider than the box breaks between characters.
+      while (!fits(line) && [...line.trimEnd()].length > 1) {
+        const chars = [...line];
+        let n = chars.length - 1;
+        while (n > 1 && !fits(chars.slice(0, n).join(''))) n--;
+        lines.push({ text: chars.slice(0, n).join(''), rtl });
+        line = chars.slice(n).join('');
+      }
+    }
+    lines.push({ text: line.trimEnd(), rtl });
+  }
+
+  const widths = lines.map(l => ctx.measureText(l.text).width);
+  const width = shape.width > 0 ? shape.width : Math.max(0, ...widths);
+  const align = shape.align || 'start';
+  lines.forEach((line, i) => {
+    const slack = width - widths[i];
+    const right = align === 'right' || (align === 'start' && line.rtl) || (align === 'end' && !line.rtl);
+    line.x = align === 'center' ? slack / 2 : right ? slack : 0;
+    line.y = i * lineHeight;
+  });
+  return { lines, width, height: lines.length * lineHeight };
+}
+
+// Trace a callout's bubble and tail into ctx's path, as callout_outline in
+// annotate/render.rs: the tail leaves the side facing its tip, centred on
+// the tip but clear of the corners.
+function traceCallout(ctx, shape) {
+  const { x, y, width: w, height: h, tail } = shape;
+  const r = Math.min(Math.max(shape.cornerRadius ?? 8, 0), Math.min(w, h) / 2);
+  const dx = (tail.x - (x + w / 2)) / (w / 2);
+  const dy = (tail.y - (y + h / 2)) / (h / 2);
+  const outside = Math.abs(dx) > 1 || Math.abs(dy) > 1;
+  const side = Math.abs(dx) >= Math.abs(dy) ? (dx > 0 ? 1 : 3) : (dy > 0 ? 2 : 0);
+  const corners = [[x + r, y + r], [x + w - r, y + r], [x + w - r, y + h - r], [x + r, y + h - r]];
+  ctx.beginPath();
+  corners.forEach(([cx, cy], k) => {
+    ctx.arc(cx, cy, r, (k + 2) * Math.PI / 2, (k + 3) * Math.PI / 2);
+    if (!outside || k !== side) return;
+    const horizontal = k % 2 === 0;
+    const [start, end] = horizontal ? [x, x + w] : [y, y + h];
+    const half = Math.min((end - start) / 2 - r, Math.min(w, h) / 6, 16);
+    if (half < 1) return;
+    const along = horizontal ? tail.x : tail.y;
+    const mid = Math.min(Math.max(along, start + r + half), end - r - half);
+    const edge = [y, x + w, y + h, x][k];
+    const dir = k < 2 ? 1 : -1;
+    const at = (a) => horizontal ? [a, edge] : [edge, a];
+    ctx.lineTo(...at(mid - dir * half));
+    ctx.lineTo(tail.x, tail.y);
+    ctx.lineTo(...at(mid + dir * half));
+  });
+  ctx.closePath();
+}
+
+// Draw a built-in stamp stretched over the shape's box, as draw_stamp in
+// annotate/embed.rs.
+function drawStamp(ctx, shape) {
+  const { x, y, width: w, height: h } = shape;
+  const at = (u, v) => [x + u * w, y + v * h];
+  const oval = (u, v, r) => {
+    ctx.beginPath();
+    ctx.ellipse(...at(u, v), r * w, r * h, 0, 0, Math.PI * 2);
+    ctx.fill();
+  };
+  const line = (width, ...pts) => {
+    ctx.lineWidth = width * Math.min(w, h);
+    ctx.beginPath();
+    pts.forEach(([u, v], i) => (i ? ctx.lineTo : ctx.moveTo).call(ctx, ...at(u, v)));
+    ctx.stroke();
+  };
+  ctx.setLineDash([]);
+  ctx.lineJoin = 'round';
+  ctx.lineCap = 'round';
+  ctx.strokeStyle = '#FFFFFF';
+  switch (shape.stamp) {
+    case 'checkmark':
+      ctx.fillStyle = '#2EA043';
+      oval(0.5, 0.5, 0.5);
+      line(0.1, [0.28, 0.52], [0.43, 0.67], [0.72, 0.36]);
+      break;
+    case 'cross':
+      ctx.fillStyle = '#D73A49';
+      oval(0.5, 0.5, 0.5);
+      line(0.1, [0.33, 0.33], [0.67, 0.67]);
+      line(0.1, [0.67, 0.33], [0.33, 0.67]);
+      break;
+    case 'warning':
+      ctx.fillStyle = '#F0B400';
+      ctx.beginPath();
+      [[0.5, 0.04], [0.97, 0.92], [0.03, 0.92]].forEach(([u, v]) => ctx.lineTo(...at(u, v)));
+      ctx.fill();
+      ctx.strokeStyle = ctx.fillStyle = '#000000';
+      line(0.09, [0.5, 0.36], [0.5, 0.6]);
+      oval(0.5, 0.74, 0.05);
+      break;
+  }
+}
+
 export class CanvasEngine {
   #baseCanvas;
   #annoCanvas;
@@ -21,6 +167,10 @@ export class CanvasEngine {
   #activeCtx;
   #container;
   #image = null;
+  // Pictures of image annotations by source: an ImageBitmap, or null while
+  // loading or after a failed load.
+  #pictures = new Map();
+  #pictureLoader = null;
   #zoom = 1;
   #panX = 0;
   #panY = 0;
@@ -196,6 +346,27 @@ export class CanvasEngine {
   }
 
   // selectedAnnotation may be null, a single annotation object, or an array of annotation objects.
+  // fn(annotation) → Promise<ImageBitmap> for image annotations that name
+  // a stored screenshot or a file.
+  setPictureLoader(fn) {
+    this.#pictureLoader = fn;
+  }
+
+  // The picture an image annotation shows, or null until it has loaded;
+  // annotations are redrawn once it arrives.
+  #picture(shape) {
+    const key = shape.imageId ? `id:${shape.imageId}` : `path:${shape.path}`;
+    if (this.#pictures.has(key)) return this.#pictures.get(key);
+    this.#pictures.set(key, null);
+    this.#pictureLoader?.(shape)
+      .then(bitmap => {
+        this.#pictures.set(key, bitmap);
+        this.#renderAnnotationsInternal();
+      })
+      .catch(err => console.warn(`Image annotation ${key}:`, err));
+    return null;
+  }
+
   renderAnnotations(annotations, selectedAnnotation = null) {
     this.#annotations = annotations ?? [];
     this.#selectedAnnotation = selectedAnnotation ?? null;
@@ -234,8 +405,13 @@ export class CanvasEngine {
     if (!shape) return;
     ctx.save();
     ctx.globalAlpha = shape.opacity ?? 1;
+    this.#applyRotation(ctx, shape);
     ctx.lineWidth = shape.strokeWidth ?? 2;
     ctx.strokeStyle = shape.strokeColor || '#FF0000';
+    // Joins, caps and dashes match the Rust compositor (annotate/render.rs).
+    ctx.lineJoin = shape.type === 'rect' ? 'miter' : 'round';
+    ctx.lineCap = shape.type === 'arrow' || shape.type === 'freehand' ? 'round' : 'butt';
+    if (shape.lineDash) ctx.setLineDash(shape.lineDash);
 
     switch (shape.type) {
       case 'rect': {
@@ -251,14 +427,18 @@ export class CanvasEngine {
         const pts = shape.points;
         if (!pts || pts.length < 2) break;
         const [p1, p2] = pts;
-        ctx.beginPath();
-        ctx.moveTo(p1.x, p1.y);
-        ctx.lineTo(p2.x, p2.y);
-        ctx.stroke();
-        // Filled arrowhead triangle at p2
         const headLen = Math.max((shape.strokeWidth ?? 2) * 5, 12);
         const angle = Math.atan2(p2.y - p1.y, p2.x - p1.x);
         const wing = Math.PI / 6;
+        // Shaft stops at the base of the head so it doesn't poke out.
+        const back = headLen * Math.cos(wing);
+        if (back < Math.hypot(p2.x - p1.x, p2.y - p1.y)) {
+          ctx.beginPath();
+          ctx.moveTo(p1.x, p1.y);
+          ctx.lineTo(p2.x - back * Math.cos(angle), p2.y - back * Math.sin(angle));
+          ctx.stroke();
+        }
+        // Filled arrowhead triangle at p2
         ctx.beginPath();
         ctx.moveTo(p2.x, p2.y);
         ctx.lineTo(p2.x - headLen * Math.cos(angle - wing), p2.y - headLen * Math.sin(angle - wing));
@@ -283,28 +463,137 @@ export class CanvasEngine {
       }
 
       case 'text': {
-        ctx.font = `${shape.fontSize || 20}px ${shape.fontFamily || 'sans-serif'}`;
+        ctx.font = textFont(shape);
+        const layout = layoutText(ctx, shape);
+        if (shape.backgroundColor || shape.borderColor) {
+          const pad = shape.padding ?? 4;
+          ctx.beginPath();
+          ctx.roundRect(shape.x - pad, shape.y - pad, layout.width + pad * 2, layout.height + pad * 2, shape.cornerRadius ?? 4);
+          if (shape.backgroundColor) {
+            ctx.fillStyle = shape.backgroundColor;
+            ctx.fill();
+          }
+          if (shape.borderColor) {
+            ctx.setLineDash([]);
+            ctx.lineWidth = shape.borderWidth ?? 1;
+            ctx.strokeStyle = shape.borderColor;
+            ctx.stroke();
+          }
+        }
         ctx.fillStyle = shape.strokeColor || '#FF0000';
         ctx.textBaseline = 'top';
-        ctx.fillText(shape.text || '', shape.x, shape.y);
+        ctx.textAlign = 'left';
+        for (const line of layout.lines) {
+          ctx.direction = line.rtl ? 'rtl' : 'ltr';
+          ctx.fillText(line.text, shape.x + line.x, shape.y + line.y);
+        }
+        ctx.direction = 'inherit';
         break;
       }
 
       case 'blur': {
         if (!this.#image) break;
-        const blockSize = Math.max(1, shape.blurRadius || 10);
+        // Redaction is never see-through, as in the exporter.
+        ctx.globalAlpha = 1;
+        const style = shape.redactStyle || 'pixelate';
+        if (style === 'solid') {
+          ctx.fillStyle = '#000000';
+          ctx.fillRect(shape.x, shape.y, shape.width, shape.height);
+          break;
+        }
+        if (style === 'noise') {
+          ctx.fillStyle = ctx.createPattern(noiseTile(), 'repeat');
+          ctx.fillRect(shape.x, shape.y, shape.width, shape.height);
+          break;
+        }
+        // Matches MIN_REDACT_BLOCK in the Rust renderer.
+        const blockSize = Math.max(12, shape.blurRadius || 10);
         const sw = Math.max(1, Math.ceil(shape.width / blockSize));
         const sh = Math.max(1, Math.ceil(shape.height / blockSize));
         const off = new OffscreenCanvas(sw, sh);
         const offCtx = off.getContext('2d');
         offCtx.imageSmoothingEnabled = false;
         offCtx.drawImage(this.#image, shape.x, shape.y, shape.width, shape.height, 0, 0, sw, sh);
-        ctx.imageSmoothingEnabled = false;
+        ctx.imageSmoothingEnabled = style === 'gaussian';
         ctx.drawImage(off, 0, 0, sw, sh, shape.x, shape.y, shape.width, shape.height);
         ctx.imageSmoothingEnabled = true;
         break;
       }
 
+      case 'callout': {
+        if (!shape.tail || !(shape.width > 0) || !(shape.height > 0)) break;
+        traceCallout(ctx, shape);
+        ctx.fillStyle = shape.fillColor || '#FFFFFF';
+        ctx.fill();
+        ctx.setLineDash([]);
+        ctx.lineJoin = 'round';
+        ctx.stroke();
+        // Text wraps 8px in from the bubble's edges (CALLOUT_PADDING).
+        const text = {
+          text: shape.text,
+          fontSize: shape.fontSize || 16,
+          fontFamily: shape.fontFamily,
+          width: Math.max(1, shape.width - 16),
+        };
+        ctx.font = textFont(text);
+        ctx.fillStyle = shape.textColor || '#000000';
+        ctx.textBaseline = 'top';
+        ctx.textAlign = 'left';
+        for (const line of layoutText(ctx, text).lines) {
+          ctx.direction = line.rtl ? 'rtl' : 'ltr';
+          ctx.fillText(line.text, shape.x + 8 + line.x, shape.y + 8 + line.y);
+        }
+        ctx.direction = 'inherit';
+        break;
+      }
+
+      case 'magnifier': {
+        if (!this.#image || !(shape.width > 0) || !(shape.height > 0)) break;
+        const zoom = Math.max(1, shape.zoom || 2);
+        const cx = shape.x + shape.width / 2, cy = shape.y + shape.height / 2;
+        const src = shape.source || { x: cx, y: cy };
+        const sw = shape.width / zoom, sh = shape.height / zoom;
+        ctx.beginPath();
+        ctx.ellipse(cx, cy, shape.width / 2, shape.height / 2, 0, 0, Math.PI * 2);
+        ctx.save();
+        ctx.clip();
+        // Samples the screenshot itself, not the annotations over it.
+        ctx.drawImage(this.#image, src.x - sw / 2, src.y - sh / 2, sw, sh, shape.x, shape.y, shape.width, shape.height);
+        ctx.restore();
+        ctx.setLineDash([]);
+        ctx.stroke();
+        break;
+      }
+
+      case 'image': {
+        if (!(shape.width > 0) || !(shape.height > 0)) break;
+        if (shape.stamp) {
+          drawStamp(ctx, shape);
+          break;
+        }
+        const picture = this.#picture(shape);
+        if (picture) {
+          ctx.drawImage(picture, shape.x, shape.y, shape.width, shape.height);
+        } else {
+          // Placeholder until the picture loads (or if it can't).
+          ctx.setLineDash([4, 4]);
+          ctx.lineWidth = 1;
+          ctx.strokeStyle = '#888888';
+          ctx.strokeRect(shape.x, shape.y, shape.width, shape.height);
+        }
+        break;
+      }
+
+      case 'spotlight': {
+        if (!this.#image) break;
+        ctx.beginPath();
+        ctx.rect(0, 0, this.#image.width, this.#image.height);
+        ctx.roundRect(shape.x, shape.y, Math.max(0, shape.width), Math.max(0, shape.height), shape.cornerRadius || 0);
+        ctx.fillStyle = shape.dimColor || '#00000099';
+        ctx.fill('evenodd');
+        break;
+      }
+
       case 'step': {
         const size = shape.fontSize || 24;
         const radius = size / 2;
@@ -331,8 +620,8 @@ export class CanvasEngine {
       }
 
       case 'highlight': {
-        // Always 0.4 opacity per spec, regardless of shape.opacity
-        ctx.globalAlpha = 0.4;
+        // Always 0.4 opacity per spec, on top of shape.opacity
+        ctx.globalAlpha = 0.4 * (shape.opacity ?? 1);
         ctx.fillStyle = shape.highlightColor || '#FFFF00';
         ctx.fillRect(shape.x, shape.y, shape.width, shape.height);
         break;
@@ -342,6 +631,18 @@ export class CanvasEngine {
     ctx.restore();
   }
 
+  // Turn ctx about the shape's bbox centre by its rotation, if it has one.
+  #applyRotation(ctx, shape) {
+    const angle = rotationOf(shape);
+    if (!angle) return;
+    const bbox = this.#getShapeBBox(shape);
+    if (!bbox) return;
+    const cx = bbox.x + bbox.w / 2, cy = bbox.y + bbox.h / 2;
+    ctx.translate(cx, cy);
+    ctx.rotate(angle * Math.PI / 180);
+    ctx.translate(-cx, -cy);
+  }
+
   // Returns bounding box {x, y, w, h} for selection indicator.
   #getShapeBBox(shape) {
     switch (shape.type) {
@@ -349,7 +650,17 @@ export class CanvasEngine {
       case 'ellipse':
       case 'blur':
       case 'highlight':
+      case 'magnifier':
+      case 'spotlight':
+      case 'image':
         return { x: shape.x, y: shape.y, w: shape.width || 0, h: shape.height || 0 };
+      case 'callout': {
+        const tail = shape.tail || { x: shape.x, y: shape.y };
+        const x = Math.min(shape.x, tail.x), y = Math.min(shape.y, tail.y);
+        const w = Math.max(shape.x + (shape.width || 0), tail.x) - x;
+        const h = Math.max(shape.y + (shape.height || 0), tail.y) - y;
+        return { x, y, w, h };
+      }
       case 'arrow':
       case 'freehand': {
         const pts = shape.points;
@@ -361,9 +672,12 @@ export class CanvasEngine {
         return { x: minX, y: minY, w: maxX - minX, h: maxY - minY };
       }
       case 'text': {
-        const size = shape.fontSize || 20;
-        const approxW = size * (shape.text?.length || 1) * 0.6;
-        return { x: shape.x, y: shape.y, w: approxW, h: size * 1.4 };
+        const ctx = this.#activeCtx;
+        ctx.save();
+        ctx.font = textFont(shape);
+        const { width, height } = layoutText(ctx, shape);
+        ctx.restore();
+        return { x: shape.x, y: shape.y, w: width, h: height };
       }
       case 'step': {
         const r = (shape.fontSize || 24) / 2;
@@ -379,6 +693,7 @@ export class CanvasEngine {
     const bbox = this.#getShapeBBox(shape);
     if (!bbox) return;
     ctx.save();
+    this.#applyRotation(ctx, shape);
     ctx.strokeStyle = '#0066FF';
     ctx.lineWidth = 2 / this.#zoom;
     ctx.setLineDash([5 / this.#zoom, 5 / this.#zoom]);
@@ -1,6 +1,6 @@
 /// Selection tool — select, move, resize, and delete annotations.
 
-import { pointInRect, pointToSegmentDist, pointInEllipse } from '../utils/geometry.js';
+import { pointInRect, pointToSegmentDist, pointInEllipse, rotatePoint, rotationOf } from '../utils/geometry.js';
 
 // 8 handle descriptors for bbox-based shapes, in NW..SE order.
 const HANDLE_DEFS = [
@@ -17,6 +17,16 @@ const HANDLE_DEFS = [
 // Pixel tolerance for arrow/freehand hit testing (image-space pixels).
 const HIT_TOLERANCE = 6;
 
+// Rough text box without a canvas to measure against: the wrap width when
+// set, and one line per paragraph.
+function approxTextSize(anno) {
+  const size = anno.fontSize || 20;
+  const paragraphs = String(anno.text || '').split('\n');
+  const longest = Math.max(1, ...paragraphs.map(p => p.length));
+  const w = anno.width > 0 ? anno.width : size * longest * 0.6;
+  return { w, h: size * 1.4 * paragraphs.length };
+}
+
 export class SelectionManager {
   #selectedList = []; // ordered array of selected annotation objects
 
@@ -107,12 +117,35 @@ export class SelectionManager {
   }
 
   #hitTestOne(x, y, anno) {
+    // Test rotated shapes in their own, unrotated frame.
+    const angle = rotationOf(anno);
+    if (angle) {
+      const b = this.getBBox(anno);
+      ({ x, y } = rotatePoint(x, y, b.x + b.w / 2, b.y + b.h / 2, -angle));
+    }
     switch (anno.type) {
       case 'rect':
       case 'blur':
       case 'highlight':
+      case 'spotlight':
+      case 'image':
         return pointInRect(x, y, anno);
 
+      case 'magnifier': {
+        const cx = anno.x + anno.width / 2;
+        const cy = anno.y + anno.height / 2;
+        return pointInEllipse(x, y, cx, cy, Math.abs(anno.width / 2), Math.abs(anno.height / 2));
+      }
+
+      case 'callout': {
+        if (pointInRect(x, y, anno)) return true;
+        // Along the tail, from the bubble's centre out to the tip.
+        const t = anno.tail;
+        if (!t) return false;
+        const cx = anno.x + anno.width / 2, cy = anno.y + anno.height / 2;
+        return pointToSegmentDist(x, y, cx, cy, t.x, t.y) <= HIT_TOLERANCE;
+      }
+
       case 'ellipse': {
         const cx = anno.x + anno.width / 2;
         const cy = anno.y + anno.height / 2;
@@ -137,9 +170,8 @@ export class SelectionManager {
       }
 
       case 'text': {
-        const size = anno.fontSize || 20;
-        const approxW = size * (anno.text?.length || 1) * 0.6;
-        return pointInRect(x, y, { x: anno.x, y: anno.y, width: approxW, height: size * 1.4 });
+        const { w, h } = approxTextSize(anno);
+        return pointInRect(x, y, { x: anno.x, y: anno.y, width: w, height: h });
       }
 
       case 'step': {
@@ -160,8 +192,21 @@ export class SelectionManager {
       case 'ellipse':
       case 'blur':
       case 'highlight':
+      case 'magnifier':
+      case 'spotlight':
+      case 'image':
         return { x: anno.x, y: anno.y, w: anno.width || 0, h: anno.height || 0 };
 
+      case 'callout': {
+        const t = anno.tail || { x: anno.x, y: anno.y };
+        const x = Math.min(anno.x, t.x), y = Math.min(anno.y, t.y);
+        return {
+          x, y,
+          w: Math.max(anno.x + (anno.width || 0), t.x) - x,
+          h: Math.max(anno.y + (anno.height || 0), t.y) - y,
+        };
+      }
+
       case 'arrow':
       case 'freehand': {
         const pts = anno.points;
@@ -175,9 +220,8 @@ export class SelectionManager {
       }
 
       case 'text': {
-        const size = anno.fontSize || 20;
-        const approxW = size * (anno.text?.length || 1) * 0.6;
-        return { x: anno.x, y: anno.y, w: approxW, h: size * 1.4 };
+        const { w, h } = approxTextSize(anno);
+        return { x: anno.x, y: anno.y, w, h };
       }
 
       case 'step': {
@@ -224,9 +268,17 @@ export class SelectionManager {
         { id: 'p1', x: pts[1].x, y: pts[1].y, cursor: 'crosshair' },
       ];
     }
-    const bbox = this.getBBox(anno);
+    // A callout's handles resize its bubble; the tail stays put.
+    const bbox = anno.type === 'callout'
+      ? { x: anno.x, y: anno.y, w: anno.width || 0, h: anno.height || 0 }
+      : this.getBBox(anno);
     if (!bbox) return [];
-    return HANDLE_DEFS.map(def => ({ id: def.id, cursor: def.cursor, ...def.getPos(bbox) }));
+    const angle = rotationOf(anno);
+    const cx = bbox.x + bbox.w / 2, cy = bbox.y + bbox.h / 2;
+    return HANDLE_DEFS.map(def => {
+      const pos = def.getPos(bbox);
+      return { id: def.id, cursor: def.cursor, ...(angle ? rotatePoint(pos.x, pos.y, cx, cy, angle) : pos) };
+    });
   }
 
   // Returns 8 corner/midpoint handles for an explicit bounding box.
@@ -265,7 +317,11 @@ export class SelectionManager {
         points: orig.points.map(p => ({ x: p.x + dx, y: p.y + dy })),
       };
     }
-    // step stores centre in x/y; text stores top-left — both use x/y directly
+    if (orig.type === 'callout' && orig.tail) {
+      return { ...orig, x: orig.x + dx, y: orig.y + dy, tail: { x: orig.tail.x + dx, y: orig.tail.y + dy } };
+    }
+    // step stores centre in x/y; text stores top-left — both use x/y directly.
+    // A magnifier keeps its source, so the loupe can be moved off the detail.
     return { ...orig, x: orig.x + dx, y: orig.y + dy };
   }
 
@@ -291,7 +347,10 @@ export class SelectionManager {
       return this.applyMove(orig, totalDx, totalDy);
     }
 
-    // rect / ellipse / blur / highlight / freehand: update bounding box
+    // rect / ellipse / blur / highlight / freehand: update bounding box,
+    // dragging along the shape's own axes when it is rotated.
+    const angle = rotationOf(orig);
+    if (angle) ({ x: totalDx, y: totalDy } = rotatePoint(totalDx, totalDy, 0, 0, -angle));
     return this.#resizeBBox(orig, handleId, totalDx, totalDy);
   }
 
@@ -369,7 +428,18 @@ export class SelectionManager {
         y: newY + (orig.y - origBBox.y) * scaleY,
       };
     }
-    // rect, ellipse, blur, highlight
+    if (orig.type === 'callout' && orig.tail) {
+      const scaled = this.#scaleAnnotation({ ...orig, type: 'rect' }, origBBox, newX, newY, scaleX, scaleY);
+      return {
+        ...scaled,
+        type: 'callout',
+        tail: {
+          x: newX + (orig.tail.x - origBBox.x) * scaleX,
+          y: newY + (orig.tail.y - origBBox.y) * scaleY,
+        },
+      };
+    }
+    // rect, ellipse, blur, highlight, magnifier, spotlight, image
     return {
       ...orig,
       x: newX + (orig.x - origBBox.x) * scaleX,
@@ -37,6 +37,7 @@ export const store = new StateStore({
   strokeWidth: 2,
   fontSize: 16,
   opacity: 1.0,
+  redactStyle: 'pixelate',
   zoom: 1.0,
   panX: 0,
   panY: 0,
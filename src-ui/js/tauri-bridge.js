@@ -7,8 +7,40 @@ export async function ping() {
   return invoke('ping');
 }
 
-export async function takeScreenshot(mode, monitor = null, windowId = null) {
-  return invoke('take_screenshot', { mode, monitor, windowId });
+export async function takeScreenshot(mode, monitor = null, windowId = null, region = null, dedupe = false) {
+  return invoke('take_screenshot', { mode, monitor, windowId, region, dedupe });
+}
+
+export async function startScrollingCapture(mode, { windowId = null, region = null, intervalMs = null, maxFrames = null } = {}) {
+  return invoke('start_scrolling_capture', { mode, windowId, region, intervalMs, maxFrames });
+}
+
+export async function stopScrollingCapture() {
+  return invoke('stop_scrolling_capture');
+}
+
+export async function startRecording(mode, { monitor = null, windowId = null, region = null, format = 'gif', fps = null, durationMs, path = null }) {
+  return invoke('start_recording', { mode, monitor, windowId, region, format, fps, durationMs, path });
+}
+
+export async function stopRecording() {
+  return invoke('stop_recording');
+}
+
+export async function startCaptureSession(mode, { monitor = null, windowId = null, region = null, intervalSecs = null, cron = null, durationSecs, maxFrames = null, skipUnchanged = false }) {
+  return invoke('start_capture_session', { mode, monitor, windowId, region, intervalSecs, cron, durationSecs, maxFrames, skipUnchanged });
+}
+
+export async function stopCaptureSession(sessionId) {
+  return invoke('stop_capture_session', { sessionId });
+}
+
+export async function getCaptureSession(sessionId) {
+  return invoke('get_capture_session', { sessionId });
+}
+
+export async function listCaptureSessions() {
+  return invoke('list_capture_sessions');
 }
 
 export async function listMonitors() {
@@ -19,6 +51,38 @@ export async function listWindows() {
   return invoke('list_windows');
 }
 
+export async function getScreenshotInfo(imageId) {
+  return invoke('get_screenshot_info', { imageId });
+}
+
+export async function listScreenshots(limit = null) {
+  return invoke('list_screenshots', { limit });
+}
+
+export async function deleteScreenshot(imageId) {
+  return invoke('delete_screenshot', { imageId });
+}
+
+export async function getStoreStats() {
+  return invoke('get_store_stats');
+}
+
+export async function searchScreenshots(query, limit = null) {
+  return invoke('search_screenshots', { query, limit });
+}
+
+export async function findSimilarScreenshots(imageId, maxDistance = null) {
+  return invoke('find_similar_screenshots', { imageId, maxDistance });
+}
+
+export async function getLineage(imageId) {
+  return invoke('get_lineage', { imageId });
+}
+
+export async function reapplyOperations(sourceId, targetId) {
+  return invoke('reapply_operations', { sourceId, targetId });
+}
+
 export async function cropImage(imageId, x, y, width, height) {
   return invoke('crop_image', {
     imageId,
@@ -49,6 +113,14 @@ export async function compositeImage(imageId, annotations) {
   return invoke('composite_image', { imageId, annotations });
 }
 
+export async function loadAnnotationImage(imageId = null, path = null) {
+  return invoke('load_annotation_image', { imageId, path });
+}
+
+export async function exportPdf(pages, path = '', ocrText = false) {
+  return invoke('export_pdf', { pages, path, ocrText });
+}
+
 export async function showSaveDialog(opts = {}) {
   return window.__TAURI__.dialog.save(opts);
 }
@@ -57,8 +129,16 @@ export async function exportAnnotations(imageId, annotations) {
   return invoke('export_annotations', { imageId, annotations });
 }
 
-export async function importAnnotations() {
-  return invoke('import_annotations');
+export async function importAnnotations(imageId = null) {
+  return invoke('import_annotations', { imageId });
+}
+
+export async function saveProject(imageId, annotations, path = null) {
+  return invoke('save_project', { imageId, annotations, path });
+}
+
+export async function openProject(path = null) {
+  return invoke('open_project', { path });
 }
 
 export async function getSettings() {
@@ -2,7 +2,7 @@
 
 import {
   getApiKey, setApiKey, deleteApiKey, testApiKey,
-  getSettings, setSettings,
+  getSettings, setSettings, getStoreStats,
   tessdataAvailable, downloadTessdata,
 } from '../tauri-bridge.js';
 
@@ -24,6 +24,10 @@ const DEFAULTS = {
     defaultMode: 'region',
     defaultFormat: 'png',
     jpegQuality: 90,
+    webpQuality: 85,
+    webpLossless: true,
+    avifQuality: 80,
+    pngOptimizationLevel: 2,
     saveDirectory: '~/Pictures/Fotos',
     copyToClipboardAfterCapture: true,
     includeMouseCursor: false,
@@ -51,6 +55,11 @@ const DEFAULTS = {
     showStatusBar: true,
     smoothZoom: true,
   },
+  library: {
+    retentionDays: 30,
+    maxScreenshots: 0,
+    memoryBudgetMb: 1024,
+  },
 };
 
 let saveTimer = null;
@@ -95,6 +104,26 @@ export function showSettingsModal() {
     updateTessdataUI(lang);
   });
   refreshKeyStatuses();
+  refreshStoreStats();
+}
+
+function formatMb(bytes) {
+  return `${(bytes / (1024 * 1024)).toFixed(1)} MB`;
+}
+
+async function refreshStoreStats() {
+  const el = document.getElementById('library-stats');
+  if (!el) return;
+  try {
+    const s = await getStoreStats();
+    el.textContent =
+      `${s.resident_images} images in memory (${formatMb(s.resident_bytes)} of ${formatMb(s.memory_budget_bytes)}), ` +
+      `${s.spilled_images} spilled to disk (${formatMb(s.spilled_bytes)}), ` +
+      `${s.library_images} in library`;
+  } catch (e) {
+    el.textContent = '';
+    console.error('Failed to load store stats:', e);
+  }
 }
 
 function hideSettingsModal() {
@@ -124,21 +153,34 @@ function mergeWithDefaults(settings) {
     annotation: { ...DEFAULTS.annotation, ...(settings.annotation ?? {}) },
     ai,
     ui: { ...DEFAULTS.ui, ...(settings.ui ?? {}) },
+    library: { ...DEFAULTS.library, ...(settings.library ?? {}) },
   };
 }
 
 function applyToForm(rawSettings) {
-  const { capture, annotation, ai, ui } = mergeWithDefaults(rawSettings);
+  const { capture, annotation, ai, ui, library } = mergeWithDefaults(rawSettings);
 
   // Capture
   setVal('pref-capture-defaultMode', capture.defaultMode);
   setVal('pref-capture-defaultFormat', capture.defaultFormat);
   setVal('pref-capture-jpegQuality', capture.jpegQuality);
+  setVal('pref-capture-webpQuality', capture.webpQuality);
+  setCheck('pref-capture-webpLossless', capture.webpLossless);
+  setVal('pref-capture-avifQuality', capture.avifQuality);
+  setVal('pref-capture-pngOptimizationLevel', String(capture.pngOptimizationLevel));
   setVal('pref-capture-saveDirectory', capture.saveDirectory);
   setCheck('pref-capture-copyToClipboard', capture.copyToClipboardAfterCapture);
   setCheck('pref-capture-includeMouseCursor', capture.includeMouseCursor);
   setVal('pref-capture-delayMs', capture.delayMs);
   updateRangeDisplay('pref-capture-jpegQuality', capture.jpegQuality);
+  updateRangeDisplay('pref-capture-webpQuality', capture.webpQuality);
+  updateRangeDisplay('pref-capture-avifQuality', capture.avifQuality);
+  updateWebpQualityRow();
+
+  // Library
+  setVal('pref-library-retentionDays', library.retentionDays);
+  setVal('pref-library-maxScreenshots', library.maxScreenshots);
+  setVal('pref-library-memoryBudgetMb', library.memoryBudgetMb);
 
   // Annotation
   setVal('pref-annotation-strokeColor', annotation.defaultStrokeColor);
@@ -182,6 +224,10 @@ function readFromForm() {
       defaultMode: getVal('pref-capture-defaultMode'),
       defaultFormat: getVal('pref-capture-defaultFormat'),
       jpegQuality: parseInt(getVal('pref-capture-jpegQuality'), 10),
+      webpQuality: parseInt(getVal('pref-capture-webpQuality'), 10),
+      webpLossless: getCheck('pref-capture-webpLossless'),
+      avifQuality: parseInt(getVal('pref-capture-avifQuality'), 10),
+      pngOptimizationLevel: parseInt(getVal('pref-capture-pngOptimizationLevel'), 10),
       saveDirectory: getVal('pref-capture-saveDirectory'),
       copyToClipboardAfterCapture: getCheck('pref-capture-copyToClipboard'),
       includeMouseCursor: getCheck('pref-capture-includeMouseCursor'),
@@ -209,6 +255,11 @@ function readFromForm() {
       showStatusBar: getCheck('pref-ui-showStatusBar'),
       smoothZoom: getCheck('pref-ui-smoothZoom'),
     },
+    library: {
+      retentionDays: parseInt(getVal('pref-library-retentionDays'), 10) || 0,
+      maxScreenshots: parseInt(getVal('pref-library-maxScreenshots'), 10) || 0,
+      memoryBudgetMb: parseInt(getVal('pref-library-memoryBudgetMb'), 10) || DEFAULTS.library.memoryBudgetMb,
+    },
   };
 }
 
@@ -392,6 +443,12 @@ function updateRangeDisplay(id, value) {
   if (el) el.textContent = value;
 }
 
+// WebP quality only applies to lossy WebP.
+function updateWebpQualityRow() {
+  const row = document.getElementById('row-webp-quality');
+  if (row) row.classList.toggle('hidden', getCheck('pref-capture-webpLossless'));
+}
+
 // ─── API key management (static providers) ────────────────────────────────────
 
 async function refreshKeyStatuses() {
@@ -472,6 +529,17 @@ export function initSettings() {
     updateRangeDisplay('pref-capture-jpegQuality', jpegRange.value);
     scheduleSave();
   });
+  const webpRange = modal.querySelector('#pref-capture-webpQuality');
+  webpRange.addEventListener('input', () => {
+    updateRangeDisplay('pref-capture-webpQuality', webpRange.value);
+    scheduleSave();
+  });
+  const avifRange = modal.querySelector('#pref-capture-avifQuality');
+  avifRange.addEventListener('input', () => {
+    updateRangeDisplay('pref-capture-avifQuality', avifRange.value);
+    scheduleSave();
+  });
+  modal.querySelector('#pref-capture-webpLossless').addEventListener('change', updateWebpQualityRow);
 
   // Apply theme immediately on change (before debounced save)
   modal.querySelector('#pref-ui-theme').addEventListener('change', (e) => {
@@ -36,3 +36,18 @@ export function pointInEllipse(px, py, cx, cy, rx, ry) {
   const ny = (py - cy) / ry;
   return nx * nx + ny * ny <= 1;
 }
+
+// (px, py) turned `degrees` clockwise on screen (y down) about (cx, cy).
+export function rotatePoint(px, py, cx, cy, degrees) {
+  const t = degrees * Math.PI / 180;
+  const cos = Math.cos(t), sin = Math.sin(t);
+  const dx = px - cx, dy = py - cy;
+  return { x: cx + dx * cos - dy * sin, y: cy + dx * sin + dy * cos };
+}
+
+// Turn in degrees applied to an annotation about its bbox centre. Only
+// rect, ellipse, text and image rotate, as in the exporter (annotate/render.rs).
+export function rotationOf(anno) {
+  const rotatable = ['rect', 'ellipse', 'text', 'image'].includes(anno.type);
+  return rotatable ? anno.rotation || 0 : 0;
+}